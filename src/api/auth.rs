@@ -1,4 +1,5 @@
 use crate::AppState;
+use crate::config::AuthConfig;
 use axum::{
     body::Body,
     extract::State,
@@ -14,54 +15,90 @@ pub async fn basic_auth(
     req: Request<Body>,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    if !state.auth_enabled {
+    if is_authenticated(&state.config.auth, &req) {
         return Ok(next.run(req).await);
     }
+    Err(unauthorized(&req))
+}
 
+/// Whether the request may use the regular API: no password is set or it
+/// carries the basic credential.
+pub fn is_authenticated(auth: &AuthConfig, req: &Request<Body>) -> bool {
+    match &auth.password {
+        Some(password) => has_credentials(req, &auth.user, password),
+        None => true,
+    }
+}
+
+/// Whether the request carries the admin credential.
+pub fn is_admin(auth: &AuthConfig, req: &Request<Body>) -> bool {
+    auth.admin_password
+        .as_ref()
+        .is_some_and(|password| has_credentials(req, &auth.admin_user, password))
+}
+
+/// Guards the `/_fake` admin API, which accepts only the admin credential.
+/// The routes are only served once an admin password is set, but without one
+/// every request is rejected all the same.
+pub async fn admin_auth(
+    State(state): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    if is_admin(&state.config.auth, &req) {
+        return Ok(next.run(req).await);
+    }
+    Err(unauthorized(&req))
+}
+
+fn has_credentials(req: &Request<Body>, user: &str, password: &str) -> bool {
     let auth_header = req
         .headers()
         .get(header::AUTHORIZATION)
         .and_then(|h| h.to_str().ok());
 
-    match auth_header {
-        Some(header) if header.starts_with("Basic ") => {
-            let credential_part = &header[6..];
-            if let Ok(decoded) = general_purpose::STANDARD.decode(credential_part) {
-                if let Ok(decoded_str) = String::from_utf8(decoded) {
-                    let mut parts = decoded_str.splitn(2, ':');
-                    let username = parts.next().unwrap_or("");
-                    let password = parts.next().unwrap_or("");
-
-                    if username == state.auth_user && password == state.auth_password {
-                        return Ok(next.run(req).await);
-                    }
-                }
-            }
-        }
-        _ => {}
+    if let Some(header) = auth_header
+        && let Some(credential_part) = header.strip_prefix("Basic ")
+        && let Ok(decoded) = general_purpose::STANDARD.decode(credential_part)
+        && let Ok(decoded_str) = String::from_utf8(decoded)
+    {
+        let mut parts = decoded_str.splitn(2, ':');
+        let username = parts.next().unwrap_or("");
+        let given_password = parts.next().unwrap_or("");
+        return username == user && given_password == password;
     }
+    false
+}
 
+pub fn unauthorized(req: &Request<Body>) -> StatusCode {
     if std::env::var("DEBUG").map(|v| v == "true").unwrap_or(false) {
         println!("--- AUTH FAILED ---");
         println!("Path: {}", req.uri());
     }
-
-    Err(StatusCode::UNAUTHORIZED)
+    StatusCode::UNAUTHORIZED
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{AuthConfig, Config};
     use crate::repository::store::InMemoryStore;
+    use crate::repository::tasks::TaskRegistry;
     use axum::middleware::from_fn_with_state;
     use tower::{Layer, Service, ServiceExt};
 
     fn setup_state(enabled: bool) -> Arc<AppState> {
         Arc::new(AppState {
             store: InMemoryStore::new(),
-            auth_user: "elastic".to_string(),
-            auth_password: "password123".to_string(),
-            auth_enabled: enabled,
+            tasks: TaskRegistry::new(),
+            config: Arc::new(Config {
+                auth: AuthConfig {
+                    password: enabled.then(|| "password123".to_string()),
+                    admin_password: Some("secret".to_string()),
+                    ..AuthConfig::default()
+                },
+                ..Config::default()
+            }),
         })
     }
 
@@ -126,4 +163,40 @@ mod tests {
 
         assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
     }
+
+    #[tokio::test]
+    async fn should_require_admin_credential_for_admin_api() {
+        let state = setup_state(true);
+        let layer = from_fn_with_state(state, admin_auth);
+        let mut service = layer.layer(tower::service_fn(handle_request));
+
+        for (credentials, status) in [
+            ("elastic:password123", StatusCode::UNAUTHORIZED),
+            ("admin:secret", StatusCode::OK),
+        ] {
+            let auth = general_purpose::STANDARD.encode(credentials);
+            let req = Request::builder()
+                .header(header::AUTHORIZATION, format!("Basic {}", auth))
+                .body(Body::empty())
+                .unwrap();
+            let res = service.ready().await.unwrap().call(req).await.unwrap();
+            assert_eq!(res.status(), status);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_reject_every_credential_for_admin_api_without_admin_password() {
+        let state = Arc::new(AppState {
+            store: InMemoryStore::new(),
+            tasks: TaskRegistry::new(),
+            config: Arc::new(Config::default()),
+        });
+        let layer = from_fn_with_state(state, admin_auth);
+        let mut service = layer.layer(tower::service_fn(handle_request));
+
+        let req = Request::builder().body(Body::empty()).unwrap();
+        let res = service.ready().await.unwrap().call(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
 }
@@ -0,0 +1,151 @@
+use super::to_error;
+use crate::AppState;
+use crate::api::namespaces::Namespaces;
+use crate::repository::persistence::StoreState;
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+
+/// Wipes the store, so every test can start from an empty cluster.
+pub async fn reset(State(state): State<Arc<AppState>>) -> Response {
+    let store = state.clone();
+    match tokio::task::spawn_blocking(move || store.store.reset()).await {
+        Ok(Ok(())) => Json(json!({ "acknowledged": true })).into_response(),
+        Ok(Err(e)) => admin_error(&e),
+        Err(e) => admin_error(&e.to_string()),
+    }
+}
+
+/// Exports indices with their documents, aliases, data streams, templates,
+/// pipelines and snapshot repositories as one JSON document that
+/// [`load`] accepts.
+pub async fn dump(State(state): State<Arc<AppState>>) -> Response {
+    let snapshot = state.store.snapshot();
+    match tokio::task::spawn_blocking(move || serde_json::to_vec(&snapshot)).await {
+        Ok(Ok(bytes)) => ([(header::CONTENT_TYPE, "application/json")], bytes).into_response(),
+        Ok(Err(e)) => admin_error(&e.to_string()),
+        Err(e) => admin_error(&e.to_string()),
+    }
+}
+
+/// Replaces the whole store with a document produced by [`dump`].
+pub async fn load(State(state): State<Arc<AppState>>, body: Body) -> Response {
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return to_error(StatusCode::BAD_REQUEST, "parse_exception", &e.to_string())
+                .into_response();
+        }
+    };
+    let loaded = tokio::task::spawn_blocking(move || {
+        let dump: StoreState = serde_json::from_slice(&bytes)
+            .map_err(|e| (true, format!("failed to parse store dump: {}", e)))?;
+        let indices = dump.indices.len();
+        state
+            .store
+            .load(dump)
+            .map(|_| indices)
+            .map_err(|e| (false, e))
+    })
+    .await;
+    match loaded {
+        Ok(Ok(indices)) => {
+            Json(json!({ "acknowledged": true, "indices": indices })).into_response()
+        }
+        Ok(Err((true, e))) => {
+            to_error(StatusCode::BAD_REQUEST, "parse_exception", &e).into_response()
+        }
+        Ok(Err((false, e))) => admin_error(&e),
+        Err(e) => admin_error(&e.to_string()),
+    }
+}
+
+/// Namespaces created by requests carrying the namespace header.
+pub async fn get_namespaces(State(namespaces): State<Arc<Namespaces>>) -> Response {
+    let listed: Map<String, Value> = namespaces
+        .names()
+        .into_iter()
+        .filter_map(|name| {
+            let indices = namespaces.index_count(&name)?;
+            Some((name, json!({ "indices": indices })))
+        })
+        .collect();
+    Json(json!({ "namespaces": listed })).into_response()
+}
+
+pub async fn delete_namespace(
+    Path(namespace): Path<String>,
+    State(namespaces): State<Arc<Namespaces>>,
+) -> Response {
+    if namespaces.remove(&namespace) {
+        Json(json!({ "acknowledged": true })).into_response()
+    } else {
+        to_error(
+            StatusCode::NOT_FOUND,
+            "resource_not_found_exception",
+            &format!("namespace [{}] missing", namespace),
+        )
+        .into_response()
+    }
+}
+
+fn admin_error(reason: &str) -> Response {
+    to_error(StatusCode::INTERNAL_SERVER_ERROR, "exception", reason).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::setup_state;
+    use crate::domain::mapping::Mapping;
+    use crate::domain::versioning::WriteConditions;
+
+    async fn body_json(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_dump_reset_and_load_the_store() {
+        let state = setup_state();
+        state
+            .store
+            .create_index("products".to_string(), Mapping::default())
+            .unwrap();
+        state
+            .store
+            .write_document(
+                "products",
+                Some("1"),
+                json!({ "name": "lamp" }),
+                None,
+                &WriteConditions::default(),
+            )
+            .unwrap();
+
+        let dumped = dump(State(state.clone())).await;
+        assert_eq!(dumped.status(), StatusCode::OK);
+        let dumped = body_json(dumped).await;
+        assert!(dumped["indices"]["products"].is_object());
+
+        let response = reset(State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(state.store.index_names().is_empty());
+
+        let response = load(State(state.clone()), Body::from(dumped.to_string())).await;
+        assert_eq!(body_json(response).await["indices"], 1);
+        assert!(state.store.get_document("products", "1").is_some());
+
+        let response = load(State(state.clone()), Body::from("{ not json")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(state.store.get_document("products", "1").is_some());
+    }
+}
@@ -0,0 +1,351 @@
+use super::{index_not_found, matches_any, to_error};
+use crate::AppState;
+use crate::domain::alias::AliasDefinition;
+use crate::repository::store::{AliasAction, IndicesOptions};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+
+pub async fn put_alias(
+    Path((index, name)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+    body: Option<Json<Value>>,
+) -> Response {
+    let definition = body
+        .map(|Json(b)| AliasDefinition::from_json(&b))
+        .unwrap_or_default();
+
+    let indices = match concrete_indices(&state, &index) {
+        Ok(indices) => indices,
+        Err(missing) => return index_not_found(&missing).into_response(),
+    };
+
+    let actions = indices
+        .into_iter()
+        .map(|index| AliasAction::Add {
+            index,
+            alias: name.clone(),
+            definition: definition.clone(),
+        })
+        .collect();
+
+    apply_actions(&state, actions)
+}
+
+pub async fn delete_alias(
+    Path((index, name)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let indices = match concrete_indices(&state, &index) {
+        Ok(indices) => indices,
+        Err(missing) => return index_not_found(&missing).into_response(),
+    };
+
+    let actions = removal_actions(&state, &indices, &name);
+    if actions.is_empty() {
+        return to_error(
+            StatusCode::NOT_FOUND,
+            "aliases_not_found_exception",
+            &format!("aliases [{}] missing", name),
+        )
+        .into_response();
+    }
+
+    apply_actions(&state, actions)
+}
+
+pub async fn get_all_aliases(State(state): State<Arc<AppState>>) -> Response {
+    render_aliases(&state, None, None)
+}
+
+pub async fn get_aliases_by_name(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    render_aliases(&state, None, Some(&name))
+}
+
+pub async fn get_index_aliases(
+    Path(index): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    render_aliases(&state, Some(&index), None)
+}
+
+pub async fn get_index_aliases_by_name(
+    Path((index, name)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    render_aliases(&state, Some(&index), Some(&name))
+}
+
+pub async fn check_alias(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> StatusCode {
+    let aliases = state.store.aliases();
+    if aliases.keys().any(|alias| matches_any(&name, alias)) {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+pub async fn update_aliases(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<Value>,
+) -> Response {
+    let Some(raw_actions) = body
+        .get("actions")
+        .and_then(|a| a.as_array())
+        .filter(|a| !a.is_empty())
+    else {
+        return to_error(
+            StatusCode::BAD_REQUEST,
+            "action_request_validation_exception",
+            "Validation Failed: 1: no actions specified;",
+        )
+        .into_response();
+    };
+
+    let mut actions = Vec::new();
+    for raw in raw_actions {
+        let Some((kind, params)) = raw.as_object().and_then(|o| o.iter().next()) else {
+            continue;
+        };
+
+        let index_expr = names_param(params, "index", "indices");
+        let indices = match concrete_indices(&state, &index_expr.join(",")) {
+            Ok(indices) => indices,
+            Err(missing) => return index_not_found(&missing).into_response(),
+        };
+
+        match kind.as_str() {
+            "add" => {
+                let definition = AliasDefinition::from_json(params);
+                for alias in names_param(params, "alias", "aliases") {
+                    for index in &indices {
+                        actions.push(AliasAction::Add {
+                            index: index.clone(),
+                            alias: alias.clone(),
+                            definition: definition.clone(),
+                        });
+                    }
+                }
+            }
+            "remove" => {
+                let pattern = names_param(params, "alias", "aliases").join(",");
+                let removals = removal_actions(&state, &indices, &pattern);
+                let must_exist = params["must_exist"].as_bool().unwrap_or(true);
+                if removals.is_empty() && must_exist {
+                    return to_error(
+                        StatusCode::NOT_FOUND,
+                        "aliases_not_found_exception",
+                        &format!("aliases [{}] missing", pattern),
+                    )
+                    .into_response();
+                }
+                actions.extend(removals);
+            }
+            "remove_index" => {
+                actions.extend(
+                    indices
+                        .into_iter()
+                        .map(|index| AliasAction::RemoveIndex { index }),
+                );
+            }
+            other => {
+                return to_error(
+                    StatusCode::BAD_REQUEST,
+                    "x_content_parse_exception",
+                    &format!("[aliases] unknown field [{}]", other),
+                )
+                .into_response();
+            }
+        }
+    }
+
+    apply_actions(&state, actions)
+}
+
+fn apply_actions(state: &AppState, actions: Vec<AliasAction>) -> Response {
+    match state.store.update_aliases(actions) {
+        Ok(()) => Json(json!({ "acknowledged": true })).into_response(),
+        Err(e) => {
+            to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e).into_response()
+        }
+    }
+}
+
+fn concrete_indices(state: &AppState, expression: &str) -> Result<Vec<String>, String> {
+    let targets = state
+        .store
+        .resolve_indices(expression, IndicesOptions::default())?;
+    if targets.is_empty() {
+        return Err(expression.to_string());
+    }
+    Ok(targets.into_iter().map(|t| t.index).collect())
+}
+
+fn removal_actions(state: &AppState, indices: &[String], pattern: &str) -> Vec<AliasAction> {
+    let mut actions = Vec::new();
+    for (alias, members) in state.store.aliases() {
+        if !matches_any(pattern, &alias) {
+            continue;
+        }
+        for index in indices {
+            if members.contains_key(index) {
+                actions.push(AliasAction::Remove {
+                    index: index.clone(),
+                    alias: alias.clone(),
+                });
+            }
+        }
+    }
+    actions
+}
+
+fn render_aliases(state: &AppState, index: Option<&str>, name: Option<&str>) -> Response {
+    let indices = match index {
+        Some(expression) => match concrete_indices(state, expression) {
+            Ok(indices) => indices,
+            Err(missing) => return index_not_found(&missing).into_response(),
+        },
+        None => state.store.index_names(),
+    };
+
+    let aliases = state.store.aliases();
+    let mut result = Map::new();
+    for index in indices {
+        let mut index_aliases = Map::new();
+        for (alias, members) in &aliases {
+            if name.is_some_and(|pattern| !matches_any(pattern, alias)) {
+                continue;
+            }
+            if let Some(definition) = members.get(&index) {
+                index_aliases.insert(alias.clone(), json!(definition));
+            }
+        }
+        if name.is_none() || !index_aliases.is_empty() {
+            result.insert(index, json!({ "aliases": index_aliases }));
+        }
+    }
+
+    if let Some(name) = name
+        && result.is_empty()
+        && !name.contains('*')
+    {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("alias [{}] missing", name), "status": 404 })),
+        )
+            .into_response();
+    }
+
+    Json(Value::Object(result)).into_response()
+}
+
+fn names_param(params: &Value, single: &str, multiple: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    if let Some(name) = params[single].as_str() {
+        names.push(name.to_string());
+    }
+    if let Some(list) = params[multiple].as_array() {
+        names.extend(
+            list.iter()
+                .filter_map(|v| v.as_str())
+                .map(|s| s.to_string()),
+        );
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::setup_state;
+    use crate::domain::mapping::Mapping;
+
+    #[tokio::test]
+    async fn should_put_get_and_delete_alias() {
+        let state = setup_state();
+        state
+            .store
+            .create_index("logs-1".to_string(), Mapping::default())
+            .unwrap();
+
+        let response = put_alias(
+            Path(("logs-1".to_string(), "logs".to_string())),
+            State(state.clone()),
+            Some(Json(json!({ "is_write_index": true }))),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            check_alias(Path("logs".to_string()), State(state.clone())).await,
+            StatusCode::OK
+        );
+
+        let response = delete_alias(
+            Path(("logs-1".to_string(), "logs".to_string())),
+            State(state.clone()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = get_aliases_by_name(Path("logs".to_string()), State(state)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_swap_aliases_atomically() {
+        let state = setup_state();
+        state
+            .store
+            .create_index("v1".to_string(), Mapping::default())
+            .unwrap();
+        state
+            .store
+            .create_index("v2".to_string(), Mapping::default())
+            .unwrap();
+        state
+            .store
+            .update_aliases(vec![AliasAction::Add {
+                index: "v1".to_string(),
+                alias: "current".to_string(),
+                definition: AliasDefinition::default(),
+            }])
+            .unwrap();
+
+        let body = json!({
+            "actions": [
+                { "remove": { "index": "v1", "alias": "current" } },
+                { "add": { "index": "v2", "alias": "current" } }
+            ]
+        });
+        let response = update_aliases(State(state.clone()), Json(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let aliases = state.store.aliases();
+        assert!(aliases["current"].contains_key("v2"));
+        assert!(!aliases["current"].contains_key("v1"));
+    }
+
+    #[tokio::test]
+    async fn should_fail_removing_missing_alias() {
+        let state = setup_state();
+        state
+            .store
+            .create_index("v1".to_string(), Mapping::default())
+            .unwrap();
+
+        let body = json!({ "actions": [{ "remove": { "index": "v1", "alias": "ghost" } }] });
+        let response = update_aliases(State(state), Json(body)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
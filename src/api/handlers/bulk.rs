@@ -0,0 +1,1214 @@
+//! The `_bulk` API. A request that fails validation, e.g. with a malformed
+//! line near its end, must leave the store untouched, so nothing is written
+//! before the whole body has been read. The body is therefore read twice:
+//! a first pass validates every action line while spooling the raw bytes
+//! (in memory up to [`SPOOL_MEMORY_LIMIT`], in a temporary file beyond), and
+//! a second pass parses the spool again and applies it [`BATCH_SIZE`]
+//! actions at a time. Only the spool holds the whole body; the parsed
+//! actions never do.
+
+use super::documents::{DATA_STREAM_OP_TYPE, index_response, update_response};
+use super::{param_flag, to_error, write_error};
+use crate::AppState;
+use crate::domain::ingest::Pipeline;
+use crate::domain::update::{UpdateKind, UpdateRequest};
+use crate::domain::versioning::WriteConditions;
+use crate::repository::store::{BatchOp, BatchResult, WriteTarget};
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use indexmap::IndexMap;
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt};
+
+/// Metadata accepted on a bulk action line; anything else is rejected the
+/// way Elasticsearch does.
+const ACTION_PARAMETERS: &[&str] = &[
+    "_index",
+    "_id",
+    "routing",
+    "require_alias",
+    "if_seq_no",
+    "if_primary_term",
+    "version",
+    "version_type",
+    "retry_on_conflict",
+    "pipeline",
+    "_source",
+];
+
+/// Parameters copied from an action line into [`WriteConditions`].
+const CONDITION_PARAMETERS: &[&str] = &["if_seq_no", "if_primary_term", "version", "version_type"];
+
+/// Number of actions applied to the store at a time.
+const BATCH_SIZE: usize = 1000;
+
+/// Size up to which a bulk body is kept in memory between the validation
+/// pass and the write pass; larger bodies are spooled to a temporary file.
+const SPOOL_MEMORY_LIMIT: usize = 8 * 1024 * 1024;
+
+enum BulkOp {
+    /// `index` or `create`; a source line that is not valid JSON fails only
+    /// its own item.
+    Index(Result<Value, String>),
+    Update(Box<UpdateRequest>),
+    Delete,
+}
+
+/// One parsed action of a bulk request.
+struct BulkAction {
+    name: String,
+    index: String,
+    id: Option<String>,
+    routing: Option<String>,
+    pipeline: Option<String>,
+    require_alias: bool,
+    conditions: WriteConditions,
+    op: BulkOp,
+}
+
+/// A failure that rejects the whole request.
+struct RequestError {
+    status: StatusCode,
+    error_type: &'static str,
+    reason: String,
+}
+
+impl RequestError {
+    fn new(error_type: &'static str, reason: String) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            error_type,
+            reason,
+        }
+    }
+
+    /// The body could not be spooled, which is not the client's fault.
+    fn io(error: std::io::Error) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            error_type: "exception",
+            reason: format!("failed to buffer the bulk request: {}", error),
+        }
+    }
+}
+
+/// An action line waiting for its source line.
+struct PendingAction {
+    name: String,
+    meta: Map<String, Value>,
+    conditions: WriteConditions,
+}
+
+/// Turns NDJSON lines into actions one line at a time, pairing each action
+/// line with the source line that follows it.
+struct BulkParser<'a> {
+    default_index: Option<&'a str>,
+    require_alias: bool,
+    /// `false` for the validation pass, which skips parsing `index` and
+    /// `create` sources since those only fail their own item.
+    keep_sources: bool,
+    line_number: usize,
+    pending: Option<PendingAction>,
+    parsed: usize,
+    validation: Vec<String>,
+}
+
+impl<'a> BulkParser<'a> {
+    fn new(default_index: Option<&'a str>, require_alias: bool, keep_sources: bool) -> Self {
+        Self {
+            default_index,
+            require_alias,
+            keep_sources,
+            line_number: 0,
+            pending: None,
+            parsed: 0,
+            validation: Vec::new(),
+        }
+    }
+
+    fn push_bytes(&mut self, line: &[u8]) -> Result<Option<BulkAction>, RequestError> {
+        let line = std::str::from_utf8(line).map_err(|e| {
+            RequestError::new("x_content_parse_exception", format!("Invalid UTF-8: {}", e))
+        })?;
+        self.push_line(line)
+    }
+
+    /// Consumes one line, returning an action once it is complete.
+    fn push_line(&mut self, line: &str) -> Result<Option<BulkAction>, RequestError> {
+        self.line_number += 1;
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        if let Some(pending) = self.pending.take() {
+            if pending.name != "update" && !self.keep_sources {
+                return Ok(Some(self.action(pending, BulkOp::Index(Ok(Value::Null)))));
+            }
+            let source =
+                serde_json::from_str::<Value>(line).map_err(|e| format!("failed to parse: {}", e));
+            if pending.name != "update" {
+                return Ok(Some(self.action(pending, BulkOp::Index(source))));
+            }
+
+            let body = source.map_err(|e| {
+                RequestError::new(
+                    "x_content_parse_exception",
+                    format!("[{}:1] {}", self.line_number, e),
+                )
+            })?;
+            let body = if ["doc", "upsert", "script"]
+                .iter()
+                .any(|key| body.get(key).is_some())
+            {
+                body
+            } else {
+                json!({ "doc": body })
+            };
+            let request = UpdateRequest::from_json(&body).and_then(|mut request| {
+                if let Some(retries) = pending.meta.get("retry_on_conflict") {
+                    let retries = retries
+                        .as_str()
+                        .map_or_else(|| retries.to_string(), str::to_string);
+                    request
+                        .set_retry_on_conflict(&retries, &pending.conditions)
+                        .map_err(|e| format!("Validation Failed: 1: {};", e))?;
+                }
+                Ok(request)
+            });
+            return match request {
+                Ok(request) => Ok(Some(
+                    self.action(pending, BulkOp::Update(Box::new(request))),
+                )),
+                Err(e) => {
+                    self.validation.push(
+                        e.trim_start_matches("Validation Failed: 1: ")
+                            .trim_end_matches(';')
+                            .to_string(),
+                    );
+                    Ok(None)
+                }
+            };
+        }
+
+        let pending = self.parse_action_line(line)?;
+        if pending.name == "delete" {
+            return Ok(Some(self.action(pending, BulkOp::Delete)));
+        }
+        self.pending = Some(pending);
+        Ok(None)
+    }
+
+    fn parse_action_line(&mut self, line: &str) -> Result<PendingAction, RequestError> {
+        let number = self.line_number;
+        let action_json: Value = serde_json::from_str(line).map_err(|e| {
+            RequestError::new(
+                "x_content_parse_exception",
+                format!("Malformed action/metadata line [{}]: {}", number, e),
+            )
+        })?;
+        let Some((name, meta)) = action_json
+            .as_object()
+            .filter(|object| object.len() == 1)
+            .and_then(|object| object.iter().next())
+            .filter(|(name, _)| ["index", "create", "update", "delete"].contains(&name.as_str()))
+        else {
+            let found = action_json
+                .as_object()
+                .and_then(|object| object.keys().next().cloned())
+                .unwrap_or_else(|| action_json.to_string());
+            return Err(RequestError::new(
+                "illegal_argument_exception",
+                format!(
+                    "Malformed action/metadata line [{}], expected field [create], [delete], \
+                     [index] or [update] but found [{}]",
+                    number, found
+                ),
+            ));
+        };
+        let meta = meta.as_object().cloned().unwrap_or_default();
+        if let Some(unknown) = meta
+            .keys()
+            .find(|key| !ACTION_PARAMETERS.contains(&key.as_str()))
+        {
+            return Err(RequestError::new(
+                "illegal_argument_exception",
+                format!(
+                    "Action/metadata line [{}] contains an unknown parameter [{}]",
+                    number, unknown
+                ),
+            ));
+        }
+
+        let condition_params: HashMap<String, String> = CONDITION_PARAMETERS
+            .iter()
+            .filter_map(|key| {
+                let value = meta.get(*key)?;
+                let value = value
+                    .as_str()
+                    .map_or_else(|| value.to_string(), str::to_string);
+                Some((key.to_string(), value))
+            })
+            .collect();
+        let mut conditions = match WriteConditions::from_params(&condition_params) {
+            Ok(conditions) => conditions,
+            Err(e) => {
+                self.validation.push(e);
+                WriteConditions::default()
+            }
+        };
+        conditions.create = name == "create";
+
+        Ok(PendingAction {
+            name: name.clone(),
+            meta,
+            conditions,
+        })
+    }
+
+    fn action(&mut self, pending: PendingAction, op: BulkOp) -> BulkAction {
+        let PendingAction {
+            name,
+            meta,
+            conditions,
+        } = pending;
+        let index = meta
+            .get("_index")
+            .and_then(Value::as_str)
+            .or(self.default_index);
+        let id = meta.get("_id").and_then(|id| match id {
+            Value::String(id) => Some(id.clone()),
+            Value::Null => None,
+            other => Some(other.to_string()),
+        });
+        if index.is_none() {
+            self.validation.push("index is missing".to_string());
+        }
+        if id.is_none() && (name == "update" || name == "delete") {
+            self.validation.push("id is missing".to_string());
+        }
+
+        self.parsed += 1;
+        BulkAction {
+            name,
+            index: index.unwrap_or_default().to_string(),
+            id,
+            routing: meta
+                .get("routing")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            pipeline: meta
+                .get("pipeline")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            require_alias: meta
+                .get("require_alias")
+                .and_then(Value::as_bool)
+                .unwrap_or(self.require_alias),
+            conditions,
+            op,
+        }
+    }
+
+    /// Rejects the request if any action parsed so far failed validation.
+    fn validate(&mut self) -> Result<(), RequestError> {
+        if self.validation.is_empty() {
+            return Ok(());
+        }
+        let failures: String = self
+            .validation
+            .iter()
+            .enumerate()
+            .map(|(i, failure)| format!("{}: {};", i + 1, failure))
+            .collect();
+        Err(RequestError::new(
+            "action_request_validation_exception",
+            format!("Validation Failed: {}", failures),
+        ))
+    }
+
+    fn finish(&mut self) -> Result<(), RequestError> {
+        if self.pending.is_some() {
+            return Err(RequestError::new(
+                "illegal_argument_exception",
+                "The bulk request must be terminated by a newline [\\n]".to_string(),
+            ));
+        }
+        if self.parsed == 0 && self.validation.is_empty() {
+            self.validation.push("no requests added".to_string());
+        }
+        self.validate()
+    }
+}
+
+fn error_item(
+    index: &str,
+    id: Option<&str>,
+    status: StatusCode,
+    error_type: &str,
+    reason: &str,
+) -> Value {
+    json!({
+        "_index": index,
+        "_id": id,
+        "status": status.as_u16(),
+        "error": { "type": error_type, "reason": reason, "index": index }
+    })
+}
+
+fn write_error_item(index: &str, id: Option<&str>, reason: &str, error_type: &str) -> Value {
+    if reason.contains("index_not_found") {
+        return error_item(
+            index,
+            id,
+            StatusCode::NOT_FOUND,
+            "index_not_found_exception",
+            &format!("no such index [{}]", index),
+        );
+    }
+    let (status, Json(error)) = write_error(reason, error_type);
+    error_item(index, id, status, &error.error.r#type, &error.error.reason)
+}
+
+fn success_item(response: Value, status: StatusCode) -> Value {
+    let mut item = response;
+    item["status"] = json!(status.as_u16());
+    item
+}
+
+fn batch_item(index: &str, id: &str, name: &str, result: Result<BatchResult, String>) -> Value {
+    let (status, result, version) = match result {
+        Ok(BatchResult::Written(written)) if written.created => {
+            (StatusCode::CREATED, "created", written.version)
+        }
+        Ok(BatchResult::Written(written)) => (StatusCode::OK, "updated", written.version),
+        Ok(BatchResult::Deleted(deleted)) if deleted.found => {
+            (StatusCode::OK, "deleted", deleted.version)
+        }
+        Ok(BatchResult::Deleted(deleted)) => (StatusCode::NOT_FOUND, "not_found", deleted.version),
+        Err(e) if name == "delete" => {
+            return write_error_item(index, Some(id), &e, "illegal_argument_exception");
+        }
+        Err(e) => return write_error_item(index, Some(id), &e, "mapper_parsing_exception"),
+    };
+    success_item(
+        json!(index_response(
+            index.to_string(),
+            id.to_string(),
+            version,
+            result
+        )),
+        status,
+    )
+}
+
+/// A batched write and the response slot its outcome goes to.
+struct PendingWrite {
+    slot: usize,
+    name: String,
+    id: String,
+    op: BatchOp,
+}
+
+/// Applies actions in order, grouping `index`, `create` and `delete`
+/// actions so that each batch costs a single store write per index.
+struct BulkExecutor<'a> {
+    state: &'a AppState,
+    /// The `?pipeline` parameter, used by actions that do not name one.
+    default_pipeline: Option<&'a str>,
+    /// Pipelines looked up so far, `None` for ids that do not exist.
+    pipelines: HashMap<String, Option<Pipeline>>,
+    items: Vec<Value>,
+    pending: IndexMap<String, Vec<PendingWrite>>,
+}
+
+impl<'a> BulkExecutor<'a> {
+    fn new(state: &'a AppState, default_pipeline: Option<&'a str>) -> Self {
+        Self {
+            state,
+            default_pipeline,
+            pipelines: HashMap::new(),
+            items: Vec::new(),
+            pending: IndexMap::new(),
+        }
+    }
+
+    /// Runs the ingest pipeline an `index` or `create` action asks for, with
+    /// `_none` turning off the request's default pipeline.
+    fn run_pipeline(&mut self, pipeline: Option<&str>, source: &mut Value) -> Result<(), String> {
+        let Some(id) = pipeline
+            .or(self.default_pipeline)
+            .filter(|id| *id != "_none")
+        else {
+            return Ok(());
+        };
+        let store = &self.state.store;
+        let pipeline = self
+            .pipelines
+            .entry(id.to_string())
+            .or_insert_with(|| store.pipeline(id));
+        match pipeline {
+            Some(pipeline) => pipeline.execute(source),
+            None => Err(format!("pipeline with id [{}] does not exist", id)),
+        }
+    }
+
+    fn execute(&mut self, actions: Vec<BulkAction>) {
+        for action in actions {
+            let slot = self.items.len();
+            self.items.push(Value::Null);
+            let name = action.name.clone();
+            if let Some(item) = self.submit(slot, action) {
+                self.items[slot] = json!({ name: item });
+            }
+        }
+        self.flush();
+    }
+
+    /// Runs or queues one action, returning its item unless it was queued.
+    fn submit(&mut self, slot: usize, action: BulkAction) -> Option<Value> {
+        let BulkAction {
+            name,
+            index,
+            id,
+            routing,
+            pipeline,
+            require_alias,
+            conditions,
+            op,
+        } = action;
+        let store = &self.state.store;
+
+        if require_alias && !store.is_alias(&index) {
+            return Some(error_item(
+                &index,
+                id.as_deref(),
+                StatusCode::NOT_FOUND,
+                "index_not_found_exception",
+                &format!(
+                    "no such index [{}] and [require_alias] request flag is [true] and [{}] is not an alias",
+                    index, index
+                ),
+            ));
+        }
+
+        let (write_index, id, op) = match op {
+            BulkOp::Index(source) => {
+                // Generated up front so that failed items still report an id.
+                let id = id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+                // The pipeline runs before the target is auto-created.
+                let source = match source {
+                    Ok(mut source) => match self.run_pipeline(pipeline.as_deref(), &mut source) {
+                        Ok(()) => Ok(source),
+                        Err(e) => {
+                            return Some(write_error_item(
+                                &index,
+                                Some(&id),
+                                &e,
+                                "illegal_argument_exception",
+                            ));
+                        }
+                    },
+                    Err(e) => Err(e),
+                };
+                let source = match store.ensure_index(&index).and(source) {
+                    Ok(source) => source,
+                    Err(e) => {
+                        return Some(write_error_item(
+                            &index,
+                            Some(&id),
+                            &e,
+                            "mapper_parsing_exception",
+                        ));
+                    }
+                };
+                if name == "index" && store.is_data_stream(&index) {
+                    return Some(error_item(
+                        &index,
+                        Some(&id),
+                        StatusCode::BAD_REQUEST,
+                        "illegal_argument_exception",
+                        DATA_STREAM_OP_TYPE,
+                    ));
+                }
+                let target = match store.resolve_write_target(&index, routing.as_deref()) {
+                    Ok(target) => target,
+                    Err(e) => {
+                        return Some(write_error_item(
+                            &index,
+                            Some(&id),
+                            &e,
+                            "illegal_argument_exception",
+                        ));
+                    }
+                };
+                let op = BatchOp::Index {
+                    id: id.clone(),
+                    source,
+                    routing: target.routing,
+                    conditions,
+                };
+                (target.index, id, op)
+            }
+            BulkOp::Update(request) => {
+                // Updates read the current document, so earlier writes have
+                // to be applied first.
+                self.flush();
+                let id = id.unwrap_or_default();
+                let target = match store.resolve_write_target(&index, routing.as_deref()) {
+                    Ok(target) => target,
+                    Err(e) => {
+                        return Some(write_error_item(
+                            &index,
+                            Some(&id),
+                            &e,
+                            "illegal_argument_exception",
+                        ));
+                    }
+                };
+                return Some(update_item(self.state, target, id, &request, &conditions));
+            }
+            BulkOp::Delete => {
+                let id = id.unwrap_or_default();
+                let write_index = store.resolve_write_index(&index).unwrap_or(index);
+                let op = BatchOp::Delete {
+                    id: id.clone(),
+                    conditions,
+                };
+                (write_index, id, op)
+            }
+        };
+
+        self.pending
+            .entry(write_index)
+            .or_default()
+            .push(PendingWrite { slot, name, id, op });
+        None
+    }
+
+    fn flush(&mut self) {
+        for (index, writes) in std::mem::take(&mut self.pending) {
+            let (targets, ops): (Vec<_>, Vec<_>) = writes
+                .into_iter()
+                .map(|write| ((write.slot, write.name, write.id), write.op))
+                .unzip();
+            match self.state.store.apply_batch(&index, ops) {
+                Ok(results) => {
+                    for ((slot, name, id), result) in targets.into_iter().zip(results) {
+                        let item = batch_item(&index, &id, &name, result);
+                        self.items[slot] = json!({ name: item });
+                    }
+                }
+                Err(e) => {
+                    for (slot, name, id) in targets {
+                        let item = batch_item(&index, &id, &name, Err(e.clone()));
+                        self.items[slot] = json!({ name: item });
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn update_item(
+    state: &AppState,
+    target: WriteTarget,
+    id: String,
+    request: &UpdateRequest,
+    conditions: &WriteConditions,
+) -> Value {
+    let WriteTarget { index, routing } = target;
+    let updated = (|| {
+        if request.upsert_source().is_some() {
+            state.store.ensure_index(&index)?;
+        }
+        state
+            .store
+            .update_document(&index, &id, request, routing.as_deref(), conditions)
+    })();
+    match updated {
+        Ok(updated) => {
+            let status = if updated.kind == UpdateKind::Created {
+                StatusCode::CREATED
+            } else {
+                StatusCode::OK
+            };
+            success_item(update_response(index, updated, request), status)
+        }
+        Err(e) => write_error_item(&index, Some(&id), &e, "illegal_argument_exception"),
+    }
+}
+
+/// The raw bulk body, kept between the validation pass and the write pass.
+/// It stays in memory up to [`SPOOL_MEMORY_LIMIT`] and moves to a temporary
+/// file, removed on drop, beyond that.
+#[derive(Default)]
+struct Spool {
+    memory: Vec<u8>,
+    file: Option<(PathBuf, tokio::fs::File)>,
+}
+
+impl Spool {
+    async fn write(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        if self.file.is_none() {
+            if self.memory.len() + chunk.len() <= SPOOL_MEMORY_LIMIT {
+                self.memory.extend_from_slice(chunk);
+                return Ok(());
+            }
+            let path = std::env::temp_dir().join(format!("es_fake-bulk-{}", uuid::Uuid::new_v4()));
+            let file = tokio::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await?;
+            self.file = Some((path, file));
+        }
+        let (_, file) = self.file.as_mut().expect("spool file was just created");
+        if !self.memory.is_empty() {
+            file.write_all(&std::mem::take(&mut self.memory)).await?;
+        }
+        file.write_all(chunk).await
+    }
+
+    async fn reader(&mut self) -> std::io::Result<Box<dyn AsyncBufRead + Unpin + Send + '_>> {
+        match &mut self.file {
+            Some((_, file)) => {
+                file.flush().await?;
+                file.rewind().await?;
+                Ok(Box::new(tokio::io::BufReader::new(file)))
+            }
+            None => Ok(Box::new(self.memory.as_slice())),
+        }
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let Some((path, _)) = self.file.take() else {
+            return;
+        };
+        // Removing a file blocks, which the runtime's workers must not.
+        let remove = move || {
+            let _ = std::fs::remove_file(path);
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(remove)),
+            Err(_) => remove(),
+        }
+    }
+}
+
+/// Validates the body chunk by chunk while spooling it, then replays the
+/// spool through the executor, see the module docs.
+async fn stream_actions(
+    body: Body,
+    default_index: Option<&str>,
+    require_alias: bool,
+    executor: &mut BulkExecutor<'_>,
+) -> Result<(), RequestError> {
+    let mut validator = BulkParser::new(default_index, require_alias, false);
+    let mut spool = Spool::default();
+    let mut stream = body.into_data_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    // Bytes of `buffer` already known to hold no newline.
+    let mut scanned = 0;
+
+    while let Some(chunk) = stream.next().await {
+        let chunk =
+            chunk.map_err(|e| RequestError::new("illegal_argument_exception", e.to_string()))?;
+        spool.write(&chunk).await.map_err(RequestError::io)?;
+        buffer.extend_from_slice(&chunk);
+
+        let mut start = 0;
+        while let Some(end) = buffer[scanned..].iter().position(|&b| b == b'\n') {
+            let end = scanned + end;
+            validator.push_bytes(&buffer[start..end])?;
+            start = end + 1;
+            scanned = start;
+        }
+        buffer.drain(..start);
+        scanned = buffer.len();
+    }
+    if !buffer.is_empty() {
+        validator.push_bytes(&buffer)?;
+    }
+    validator.finish()?;
+
+    let mut parser = BulkParser::new(default_index, require_alias, true);
+    let mut reader = spool.reader().await.map_err(RequestError::io)?;
+    let mut line = Vec::new();
+    let mut actions = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        line.clear();
+        if reader
+            .read_until(b'\n', &mut line)
+            .await
+            .map_err(RequestError::io)?
+            == 0
+        {
+            break;
+        }
+        let content = line.strip_suffix(b"\n").unwrap_or(&line);
+        actions.extend(parser.push_bytes(content)?);
+        if actions.len() == BATCH_SIZE {
+            executor.execute(std::mem::replace(
+                &mut actions,
+                Vec::with_capacity(BATCH_SIZE),
+            ));
+        }
+    }
+    if !actions.is_empty() {
+        executor.execute(actions);
+    }
+    Ok(())
+}
+
+async fn run_bulk(
+    state: &AppState,
+    default_index: Option<&str>,
+    params: &HashMap<String, String>,
+    body: Body,
+) -> Response {
+    let start = Instant::now();
+    let require_alias = param_flag(params, "require_alias").unwrap_or(false);
+    let mut executor = BulkExecutor::new(state, params.get("pipeline").map(String::as_str));
+
+    if let Err(e) = stream_actions(body, default_index, require_alias, &mut executor).await {
+        return to_error(e.status, e.error_type, &e.reason).into_response();
+    }
+
+    let items = executor.items;
+    let errors = items
+        .iter()
+        .filter_map(|item| item.as_object()?.values().next())
+        .any(|item| item.get("error").is_some());
+    Json(json!({
+        "took": start.elapsed().as_millis(),
+        "errors": errors,
+        "items": items
+    }))
+    .into_response()
+}
+
+pub async fn bulk(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    body: Body,
+) -> Response {
+    run_bulk(&state, None, &params, body).await
+}
+
+pub async fn bulk_index(
+    Path(index): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    body: Body,
+) -> Response {
+    run_bulk(&state, Some(&index), &params, body).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::setup_state;
+    use crate::domain::alias::AliasDefinition;
+    use crate::domain::mapping::Mapping;
+    use crate::domain::template::IndexTemplate;
+    use crate::repository::store::AliasAction;
+
+    async fn body_json(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn ndjson(lines: &[Value]) -> String {
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    #[tokio::test]
+    async fn should_handle_full_bulk_workflow() {
+        let state = setup_state();
+        let index = "bulk-test".to_string();
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
+
+        let bulk_body = ndjson(&[
+            json!({"index": {"_index": &index, "_id": "1"}}),
+            json!({"field": "v1"}),
+            json!({"update": {"_index": &index, "_id": "1"}}),
+            json!({"doc": {"field": "v2"}}),
+            json!({"delete": {"_index": &index, "_id": "1"}}),
+        ]);
+
+        let response = bulk(
+            Query(HashMap::new()),
+            State(state.clone()),
+            Body::from(bulk_body),
+        )
+        .await;
+        let response = body_json(response).await;
+        let items = response["items"].as_array().unwrap();
+
+        assert_eq!(response["errors"], false);
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0]["index"]["result"], "created");
+        assert_eq!(items[0]["index"]["_seq_no"], 0);
+        assert_eq!(items[1]["update"]["result"], "updated");
+        assert_eq!(items[1]["update"]["_version"], 2);
+        assert_eq!(items[2]["delete"]["result"], "deleted");
+        assert_eq!(items[2]["delete"]["_version"], 3);
+    }
+
+    #[tokio::test]
+    async fn should_report_item_errors_with_statuses() {
+        let state = setup_state();
+        let bulk_body = ndjson(&[
+            json!({"create": {"_id": "1"}}),
+            json!({"n": 1}),
+            json!({"create": {"_id": "1"}}),
+            json!({"n": 2}),
+            json!({"update": {"_id": "missing"}}),
+            json!({"doc": {"n": 3}}),
+            json!({"delete": {"_id": "missing"}}),
+            json!({"delete": {"_index": "nowhere", "_id": "1"}}),
+            json!({"index": {"require_alias": true}}),
+            json!({"n": 4}),
+        ]) + "{\"index\":{}}\n{not json}\n";
+
+        let response = bulk_index(
+            Path("items".to_string()),
+            Query(HashMap::new()),
+            State(state),
+            Body::from(bulk_body),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = body_json(response).await;
+        let items = response["items"].as_array().unwrap();
+        let statuses: Vec<u64> = items
+            .iter()
+            .map(|item| {
+                item.as_object().unwrap().values().next().unwrap()["status"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect();
+
+        assert_eq!(response["errors"], true);
+        assert_eq!(statuses, vec![201, 409, 404, 404, 404, 404, 400]);
+        assert_eq!(items[0]["create"]["_index"], "items");
+        assert_eq!(
+            items[1]["create"]["error"]["type"],
+            "version_conflict_engine_exception"
+        );
+        assert_eq!(
+            items[2]["update"]["error"]["type"],
+            "document_missing_exception"
+        );
+        assert_eq!(items[3]["delete"]["result"], "not_found");
+        assert!(items[3]["delete"].get("error").is_none());
+        assert_eq!(
+            items[4]["delete"]["error"]["type"],
+            "index_not_found_exception"
+        );
+        assert_eq!(
+            items[5]["index"]["error"]["type"],
+            "index_not_found_exception"
+        );
+        assert_eq!(
+            items[6]["index"]["error"]["type"],
+            "mapper_parsing_exception"
+        );
+        assert!(items[6]["index"]["_id"].is_string());
+    }
+
+    #[tokio::test]
+    async fn should_write_through_aliases_when_required() {
+        let state = setup_state();
+        state
+            .store
+            .create_index("logs-1".to_string(), Mapping::default())
+            .unwrap();
+        state
+            .store
+            .update_aliases(vec![AliasAction::Add {
+                index: "logs-1".to_string(),
+                alias: "logs".to_string(),
+                definition: AliasDefinition::from_json(&json!({})),
+            }])
+            .unwrap();
+
+        let params = HashMap::from([("require_alias".to_string(), "true".to_string())]);
+        let bulk_body = ndjson(&[
+            json!({"index": {"_index": "logs", "_id": "1"}}),
+            json!({"n": 1}),
+        ]);
+        let response =
+            body_json(bulk(Query(params), State(state.clone()), Body::from(bulk_body)).await).await;
+
+        assert_eq!(response["errors"], false);
+        assert_eq!(response["items"][0]["index"]["_index"], "logs-1");
+        assert!(state.store.get_document("logs-1", "1").is_some());
+    }
+
+    #[tokio::test]
+    async fn should_require_create_for_auto_created_data_streams() {
+        let state = setup_state();
+        let template: IndexTemplate = serde_json::from_value(json!({
+            "index_patterns": ["logs-*"],
+            "data_stream": {}
+        }))
+        .unwrap();
+        state.store.put_index_template("logs", template).unwrap();
+
+        let bulk_body = ndjson(&[
+            json!({"index": {"_index": "logs-app", "_id": "1"}}),
+            json!({"@timestamp": "2024-01-01T00:00:00Z"}),
+            json!({"create": {"_index": "logs-app", "_id": "2"}}),
+            json!({"@timestamp": "2024-01-01T00:00:00Z"}),
+        ]);
+        let response = body_json(
+            bulk(
+                Query(HashMap::new()),
+                State(state.clone()),
+                Body::from(bulk_body),
+            )
+            .await,
+        )
+        .await;
+
+        assert_eq!(response["errors"], true);
+        assert_eq!(response["items"][0]["index"]["status"], 400);
+        assert_eq!(response["items"][1]["create"]["status"], 201);
+        assert!(state.store.is_data_stream("logs-app"));
+    }
+
+    #[tokio::test]
+    async fn should_run_ingest_pipelines_on_index_actions() {
+        let state = setup_state();
+        state
+            .store
+            .put_pipeline(
+                "tag",
+                Pipeline::from_json(&json!({
+                    "processors": [{ "set": { "field": "tagged", "value": true } }]
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+
+        let params = HashMap::from([("pipeline".to_string(), "tag".to_string())]);
+        let bulk_body = ndjson(&[
+            json!({"index": {"_index": "tagged", "_id": "1"}}),
+            json!({"n": 1}),
+            json!({"create": {"_index": "tagged", "_id": "2", "pipeline": "_none"}}),
+            json!({"n": 2}),
+            json!({"index": {"_index": "untouched", "_id": "3", "pipeline": "missing"}}),
+            json!({"n": 3}),
+        ]);
+        let response =
+            body_json(bulk(Query(params), State(state.clone()), Body::from(bulk_body)).await).await;
+
+        assert_eq!(response["errors"], true);
+        assert_eq!(
+            state.store.get_document("tagged", "1").unwrap(),
+            json!({ "n": 1, "tagged": true })
+        );
+        assert_eq!(
+            state.store.get_document("tagged", "2").unwrap(),
+            json!({ "n": 2 })
+        );
+        let failed = &response["items"][2]["index"];
+        assert_eq!(failed["status"], 400);
+        assert_eq!(
+            failed["error"]["reason"],
+            "pipeline with id [missing] does not exist"
+        );
+        assert!(state.store.get_index("untouched").is_none());
+    }
+
+    #[tokio::test]
+    async fn should_reject_malformed_requests() {
+        let state = setup_state();
+        let cases = [
+            ("{\"explode\":{}}\n", "illegal_argument_exception"),
+            (
+                "{\"index\":{\"_index\":\"a\",\"bogus\":1}}\n{}\n",
+                "illegal_argument_exception",
+            ),
+            (
+                "{\"index\":{\"_index\":\"a\"}}\n",
+                "illegal_argument_exception",
+            ),
+            (
+                "{\"delete\":{\"_id\":\"1\"}}\n",
+                "action_request_validation_exception",
+            ),
+            (
+                "{\"update\":{\"_index\":\"a\",\"_id\":\"1\"}}\n{oops\n",
+                "x_content_parse_exception",
+            ),
+            (
+                "{\"update\":{\"_index\":\"a\",\"_id\":\"1\",\"retry_on_conflict\":3,\
+                 \"if_seq_no\":1,\"if_primary_term\":1}}\n{\"doc\":{}}\n",
+                "action_request_validation_exception",
+            ),
+            ("\n", "action_request_validation_exception"),
+        ];
+
+        for (body, error_type) in cases {
+            let response = bulk(
+                Query(HashMap::new()),
+                State(state.clone()),
+                Body::from(body.to_string()),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", body);
+            assert_eq!(
+                body_json(response).await["error"]["type"],
+                error_type,
+                "{}",
+                body
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn should_apply_nothing_when_a_later_line_is_malformed() {
+        let state = setup_state();
+        let mut body = String::new();
+        for i in 0..(BATCH_SIZE * 3) {
+            body.push_str(&ndjson(&[
+                json!({"index": {"_id": i.to_string()}}),
+                json!({"n": i}),
+            ]));
+        }
+        body.push_str("{\"index\":{\"_id\":\"last\"}\n");
+
+        let response = bulk_index(
+            Path("partial".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Body::from(body),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body_json(response).await["error"]["type"],
+            "x_content_parse_exception"
+        );
+        assert!(state.store.get_index("partial").is_none());
+    }
+
+    #[tokio::test]
+    async fn should_stream_large_bodies_in_batches() {
+        let state = setup_state();
+        let mut lines = Vec::new();
+        for i in 0..(BATCH_SIZE * 2 + 500) {
+            lines.push(json!({"index": {"_id": i.to_string()}}));
+            lines.push(json!({"n": i}));
+        }
+        lines.extend([
+            json!({"update": {"_id": "0", "retry_on_conflict": 3}}),
+            json!({"doc": {"n": -1}}),
+            json!({"delete": {"_id": "1"}}),
+            json!({"create": {"_id": "1"}}),
+            json!({"n": 1}),
+        ]);
+        // Small chunks split lines across reads.
+        let body = ndjson(&lines).into_bytes();
+        let chunks: Vec<Result<Vec<u8>, std::io::Error>> =
+            body.chunks(7).map(|chunk| Ok(chunk.to_vec())).collect();
+
+        let response = bulk_index(
+            Path("big".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Body::from_stream(futures::stream::iter(chunks)),
+        )
+        .await;
+        let response = body_json(response).await;
+        let items = response["items"].as_array().unwrap();
+
+        assert_eq!(response["errors"], false);
+        assert_eq!(items.len(), BATCH_SIZE * 2 + 503);
+        assert_eq!(
+            items[BATCH_SIZE * 2 + 499]["index"]["_seq_no"],
+            BATCH_SIZE * 2 + 499
+        );
+        assert_eq!(items[BATCH_SIZE * 2 + 500]["update"]["result"], "updated");
+        assert_eq!(items[BATCH_SIZE * 2 + 502]["create"]["result"], "created");
+        assert_eq!(state.store.get_document("big", "0").unwrap()["n"], -1);
+        assert_eq!(
+            state.store.get_index("big").unwrap().documents.len(),
+            BATCH_SIZE * 2 + 500
+        );
+    }
+
+    #[tokio::test]
+    async fn should_spool_bodies_over_the_memory_limit_to_disk() {
+        let state = setup_state();
+        let padding = "x".repeat(1024 * 1024);
+        let mut lines = Vec::new();
+        for i in 0..(SPOOL_MEMORY_LIMIT / padding.len() + 2) {
+            lines.push(json!({"index": {"_id": i.to_string()}}));
+            lines.push(json!({"n": i, "padding": padding}));
+        }
+        let body = ndjson(&lines).into_bytes();
+        assert!(body.len() > SPOOL_MEMORY_LIMIT);
+        let chunks: Vec<Result<Vec<u8>, std::io::Error>> = body
+            .chunks(64 * 1024)
+            .map(|chunk| Ok(chunk.to_vec()))
+            .collect();
+
+        let response = bulk_index(
+            Path("spooled".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Body::from_stream(futures::stream::iter(chunks)),
+        )
+        .await;
+
+        let response = body_json(response).await;
+        assert_eq!(response["errors"], false, "{}", response);
+        let document = state.store.get_document("spooled", "9").unwrap();
+        assert_eq!(document["n"], 9);
+        assert_eq!(document["padding"], padding);
+    }
+
+    #[tokio::test]
+    async fn should_remove_the_spool_file_on_drop() {
+        let mut spool = Spool::default();
+        spool
+            .write(&vec![b'x'; SPOOL_MEMORY_LIMIT + 1])
+            .await
+            .unwrap();
+        let path = spool.file.as_ref().unwrap().0.clone();
+        assert!(path.exists());
+
+        drop(spool);
+        for _ in 0..100 {
+            if !path.exists() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("{} was not removed", path.display());
+    }
+}
@@ -0,0 +1,718 @@
+use super::{index_not_found, indices_options, param_flag, to_error, write_error};
+use crate::AppState;
+use crate::domain::query::{Query as SearchQuery, parse_filter, parse_query};
+use crate::domain::script::Script;
+use crate::domain::update::{UpdateKind, UpdateRequest};
+use crate::domain::versioning::{WriteConditions, is_version_conflict};
+use crate::repository::store::{InMemoryStore, IndexTarget};
+use crate::repository::tasks::TaskRegistry;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+const UPDATE_BY_QUERY_ACTION: &str = "indices:data/write/update/byquery";
+const DELETE_BY_QUERY_ACTION: &str = "indices:data/write/delete/byquery";
+
+/// Counters shared by the `_delete_by_query`, `_update_by_query` and
+/// `_reindex` responses, also reported as their task status.
+#[derive(Default)]
+pub(super) struct ByQueryCounts {
+    pub(super) total: usize,
+    pub(super) updated: usize,
+    pub(super) created: usize,
+    pub(super) deleted: usize,
+    pub(super) noops: usize,
+    version_conflicts: usize,
+    failures: Vec<Value>,
+    canceled: bool,
+}
+
+impl ByQueryCounts {
+    /// Counts a failed write. Returns whether the operation should carry on,
+    /// which is only the case for version conflicts with `conflicts=proceed`.
+    pub(super) fn record_failure(
+        &mut self,
+        index: &str,
+        id: &str,
+        reason: &str,
+        proceed: bool,
+    ) -> bool {
+        if is_version_conflict(reason) || reason.ends_with("document missing") {
+            self.version_conflicts += 1;
+            if proceed {
+                return true;
+            }
+        }
+        self.failures.push(failure(index, id, reason));
+        false
+    }
+
+    fn status(&self) -> Value {
+        let mut status = json!({
+            "total": self.total,
+            "updated": self.updated,
+            "created": self.created,
+            "deleted": self.deleted,
+            "batches": 1,
+            "version_conflicts": self.version_conflicts,
+            "noops": self.noops,
+            "retries": { "bulk": 0, "search": 0 },
+            "throttled_millis": 0,
+            "requests_per_second": -1.0,
+            "throttled_until_millis": 0
+        });
+        if self.canceled {
+            status["canceled"] = json!("by user request");
+        }
+        status
+    }
+
+    fn response(&self, took: u128, include_created: bool) -> Value {
+        let mut response = json!({ "took": took, "timed_out": false });
+        let Value::Object(status) = self.status() else {
+            unreachable!("status is an object");
+        };
+        for (key, value) in status {
+            if key != "created" || include_created {
+                response[key] = value;
+            }
+        }
+        response["failures"] = json!(self.failures);
+        response
+    }
+}
+
+fn failure(index: &str, id: &str, reason: &str) -> Value {
+    let (status, Json(error)) = if reason.ends_with("document missing") {
+        to_error(
+            StatusCode::CONFLICT,
+            "version_conflict_engine_exception",
+            &format!("[{}]: version conflict, document was deleted", id),
+        )
+    } else {
+        write_error(reason, "illegal_argument_exception")
+    };
+    json!({
+        "index": index,
+        "id": id,
+        "cause": {
+            "type": error.error.r#type,
+            "reason": error.error.reason,
+            "index": index
+        },
+        "status": status.as_u16()
+    })
+}
+
+/// Documents of the targets matching the query, read up front so that the
+/// writes that follow do not affect what is visited.
+pub(super) fn matching_documents(
+    store: &InMemoryStore,
+    target: &IndexTarget,
+    query: &dyn SearchQuery,
+) -> Vec<crate::domain::document::StoredDocument> {
+    let Some(index_data) = store.get_index(&target.index) else {
+        return Vec::new();
+    };
+    let alias_filter = target.filter.as_ref().map(parse_filter);
+    index_data
+        .documents
+        .matching(&target.index, query)
+        .into_iter()
+        .map(|(_, d)| d)
+        .filter(|d| {
+            alias_filter
+                .as_ref()
+                .is_none_or(|f| f.matches_document(&target.index, d))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Number of documents a job visits between two updates of its task status.
+const STATUS_INTERVAL: usize = 100;
+
+/// Lets a job report its progress to its task and notice cancellation.
+pub(super) struct Progress<'a> {
+    tasks: &'a TaskRegistry,
+    task: u64,
+}
+
+impl Progress<'_> {
+    /// Called before each document: publishes the counts as the task status
+    /// every [`STATUS_INTERVAL`] documents and returns `false` once the job
+    /// has to stop, either because the task was cancelled or `max_docs`
+    /// documents have been visited.
+    pub(super) fn advance(&self, counts: &mut ByQueryCounts, max_docs: Option<usize>) -> bool {
+        if counts.total.is_multiple_of(STATUS_INTERVAL) {
+            self.tasks.set_status(self.task, counts.status());
+        }
+        if self.tasks.is_cancelled(self.task) {
+            counts.canceled = true;
+            return false;
+        }
+        if max_docs.is_some_and(|max| counts.total >= max) {
+            return false;
+        }
+        counts.total += 1;
+        true
+    }
+}
+
+/// Runs a job as a task on the blocking thread pool, waiting for it unless
+/// `wait_for_completion=false`, in which case the response only carries the
+/// task id and the result is stored for `GET /_tasks/{task_id}`.
+pub(super) async fn run_job<F>(
+    state: &Arc<AppState>,
+    params: &HashMap<String, String>,
+    action: &str,
+    description: String,
+    start: Instant,
+    include_created: bool,
+    job: F,
+) -> Response
+where
+    F: FnOnce(&InMemoryStore, &Progress) -> ByQueryCounts + Send + 'static,
+{
+    let task = state.tasks.register(action, description);
+    let background = param_flag(params, "wait_for_completion") == Some(false);
+    let job_state = state.clone();
+    let handle = tokio::task::spawn_blocking(move || {
+        let progress = Progress {
+            tasks: &job_state.tasks,
+            task,
+        };
+        let counts = job(&job_state.store, &progress);
+        job_state.tasks.set_status(task, counts.status());
+        let response = counts.response(start.elapsed().as_millis(), include_created);
+        job_state
+            .tasks
+            .complete(task, background.then(|| response.clone()));
+        response
+    });
+    if background {
+        // Nobody waits for the response, so a job that panics still has to
+        // leave its task completed, with the panic as the error.
+        let watcher_state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle.await {
+                watcher_state.tasks.fail(
+                    task,
+                    json!({ "type": "exception", "reason": e.to_string() }),
+                );
+            }
+        });
+        return Json(json!({ "task": state.tasks.task_id(task) })).into_response();
+    }
+
+    let response = match handle.await {
+        Ok(response) => response,
+        Err(e) => {
+            state.tasks.complete(task, None);
+            return to_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "exception",
+                &e.to_string(),
+            )
+            .into_response();
+        }
+    };
+    let status = response["failures"]
+        .get(0)
+        .and_then(|f| f["status"].as_u64())
+        .and_then(|s| StatusCode::from_u16(s as u16).ok())
+        .unwrap_or(StatusCode::OK);
+    (status, Json(response)).into_response()
+}
+
+/// Reads `conflicts` (`abort` or `proceed`) from the URL or the body.
+pub(super) fn proceed_on_conflict(
+    params: &HashMap<String, String>,
+    body: &Value,
+) -> Result<bool, String> {
+    let conflicts = params
+        .get("conflicts")
+        .map(String::as_str)
+        .or_else(|| body["conflicts"].as_str());
+    match conflicts {
+        None | Some("abort") => Ok(false),
+        Some("proceed") => Ok(true),
+        Some(other) => Err(format!(
+            "conflicts may only be \"proceed\" or \"abort\" but was [{}]",
+            other
+        )),
+    }
+}
+
+pub(super) fn max_docs(
+    params: &HashMap<String, String>,
+    body: &Value,
+) -> Result<Option<usize>, String> {
+    match params.get("max_docs") {
+        Some(value) => value.parse().map(Some).map_err(|_| {
+            format!(
+                "Failed to parse int parameter [max_docs] with value [{}]",
+                value
+            )
+        }),
+        None => Ok(body["max_docs"].as_u64().map(|n| n as usize)),
+    }
+}
+
+/// A `_delete_by_query` request resolved against the store.
+struct DeleteByQuery {
+    targets: Vec<IndexTarget>,
+    query: Box<dyn SearchQuery>,
+    proceed_on_conflict: bool,
+    max_docs: Option<usize>,
+}
+
+impl DeleteByQuery {
+    fn run(&self, store: &InMemoryStore, progress: &Progress) -> ByQueryCounts {
+        let mut counts = ByQueryCounts::default();
+
+        for target in &self.targets {
+            for doc in matching_documents(store, target, self.query.as_ref()) {
+                if !progress.advance(&mut counts, self.max_docs) {
+                    return counts;
+                }
+
+                let conditions = WriteConditions {
+                    if_seq_no: Some(doc.version.seq_no),
+                    if_primary_term: Some(doc.version.primary_term),
+                    ..WriteConditions::default()
+                };
+                let reason = match store.remove_document(&target.index, &doc.id, &conditions) {
+                    Ok(deleted) if deleted.found => {
+                        counts.deleted += 1;
+                        continue;
+                    }
+                    Ok(_) => format!("[{}]: document missing", doc.id),
+                    Err(e) => e,
+                };
+                if !counts.record_failure(&target.index, &doc.id, &reason, self.proceed_on_conflict)
+                {
+                    return counts;
+                }
+            }
+        }
+        counts
+    }
+}
+
+pub async fn delete_by_query(
+    Path(index): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<Value>,
+) -> Response {
+    let start = Instant::now();
+    let targets = match state
+        .store
+        .resolve_indices(&index, indices_options(&params))
+    {
+        Ok(targets) => targets,
+        Err(missing) => return index_not_found(&missing).into_response(),
+    };
+    let (proceed_on_conflict, max_docs) = match proceed_on_conflict(&params, &body)
+        .and_then(|p| Ok((p, max_docs(&params, &body)?)))
+    {
+        Ok(options) => options,
+        Err(e) => {
+            return to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e)
+                .into_response();
+        }
+    };
+
+    let query = match parse_query(&body) {
+        Ok(query) => query,
+        Err(e) => return write_error(&e, "script_exception").into_response(),
+    };
+    let job = DeleteByQuery {
+        targets,
+        query,
+        proceed_on_conflict,
+        max_docs,
+    };
+    run_job(
+        &state,
+        &params,
+        DELETE_BY_QUERY_ACTION,
+        format!("delete-by-query [{}]", index),
+        start,
+        false,
+        move |store, progress| job.run(store, progress),
+    )
+    .await
+}
+
+/// An `_update_by_query` request resolved against the store, ready to run
+/// either inline or as a background task.
+struct UpdateByQuery {
+    targets: Vec<IndexTarget>,
+    query: Box<dyn SearchQuery>,
+    request: UpdateRequest,
+    proceed_on_conflict: bool,
+    max_docs: Option<usize>,
+}
+
+impl UpdateByQuery {
+    /// Rewrites every matching document through the update path, guarding
+    /// each write with the `_seq_no` it was read at so concurrent changes
+    /// surface as version conflicts.
+    fn run(&self, store: &InMemoryStore, progress: &Progress) -> ByQueryCounts {
+        let mut counts = ByQueryCounts::default();
+
+        for target in &self.targets {
+            for doc in matching_documents(store, target, self.query.as_ref()) {
+                if !progress.advance(&mut counts, self.max_docs) {
+                    return counts;
+                }
+
+                let conditions = WriteConditions {
+                    if_seq_no: Some(doc.version.seq_no),
+                    if_primary_term: Some(doc.version.primary_term),
+                    ..WriteConditions::default()
+                };
+                match store.update_document(
+                    &target.index,
+                    &doc.id,
+                    &self.request,
+                    doc.routing.as_deref(),
+                    &conditions,
+                ) {
+                    Ok(updated) => match updated.kind {
+                        UpdateKind::Created | UpdateKind::Updated => counts.updated += 1,
+                        UpdateKind::Noop => counts.noops += 1,
+                        UpdateKind::Deleted => counts.deleted += 1,
+                    },
+                    Err(e) => {
+                        if !counts.record_failure(
+                            &target.index,
+                            &doc.id,
+                            &e,
+                            self.proceed_on_conflict,
+                        ) {
+                            return counts;
+                        }
+                    }
+                }
+            }
+        }
+        counts
+    }
+}
+
+pub async fn update_by_query(
+    Path(index): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    body: Option<Json<Value>>,
+) -> Response {
+    let start = Instant::now();
+    let body = body.map(|Json(b)| b).unwrap_or_else(|| json!({}));
+    let targets = match state
+        .store
+        .resolve_indices(&index, indices_options(&params))
+    {
+        Ok(targets) => targets,
+        Err(missing) => return index_not_found(&missing).into_response(),
+    };
+
+    let script = match body.get("script").map(Script::from_json).transpose() {
+        Ok(script) => script,
+        Err(e) => return write_error(&e, "script_exception").into_response(),
+    };
+    let (proceed_on_conflict, max_docs) = match proceed_on_conflict(&params, &body)
+        .and_then(|p| Ok((p, max_docs(&params, &body)?)))
+    {
+        Ok(options) => options,
+        Err(e) => {
+            return to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e)
+                .into_response();
+        }
+    };
+
+    let query = match parse_query(&body) {
+        Ok(query) => query,
+        Err(e) => return write_error(&e, "script_exception").into_response(),
+    };
+    let job = UpdateByQuery {
+        targets,
+        query,
+        request: UpdateRequest::from_script(script),
+        proceed_on_conflict,
+        max_docs,
+    };
+    run_job(
+        &state,
+        &params,
+        UPDATE_BY_QUERY_ACTION,
+        format!("update-by-query [{}]", index),
+        start,
+        false,
+        move |store, progress| job.run(store, progress),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::setup_state;
+    use crate::domain::mapping::Mapping;
+
+    fn setup_counters(state: &AppState, index: &str) {
+        state
+            .store
+            .create_index(index.to_string(), Mapping::default())
+            .unwrap();
+        for (id, count) in [("1", 1), ("2", 5), ("3", 10)] {
+            state
+                .store
+                .write_document(
+                    index,
+                    Some(id),
+                    json!({ "count": count, "kind": "counter" }),
+                    None,
+                    &WriteConditions::default(),
+                )
+                .unwrap();
+        }
+    }
+
+    async fn body_json(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_update_matching_documents_with_script() {
+        let state = setup_state();
+        setup_counters(&state, "counters");
+
+        let body = json!({
+            "query": { "term": { "kind": "counter" } },
+            "script": {
+                "source": "if (ctx._source.count > params.max) { ctx.op = 'noop' } \
+                           else { ctx._source.count += 1 }",
+                "params": { "max": 5 }
+            }
+        });
+        let response = update_by_query(
+            Path("counters".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Some(Json(body)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = body_json(response).await;
+        assert_eq!(response["total"], 3);
+        assert_eq!(response["updated"], 2);
+        assert_eq!(response["noops"], 1);
+        assert_eq!(response["version_conflicts"], 0);
+        assert_eq!(
+            state.store.get_document("counters", "2").unwrap()["count"],
+            6
+        );
+        assert_eq!(
+            state
+                .store
+                .get_stored_document("counters", "3")
+                .unwrap()
+                .version
+                .version,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn should_limit_to_max_docs_and_run_as_task() {
+        let state = setup_state();
+        setup_counters(&state, "counters");
+
+        let params = HashMap::from([
+            ("max_docs".to_string(), "2".to_string()),
+            ("wait_for_completion".to_string(), "false".to_string()),
+        ]);
+        let response = update_by_query(
+            Path("counters".to_string()),
+            Query(params),
+            State(state.clone()),
+            None,
+        )
+        .await;
+        let task_id = body_json(response).await["task"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let mut task = state.tasks.get(&task_id).unwrap();
+        while !task.completed() {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            task = state.tasks.get(&task_id).unwrap();
+        }
+        let response = task.response.unwrap();
+        assert_eq!(response["total"], 2);
+        assert_eq!(response["updated"], 2);
+    }
+
+    #[tokio::test]
+    async fn should_reject_unknown_conflicts_mode() {
+        let state = setup_state();
+        setup_counters(&state, "counters");
+
+        let params = HashMap::from([("conflicts".to_string(), "ignore".to_string())]);
+        let response = update_by_query(
+            Path("counters".to_string()),
+            Query(params),
+            State(state),
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_delete_by_query() {
+        let state = setup_state();
+        setup_counters(&state, "counters");
+
+        let query = json!({ "query": { "term": { "count": 10 } } });
+        let response = delete_by_query(
+            Path("counters".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(query),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_json(response).await["deleted"], 1);
+        assert!(state.store.get_document("counters", "3").is_none());
+        assert!(state.store.get_document("counters", "2").is_some());
+    }
+
+    #[tokio::test]
+    async fn should_delete_by_query_as_task() {
+        let state = setup_state();
+        setup_counters(&state, "counters");
+
+        let params = HashMap::from([("wait_for_completion".to_string(), "false".to_string())]);
+        let response = delete_by_query(
+            Path("counters".to_string()),
+            Query(params),
+            State(state.clone()),
+            Json(json!({ "query": { "match_all": {} } })),
+        )
+        .await;
+        let task_id = body_json(response).await["task"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let mut task = state.tasks.get(&task_id).unwrap();
+        for _ in 0..100 {
+            if task.completed() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            task = state.tasks.get(&task_id).unwrap();
+        }
+        assert_eq!(task.action, DELETE_BY_QUERY_ACTION);
+        assert_eq!(task.response.unwrap()["deleted"], 3);
+    }
+
+    #[tokio::test]
+    async fn should_fail_background_tasks_whose_job_panics() {
+        let state = setup_state();
+        let params = HashMap::from([("wait_for_completion".to_string(), "false".to_string())]);
+        let response = run_job(
+            &state,
+            &params,
+            UPDATE_BY_QUERY_ACTION,
+            "panics".to_string(),
+            Instant::now(),
+            false,
+            |_, _| -> ByQueryCounts { panic!("job failed") },
+        )
+        .await;
+        let task_id = body_json(response).await["task"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let mut task = state.tasks.get(&task_id).unwrap();
+        for _ in 0..100 {
+            if task.completed() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            task = state.tasks.get(&task_id).unwrap();
+        }
+        assert!(task.response.is_none());
+        let error = task.error.unwrap();
+        assert_eq!(error["type"], "exception");
+        assert!(error["reason"].as_str().unwrap().contains("job failed"));
+    }
+
+    #[test]
+    fn should_stop_cancelled_jobs() {
+        let state = setup_state();
+        setup_counters(&state, "counters");
+        let task = state
+            .tasks
+            .register(UPDATE_BY_QUERY_ACTION, "test".to_string());
+        state.tasks.cancel(&state.tasks.task_id(task)).unwrap();
+
+        let job = UpdateByQuery {
+            targets: state
+                .store
+                .resolve_indices("counters", Default::default())
+                .unwrap(),
+            query: parse_query(&json!({})).unwrap(),
+            request: UpdateRequest::from_script(None),
+            proceed_on_conflict: false,
+            max_docs: None,
+        };
+        let progress = Progress {
+            tasks: &state.tasks,
+            task,
+        };
+        let response = job.run(&state.store, &progress).response(0, false);
+        assert_eq!(response["total"], 0);
+        assert_eq!(response["canceled"], "by user request");
+    }
+
+    #[test]
+    fn should_publish_the_task_status_in_batches() {
+        let state = setup_state();
+        let task = state
+            .tasks
+            .register(DELETE_BY_QUERY_ACTION, "test".to_string());
+        let progress = Progress {
+            tasks: &state.tasks,
+            task,
+        };
+
+        let mut counts = ByQueryCounts::default();
+        for _ in 0..STATUS_INTERVAL + 10 {
+            assert!(progress.advance(&mut counts, None));
+        }
+        let task_id = state.tasks.task_id(task);
+        let status = state.tasks.get(&task_id).unwrap().status.unwrap();
+        assert_eq!(status["total"], STATUS_INTERVAL);
+        assert_eq!(counts.total, STATUS_INTERVAL + 10);
+    }
+}
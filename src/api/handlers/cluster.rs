@@ -1,13 +1,17 @@
+use crate::AppState;
 use crate::api::responses::{ClusterHealthResponse, InfoResponse, VersionInfo};
 use axum::Json;
+use axum::extract::State;
 use axum::http::StatusCode;
+use std::sync::Arc;
 
-pub async fn info() -> Json<InfoResponse> {
+pub async fn info(State(state): State<Arc<AppState>>) -> Json<InfoResponse> {
+    let cluster = &state.config.cluster;
     Json(InfoResponse {
-        name: "es_fake".to_string(),
-        cluster_name: "docker-cluster".to_string(),
+        name: cluster.node_name.clone(),
+        cluster_name: cluster.name.clone(),
         version: VersionInfo {
-            number: "8.10.0".to_string(),
+            number: cluster.version.clone(),
             build_flavor: "default".to_string(),
         },
         tagline: "You Know, for Search".to_string(),
@@ -18,9 +22,9 @@ pub async fn ping() -> StatusCode {
     StatusCode::OK
 }
 
-pub async fn cluster_health() -> Json<ClusterHealthResponse> {
+pub async fn cluster_health(State(state): State<Arc<AppState>>) -> Json<ClusterHealthResponse> {
     Json(ClusterHealthResponse {
-        cluster_name: "docker-cluster".to_string(),
+        cluster_name: state.config.cluster.name.clone(),
         status: "green".to_string(),
         timed_out: false,
         number_of_nodes: 1,
@@ -41,10 +45,11 @@ pub async fn cluster_health() -> Json<ClusterHealthResponse> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::api::handlers::setup_state;
 
     #[tokio::test]
     async fn should_return_info_with_correct_version_and_tagline() {
-        let response = info().await;
+        let response = info(State(setup_state())).await;
         assert_eq!(response.version.number, "8.10.0");
         assert_eq!(response.version.build_flavor, "default");
         assert_eq!(response.tagline, "You Know, for Search");
@@ -59,7 +64,7 @@ mod tests {
 
     #[tokio::test]
     async fn should_return_green_cluster_health_with_active_shards() {
-        let response = cluster_health().await;
+        let response = cluster_health(State(setup_state())).await;
         assert_eq!(response.status, "green");
         assert_eq!(response.cluster_name, "docker-cluster");
         assert_eq!(response.number_of_nodes, 1);
@@ -71,7 +76,7 @@ mod tests {
 
     #[tokio::test]
     async fn should_have_zero_pending_tasks_in_health_check() {
-        let response = cluster_health().await;
+        let response = cluster_health(State(setup_state())).await;
         assert_eq!(response.number_of_pending_tasks, 0);
         assert_eq!(response.relocating_shards, 0);
         assert_eq!(response.unassigned_shards, 0);
@@ -0,0 +1,302 @@
+use super::{index_not_found, matches_any, param_flag, to_error};
+use crate::AppState;
+use crate::domain::data_stream::{DataStream, RolloverConditions, TIMESTAMP_FIELD};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub async fn put_data_stream(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    if state.store.is_data_stream(&name) {
+        return to_error(
+            StatusCode::BAD_REQUEST,
+            "resource_already_exists_exception",
+            &format!("data_stream [{}] already exists", name),
+        )
+        .into_response();
+    }
+
+    match state.store.create_data_stream(&name) {
+        Ok(()) => Json(json!({ "acknowledged": true })).into_response(),
+        Err(e) => {
+            to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e).into_response()
+        }
+    }
+}
+
+pub async fn get_data_streams(State(state): State<Arc<AppState>>) -> Response {
+    render_data_streams(&state, None)
+}
+
+pub async fn get_data_stream(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    render_data_streams(&state, Some(&name))
+}
+
+pub async fn delete_data_stream(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let matched: Vec<String> = state
+        .store
+        .data_streams()
+        .into_keys()
+        .filter(|stream| matches_any(&name, stream))
+        .collect();
+
+    if matched.is_empty() && !name.contains('*') {
+        return index_not_found(&name).into_response();
+    }
+
+    for stream in matched {
+        if let Err(e) = state.store.delete_data_stream(&stream) {
+            return to_error(StatusCode::INTERNAL_SERVER_ERROR, "exception", &e).into_response();
+        }
+    }
+    Json(json!({ "acknowledged": true })).into_response()
+}
+
+pub async fn rollover(
+    Path(target): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    body: Option<Json<Value>>,
+) -> Response {
+    execute_rollover(&state, &target, None, &params, body.map(|Json(b)| b))
+}
+
+pub async fn rollover_to(
+    Path((target, new_index)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    body: Option<Json<Value>>,
+) -> Response {
+    execute_rollover(
+        &state,
+        &target,
+        Some(new_index),
+        &params,
+        body.map(|Json(b)| b),
+    )
+}
+
+fn execute_rollover(
+    state: &AppState,
+    target: &str,
+    new_index: Option<String>,
+    params: &HashMap<String, String>,
+    body: Option<Value>,
+) -> Response {
+    let conditions = body
+        .as_ref()
+        .map(RolloverConditions::from_json)
+        .unwrap_or_default();
+    let dry_run = param_flag(params, "dry_run").unwrap_or(false);
+
+    let outcome = match state
+        .store
+        .rollover(target, new_index, &conditions, dry_run)
+    {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            return to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e)
+                .into_response();
+        }
+    };
+
+    let evaluated: Map<String, Value> = outcome
+        .conditions
+        .into_iter()
+        .map(|(condition, met)| (condition, Value::Bool(met)))
+        .collect();
+    Json(json!({
+        "acknowledged": outcome.rolled_over,
+        "shards_acknowledged": outcome.rolled_over,
+        "old_index": outcome.old_index,
+        "new_index": outcome.new_index,
+        "rolled_over": outcome.rolled_over,
+        "dry_run": dry_run,
+        "conditions": evaluated
+    }))
+    .into_response()
+}
+
+fn render_data_streams(state: &AppState, name: Option<&str>) -> Response {
+    let mut streams: Vec<DataStream> = state
+        .store
+        .data_streams()
+        .into_values()
+        .filter(|stream| name.is_none_or(|pattern| matches_any(pattern, &stream.name)))
+        .collect();
+    streams.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if let Some(name) = name
+        && streams.is_empty()
+        && !name.contains('*')
+    {
+        return index_not_found(name).into_response();
+    }
+
+    let entries: Vec<Value> = streams
+        .iter()
+        .map(|stream| {
+            let indices: Vec<Value> = stream
+                .indices
+                .iter()
+                .map(|index| {
+                    let uuid = state
+                        .store
+                        .get_index(index)
+                        .and_then(|data| data.settings.get("uuid").map(|u| u.to_string()));
+                    json!({ "index_name": index, "index_uuid": uuid })
+                })
+                .collect();
+            json!({
+                "name": stream.name,
+                "timestamp_field": { "name": TIMESTAMP_FIELD },
+                "indices": indices,
+                "generation": stream.generation,
+                "status": "GREEN",
+                "template": stream.template,
+                "hidden": false,
+                "system": false
+            })
+        })
+        .collect();
+    Json(json!({ "data_streams": entries })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::setup_state;
+    use crate::domain::template::IndexTemplate;
+
+    fn put_logs_template(state: &AppState) {
+        let template: IndexTemplate = serde_json::from_value(json!({
+            "index_patterns": ["logs-*"],
+            "data_stream": {}
+        }))
+        .unwrap();
+        state.store.put_index_template("logs", template).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_auto_create_data_stream_and_require_timestamp() {
+        let state = setup_state();
+        put_logs_template(&state);
+
+        state.store.ensure_index("logs-app").unwrap();
+        assert!(state.store.is_data_stream("logs-app"));
+
+        let write_index = state.store.resolve_write_index("logs-app").unwrap();
+        assert!(write_index.starts_with(".ds-logs-app-"));
+        assert!(write_index.ends_with("-000001"));
+
+        let missing = state
+            .store
+            .add_document(&write_index, json!({ "msg": "a" }));
+        assert!(missing.unwrap_err().contains("@timestamp"));
+        state
+            .store
+            .add_document(
+                &write_index,
+                json!({ "@timestamp": "2024-01-01T00:00:00Z", "msg": "a" }),
+            )
+            .unwrap();
+
+        let response = get_data_stream(Path("logs-*".to_string()), State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = delete_data_stream(Path("logs-app".to_string()), State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(state.store.get_index(&write_index).is_none());
+    }
+
+    #[tokio::test]
+    async fn should_reject_data_stream_without_template() {
+        let state = setup_state();
+        let response = put_data_stream(Path("metrics".to_string()), State(state)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_roll_over_data_stream_when_condition_met() {
+        let state = setup_state();
+        put_logs_template(&state);
+        state.store.create_data_stream("logs-app").unwrap();
+        let first = state.store.resolve_write_index("logs-app").unwrap();
+
+        let body = json!({ "conditions": { "max_docs": 1 } });
+        let response = rollover(
+            Path("logs-app".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Some(Json(body.clone())),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.store.resolve_write_index("logs-app").unwrap(), first);
+
+        state
+            .store
+            .add_document(&first, json!({ "@timestamp": "2024-01-01" }))
+            .unwrap();
+        rollover(
+            Path("logs-app".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Some(Json(body)),
+        )
+        .await;
+
+        let second = state.store.resolve_write_index("logs-app").unwrap();
+        assert!(second.ends_with("-000002"));
+        assert_eq!(state.store.data_streams()["logs-app"].indices.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn should_roll_over_alias_to_next_index() {
+        let state = setup_state();
+        state
+            .store
+            .create_index_with(
+                "events-000001".to_string(),
+                crate::repository::store::IndexDefinition {
+                    aliases: HashMap::from([(
+                        "events".to_string(),
+                        crate::domain::alias::AliasDefinition {
+                            is_write_index: Some(true),
+                            ..Default::default()
+                        },
+                    )]),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let response = rollover(
+            Path("events".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            state.store.resolve_write_index("events").unwrap(),
+            "events-000002"
+        );
+        assert_eq!(state.store.aliases()["events"].len(), 2);
+    }
+}
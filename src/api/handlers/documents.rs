@@ -1,297 +1,612 @@
-use super::to_error;
+use super::{to_error, write_error};
 use crate::AppState;
-use crate::api::responses::{ErrorResponse, IndexResponse, ShardsInfo};
-use crate::domain::query::parse_query;
+use crate::api::responses::{ErrorResponse, IndexResponse, ShardsInfo, create_error_response};
+use crate::domain::document::StoredDocument;
+use crate::domain::source::SourceFilter;
+use crate::domain::update::{UpdateKind, UpdateRequest};
+use crate::domain::versioning::{DocVersion, WriteConditions};
+use crate::repository::store::{UpdateResult, WriteResult};
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
 };
 use serde_json::{Value, json};
+use std::collections::HashMap;
 use std::sync::Arc;
 
+pub const DATA_STREAM_OP_TYPE: &str =
+    "only write ops with an op_type of create are allowed in data streams";
+
+fn data_stream_write_error() -> (StatusCode, Json<ErrorResponse>) {
+    to_error(
+        StatusCode::BAD_REQUEST,
+        "illegal_argument_exception",
+        DATA_STREAM_OP_TYPE,
+    )
+}
+
 pub async fn index_document(
     Path(index): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
     State(state): State<Arc<AppState>>,
     Json(doc): Json<Value>,
-) -> Result<Json<IndexResponse>, (StatusCode, Json<ErrorResponse>)> {
-    if state.store.get_index(&index).is_none() {
-        state
-            .store
-            .create_index(index.clone(), crate::domain::mapping::Mapping::default());
+) -> Result<(StatusCode, Json<IndexResponse>), (StatusCode, Json<ErrorResponse>)> {
+    state
+        .store
+        .ensure_index(&index)
+        .map_err(|e| to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e))?;
+    // Without an id the op_type defaults to `create`, which data streams take.
+    if state.store.is_data_stream(&index)
+        && params
+            .get("op_type")
+            .is_some_and(|op_type| op_type != "create")
+    {
+        return Err(data_stream_write_error());
     }
+    let target = state
+        .store
+        .resolve_write_target(&index, params.get("routing").map(|r| r.as_str()))
+        .map_err(|e| to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e))?;
 
-    let id = state.store.add_document(&index, doc).map_err(|e| {
-        // Inteligenckie mapowanie błędów
-        let status = if e.contains("index_not_found") {
-            StatusCode::NOT_FOUND
-        } else {
-            StatusCode::BAD_REQUEST
-        };
-        to_error(status, "mapper_parsing_exception", &e)
-    })?;
+    let written = state
+        .store
+        .write_document(
+            &target.index,
+            None,
+            doc,
+            target.routing.as_deref(),
+            &WriteConditions::default(),
+        )
+        .map_err(|e| write_error(&e, "mapper_parsing_exception"))?;
 
-    Ok(Json(IndexResponse {
-        _index: index,
-        _id: id,
-        result: "created".to_string(),
-        _version: 1,
-        _shards: ShardsInfo::default(),
-    }))
+    Ok(write_response(target.index, written))
 }
 
 pub async fn index_document_with_id(
     Path((index, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
     State(state): State<Arc<AppState>>,
-    Json(mut doc): Json<Value>,
-) -> Result<Json<IndexResponse>, (StatusCode, Json<ErrorResponse>)> {
-    if let Some(obj) = doc.as_object_mut() {
-        obj.insert("_id".to_string(), Value::String(id.clone()));
+    Json(doc): Json<Value>,
+) -> Result<(StatusCode, Json<IndexResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let conditions = WriteConditions::from_params(&params).map_err(|e| {
+        to_error(
+            StatusCode::BAD_REQUEST,
+            "action_request_validation_exception",
+            &e,
+        )
+    })?;
+    state
+        .store
+        .ensure_index(&index)
+        .map_err(|e| to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e))?;
+    // Checked once the name exists, so a write that auto-creates a data
+    // stream is held to the same rule.
+    if state.store.is_data_stream(&index)
+        && params.get("op_type").map(|s| s.as_str()) != Some("create")
+    {
+        return Err(data_stream_write_error());
     }
-    let saved_id = state
+    let target = state
         .store
-        .add_document(&index, doc)
-        .map_err(|e| to_error(StatusCode::BAD_REQUEST, "mapper_parsing_exception", &e))?;
+        .resolve_write_target(&index, params.get("routing").map(|r| r.as_str()))
+        .map_err(|e| to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e))?;
+    let written = state
+        .store
+        .write_document(
+            &target.index,
+            Some(&id),
+            doc,
+            target.routing.as_deref(),
+            &conditions,
+        )
+        .map_err(|e| write_error(&e, "mapper_parsing_exception"))?;
 
-    Ok(Json(IndexResponse {
-        _index: index,
-        _id: saved_id,
-        result: "updated".to_string(),
-        _version: 1,
-        _shards: ShardsInfo::default(),
-    }))
+    Ok(write_response(target.index, written))
+}
+
+/// `PUT /{index}/_create/{id}`: indexes a document only if the id is not
+/// taken yet, the same as `op_type=create`.
+pub async fn create_document(
+    Path((index, id)): Path<(String, String)>,
+    Query(mut params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    Json(doc): Json<Value>,
+) -> Result<(StatusCode, Json<IndexResponse>), (StatusCode, Json<ErrorResponse>)> {
+    params.insert("op_type".to_string(), "create".to_string());
+    index_document_with_id(Path((index, id)), Query(params), State(state), Json(doc)).await
 }
 
 pub async fn update_document(
     Path((index, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
     State(state): State<Arc<AppState>>,
     Json(body): Json<Value>,
-) -> Result<Json<IndexResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let patch = body.get("doc").cloned().ok_or_else(|| {
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<ErrorResponse>)> {
+    let conditions = WriteConditions::from_params(&params).map_err(|e| {
         to_error(
             StatusCode::BAD_REQUEST,
             "action_request_validation_exception",
-            "Validation Failed: 1: script or doc is missing;",
+            &e,
         )
     })?;
+    let mut request = UpdateRequest::from_json(&body)
+        .map_err(|e| write_error(&e, "action_request_validation_exception"))?;
+    if let Some(retries) = params.get("retry_on_conflict") {
+        request
+            .set_retry_on_conflict(retries, &conditions)
+            .map_err(|e| {
+                to_error(
+                    StatusCode::BAD_REQUEST,
+                    "action_request_validation_exception",
+                    &format!("Validation Failed: 1: {};", e),
+                )
+            })?;
+    }
+    if let Some(source) = params.get("_source") {
+        request.fetch_source = match source.as_str() {
+            "false" => None,
+            "true" | "" => Some(SourceFilter::default()),
+            fields => Some(SourceFilter::parse(&json!(fields))),
+        };
+    }
+    if request.upsert_source().is_some() {
+        state
+            .store
+            .ensure_index(&index)
+            .map_err(|e| to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e))?;
+    }
+    if state.store.is_data_stream(&index) {
+        return Err(data_stream_write_error());
+    }
+    let target = state
+        .store
+        .resolve_write_target(&index, params.get("routing").map(String::as_str))
+        .map_err(|e| to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e))?;
+    let index = target.index;
 
-    let saved_id = state
+    let updated = state
         .store
-        .patch_document(&index, &id, patch)
-        .map_err(|e| {
-            let status = if e.contains("index_not_found") {
-                StatusCode::NOT_FOUND
-            } else {
-                StatusCode::BAD_REQUEST
-            };
-            to_error(status, &e, &e)
-        })?;
-
-    Ok(Json(IndexResponse {
+        .update_document(
+            &index,
+            &id,
+            &request,
+            target.routing.as_deref(),
+            &conditions,
+        )
+        .map_err(|e| write_error(&e, "illegal_argument_exception"))?;
+
+    let status = if updated.kind == UpdateKind::Created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(update_response(index, updated, &request))))
+}
+
+pub fn update_response(index: String, updated: UpdateResult, request: &UpdateRequest) -> Value {
+    let mut response = json!(index_response(
+        index,
+        updated.id,
+        updated.version,
+        updated.kind.as_str()
+    ));
+    if updated.kind == UpdateKind::Noop {
+        response["_shards"] = json!({ "total": 0, "successful": 0, "failed": 0 });
+    }
+    if let Some(filter) = &request.fetch_source
+        && updated.source.is_object()
+        && updated.kind != UpdateKind::Deleted
+    {
+        response["get"] = json!({
+            "_seq_no": updated.version.seq_no,
+            "_primary_term": updated.version.primary_term,
+            "found": true,
+            "_source": filter.apply(&updated.source)
+        });
+    }
+    response
+}
+
+fn write_response(index: String, written: WriteResult) -> (StatusCode, Json<IndexResponse>) {
+    if written.created {
+        (
+            StatusCode::CREATED,
+            Json(index_response(
+                index,
+                written.id,
+                written.version,
+                "created",
+            )),
+        )
+    } else {
+        (
+            StatusCode::OK,
+            Json(index_response(
+                index,
+                written.id,
+                written.version,
+                "updated",
+            )),
+        )
+    }
+}
+
+pub fn index_response(
+    index: String,
+    id: String,
+    version: DocVersion,
+    result: &str,
+) -> IndexResponse {
+    IndexResponse {
         _index: index,
-        _id: saved_id,
-        result: "updated".to_string(),
-        _version: 1,
+        _id: id,
+        _version: version.version,
+        result: result.to_string(),
         _shards: ShardsInfo::default(),
-    }))
+        _seq_no: version.seq_no,
+        _primary_term: version.primary_term,
+    }
 }
 
+/// A missing document is reported as `found: false` with a 404, a missing
+/// index as an error.
 pub async fn get_document(
     Path((index, id)): Path<(String, String)>,
     State(state): State<Arc<AppState>>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<ErrorResponse>)> {
+    let index = state
+        .store
+        .resolve_write_index(&index)
+        .map_err(|e| to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e))?;
+    if state.store.get_index(&index).is_none() {
+        return Err(to_error(
+            StatusCode::NOT_FOUND,
+            "index_not_found_exception",
+            &format!("no such index [{}]", index),
+        ));
+    }
+    let Some(doc) = state.store.get_stored_document(&index, &id) else {
+        let missing = json!({ "_index": index, "_id": id, "found": false });
+        return Ok((StatusCode::NOT_FOUND, Json(missing)));
+    };
+    let mut response = found_document(&index, &doc);
+    response["_source"] = doc.source;
+    Ok((StatusCode::OK, Json(response)))
+}
+
+fn found_document(index: &str, doc: &StoredDocument) -> Value {
+    let mut entry = json!({
+        "_index": index,
+        "_id": doc.id,
+        "_version": doc.version.version,
+        "_seq_no": doc.version.seq_no,
+        "_primary_term": doc.version.primary_term
+    });
+    if let Some(routing) = &doc.routing {
+        entry["_routing"] = json!(routing);
+    }
+    entry["found"] = json!(true);
+    entry
+}
+
+pub async fn get_source(
+    Path((index, id)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
 ) -> Result<Json<Value>, (StatusCode, Json<ErrorResponse>)> {
+    let index = state
+        .store
+        .resolve_write_index(&index)
+        .map_err(|e| to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e))?;
+    if state.store.get_index(&index).is_none() {
+        return Err(to_error(
+            StatusCode::NOT_FOUND,
+            "index_not_found_exception",
+            &format!("no such index [{}]", index),
+        ));
+    }
     let doc = state.store.get_document(&index, &id).ok_or_else(|| {
         to_error(
             StatusCode::NOT_FOUND,
-            "index_not_found_exception",
-            "no such index or document",
+            "resource_not_found_exception",
+            &format!("Document not found [{}]/[{}]", index, id),
         )
     })?;
-    Ok(Json(json!({ "_index": index, "_id": id, "_source": doc })))
+    Ok(Json(doc))
 }
 
-pub async fn delete_document(
-    Path((index, id)): Path<(String, String)>,
+pub async fn mget(
     State(state): State<Arc<AppState>>,
-) -> impl IntoResponse {
-    if state.store.delete_document(&index, &id) {
-        Json(json!({
-            "_index": index,
-            "_id": id,
-            "_version": 1,
-            "result": "deleted",
-            "_shards": ShardsInfo::default(),
-            "status": 200
-        }))
-        .into_response()
-    } else {
+    Json(body): Json<Value>,
+) -> Result<Json<Value>, (StatusCode, Json<ErrorResponse>)> {
+    multi_get(&state, None, &body).map(Json).map_err(|e| {
         to_error(
-            StatusCode::NOT_FOUND,
-            "document_missing_exception",
-            "document not found",
+            StatusCode::BAD_REQUEST,
+            "action_request_validation_exception",
+            &e,
         )
-        .into_response()
-    }
+    })
 }
 
-pub async fn bulk(State(state): State<Arc<AppState>>, body: String) -> Json<Value> {
-    let mut results = Vec::new();
-    let mut lines = body.lines();
+pub async fn mget_index(
+    Path(index): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<Value>,
+) -> Result<Json<Value>, (StatusCode, Json<ErrorResponse>)> {
+    multi_get(&state, Some(&index), &body)
+        .map(Json)
+        .map_err(|e| {
+            to_error(
+                StatusCode::BAD_REQUEST,
+                "action_request_validation_exception",
+                &e,
+            )
+        })
+}
 
-    while let Some(line) = lines.next() {
-        let line = line.trim();
-        if line.is_empty() {
+fn multi_get(state: &AppState, default_index: Option<&str>, body: &Value) -> Result<Value, String> {
+    let requests: Vec<Value> = if let Some(docs) = body.get("docs").and_then(|d| d.as_array()) {
+        docs.clone()
+    } else if let Some(ids) = body.get("ids").and_then(|i| i.as_array()) {
+        ids.iter().map(|id| json!({ "_id": id })).collect()
+    } else {
+        Vec::new()
+    };
+
+    if requests.is_empty() {
+        return Err("Validation Failed: 1: no documents to get;".to_string());
+    }
+
+    let mut docs = Vec::with_capacity(requests.len());
+    for (pos, request) in requests.iter().enumerate() {
+        let index = match request["_index"].as_str().or(default_index) {
+            Some(index) => state
+                .store
+                .resolve_write_index(index)
+                .unwrap_or_else(|_| index.to_string()),
+            None => {
+                return Err(format!(
+                    "Validation Failed: 1: index is missing for doc {};",
+                    pos
+                ));
+            }
+        };
+        let id = match &request["_id"] {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            _ => {
+                return Err(format!(
+                    "Validation Failed: 1: id is missing for doc {};",
+                    pos
+                ));
+            }
+        };
+
+        if state.store.get_index(&index).is_none() {
+            let error = create_error_response(
+                StatusCode::NOT_FOUND.as_u16(),
+                "index_not_found_exception",
+                &format!("no such index [{}]", index),
+            );
+            docs.push(json!({ "_index": index, "_id": id, "error": error.error }));
             continue;
         }
 
-        if let Ok(action_json) = serde_json::from_str::<Value>(line) {
-            let action_type = action_json
-                .as_object()
-                .and_then(|obj| obj.keys().next())
-                .cloned();
-
-            match action_type.as_deref() {
-                Some("index") | Some("create") => {
-                    let act = &action_json[action_type.as_ref().unwrap()];
-                    let index_name = act["_index"].as_str().unwrap_or("unknown").to_string();
-                    let id = act["_id"].as_str().map(|s| s.to_string());
-
-                    if let Some(data_line) = lines.next() {
-                        if let Ok(mut doc) = serde_json::from_str::<Value>(data_line) {
-                            if let Some(doc_id) = id {
-                                if let Some(obj) = doc.as_object_mut() {
-                                    obj.insert("_id".to_string(), Value::String(doc_id.clone()));
-                                }
-                            }
-                            let res = state.store.add_document(&index_name, doc);
-                            results.push(json!({
-                                "index": {
-                                    "_index": index_name,
-                                    "_id": res.as_ref().ok(),
-                                    "status": if res.is_ok() { 201 } else { 400 },
-                                    "result": if res.is_ok() { "created" } else { "error" }
-                                }
-                            }));
-                        }
-                    }
-                }
-                Some("update") => {
-                    let act = &action_json["update"];
-                    let index_name = act["_index"].as_str().unwrap_or("unknown").to_string();
-                    let id = act["_id"].as_str().unwrap_or_default().to_string();
-
-                    if let Some(data_line) = lines.next() {
-                        if let Ok(body) = serde_json::from_str::<Value>(data_line) {
-                            let patch = body.get("doc").cloned().unwrap_or(body);
-                            let res = state.store.patch_document(&index_name, &id, patch);
-                            results.push(json!({
-                                "update": {
-                                    "_index": index_name,
-                                    "_id": id,
-                                    "status": if res.is_ok() { 200 } else { 404 },
-                                    "result": if res.is_ok() { "updated" } else { "error" }
-                                }
-                            }));
-                        }
-                    }
-                }
-                Some("delete") => {
-                    let act = &action_json["delete"];
-                    let index_name = act["_index"].as_str().unwrap_or("unknown").to_string();
-                    let id = act["_id"].as_str().unwrap_or_default().to_string();
-                    let deleted = state.store.delete_document(&index_name, &id);
-                    results.push(json!({
-                        "delete": {
-                            "_index": index_name,
-                            "_id": id,
-                            "status": if deleted { 200 } else { 404 },
-                            "result": if deleted { "deleted" } else { "not_found" }
-                        }
-                    }));
+        match state.store.get_stored_document(&index, &id) {
+            Some(doc) => {
+                let filter = request
+                    .get("_source")
+                    .map(SourceFilter::parse)
+                    .unwrap_or_default();
+                let mut entry = found_document(&index, &doc);
+                if let Some(filtered) = filter.apply(&doc.source) {
+                    entry["_source"] = filtered;
                 }
-                _ => {}
+                docs.push(entry);
             }
+            None => docs.push(json!({ "_index": index, "_id": id, "found": false })),
         }
     }
 
-    Json(json!({ "took": 1, "errors": false, "items": results }))
+    Ok(json!({ "docs": docs }))
 }
 
-pub async fn delete_by_query(
-    Path(index): Path<String>,
+pub async fn delete_document(
+    Path((index, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
     State(state): State<Arc<AppState>>,
-    Json(query_json): Json<Value>,
 ) -> impl IntoResponse {
-    let index_data = match state.store.get_index(&index) {
-        Some(data) => data,
-        None => {
+    if state.store.is_data_stream(&index) {
+        return data_stream_write_error().into_response();
+    }
+    let conditions = match WriteConditions::from_params(&params) {
+        Ok(conditions) => conditions,
+        Err(e) => {
             return to_error(
-                StatusCode::NOT_FOUND,
-                "index_not_found_exception",
-                &format!("no such index [{}]", index),
+                StatusCode::BAD_REQUEST,
+                "action_request_validation_exception",
+                &e,
             )
             .into_response();
         }
     };
-
-    let query = parse_query(&query_json);
-    let ids_to_delete: Vec<String> = index_data
-        .documents
-        .iter()
-        .filter(|d| query.matches(d))
-        .filter_map(|d| d["_id"].as_str().map(|s| s.to_string()))
-        .collect();
-
-    let deleted_count = ids_to_delete.len();
-    for id in ids_to_delete {
-        state.store.delete_document(&index, &id);
+    let index = match state.store.resolve_write_index(&index) {
+        Ok(index) => index,
+        Err(e) => {
+            return to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e)
+                .into_response();
+        }
+    };
+    match state.store.remove_document(&index, &id, &conditions) {
+        Ok(deleted) if deleted.found => Json(json!({
+            "_index": index,
+            "_id": id,
+            "_version": deleted.version.version,
+            "result": "deleted",
+            "_shards": ShardsInfo::default(),
+            "_seq_no": deleted.version.seq_no,
+            "_primary_term": deleted.version.primary_term,
+            "status": 200
+        }))
+        .into_response(),
+        Ok(_) => to_error(
+            StatusCode::NOT_FOUND,
+            "document_missing_exception",
+            "document not found",
+        )
+        .into_response(),
+        Err(e) => write_error(&e, "illegal_argument_exception").into_response(),
     }
-
-    Json(json!({
-        "took": 1,
-        "timed_out": false,
-        "total": deleted_count,
-        "deleted": deleted_count,
-        "batches": 1,
-        "version_conflicts": 0,
-        "noops": 0,
-        "retries": { "bulk": 0, "search": 0 },
-        "throttled_millis": 0,
-        "requests_per_second": -1.0,
-        "throttled_until_millis": 0,
-        "failures": []
-    }))
-    .into_response()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::api::handlers::setup_state;
+    use crate::config::Config;
+    use crate::domain::alias::AliasDefinition;
     use crate::domain::mapping::Mapping;
+    use crate::domain::template::IndexTemplate;
+    use crate::repository::persistence::{FsyncPolicy, PersistenceOptions};
+    use crate::repository::store::{AliasAction, InMemoryStore};
+    use crate::repository::tasks::TaskRegistry;
+
+    fn put_document(state: &AppState, index: &str, id: &str, source: Value) {
+        state
+            .store
+            .write_document(index, Some(id), source, None, &WriteConditions::default())
+            .unwrap();
+    }
 
     #[tokio::test]
     async fn should_index_and_get_document() {
         let state = setup_state();
         let index = "docs".to_string();
-        state.store.create_index(index.clone(), Mapping::default());
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
 
         let doc = json!({ "title": "test" });
-        let res = index_document(Path(index.clone()), State(state.clone()), Json(doc))
-            .await
-            .unwrap();
+        let (status, Json(res)) = index_document(
+            Path(index.clone()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(doc),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
         let id = res._id.clone();
 
-        let fetched = get_document(Path((index, id)), State(state)).await.unwrap();
+        let (status, fetched) = get_document(Path((index, id)), State(state)).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
         assert_eq!(fetched["_source"]["title"], "test");
     }
 
+    #[tokio::test]
+    async fn should_route_writes_through_an_alias() {
+        let state = setup_state();
+        state
+            .store
+            .create_index("orders".to_string(), Mapping::default())
+            .unwrap();
+        state
+            .store
+            .update_aliases(vec![AliasAction::Add {
+                index: "orders".to_string(),
+                alias: "shop-1".to_string(),
+                definition: AliasDefinition::from_json(&json!({ "routing": "1" })),
+            }])
+            .unwrap();
+
+        let (status, _) = index_document_with_id(
+            Path(("shop-1".to_string(), "a".to_string())),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(json!({ "n": 1 })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, fetched) = get_document(
+            Path(("orders".to_string(), "a".to_string())),
+            State(state.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(fetched["_routing"], "1");
+
+        let params = HashMap::from([("routing".to_string(), "2".to_string())]);
+        let Err((status, Json(error))) = index_document_with_id(
+            Path(("shop-1".to_string(), "b".to_string())),
+            Query(params),
+            State(state),
+            Json(json!({ "n": 2 })),
+        )
+        .await
+        else {
+            panic!("expected a routing conflict");
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.error.r#type, "illegal_argument_exception");
+    }
+
+    #[tokio::test]
+    async fn should_require_create_when_a_write_auto_creates_a_data_stream() {
+        let state = setup_state();
+        let template: IndexTemplate = serde_json::from_value(json!({
+            "index_patterns": ["logs-*"],
+            "data_stream": {}
+        }))
+        .unwrap();
+        state.store.put_index_template("logs", template).unwrap();
+        let doc = json!({ "@timestamp": "2024-01-01T00:00:00Z", "msg": "a" });
+
+        let Err((status, Json(error))) = index_document_with_id(
+            Path(("logs-new".to_string(), "1".to_string())),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(doc.clone()),
+        )
+        .await
+        else {
+            panic!("expected an op_type error");
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.error.reason, DATA_STREAM_OP_TYPE);
+
+        let params = HashMap::from([("op_type".to_string(), "create".to_string())]);
+        let (status, _) = index_document_with_id(
+            Path(("logs-other".to_string(), "1".to_string())),
+            Query(params),
+            State(state.clone()),
+            Json(doc.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, _) = index_document(
+            Path("logs-third".to_string()),
+            Query(HashMap::new()),
+            State(state),
+            Json(doc),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
     #[tokio::test]
     async fn should_handle_partial_update() {
         let state = setup_state();
         let index = "updates".to_string();
-        state.store.create_index(index.clone(), Mapping::default());
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
         let id = state
             .store
             .add_document(&index, json!({ "a": 1, "b": 2 }))
@@ -300,6 +615,7 @@ mod tests {
         let update = json!({ "doc": { "b": 3 } });
         let _ = update_document(
             Path((index.clone(), id.clone())),
+            Query(HashMap::new()),
             State(state.clone()),
             Json(update),
         )
@@ -312,51 +628,292 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn should_return_404_on_missing_document() {
+    async fn should_update_with_script_and_honour_ctx_op() {
         let state = setup_state();
-        let result = get_document(Path(("none".into(), "1".into())), State(state)).await;
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err().0, StatusCode::NOT_FOUND);
+        let index = "scripted-updates".to_string();
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
+        put_document(&state, &index, "1", json!({ "count": 1 }));
+
+        let script = json!({ "script": {
+            "source": "if (ctx._source.count >= params.max) { ctx.op = 'delete' } \
+                       else { ctx._source.count += params.n }",
+            "params": { "n": 2, "max": 3 }
+        } });
+        let (_, Json(res)) = update_document(
+            Path((index.clone(), "1".to_string())),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(script.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res["result"], "updated");
+        assert_eq!(state.store.get_document(&index, "1").unwrap()["count"], 3);
+
+        let (_, Json(res)) = update_document(
+            Path((index.clone(), "1".to_string())),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(script),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res["result"], "deleted");
+        assert!(state.store.get_document(&index, "1").is_none());
+
+        let Err((status, Json(error))) = update_document(
+            Path((index, "2".to_string())),
+            Query(HashMap::new()),
+            State(state),
+            Json(json!({ "script": "ctx._source.x = ", "upsert": {} })),
+        )
+        .await
+        else {
+            panic!("expected a compile error");
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.error.r#type, "script_exception");
+    }
+
+    #[tokio::test]
+    async fn should_keep_routing_of_upserted_documents() {
+        let state = setup_state();
+        let params = HashMap::from([("routing".to_string(), "user-7".to_string())]);
+
+        let (status, _) = update_document(
+            Path(("carts".to_string(), "1".to_string())),
+            Query(params),
+            State(state.clone()),
+            Json(json!({ "doc": { "n": 2 }, "upsert": { "n": 1 } })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, fetched) = get_document(Path(("carts".to_string(), "1".to_string())), State(state))
+            .await
+            .unwrap();
+        assert_eq!(fetched["_routing"], "user-7");
     }
 
     #[tokio::test]
-    async fn should_handle_full_bulk_workflow() {
+    async fn should_reject_stale_seq_no_with_conflict() {
         let state = setup_state();
-        let index = "bulk-test".to_string();
-        state.store.create_index(index.clone(), Mapping::default());
+        let index = "occ".to_string();
+        let params = |seq_no: &str| {
+            HashMap::from([
+                ("if_seq_no".to_string(), seq_no.to_string()),
+                ("if_primary_term".to_string(), "1".to_string()),
+            ])
+        };
+
+        let (_, Json(first)) = index_document_with_id(
+            Path((index.clone(), "1".to_string())),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(json!({ "n": 1 })),
+        )
+        .await
+        .unwrap();
+        assert_eq!((first._version, first._seq_no), (1, 0));
+
+        let (_, Json(second)) = index_document_with_id(
+            Path((index.clone(), "1".to_string())),
+            Query(params("0")),
+            State(state.clone()),
+            Json(json!({ "n": 2 })),
+        )
+        .await
+        .unwrap();
+        assert_eq!((second._version, second._seq_no), (2, 1));
+
+        let Err(stale) = index_document_with_id(
+            Path((index.clone(), "1".to_string())),
+            Query(params("0")),
+            State(state.clone()),
+            Json(json!({ "n": 3 })),
+        )
+        .await
+        else {
+            panic!("expected a version conflict");
+        };
+        assert_eq!(stale.0, StatusCode::CONFLICT);
+        assert_eq!(stale.1.error.r#type, "version_conflict_engine_exception");
+
+        let (_, fetched) = get_document(Path((index, "1".to_string())), State(state))
+            .await
+            .unwrap();
+        assert_eq!(fetched["_version"], 2);
+        assert_eq!(fetched["_source"]["n"], 2);
+    }
+
+    #[tokio::test]
+    async fn should_create_only_when_id_is_free() {
+        let state = setup_state();
+        let index = "events".to_string();
+
+        let (status, Json(created)) = create_document(
+            Path((index.clone(), "1".to_string())),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(json!({ "n": 1 })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created.result, "created");
+
+        let Err(conflict) = create_document(
+            Path((index.clone(), "1".to_string())),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(json!({ "n": 2 })),
+        )
+        .await
+        else {
+            panic!("expected a version conflict");
+        };
+        assert_eq!(conflict.0, StatusCode::CONFLICT);
+
+        let (status, Json(updated)) = index_document_with_id(
+            Path((index.clone(), "1".to_string())),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(json!({ "n": 3 })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated.result, "updated");
 
         let bulk_body = format!(
-            "{}\n{}\n{}\n{}\n{}\n",
-            json!({"index": {"_index": &index, "_id": "1"}}),
-            json!({"field": "v1"}),
-            json!({"update": {"_index": &index, "_id": "1"}}),
-            json!({"doc": {"field": "v2"}}),
-            json!({"delete": {"_index": &index, "_id": "1"}})
+            "{}\n{}\n",
+            json!({"create": {"_index": &index, "_id": "1"}}),
+            json!({"n": 4})
         );
+        let response = crate::api::handlers::bulk::bulk(
+            Query(HashMap::new()),
+            State(state),
+            axum::body::Body::from(bulk_body),
+        )
+        .await
+        .into_body();
+        let response: Value =
+            serde_json::from_slice(&axum::body::to_bytes(response, usize::MAX).await.unwrap())
+                .unwrap();
+        assert_eq!(response["errors"], true);
+        assert_eq!(response["items"][0]["create"]["status"], 409);
+        assert_eq!(
+            response["items"][0]["create"]["error"]["type"],
+            "version_conflict_engine_exception"
+        );
+    }
 
-        let response = bulk(State(state.clone()), bulk_body).await;
-        let items = response["items"].as_array().unwrap();
+    #[tokio::test]
+    async fn should_return_404_on_missing_document() {
+        let state = setup_state();
+        let result = get_document(Path(("none".into(), "1".into())), State(state.clone())).await;
+        let (status, error) = result.unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error.error.r#type, "index_not_found_exception");
 
-        assert_eq!(items.len(), 3);
-        assert_eq!(items[0]["index"]["result"], "created");
-        assert_eq!(items[1]["update"]["result"], "updated");
-        assert_eq!(items[2]["delete"]["result"], "deleted");
+        state
+            .store
+            .create_index("orders".to_string(), Mapping::default())
+            .unwrap();
+        let result = get_document(Path(("orders".into(), "1".into())), State(state)).await;
+        let (status, missing) = result.unwrap();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            missing.0,
+            json!({ "_index": "orders", "_id": "1", "found": false })
+        );
     }
 
     #[tokio::test]
-    async fn should_delete_by_query() {
+    async fn should_multi_get_docs_and_ids() {
         let state = setup_state();
-        let index = "delete-query".to_string();
-        state.store.create_index(index.clone(), Mapping::default());
-        
-        state.store.add_document(&index, json!({ "status": "old", "_id": "1" })).unwrap();
-        state.store.add_document(&index, json!({ "status": "new", "_id": "2" })).unwrap();
-
-        let query = json!({ "query": { "term": { "status": "old" } } });
-        let response = delete_by_query(Path(index.clone()), State(state.clone()), Json(query)).await.into_response();
-        
-        assert_eq!(response.status(), StatusCode::OK);
-        assert!(state.store.get_document(&index, "1").is_none());
-        assert!(state.store.get_document(&index, "2").is_some());
+        let index = "mget".to_string();
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
+        put_document(&state, &index, "1", json!({ "title": "a", "secret": "x" }));
+
+        let body = json!({
+            "docs": [
+                { "_index": &index, "_id": "1", "_source": ["title"] },
+                { "_index": &index, "_id": "2" },
+                { "_index": "missing", "_id": "1" }
+            ]
+        });
+        let Json(response) = mget(State(state.clone()), Json(body)).await.unwrap();
+        let docs = response["docs"].as_array().unwrap();
+
+        assert_eq!(docs[0]["found"], true);
+        assert_eq!(docs[0]["_source"], json!({ "title": "a" }));
+        assert_eq!(docs[1]["found"], false);
+        assert_eq!(docs[2]["error"]["type"], "index_not_found_exception");
+
+        let body = json!({ "ids": ["1", "3"] });
+        let Json(response) = mget_index(Path(index), State(state), Json(body))
+            .await
+            .unwrap();
+        assert_eq!(response["docs"][0]["found"], true);
+        assert_eq!(response["docs"][1]["found"], false);
+    }
+
+    #[tokio::test]
+    async fn should_return_raw_source() {
+        let state = setup_state();
+        let index = "source".to_string();
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
+        put_document(&state, &index, "1", json!({ "title": "a" }));
+
+        let Json(source) = get_source(Path((index.clone(), "1".into())), State(state.clone()))
+            .await
+            .unwrap();
+        assert_eq!(source, json!({ "title": "a" }));
+
+        let missing = get_source(Path((index, "2".into())), State(state)).await;
+        assert_eq!(missing.unwrap_err().0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_fail_writes_the_wal_cannot_take_with_a_server_error() {
+        let dir = std::env::temp_dir().join(format!("es_fake-{}", uuid::Uuid::new_v4()));
+        let store = InMemoryStore::open(PersistenceOptions {
+            data_dir: dir.clone(),
+            fsync: FsyncPolicy::Always,
+        })
+        .unwrap();
+        store
+            .create_index("docs".to_string(), Mapping::default())
+            .unwrap();
+        store.fail_wal_appends();
+        let state = Arc::new(AppState {
+            store,
+            tasks: TaskRegistry::new(),
+            config: Arc::new(Config::default()),
+        });
+
+        let (status, Json(error)) = index_document(
+            Path("docs".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(json!({ "title": "lost" })),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(error.error.reason.starts_with("failed to append"));
+        assert_eq!(state.store.get_index("docs").unwrap().documents.len(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
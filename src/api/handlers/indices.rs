@@ -1,7 +1,10 @@
 use super::to_error;
 use crate::AppState;
 use crate::api::responses::{RefreshResponse, ShardsInfo};
+use crate::domain::alias::AliasDefinition;
 use crate::domain::mapping::Mapping;
+use crate::domain::settings::IndexSettings;
+use crate::repository::store::IndexDefinition;
 use axum::{
     Json,
     extract::{Path, State},
@@ -25,15 +28,70 @@ pub async fn check_index(
 pub async fn create_index(
     Path(index): Path<String>,
     State(state): State<Arc<AppState>>,
-    mapping: Option<Json<Mapping>>,
-) -> Json<Value> {
-    let m = mapping.map(|Json(inner)| inner).unwrap_or_default();
-    state.store.create_index(index.clone(), m);
+    body: Option<Json<Value>>,
+) -> impl IntoResponse {
+    if state.store.is_alias(&index) {
+        return to_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_index_name_exception",
+            &format!("Invalid index name [{}], already exists as alias", index),
+        )
+        .into_response();
+    }
+
+    let body = body.map(|Json(inner)| inner).unwrap_or_else(|| json!({}));
+    let definition = match parse_index_definition(&body) {
+        Ok(definition) => definition,
+        Err(e) => {
+            return to_error(
+                StatusCode::BAD_REQUEST,
+                "mapper_parsing_exception",
+                &format!("Failed to parse mapping: {}", e),
+            )
+            .into_response();
+        }
+    };
+    if let Err(e) = state.store.create_index_with(index.clone(), definition) {
+        return to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e).into_response();
+    }
+
     Json(json!({
         "acknowledged": true,
         "shards_acknowledged": true,
         "index": index
     }))
+    .into_response()
+}
+
+fn parse_index_definition(body: &Value) -> Result<IndexDefinition, serde_json::Error> {
+    let mapping_json = match body.get("mappings") {
+        Some(mappings) => Some(mappings),
+        None if body.get("properties").is_some() || body.get("dynamic").is_some() => Some(body),
+        None => None,
+    };
+    let mapping = mapping_json
+        .map(|m| serde_json::from_value::<Mapping>(m.clone()))
+        .transpose()?;
+
+    let aliases = body
+        .get("aliases")
+        .and_then(|a| a.as_object())
+        .map(|aliases| {
+            aliases
+                .iter()
+                .map(|(name, definition)| (name.clone(), AliasDefinition::from_json(definition)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(IndexDefinition {
+        mapping,
+        settings: body
+            .get("settings")
+            .map(IndexSettings::from_json)
+            .unwrap_or_default(),
+        aliases,
+    })
 }
 
 pub async fn get_mapping(
@@ -66,15 +124,17 @@ pub async fn get_settings(
     Path(index): Path<String>,
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
-    if state.store.get_index(&index).is_some() {
+    if let Some(data) = state.store.get_index(&index) {
+        let mut settings = IndexSettings::from_json(&json!({
+            "number_of_shards": "1",
+            "number_of_replicas": "0"
+        }));
+        settings.merge(&data.settings);
+        settings.set("provided_name", &index);
         Json(json!({
             &index: {
                 "settings": {
-                    "index": {
-                        "number_of_shards": "1",
-                        "number_of_replicas": "0",
-                        "provided_name": index
-                    }
+                    "index": settings.to_json()
                 }
             }
         }))
@@ -93,15 +153,15 @@ pub async fn delete_index(
     Path(index): Path<String>,
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
-    if state.store.delete_index(&index) {
-        Json(json!({ "acknowledged": true })).into_response()
-    } else {
-        to_error(
+    match state.store.delete_index(&index) {
+        Ok(true) => Json(json!({ "acknowledged": true })).into_response(),
+        Ok(false) => to_error(
             StatusCode::NOT_FOUND,
             "index_not_found_exception",
             &format!("no such index [{}]", index),
         )
-        .into_response()
+        .into_response(),
+        Err(e) => to_error(StatusCode::INTERNAL_SERVER_ERROR, "exception", &e).into_response(),
     }
 }
 
@@ -134,11 +194,44 @@ mod tests {
         assert_eq!(status, StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn should_create_index_with_mappings_and_aliases() {
+        let state = setup_state();
+        let body = json!({
+            "mappings": { "properties": { "title": { "type": "text" } } },
+            "aliases": { "articles": { "is_write_index": true } }
+        });
+
+        let response = create_index(
+            Path("articles-v1".to_string()),
+            State(state.clone()),
+            Some(Json(body)),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let index = state.store.get_index("articles-v1").unwrap();
+        assert!(index.mapping.properties.contains_key("title"));
+        assert_eq!(
+            state.store.resolve_write_index("articles").unwrap(),
+            "articles-v1"
+        );
+
+        let response = create_index(Path("articles".to_string()), State(state), None)
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn should_get_index_settings() {
         let state = setup_state();
         let index = "settings-test".to_string();
-        state.store.create_index(index.clone(), Mapping::default());
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
 
         let response = get_settings(Path(index.clone()), State(state))
             .await
@@ -150,7 +243,10 @@ mod tests {
     async fn should_handle_mapping_lifecycle() {
         let state = setup_state();
         let index = "mapping-life".to_string();
-        state.store.create_index(index.clone(), Mapping::default());
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
 
         let new_mapping = json!({ "properties": { "field": { "type": "text" } } });
         let m: Mapping = serde_json::from_value(new_mapping).unwrap();
@@ -164,7 +260,10 @@ mod tests {
     async fn should_handle_delete_index() {
         let state = setup_state();
         let index = "to-delete".to_string();
-        state.store.create_index(index.clone(), Mapping::default());
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
 
         let response = delete_index(Path(index.clone()), State(state.clone()))
             .await
@@ -177,7 +276,10 @@ mod tests {
     async fn should_handle_head_index() {
         let state = setup_state();
         let index = "head-test".to_string();
-        state.store.create_index(index.clone(), Mapping::default());
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
 
         let status = check_index(Path(index), State(state)).await;
         assert_eq!(status, StatusCode::OK);
@@ -194,7 +296,10 @@ mod tests {
     async fn should_handle_refresh_as_noop_success() {
         let state = setup_state();
         let index = "refresh-test".to_string();
-        state.store.create_index(index.clone(), Mapping::default());
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
 
         let response = refresh(Path(index), State(state)).await.into_response();
         assert_eq!(response.status(), StatusCode::OK);
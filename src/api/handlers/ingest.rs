@@ -0,0 +1,104 @@
+use super::{matches_any, to_error};
+use crate::AppState;
+use crate::domain::ingest::Pipeline;
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+
+pub async fn put_pipeline(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<Value>,
+) -> Response {
+    match Pipeline::from_json(&body) {
+        Ok(pipeline) => match state.store.put_pipeline(&id, pipeline) {
+            Ok(()) => Json(json!({ "acknowledged": true })).into_response(),
+            Err(e) => to_error(StatusCode::INTERNAL_SERVER_ERROR, "exception", &e).into_response(),
+        },
+        Err(e) => to_error(StatusCode::BAD_REQUEST, "parse_exception", &e).into_response(),
+    }
+}
+
+pub async fn get_pipelines(State(state): State<Arc<AppState>>) -> Response {
+    render_pipelines(&state, None)
+}
+
+pub async fn get_pipeline(Path(id): Path<String>, State(state): State<Arc<AppState>>) -> Response {
+    render_pipelines(&state, Some(&id))
+}
+
+pub async fn delete_pipeline(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    match state.store.delete_pipeline(&id) {
+        Ok(true) => Json(json!({ "acknowledged": true })).into_response(),
+        Ok(false) => to_error(
+            StatusCode::NOT_FOUND,
+            "resource_not_found_exception",
+            &format!("pipeline [{}] is missing", id),
+        )
+        .into_response(),
+        Err(e) => to_error(StatusCode::INTERNAL_SERVER_ERROR, "exception", &e).into_response(),
+    }
+}
+
+fn render_pipelines(state: &AppState, id: Option<&str>) -> Response {
+    let mut matched: Vec<(String, Pipeline)> = state
+        .store
+        .pipelines()
+        .into_iter()
+        .filter(|(name, _)| id.is_none_or(|pattern| matches_any(pattern, name)))
+        .collect();
+    matched.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // Elasticsearch answers an unknown pipeline with a bare `{}` and a 404.
+    if id.is_some() && matched.is_empty() {
+        return (StatusCode::NOT_FOUND, Json(json!({}))).into_response();
+    }
+
+    let body: Map<String, Value> = matched
+        .into_iter()
+        .map(|(name, pipeline)| (name, pipeline.definition))
+        .collect();
+    Json(Value::Object(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::setup_state;
+
+    #[tokio::test]
+    async fn should_manage_pipelines() {
+        let state = setup_state();
+        let body = json!({ "processors": [{ "set": { "field": "a", "value": 1 } }] });
+
+        let response = put_pipeline(
+            Path("p1".to_string()),
+            State(state.clone()),
+            Json(body.clone()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.store.pipeline("p1").unwrap().definition, body);
+
+        let response = get_pipeline(Path("p*".to_string()), State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let invalid = json!({ "processors": [{ "unknown": {} }] });
+        let response =
+            put_pipeline(Path("p2".to_string()), State(state.clone()), Json(invalid)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = delete_pipeline(Path("p1".to_string()), State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = get_pipeline(Path("p1".to_string()), State(state)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,29 +1,108 @@
+pub mod admin;
+pub mod aliases;
+pub mod bulk;
+pub mod by_query;
 pub mod cluster;
+pub mod data_streams;
 pub mod documents;
 pub mod indices;
+pub mod ingest;
+pub mod reindex;
 pub mod search;
+pub mod snapshots;
+pub mod tasks;
+pub mod templates;
 
+use crate::api::responses::{ErrorResponse, create_error_response};
+use crate::domain::script::is_script_error;
+use crate::domain::source::wildcard_match;
+use crate::domain::versioning::is_version_conflict;
+use crate::repository::persistence::is_wal_failure;
+use crate::repository::store::IndicesOptions;
 use axum::Json;
 use axum::http::StatusCode;
-use crate::api::responses::{ErrorResponse, create_error_response};
+use std::collections::HashMap;
 
+/// Builds an error response. A change the write-ahead log could not take is
+/// a server-side failure whatever the request, so it is reported as a 500.
 fn to_error(
     status: StatusCode,
     error_type: &str,
     reason: &str,
 ) -> (StatusCode, Json<ErrorResponse>) {
+    let (status, error_type) = if is_wal_failure(reason) {
+        (StatusCode::INTERNAL_SERVER_ERROR, "exception")
+    } else {
+        (status, error_type)
+    };
     (
         status,
         Json(create_error_response(status.as_u16(), error_type, reason)),
     )
 }
 
+fn index_not_found(index: &str) -> (StatusCode, Json<ErrorResponse>) {
+    to_error(
+        StatusCode::NOT_FOUND,
+        "index_not_found_exception",
+        &format!("no such index [{}]", index),
+    )
+}
+
+/// Maps a failed document write to an error response, reporting optimistic
+/// concurrency failures as 409 conflicts and script failures as
+/// `script_exception`.
+fn write_error(reason: &str, error_type: &str) -> (StatusCode, Json<ErrorResponse>) {
+    if is_version_conflict(reason) {
+        to_error(
+            StatusCode::CONFLICT,
+            "version_conflict_engine_exception",
+            reason,
+        )
+    } else if reason.ends_with("document missing") {
+        to_error(StatusCode::NOT_FOUND, "document_missing_exception", reason)
+    } else if reason.contains("index_not_found") {
+        to_error(StatusCode::NOT_FOUND, "index_not_found_exception", reason)
+    } else if is_script_error(reason) {
+        to_error(StatusCode::BAD_REQUEST, "script_exception", reason)
+    } else {
+        to_error(StatusCode::BAD_REQUEST, error_type, reason)
+    }
+}
+
+/// Matches a name against a comma-separated list of names or wildcard
+/// patterns, as accepted by the alias and template APIs.
+fn matches_any(patterns: &str, name: &str) -> bool {
+    patterns
+        .split(',')
+        .map(|p| p.trim())
+        .any(|p| p == "_all" || wildcard_match(p, name))
+}
+
+fn param_flag(params: &HashMap<String, String>, name: &str) -> Option<bool> {
+    params.get(name).map(|v| v.is_empty() || v == "true")
+}
+
+fn indices_options(params: &HashMap<String, String>) -> IndicesOptions {
+    let defaults = IndicesOptions::default();
+    IndicesOptions {
+        ignore_unavailable: param_flag(params, "ignore_unavailable")
+            .unwrap_or(defaults.ignore_unavailable),
+        allow_no_indices: param_flag(params, "allow_no_indices")
+            .unwrap_or(defaults.allow_no_indices),
+    }
+}
+
 #[cfg(test)]
-fn setup_state() -> std::sync::Arc<crate::AppState> {
+pub(crate) fn setup_state() -> std::sync::Arc<crate::AppState> {
     std::sync::Arc::new(crate::AppState {
         store: crate::repository::store::InMemoryStore::new(),
-        auth_user: "elastic".to_string(),
-        auth_password: "".to_string(),
-        auth_enabled: false,
+        tasks: crate::repository::tasks::TaskRegistry::new(),
+        config: std::sync::Arc::new(crate::config::Config {
+            path: crate::config::PathConfig {
+                repo: vec![std::env::temp_dir()],
+            },
+            ..Default::default()
+        }),
     })
-}
\ No newline at end of file
+}
@@ -0,0 +1,394 @@
+use super::by_query::{
+    ByQueryCounts, Progress, matching_documents, max_docs, proceed_on_conflict, run_job,
+};
+use super::documents::DATA_STREAM_OP_TYPE;
+use super::{index_not_found, indices_options, to_error, write_error};
+use crate::AppState;
+use crate::domain::ingest::Pipeline;
+use crate::domain::query::{Query as SearchQuery, parse_query};
+use crate::domain::script::{Script, ScriptOp, UpdateContext};
+use crate::domain::source::SourceFilter;
+use crate::domain::update::UpdateKind;
+use crate::domain::versioning::WriteConditions;
+use crate::repository::store::{InMemoryStore, IndexTarget};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+const REINDEX_ACTION: &str = "indices:data/write/reindex";
+
+/// A `_reindex` request: copies matching documents of the source indices
+/// into `dest`, through the optional script and ingest pipeline.
+struct Reindex {
+    sources: Vec<IndexTarget>,
+    query: Box<dyn SearchQuery>,
+    source_filter: SourceFilter,
+    dest: String,
+    create_only: bool,
+    pipeline: Option<Pipeline>,
+    script: Option<Script>,
+    proceed_on_conflict: bool,
+    max_docs: Option<usize>,
+}
+
+impl Reindex {
+    fn run(&self, store: &InMemoryStore, progress: &Progress) -> ByQueryCounts {
+        let mut counts = ByQueryCounts::default();
+
+        for target in &self.sources {
+            for doc in matching_documents(store, target, self.query.as_ref()) {
+                if !progress.advance(&mut counts, self.max_docs) {
+                    return counts;
+                }
+
+                let mut context = UpdateContext {
+                    index: self.dest.clone(),
+                    id: doc.id.clone(),
+                    version: Some(doc.version.version),
+                    routing: doc.routing.clone(),
+                    source: self
+                        .source_filter
+                        .apply(&doc.source)
+                        .unwrap_or_else(|| json!({})),
+                    op: ScriptOp::Index,
+                };
+                let outcome = self.copy(store, &mut context);
+                match outcome {
+                    Ok(Some(UpdateKind::Created)) => counts.created += 1,
+                    Ok(Some(UpdateKind::Deleted)) => counts.deleted += 1,
+                    Ok(Some(UpdateKind::Noop)) => counts.noops += 1,
+                    Ok(Some(UpdateKind::Updated)) => counts.updated += 1,
+                    Ok(None) => {}
+                    Err(e) => {
+                        if !counts.record_failure(
+                            &context.index,
+                            &context.id,
+                            &e,
+                            self.proceed_on_conflict,
+                        ) {
+                            return counts;
+                        }
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    /// Copies one document, returning what happened to its destination.
+    fn copy(
+        &self,
+        store: &InMemoryStore,
+        context: &mut UpdateContext,
+    ) -> Result<Option<UpdateKind>, String> {
+        if let Some(script) = &self.script {
+            script.execute_update(context)?;
+        }
+        match context.op {
+            ScriptOp::Noop => return Ok(Some(UpdateKind::Noop)),
+            ScriptOp::Delete => {
+                let index = store.resolve_write_index(&context.index)?;
+                let removed =
+                    store.remove_document(&index, &context.id, &WriteConditions::default());
+                return Ok(removed?.found.then_some(UpdateKind::Deleted));
+            }
+            ScriptOp::Index => {}
+        }
+        if let Some(pipeline) = &self.pipeline {
+            pipeline.execute(&mut context.source)?;
+        }
+
+        store.ensure_index(&context.index)?;
+        // A destination auto-created above may be a data stream.
+        if !self.create_only && store.is_data_stream(&context.index) {
+            return Err(DATA_STREAM_OP_TYPE.to_string());
+        }
+        let index = store.resolve_write_index(&context.index)?;
+        let conditions = WriteConditions {
+            create: self.create_only,
+            ..WriteConditions::default()
+        };
+        let written = store.write_document(
+            &index,
+            Some(&context.id),
+            context.source.clone(),
+            context.routing.as_deref(),
+            &conditions,
+        )?;
+        Ok(Some(if written.created {
+            UpdateKind::Created
+        } else {
+            UpdateKind::Updated
+        }))
+    }
+}
+
+fn bad_request(reason: &str) -> Response {
+    to_error(
+        StatusCode::BAD_REQUEST,
+        "action_request_validation_exception",
+        reason,
+    )
+    .into_response()
+}
+
+pub async fn reindex(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<Value>,
+) -> Response {
+    let start = Instant::now();
+    let source = &body["source"];
+    let dest = &body["dest"];
+
+    let source_index = match &source["index"] {
+        Value::String(index) => index.clone(),
+        Value::Array(indices) => indices
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(","),
+        _ => {
+            return bad_request(
+                "Validation Failed: 1: use _all if you really want to copy from all existing indexes;",
+            );
+        }
+    };
+    let Some(dest_index) = dest["index"].as_str().map(str::to_string) else {
+        return bad_request("Validation Failed: 1: index must be specified;");
+    };
+    let create_only = match dest["op_type"].as_str() {
+        None | Some("index") => false,
+        Some("create") => true,
+        Some(other) => {
+            return bad_request(&format!(
+                "opType must be 'create' or 'index', found: [{}]",
+                other
+            ));
+        }
+    };
+    if state.store.is_data_stream(&dest_index) && !create_only {
+        return to_error(
+            StatusCode::BAD_REQUEST,
+            "illegal_argument_exception",
+            DATA_STREAM_OP_TYPE,
+        )
+        .into_response();
+    }
+
+    let sources = match state
+        .store
+        .resolve_indices(&source_index, indices_options(&params))
+    {
+        Ok(sources) => sources,
+        Err(missing) => return index_not_found(&missing).into_response(),
+    };
+    let dest_write_index = state.store.resolve_write_index(&dest_index).ok();
+    if let Some(target) = sources
+        .iter()
+        .find(|t| t.index == dest_index || Some(&t.index) == dest_write_index.as_ref())
+    {
+        return bad_request(&format!(
+            "Validation Failed: 1: reindex cannot write into an index its reading from [{}];",
+            target.index
+        ));
+    }
+
+    let pipeline = match dest["pipeline"].as_str() {
+        Some(id) => match state.store.pipeline(id) {
+            Some(pipeline) => Some(pipeline),
+            None => {
+                return to_error(
+                    StatusCode::BAD_REQUEST,
+                    "illegal_argument_exception",
+                    &format!("pipeline with id [{}] does not exist", id),
+                )
+                .into_response();
+            }
+        },
+        None => None,
+    };
+    let script = match body.get("script").map(Script::from_json).transpose() {
+        Ok(script) => script,
+        Err(e) => return write_error(&e, "script_exception").into_response(),
+    };
+    let (proceed_on_conflict, max_docs) = match proceed_on_conflict(&params, &body)
+        .and_then(|p| Ok((p, max_docs(&params, &body)?)))
+    {
+        Ok(options) => options,
+        Err(e) => {
+            return to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e)
+                .into_response();
+        }
+    };
+
+    let query = match parse_query(source) {
+        Ok(query) => query,
+        Err(e) => return write_error(&e, "script_exception").into_response(),
+    };
+    let description = format!("reindex from [{}] to [{}]", source_index, dest_index);
+    let job = Reindex {
+        sources,
+        query,
+        source_filter: source
+            .get("_source")
+            .map(SourceFilter::parse)
+            .unwrap_or_default(),
+        dest: dest_index,
+        create_only,
+        pipeline,
+        script,
+        proceed_on_conflict,
+        max_docs,
+    };
+    run_job(
+        &state,
+        &params,
+        REINDEX_ACTION,
+        description,
+        start,
+        true,
+        move |store, progress| job.run(store, progress),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::setup_state;
+    use crate::domain::mapping::Mapping;
+
+    fn setup_counters(state: &AppState, index: &str) {
+        state
+            .store
+            .create_index(index.to_string(), Mapping::default())
+            .unwrap();
+        for (id, count) in [("1", 1), ("2", 5), ("3", 10)] {
+            state
+                .store
+                .write_document(
+                    index,
+                    Some(id),
+                    json!({ "count": count, "kind": "counter" }),
+                    None,
+                    &WriteConditions::default(),
+                )
+                .unwrap();
+        }
+    }
+
+    async fn body_json(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_reindex_with_query_source_filter_and_script() {
+        let state = setup_state();
+        setup_counters(&state, "counters");
+
+        let body = json!({
+            "source": {
+                "index": "counters",
+                "query": { "bool": { "must_not": { "term": { "count": 10 } } } },
+                "_source": ["count"]
+            },
+            "dest": { "index": "copies" },
+            "script": "ctx._source.copied = true; if (ctx._id == '1') { ctx.op = 'noop' }"
+        });
+        let response = reindex(Query(HashMap::new()), State(state.clone()), Json(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = body_json(response).await;
+        assert_eq!(response["total"], 2);
+        assert_eq!(response["created"], 1);
+        assert_eq!(response["noops"], 1);
+        assert!(state.store.get_document("copies", "1").is_none());
+        assert_eq!(
+            state.store.get_document("copies", "2").unwrap(),
+            json!({ "count": 5, "copied": true })
+        );
+    }
+
+    #[tokio::test]
+    async fn should_honour_create_op_type_and_pipeline() {
+        let state = setup_state();
+        setup_counters(&state, "counters");
+        state
+            .store
+            .put_pipeline(
+                "tag",
+                Pipeline::from_json(&json!({
+                    "processors": [{ "set": { "field": "tagged", "value": true } }]
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        let body = |conflicts: &str| {
+            json!({
+                "conflicts": conflicts,
+                "source": { "index": "counters" },
+                "dest": { "index": "copies", "op_type": "create", "pipeline": "tag" }
+            })
+        };
+
+        let response = reindex(
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(body("abort")),
+        )
+        .await;
+        assert_eq!(body_json(response).await["created"], 3);
+        assert_eq!(
+            state.store.get_document("copies", "1").unwrap()["tagged"],
+            true
+        );
+
+        let response = reindex(
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(body("abort")),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = reindex(Query(HashMap::new()), State(state), Json(body("proceed"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_json(response).await["version_conflicts"], 3);
+    }
+
+    #[tokio::test]
+    async fn should_reject_missing_pipeline_and_self_copy() {
+        let state = setup_state();
+        setup_counters(&state, "counters");
+
+        let response = reindex(
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(json!({
+                "source": { "index": "counters" },
+                "dest": { "index": "copies", "pipeline": "missing" }
+            })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = reindex(
+            Query(HashMap::new()),
+            State(state),
+            Json(json!({ "source": { "index": "counters" }, "dest": { "index": "counters" } })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
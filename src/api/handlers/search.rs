@@ -1,11 +1,15 @@
-use super::to_error;
+use super::{index_not_found, indices_options, param_flag, to_error, write_error};
 use crate::AppState;
 use crate::api::responses::*;
-use crate::domain::engine::SearchEngine;
-use crate::domain::query::{parse_aggregations, parse_pagination, parse_query, parse_sort};
+use crate::domain::engine::{AggregationValue, IndexedDocument, SearchEngine, SortOptions};
+use crate::domain::query::{
+    MatchAllQuery, Query as SearchQuery, parse_aggregations, parse_filter, parse_pagination,
+    parse_query, parse_sort,
+};
+use crate::repository::store::IndexData;
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
 };
 use serde_json::Value;
@@ -15,101 +19,254 @@ use std::time::Instant;
 
 pub async fn count(
     Path(index): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
     State(state): State<Arc<AppState>>,
     Json(query_json): Json<Value>,
 ) -> Result<Json<CountResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let index_data = state.store.get_index(&index).ok_or_else(|| {
-        to_error(
-            StatusCode::NOT_FOUND,
-            "index_not_found_exception",
-            &format!("no such index [{}]", index),
-        )
-    })?;
-    let query = parse_query(&query_json);
-    let count = index_data
-        .documents
-        .iter()
-        .filter(|d| query.matches(d))
-        .count();
-    Ok(Json(CountResponse {
-        count,
-        _shards: ShardsInfo::default(),
-    }))
+    let indices = resolve_targets(&state, &index, &params).map_err(|e| index_not_found(&e))?;
+    let query = parse_query(&query_json).map_err(|e| write_error(&e, "script_exception"))?;
+    Ok(Json(execute_count(&indices, query.as_ref())))
+}
+
+pub async fn count_all(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    Json(query_json): Json<Value>,
+) -> Result<Json<CountResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let indices = resolve_targets(&state, "_all", &params).map_err(|e| index_not_found(&e))?;
+    let query = parse_query(&query_json).map_err(|e| write_error(&e, "script_exception"))?;
+    Ok(Json(execute_count(&indices, query.as_ref())))
 }
 
 pub async fn search(
     Path(index): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    Json(query_json): Json<Value>,
+) -> Result<Json<SearchResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let start = Instant::now();
+    if let Some(reason) = result_window_error(&state, &query_json) {
+        return Err(to_error(
+            StatusCode::BAD_REQUEST,
+            "illegal_argument_exception",
+            &reason,
+        ));
+    }
+    let indices = resolve_targets(&state, &index, &params).map_err(|e| index_not_found(&e))?;
+    let query = parse_query(&query_json).map_err(|e| write_error(&e, "script_exception"))?;
+    let sort = parse_sort(&query_json).map_err(|e| write_error(&e, "script_exception"))?;
+    Ok(Json(execute_search(
+        &indices,
+        &query_json,
+        query.as_ref(),
+        sort,
+        &params,
+        start,
+    )))
+}
+
+pub async fn search_all(
+    Query(params): Query<HashMap<String, String>>,
     State(state): State<Arc<AppState>>,
     Json(query_json): Json<Value>,
 ) -> Result<Json<SearchResponse>, (StatusCode, Json<ErrorResponse>)> {
     let start = Instant::now();
-    let index_data = state.store.get_index(&index).ok_or_else(|| {
-        to_error(
-            StatusCode::NOT_FOUND,
-            "index_not_found_exception",
-            &format!("no such index [{}]", index),
+    if let Some(reason) = result_window_error(&state, &query_json) {
+        return Err(to_error(
+            StatusCode::BAD_REQUEST,
+            "illegal_argument_exception",
+            &reason,
+        ));
+    }
+    let indices = resolve_targets(&state, "_all", &params).map_err(|e| index_not_found(&e))?;
+    let query = parse_query(&query_json).map_err(|e| write_error(&e, "script_exception"))?;
+    let sort = parse_sort(&query_json).map_err(|e| write_error(&e, "script_exception"))?;
+    Ok(Json(execute_search(
+        &indices,
+        &query_json,
+        query.as_ref(),
+        sort,
+        &params,
+        start,
+    )))
+}
+
+/// Why a page reaching past the configured `max_result_window` is rejected.
+fn result_window_error(state: &AppState, query_json: &Value) -> Option<String> {
+    let (from, size) = parse_pagination(query_json);
+    let window = state.config.limits.max_result_window;
+    let requested = from.saturating_add(size);
+    (requested > window).then(|| {
+        format!(
+            "Result window is too large, from + size must be less than or equal to: [{}] but \
+             was [{}]. See the scroll api for a more efficient way to request large data sets. \
+             This limit can be set by changing the [index.max_result_window] index level setting.",
+            window, requested
         )
-    })?;
+    })
+}
 
-    let query = parse_query(&query_json);
-    let sort = parse_sort(&query_json);
-    let (from, size) = parse_pagination(&query_json);
-    let agg_definitions = parse_aggregations(&query_json);
+struct SearchTarget {
+    name: String,
+    data: Arc<IndexData>,
+    alias_filter: Option<Box<dyn SearchQuery>>,
+}
 
-    let filtered_docs =
-        SearchEngine::search(&index_data.documents, query.as_ref(), sort, from, size);
-    let hits: Vec<SearchHit> = filtered_docs
+fn resolve_targets(
+    state: &AppState,
+    expression: &str,
+    params: &HashMap<String, String>,
+) -> Result<Vec<SearchTarget>, String> {
+    let targets = state
+        .store
+        .resolve_indices(expression, indices_options(params))?;
+    Ok(targets
+        .into_iter()
+        .filter_map(|target| {
+            state
+                .store
+                .get_index(&target.index)
+                .map(|data| SearchTarget {
+                    name: target.index,
+                    data,
+                    alias_filter: target.filter.as_ref().map(parse_filter),
+                })
+        })
+        .collect())
+}
+
+/// Documents of the targets matching `query`, planned against each index's
+/// postings.
+fn collect_documents<'a>(
+    targets: &'a [SearchTarget],
+    query: &dyn SearchQuery,
+) -> Vec<IndexedDocument<'a>> {
+    targets
         .iter()
-        .map(|doc| SearchHit {
-            _index: index.clone(),
-            _id: doc["_id"].as_str().unwrap_or("unknown").to_string(),
-            _score: 1.0,
-            _source: doc.clone(),
+        .flat_map(|target| {
+            target
+                .data
+                .documents
+                .matching(&target.name, query)
+                .into_iter()
+                .filter(|(_, doc)| {
+                    target
+                        .alias_filter
+                        .as_ref()
+                        .is_none_or(|f| f.matches_document(&target.name, doc))
+                })
+                .map(move |(position, doc)| IndexedDocument {
+                    index: target.name.as_str(),
+                    doc,
+                    position,
+                    values: target.data.documents.doc_values(),
+                })
+        })
+        .collect()
+}
+
+fn execute_count(indices: &[SearchTarget], query: &dyn SearchQuery) -> CountResponse {
+    let count = collect_documents(indices, query).len();
+    CountResponse {
+        count,
+        _shards: shards_for(indices.len()),
+    }
+}
+
+fn execute_search(
+    indices: &[SearchTarget],
+    query_json: &Value,
+    query: &dyn SearchQuery,
+    sort: Option<SortOptions>,
+    params: &HashMap<String, String>,
+    start: Instant,
+) -> SearchResponse {
+    let flag = |name: &str| {
+        query_json[name]
+            .as_bool()
+            .or_else(|| param_flag(params, name))
+    };
+    let with_seq_no = flag("seq_no_primary_term").unwrap_or(false);
+    let with_version = flag("version").unwrap_or(false);
+    let (from, size) = parse_pagination(query_json);
+    let agg_definitions = parse_aggregations(query_json);
+
+    let all_filtered = collect_documents(indices, query);
+
+    let page = SearchEngine::search(&all_filtered, &MatchAllQuery, sort, from, size);
+    let hits: Vec<SearchHit> = page
+        .iter()
+        .map(|hit| {
+            let version = hit.doc.version;
+            SearchHit {
+                _index: hit.index.to_string(),
+                _id: hit.doc.id.clone(),
+                _version: with_version.then_some(version.version),
+                _seq_no: with_seq_no.then_some(version.seq_no),
+                _primary_term: with_seq_no.then_some(version.primary_term),
+                _score: 1.0,
+                _routing: hit.doc.routing.clone(),
+                _source: hit.doc.source.clone(),
+            }
         })
         .collect();
 
     let mut aggregations = None;
     if !agg_definitions.is_empty() {
-        let all_filtered = index_data
-            .documents
-            .iter()
-            .filter(|d| query.matches(d))
-            .cloned()
-            .collect::<Vec<Value>>();
         let agg_results = SearchEngine::aggregate(&all_filtered, &agg_definitions);
         let mut map = HashMap::new();
         for res in agg_results {
-            map.insert(
-                res.name,
-                AggregationBuckets {
-                    buckets: res
-                        .buckets
-                        .into_iter()
-                        .map(|b| BucketResponse {
-                            key: b.key,
-                            doc_count: b.doc_count,
-                        })
-                        .collect(),
-                },
-            );
+            let response = match res.value {
+                AggregationValue::Buckets(buckets) => {
+                    AggregationResponse::Buckets(AggregationBuckets {
+                        buckets: buckets
+                            .into_iter()
+                            .map(|b| BucketResponse {
+                                key: b.key,
+                                doc_count: b.doc_count,
+                            })
+                            .collect(),
+                    })
+                }
+                AggregationValue::Metric(value) => {
+                    AggregationResponse::Metric(MetricValue { value })
+                }
+                AggregationValue::Stats(stats) => AggregationResponse::Stats(StatsResponse {
+                    count: stats.count,
+                    min: stats.min,
+                    max: stats.max,
+                    avg: stats.avg(),
+                    sum: stats.sum,
+                }),
+            };
+            map.insert(res.name, response);
         }
         aggregations = Some(map);
     }
 
-    Ok(Json(SearchResponse {
+    SearchResponse {
         took: start.elapsed().as_millis(),
         timed_out: false,
-        _shards: ShardsInfo::default(),
+        _shards: shards_for(indices.len()),
         hits: HitsMetadata {
             total: TotalHits {
-                value: index_data.documents.len(),
+                value: all_filtered.len(),
                 relation: "eq".to_string(),
             },
             max_score: if hits.is_empty() { None } else { Some(1.0) },
             hits,
         },
         aggregations,
-    }))
+    }
+}
+
+fn shards_for(index_count: usize) -> ShardsInfo {
+    let count = index_count as u32;
+    ShardsInfo {
+        total: count,
+        successful: count,
+        ..ShardsInfo::default()
+    }
 }
 
 #[cfg(test)]
@@ -117,13 +274,17 @@ mod tests {
     use super::*;
     use crate::api::handlers::setup_state;
     use crate::domain::mapping::Mapping;
+    use crate::domain::versioning::WriteConditions;
     use serde_json::json;
 
     #[tokio::test]
     async fn should_search_with_aggregations() {
         let state = setup_state();
         let index = "search-agg".to_string();
-        state.store.create_index(index.clone(), Mapping::default());
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
         state
             .store
             .add_document(&index, json!({ "category": "A" }))
@@ -138,25 +299,305 @@ mod tests {
             .unwrap();
 
         let query = json!({ "aggs": { "cats": { "terms": { "field": "category" } } } });
-        let Json(response) = search(Path(index), State(state), Json(query))
-            .await
-            .unwrap();
+        let Json(response) = search(
+            Path(index),
+            Query(HashMap::new()),
+            State(state),
+            Json(query),
+        )
+        .await
+        .unwrap();
 
         let aggs = response.aggregations.as_ref().unwrap();
-        let buckets = &aggs["cats"].buckets;
-        assert_eq!(buckets.len(), 2);
+        let AggregationResponse::Buckets(cats) = &aggs["cats"] else {
+            panic!("expected buckets");
+        };
+        assert_eq!(cats.buckets.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn should_reject_script_queries_that_do_not_compile() {
+        let state = setup_state();
+        state
+            .store
+            .create_index("scripted".to_string(), Mapping::default())
+            .unwrap();
+
+        let queries = [
+            json!({ "query": { "script": { "script": "doc['n'].value > " } } }),
+            json!({ "sort": [{ "_script": { "script": "doc['n'].value * ", "order": "desc" } }] }),
+        ];
+        for query in queries {
+            let Err((status, Json(error))) = search(
+                Path("scripted".to_string()),
+                Query(HashMap::new()),
+                State(state.clone()),
+                Json(query),
+            )
+            .await
+            else {
+                panic!("expected a compile error");
+            };
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(error.error.r#type, "script_exception");
+        }
+    }
+
+    #[tokio::test]
+    async fn should_sort_and_aggregate_on_typed_doc_values() {
+        let state = setup_state();
+        let index = "typed".to_string();
+        let mapping: Mapping =
+            serde_json::from_value(json!({ "properties": { "price": { "type": "double" } } }))
+                .unwrap();
+        state.store.create_index(index.clone(), mapping).unwrap();
+        for (price, tags) in [
+            (9.5, json!(["a", "b"])),
+            (10.0, json!("b")),
+            (2.0, json!([])),
+        ] {
+            state
+                .store
+                .add_document(&index, json!({ "price": price, "tags": tags }))
+                .unwrap();
+        }
+
+        let query = json!({
+            "sort": [{ "price": { "order": "desc" } }],
+            "size": 2,
+            "aggs": {
+                "tags": { "terms": { "field": "tags.keyword" } },
+                "total": { "sum": { "field": "price" } },
+                "prices": { "stats": { "field": "price" } },
+                "tagged": { "value_count": { "field": "tags" } }
+            }
+        });
+        let Json(response) = search(
+            Path(index),
+            Query(HashMap::new()),
+            State(state),
+            Json(query),
+        )
+        .await
+        .unwrap();
+
+        let prices: Vec<&Value> = response
+            .hits
+            .hits
+            .iter()
+            .map(|h| &h._source["price"])
+            .collect();
+        assert_eq!(prices, vec![&json!(10.0), &json!(9.5)]);
+        let aggs = serde_json::to_value(response.aggregations.unwrap()).unwrap();
+        assert_eq!(
+            aggs["tags"]["buckets"],
+            json!([{ "key": "b", "doc_count": 2 }, { "key": "a", "doc_count": 1 }])
+        );
+        assert_eq!(aggs["total"], json!({ "value": 21.5 }));
+        assert_eq!(
+            aggs["prices"],
+            json!({ "count": 3, "min": 2.0, "max": 10.0, "avg": 21.5 / 3.0, "sum": 21.5 })
+        );
+        assert_eq!(aggs["tagged"], json!({ "value": 3 }));
     }
 
     #[tokio::test]
     async fn should_count_documents() {
         let state = setup_state();
         let index = "count-test".to_string();
-        state.store.create_index(index.clone(), Mapping::default());
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
         state.store.add_document(&index, json!({ "v": 1 })).unwrap();
         state.store.add_document(&index, json!({ "v": 2 })).unwrap();
 
         let query = json!({ "query": { "term": { "v": 1 } } });
-        let Json(response) = count(Path(index), State(state), Json(query)).await.unwrap();
+        let Json(response) = count(
+            Path(index),
+            Query(HashMap::new()),
+            State(state),
+            Json(query),
+        )
+        .await
+        .unwrap();
         assert_eq!(response.count, 1);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn should_search_across_multiple_indices() {
+        let state = setup_state();
+        for (index, status) in [("logs-a", "ok"), ("logs-b", "ok"), ("logs-old", "ok")] {
+            state
+                .store
+                .create_index(index.to_string(), Mapping::default())
+                .unwrap();
+            state
+                .store
+                .add_document(index, json!({ "status": status }))
+                .unwrap();
+        }
+
+        let query = json!({ "query": { "term": { "status": "ok" } } });
+        let Json(response) = search(
+            Path("logs-*,-logs-old".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(query),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.hits.total.value, 2);
+        let mut indices: Vec<&str> = response
+            .hits
+            .hits
+            .iter()
+            .map(|h| h._index.as_str())
+            .collect();
+        indices.sort();
+        assert_eq!(indices, vec!["logs-a", "logs-b"]);
+
+        let Json(response) =
+            count_all(Query(HashMap::new()), State(state.clone()), Json(json!({})))
+                .await
+                .unwrap();
+        assert_eq!(response.count, 3);
+
+        let past_window = json!({ "from": 9_995, "size": 10 });
+        let error = search_all(Query(HashMap::new()), State(state), Json(past_window))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_query_and_sort_on_metadata_fields() {
+        let state = setup_state();
+        for index in ["users-a", "users-b"] {
+            state
+                .store
+                .create_index(index.to_string(), Mapping::default())
+                .unwrap();
+        }
+        for (index, id) in [("users-a", "1"), ("users-a", "2"), ("users-b", "3")] {
+            state
+                .store
+                .write_document(
+                    index,
+                    Some(id),
+                    json!({ "name": id }),
+                    None,
+                    &WriteConditions::default(),
+                )
+                .unwrap();
+        }
+        let ids = |response: &SearchResponse| -> Vec<String> {
+            response.hits.hits.iter().map(|h| h._id.clone()).collect()
+        };
+
+        for (query, expected) in [
+            (json!({ "term": { "_id": "2" } }), vec!["2"]),
+            (json!({ "ids": { "values": ["1", "3"] } }), vec!["1", "3"]),
+            (json!({ "terms": { "_index": ["users-b"] } }), vec!["3"]),
+            (
+                json!({ "bool": { "must_not": { "term": { "_id": 1 } } } }),
+                vec!["2", "3"],
+            ),
+        ] {
+            let Json(response) = search(
+                Path("users-*".to_string()),
+                Query(HashMap::new()),
+                State(state.clone()),
+                Json(json!({ "query": query, "sort": "_id" })),
+            )
+            .await
+            .unwrap();
+            assert_eq!(ids(&response), expected, "{}", query);
+        }
+
+        let sorted = json!({ "sort": [{ "_id": "desc" }] });
+        let Json(response) = search(
+            Path("users-*".to_string()),
+            Query(HashMap::new()),
+            State(state),
+            Json(sorted),
+        )
+        .await
+        .unwrap();
+        assert_eq!(ids(&response), vec!["3", "2", "1"]);
+    }
+
+    #[tokio::test]
+    async fn should_filter_and_sort_with_scripts() {
+        let state = setup_state();
+        let index = "scripted".to_string();
+        state
+            .store
+            .create_index(index.clone(), Mapping::default())
+            .unwrap();
+        for (price, qty) in [(10, 1), (3, 5), (7, 0)] {
+            state
+                .store
+                .add_document(&index, json!({ "price": price, "qty": qty }))
+                .unwrap();
+        }
+
+        let query = json!({
+            "query": { "bool": { "filter": { "script": { "script": {
+                "source": "doc['qty'].value > params.min",
+                "params": { "min": 0 }
+            } } } } },
+            "sort": [{ "_script": {
+                "type": "number",
+                "script": "doc['price'].value * doc['qty'].value",
+                "order": "desc"
+            } }]
+        });
+        let Json(response) = search(
+            Path(index),
+            Query(HashMap::new()),
+            State(state),
+            Json(query),
+        )
+        .await
+        .unwrap();
+
+        let prices: Vec<&Value> = response
+            .hits
+            .hits
+            .iter()
+            .map(|h| &h._source["price"])
+            .collect();
+        assert_eq!(prices, vec![&json!(3), &json!(10)]);
+    }
+
+    #[tokio::test]
+    async fn should_respect_ignore_unavailable() {
+        let state = setup_state();
+        state
+            .store
+            .create_index("present".to_string(), Mapping::default())
+            .unwrap();
+
+        let result = search(
+            Path("present,missing".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(json!({})),
+        )
+        .await;
+        assert_eq!(result.err().unwrap().0, StatusCode::NOT_FOUND);
+
+        let params = HashMap::from([("ignore_unavailable".to_string(), "true".to_string())]);
+        let result = search(
+            Path("present,missing".to_string()),
+            Query(params),
+            State(state),
+            Json(json!({})),
+        )
+        .await;
+        assert!(result.is_ok());
+    }
+}
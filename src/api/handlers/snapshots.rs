@@ -0,0 +1,909 @@
+use super::{index_not_found, matches_any, param_flag, to_error};
+use crate::AppState;
+use crate::domain::ingest::Pipeline;
+use crate::domain::snapshot::{
+    RepositoryDefinition, RestoreRequest, SnapshotInfo, SnapshotRequest, SnapshotShards,
+    validate_snapshot_name, version_id,
+};
+use crate::domain::time::{format_timestamp, now_millis};
+use crate::repository::persistence::StoreSnapshot;
+use crate::repository::snapshots::FsRepository;
+use crate::repository::store::{AliasAction, IndicesOptions};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub async fn put_repository(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<Value>,
+) -> Response {
+    let definition = match RepositoryDefinition::from_json(&name, &body) {
+        Ok(definition) => definition,
+        Err(e) if e.starts_with("Validation Failed") => {
+            return to_error(
+                StatusCode::BAD_REQUEST,
+                "action_request_validation_exception",
+                &e,
+            )
+            .into_response();
+        }
+        Err(e) => {
+            return to_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "repository_exception",
+                &e,
+            )
+            .into_response();
+        }
+    };
+    let location = match definition.resolve_location(&name, &state.config.path.repo) {
+        Ok(location) => location,
+        Err(e) => {
+            return to_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "repository_exception",
+                &e,
+            )
+            .into_response();
+        }
+    };
+    if let Err(e) = FsRepository::new(location).verify() {
+        return to_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "repository_verification_exception",
+            &format!("[{}] cannot write to the repository location {}", name, e),
+        )
+        .into_response();
+    }
+    match state.store.put_snapshot_repository(&name, definition) {
+        Ok(()) => Json(json!({ "acknowledged": true })).into_response(),
+        Err(e) => to_error(StatusCode::INTERNAL_SERVER_ERROR, "exception", &e).into_response(),
+    }
+}
+
+pub async fn get_repositories(State(state): State<Arc<AppState>>) -> Response {
+    render_repositories(&state, "_all")
+}
+
+pub async fn get_repository(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    render_repositories(&state, &name)
+}
+
+fn render_repositories(state: &AppState, names: &str) -> Response {
+    let mut matched: Vec<(String, RepositoryDefinition)> = state
+        .store
+        .snapshot_repositories()
+        .into_iter()
+        .filter(|(name, _)| matches_any(names, name))
+        .collect();
+    matched.sort_by(|a, b| a.0.cmp(&b.0));
+
+    if let Some(missing) =
+        explicit_names(names).find(|name| !matched.iter().any(|(matched, _)| matched == name))
+    {
+        return repository_missing(missing);
+    }
+
+    let body: Map<String, Value> = matched
+        .into_iter()
+        .map(|(name, definition)| (name, json!(definition)))
+        .collect();
+    Json(Value::Object(body)).into_response()
+}
+
+pub async fn delete_repository(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let matched: Vec<String> = state
+        .store
+        .snapshot_repositories()
+        .into_keys()
+        .filter(|repository| matches_any(&name, repository))
+        .collect();
+    if matched.is_empty() {
+        return repository_missing(&name);
+    }
+    // Like Elasticsearch, only the registration goes; the snapshots stay on
+    // disk and show up again when the location is registered anew.
+    for repository in matched {
+        if let Err(e) = state.store.delete_snapshot_repository(&repository) {
+            return to_error(StatusCode::INTERNAL_SERVER_ERROR, "exception", &e).into_response();
+        }
+    }
+    Json(json!({ "acknowledged": true })).into_response()
+}
+
+pub async fn create_snapshot(
+    Path((repository, snapshot)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    body: Option<Json<Value>>,
+) -> Response {
+    let request = SnapshotRequest::from_json(&body.map_or(Value::Null, |Json(b)| b));
+    if let Err(reason) = validate_snapshot_name(&snapshot) {
+        return invalid_snapshot_name(&repository, &snapshot, &reason);
+    }
+    let fs = match open_repository(&state, &repository) {
+        Ok(fs) => fs,
+        Err(response) => return *response,
+    };
+    match fs.snapshot(&snapshot) {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            return invalid_snapshot_name(
+                &repository,
+                &snapshot,
+                "snapshot with the same name already exists",
+            );
+        }
+        Err(e) => return repository_error(&repository, &e),
+    }
+
+    let options = IndicesOptions {
+        ignore_unavailable: request.ignore_unavailable,
+        allow_no_indices: true,
+    };
+    let mut indices: Vec<String> = match state.store.resolve_indices(&request.indices, options) {
+        Ok(targets) => targets.into_iter().map(|target| target.index).collect(),
+        Err(missing) => return index_not_found(&missing).into_response(),
+    };
+    indices.sort();
+
+    let start = now_millis();
+    let store = snapshot_contents(
+        state.store.snapshot(),
+        &indices,
+        request.include_global_state,
+    );
+    let mut data_streams: Vec<String> = store.data_streams.keys().cloned().collect();
+    data_streams.sort();
+    let info = SnapshotInfo {
+        snapshot: snapshot.clone(),
+        uuid: uuid::Uuid::new_v4().simple().to_string(),
+        repository: repository.clone(),
+        version_id: version_id(&state.config.cluster.version),
+        version: state.config.cluster.version.clone(),
+        shards: SnapshotShards {
+            total: indices.len(),
+            failed: 0,
+            successful: indices.len(),
+        },
+        indices,
+        data_streams,
+        include_global_state: request.include_global_state,
+        metadata: request.metadata,
+        state: "SUCCESS".to_string(),
+        start_time: format_timestamp(start),
+        start_time_in_millis: start,
+        end_time: String::new(),
+        end_time_in_millis: 0,
+        duration_in_millis: 0,
+        failures: Vec::new(),
+        feature_states: Vec::new(),
+    };
+
+    let written = tokio::task::spawn_blocking(move || fs.write(info, store)).await;
+    match written {
+        Ok(Ok(info)) if param_flag(&params, "wait_for_completion") == Some(true) => {
+            Json(json!({ "snapshot": info })).into_response()
+        }
+        Ok(Ok(_)) => Json(json!({ "accepted": true })).into_response(),
+        Ok(Err(e)) => repository_error(&repository, &e),
+        Err(e) => repository_error(&repository, &e.to_string()),
+    }
+}
+
+/// Narrows a snapshot of the whole store down to `indices`, the aliases and
+/// data streams that only point at them and, with `include_global_state`,
+/// the templates and pipelines.
+fn snapshot_contents(
+    mut store: StoreSnapshot,
+    indices: &[String],
+    include_global_state: bool,
+) -> StoreSnapshot {
+    let selected = |index: &String| indices.binary_search(index).is_ok();
+    store.indices.retain(|name, _| selected(name));
+    for members in store.aliases.values_mut() {
+        members.retain(|index, _| selected(index));
+    }
+    store.aliases.retain(|_, members| !members.is_empty());
+    store
+        .data_streams
+        .retain(|_, stream| stream.indices.iter().all(selected));
+    if !include_global_state {
+        store.index_templates.clear();
+        store.component_templates.clear();
+        store.pipelines.clear();
+    }
+    store.snapshot_repositories.clear();
+    store
+}
+
+pub async fn get_snapshots(
+    Path((repository, names)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let fs = match open_repository(&state, &repository) {
+        Ok(fs) => fs,
+        Err(response) => return *response,
+    };
+    let snapshots = match fs.snapshots() {
+        Ok(snapshots) => snapshots,
+        Err(e) => return repository_error(&repository, &e),
+    };
+    // Snapshots complete before the request that creates them returns, so
+    // none is ever in progress.
+    let matched: Vec<SnapshotInfo> = snapshots
+        .into_iter()
+        .filter(|info| names != "_current" && matches_any(&names, &info.snapshot))
+        .collect();
+
+    if param_flag(&params, "ignore_unavailable") != Some(true)
+        && let Some(missing) = explicit_names(&names)
+            .find(|name| *name != "_current" && !matched.iter().any(|s| s.snapshot == *name))
+    {
+        return snapshot_missing(&repository, missing);
+    }
+
+    Json(json!({
+        "snapshots": matched,
+        "total": matched.len(),
+        "remaining": 0
+    }))
+    .into_response()
+}
+
+pub async fn delete_snapshot(
+    Path((repository, names)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let fs = match open_repository(&state, &repository) {
+        Ok(fs) => fs,
+        Err(response) => return *response,
+    };
+    let snapshots = match fs.snapshots() {
+        Ok(snapshots) => snapshots,
+        Err(e) => return repository_error(&repository, &e),
+    };
+    let matched: Vec<SnapshotInfo> = snapshots
+        .into_iter()
+        .filter(|info| matches_any(&names, &info.snapshot))
+        .collect();
+    if let Some(missing) =
+        explicit_names(&names).find(|name| !matched.iter().any(|info| info.snapshot == *name))
+    {
+        return snapshot_missing(&repository, missing);
+    }
+
+    for info in &matched {
+        if let Err(e) = fs.delete(info) {
+            return repository_error(&repository, &e);
+        }
+    }
+    Json(json!({ "acknowledged": true })).into_response()
+}
+
+pub async fn snapshot_status(
+    Path((repository, names)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let fs = match open_repository(&state, &repository) {
+        Ok(fs) => fs,
+        Err(response) => return *response,
+    };
+    let mut statuses = Vec::new();
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let info = match fs.snapshot(name) {
+            Ok(Some(info)) => info,
+            Ok(None) => return snapshot_missing(&repository, name),
+            Err(e) => return repository_error(&repository, &e),
+        };
+        let sizes = fs.index_sizes(&info);
+        let total_size: u64 = sizes.iter().map(|(_, size)| size).sum();
+        let indices: Map<String, Value> = sizes
+            .into_iter()
+            .map(|(index, size)| {
+                let stats = transfer_stats(1, size, &info);
+                let body = json!({
+                    "shards_stats": shards_stats(1),
+                    "stats": stats,
+                    "shards": { "0": { "stage": "DONE", "stats": stats } }
+                });
+                (index, body)
+            })
+            .collect();
+        statuses.push(json!({
+            "snapshot": info.snapshot,
+            "repository": info.repository,
+            "uuid": info.uuid,
+            "state": info.state,
+            "include_global_state": info.include_global_state,
+            "shards_stats": shards_stats(info.indices.len()),
+            "stats": transfer_stats(info.indices.len(), total_size, &info),
+            "indices": indices
+        }));
+    }
+    Json(json!({ "snapshots": statuses })).into_response()
+}
+
+/// `GET /_snapshot/_status`: snapshots currently running, which is never
+/// any since they complete within the create request.
+pub async fn running_snapshots() -> Response {
+    Json(json!({ "snapshots": [] })).into_response()
+}
+
+pub async fn running_repository_snapshots(
+    Path(repository): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    match open_repository(&state, &repository) {
+        Ok(_) => running_snapshots().await,
+        Err(response) => *response,
+    }
+}
+
+fn shards_stats(done: usize) -> Value {
+    json!({
+        "initializing": 0,
+        "started": 0,
+        "finalizing": 0,
+        "done": done,
+        "failed": 0,
+        "total": done
+    })
+}
+
+fn transfer_stats(file_count: usize, size: u64, info: &SnapshotInfo) -> Value {
+    let files = json!({ "file_count": file_count, "size_in_bytes": size });
+    json!({
+        "incremental": files,
+        "total": files,
+        "start_time_in_millis": info.start_time_in_millis,
+        "time_in_millis": info.duration_in_millis
+    })
+}
+
+pub async fn restore_snapshot(
+    Path((repository, snapshot)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    body: Option<Json<Value>>,
+) -> Response {
+    let request = match RestoreRequest::from_json(&body.map_or(Value::Null, |Json(b)| b)) {
+        Ok(request) => request,
+        Err(e) => {
+            return to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e)
+                .into_response();
+        }
+    };
+    let fs = match open_repository(&state, &repository) {
+        Ok(fs) => fs,
+        Err(response) => return *response,
+    };
+    let info = match fs.snapshot(&snapshot) {
+        Ok(Some(info)) => info,
+        Ok(None) => return snapshot_missing(&repository, &snapshot),
+        Err(e) => return repository_error(&repository, &e),
+    };
+    let restore_error = |reason: &str| {
+        to_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "snapshot_restore_exception",
+            &format!("[{}:{}/{}] {}", repository, snapshot, info.uuid, reason),
+        )
+        .into_response()
+    };
+
+    let read = tokio::task::spawn_blocking({
+        let info = info.clone();
+        let request = request.clone();
+        move || -> Result<_, String> {
+            let global = fs.read_global(&info.snapshot)?;
+            // A selected data stream brings along all of its backing indices.
+            let streams: Vec<_> = global
+                .data_streams
+                .values()
+                .filter(|stream| request.selects(&stream.name))
+                .cloned()
+                .collect();
+            let mut indices = Vec::new();
+            for index in &info.indices {
+                let in_stream = streams.iter().any(|s| s.indices.contains(index));
+                if in_stream || request.selects(index) {
+                    indices.push((index.clone(), fs.read_index(&info, index)?));
+                }
+            }
+            Ok((global, streams, indices))
+        }
+    })
+    .await;
+    let (global, streams, indices) = match read {
+        Ok(Ok(read)) => read,
+        Ok(Err(e)) => return restore_error(&e),
+        Err(e) => return restore_error(&e.to_string()),
+    };
+
+    if !request.ignore_unavailable
+        && let Some(missing) = request.indices.as_deref().and_then(|names| {
+            explicit_names(names).find(|name| {
+                !info.indices.iter().any(|index| index == name)
+                    && !streams.iter().any(|stream| stream.name == *name)
+            })
+        })
+    {
+        return index_not_found(missing).into_response();
+    }
+
+    let selected: Vec<String> = indices.iter().map(|(name, _)| name.clone()).collect();
+    let restored: Vec<String> = selected.iter().map(|name| request.renamed(name)).collect();
+    let renamed_indices = indices
+        .into_iter()
+        .map(|(name, mut index)| {
+            index
+                .settings
+                .set("uuid", &uuid::Uuid::new_v4().simple().to_string());
+            (request.renamed(&name), index)
+        })
+        .collect();
+    let renamed_streams = streams
+        .into_iter()
+        .map(|mut stream| {
+            stream.name = request.renamed(&stream.name);
+            stream.indices = stream.indices.iter().map(|i| request.renamed(i)).collect();
+            stream
+        })
+        .collect();
+    if let Err(e) = state
+        .store
+        .restore_indices(renamed_indices, renamed_streams)
+    {
+        return restore_error(&e);
+    }
+
+    if request.include_aliases {
+        let request = &request;
+        let actions: Vec<AliasAction> = global
+            .aliases
+            .into_iter()
+            .flat_map(|(alias, members)| {
+                members
+                    .into_iter()
+                    .filter(|(index, _)| selected.contains(index))
+                    .map(move |(index, definition)| AliasAction::Add {
+                        index: request.renamed(&index),
+                        alias: alias.clone(),
+                        definition,
+                    })
+            })
+            .collect();
+        if !actions.is_empty()
+            && let Err(e) = state.store.update_aliases(actions)
+        {
+            return restore_error(&e);
+        }
+    }
+
+    if request.include_global_state {
+        for (name, template) in global.component_templates {
+            if let Err(e) = state.store.put_component_template(&name, template) {
+                return restore_error(&e);
+            }
+        }
+        for (name, template) in global.index_templates {
+            if let Err(e) = state.store.put_index_template(&name, template) {
+                return restore_error(&e);
+            }
+        }
+        for (id, definition) in global.pipelines {
+            let stored = Pipeline::from_json(&definition)
+                .and_then(|pipeline| state.store.put_pipeline(&id, pipeline));
+            if let Err(e) = stored {
+                return restore_error(&e);
+            }
+        }
+    }
+
+    if param_flag(&params, "wait_for_completion") != Some(true) {
+        return Json(json!({ "accepted": true })).into_response();
+    }
+    Json(json!({
+        "snapshot": {
+            "snapshot": snapshot,
+            "indices": restored,
+            "shards": SnapshotShards {
+                total: restored.len(),
+                failed: 0,
+                successful: restored.len(),
+            }
+        }
+    }))
+    .into_response()
+}
+
+/// Names in a comma-separated list that are neither wildcards nor `_all`;
+/// those must exist.
+fn explicit_names(names: &str) -> impl Iterator<Item = &str> {
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && *name != "_all" && !name.contains('*'))
+        .filter(|name| !name.starts_with('-'))
+}
+
+/// The registered repository, provided its location still falls under
+/// `path.repo`: a definition may also arrive through `_fake/load` or a data
+/// directory written under another configuration.
+fn open_repository(state: &AppState, name: &str) -> Result<FsRepository, Box<Response>> {
+    let definition = state
+        .store
+        .snapshot_repository(name)
+        .ok_or_else(|| Box::new(repository_missing(name)))?;
+    definition
+        .resolve_location(name, &state.config.path.repo)
+        .map(FsRepository::new)
+        .map_err(|e| {
+            Box::new(
+                to_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "repository_exception",
+                    &e,
+                )
+                .into_response(),
+            )
+        })
+}
+
+fn repository_missing(name: &str) -> Response {
+    to_error(
+        StatusCode::NOT_FOUND,
+        "repository_missing_exception",
+        &format!("[{}] missing", name),
+    )
+    .into_response()
+}
+
+fn snapshot_missing(repository: &str, snapshot: &str) -> Response {
+    to_error(
+        StatusCode::NOT_FOUND,
+        "snapshot_missing_exception",
+        &format!("[{}:{}] is missing", repository, snapshot),
+    )
+    .into_response()
+}
+
+fn invalid_snapshot_name(repository: &str, snapshot: &str, reason: &str) -> Response {
+    to_error(
+        StatusCode::BAD_REQUEST,
+        "invalid_snapshot_name_exception",
+        &format!(
+            "[{}:{}] Invalid snapshot name [{}], {}",
+            repository, snapshot, snapshot, reason
+        ),
+    )
+    .into_response()
+}
+
+fn repository_error(repository: &str, reason: &str) -> Response {
+    to_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "repository_exception",
+        &format!("[{}] {}", repository, reason),
+    )
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::setup_state;
+    use crate::domain::mapping::Mapping;
+
+    async fn body_json(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn path(repository: &str, snapshot: &str) -> Path<(String, String)> {
+        Path((repository.to_string(), snapshot.to_string()))
+    }
+
+    fn wait() -> Query<HashMap<String, String>> {
+        Query(HashMap::from([(
+            "wait_for_completion".to_string(),
+            "true".to_string(),
+        )]))
+    }
+
+    /// Registers the `backups` repository over the given indices, each
+    /// holding one document, and snapshots all of them as `nightly`.
+    async fn setup_snapshot(state: &Arc<AppState>, indices: &[&str]) -> std::path::PathBuf {
+        let location = std::env::temp_dir().join(format!("es_fake-{}", uuid::Uuid::new_v4()));
+        for index in indices {
+            state
+                .store
+                .create_index(index.to_string(), Mapping::default())
+                .unwrap();
+            state
+                .store
+                .add_document(index, json!({ "message": index }))
+                .unwrap();
+        }
+        let response = put_repository(
+            Path("backups".to_string()),
+            State(state.clone()),
+            Json(json!({ "type": "fs", "settings": { "location": location } })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = create_snapshot(
+            path("backups", "nightly"),
+            wait(),
+            State(state.clone()),
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        location
+    }
+
+    #[tokio::test]
+    async fn should_snapshot_and_restore_renamed_indices() {
+        let state = setup_state();
+        let location = std::env::temp_dir().join(format!("es_fake-{}", uuid::Uuid::new_v4()));
+        for index in ["logs-1", "metrics"] {
+            state
+                .store
+                .create_index(index.to_string(), Mapping::default())
+                .unwrap();
+            state
+                .store
+                .add_document(index, json!({ "message": index }))
+                .unwrap();
+        }
+
+        let response = put_repository(
+            Path("backups".to_string()),
+            State(state.clone()),
+            Json(json!({ "type": "fs", "settings": { "location": location } })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = create_snapshot(
+            path("backups", "nightly"),
+            wait(),
+            State(state.clone()),
+            Some(Json(json!({ "indices": ["logs-*"] }))),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_json(response).await["snapshot"]["indices"],
+            json!(["logs-1"])
+        );
+
+        let response = create_snapshot(
+            path("backups", "nightly"),
+            wait(),
+            State(state.clone()),
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = restore_snapshot(
+            path("backups", "nightly"),
+            wait(),
+            State(state.clone()),
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = json!({ "rename_pattern": "logs-(.+)", "rename_replacement": "copy-$1" });
+        let response = restore_snapshot(
+            path("backups", "nightly"),
+            wait(),
+            State(state.clone()),
+            Some(Json(body)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_json(response).await["snapshot"]["indices"],
+            json!(["copy-1"])
+        );
+        let restored = state.store.get_index("copy-1").unwrap();
+        assert_eq!(restored.documents.len(), 1);
+
+        let response = delete_snapshot(path("backups", "nightly"), State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = get_snapshots(
+            path("backups", "nightly"),
+            Query(HashMap::new()),
+            State(state),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        std::fs::remove_dir_all(&location).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_delete_snapshots() {
+        let state = setup_state();
+        let location = setup_snapshot(&state, &["logs-1"]).await;
+        let response = create_snapshot(
+            path("backups", "weekly"),
+            wait(),
+            State(state.clone()),
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = delete_snapshot(path("backups", "missing"), State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            body_json(response).await["error"]["type"],
+            "snapshot_missing_exception"
+        );
+        let response = delete_snapshot(path("unknown", "nightly"), State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = delete_snapshot(path("backups", "nightly"), State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = get_snapshots(
+            path("backups", "_all"),
+            Query(HashMap::new()),
+            State(state.clone()),
+        )
+        .await;
+        let snapshots = body_json(response).await["snapshots"].clone();
+        assert_eq!(snapshots.as_array().unwrap().len(), 1);
+        assert_eq!(snapshots[0]["snapshot"], "weekly");
+        let response = snapshot_status(path("backups", "weekly"), State(state.clone())).await;
+        let status = body_json(response).await["snapshots"][0].clone();
+        assert!(status["stats"]["total"]["size_in_bytes"].as_u64().unwrap() > 0);
+
+        for name in [".", ".."] {
+            let response =
+                create_snapshot(path("backups", name), wait(), State(state.clone()), None).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        std::fs::remove_dir_all(&location).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_confine_repository_locations_to_path_repo() {
+        let state = setup_state();
+        let response = put_repository(
+            Path("escape".to_string()),
+            State(state.clone()),
+            Json(json!({ "type": "fs", "settings": { "location": "/etc/es_fake" } })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            body_json(response).await["error"]["type"],
+            "repository_exception"
+        );
+        assert!(state.store.snapshot_repository("escape").is_none());
+
+        let relative = format!("es_fake-{}", uuid::Uuid::new_v4());
+        let response = put_repository(
+            Path("backups".to_string()),
+            State(state.clone()),
+            Json(json!({ "type": "fs", "settings": { "location": relative } })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let location = std::env::temp_dir().join(&relative);
+        assert!(location.is_dir());
+        std::fs::remove_dir_all(&location).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_report_snapshot_status() {
+        let state = setup_state();
+        let location = setup_snapshot(&state, &["logs-1", "metrics"]).await;
+
+        let response = snapshot_status(path("backups", "nightly"), State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let status = body_json(response).await["snapshots"][0].clone();
+        assert_eq!(status["snapshot"], "nightly");
+        assert_eq!(status["repository"], "backups");
+        assert_eq!(status["state"], "SUCCESS");
+        assert_eq!(status["shards_stats"]["done"], 2);
+        assert_eq!(status["shards_stats"]["failed"], 0);
+        assert!(status["stats"]["total"]["size_in_bytes"].as_u64().unwrap() > 0);
+        let indices = status["indices"].as_object().unwrap();
+        assert_eq!(indices.len(), 2);
+        assert_eq!(indices["logs-1"]["shards"]["0"]["stage"], "DONE");
+
+        let response = snapshot_status(path("backups", "nightly,missing"), State(state)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            body_json(running_snapshots().await).await,
+            json!({ "snapshots": [] })
+        );
+        std::fs::remove_dir_all(&location).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_restore_selected_indices_under_new_names() {
+        let state = setup_state();
+        let location = setup_snapshot(&state, &["logs-1", "logs-2", "metrics"]).await;
+
+        let body = json!({
+            "indices": "logs-*,-logs-2",
+            "rename_pattern": "logs-(.+)",
+            "rename_replacement": "restored-$1"
+        });
+        let response = restore_snapshot(
+            path("backups", "nightly"),
+            wait(),
+            State(state.clone()),
+            Some(Json(body)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let restore = body_json(response).await["snapshot"].clone();
+        assert_eq!(restore["indices"], json!(["restored-1"]));
+        assert_eq!(restore["shards"]["successful"], 1);
+        let restored = state.store.get_index("restored-1").unwrap();
+        assert_eq!(restored.documents.len(), 1);
+        assert!(state.store.get_index("restored-2").is_none());
+
+        let body = json!({ "indices": "missing" });
+        let response = restore_snapshot(
+            path("backups", "nightly"),
+            wait(),
+            State(state),
+            Some(Json(body)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        std::fs::remove_dir_all(&location).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_refuse_to_restore_onto_an_existing_open_index() {
+        let state = setup_state();
+        let location = setup_snapshot(&state, &["logs-1", "logs-2"]).await;
+        state.store.delete_index("logs-2").unwrap();
+
+        let response = restore_snapshot(
+            path("backups", "nightly"),
+            wait(),
+            State(state.clone()),
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let error = body_json(response).await["error"].clone();
+        assert_eq!(error["type"], "snapshot_restore_exception");
+        let reason = error["reason"].as_str().unwrap();
+        assert!(
+            reason.contains("cannot restore index [logs-1] because an open index with same name"),
+            "{}",
+            reason
+        );
+        // Nothing is restored when one of the indices is taken.
+        assert!(state.store.get_index("logs-2").is_none());
+        std::fs::remove_dir_all(&location).unwrap();
+    }
+}
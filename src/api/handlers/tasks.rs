@@ -0,0 +1,189 @@
+use super::{matches_any, param_flag, to_error};
+use crate::AppState;
+use crate::repository::tasks::{TaskInfo, TaskRegistry};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+fn render_task(registry: &TaskRegistry, task: &TaskInfo, detailed: bool) -> Value {
+    let mut rendered = json!({
+        "node": registry.node_id(),
+        "id": task.id,
+        "type": "transport",
+        "action": task.action
+    });
+    if detailed {
+        if let Some(status) = &task.status {
+            rendered["status"] = status.clone();
+        }
+        rendered["description"] = json!(task.description);
+    }
+    for (key, value) in [
+        ("start_time_in_millis", json!(task.start_time_millis)),
+        ("running_time_in_nanos", json!(task.running_time_nanos)),
+        ("cancellable", json!(true)),
+        ("cancelled", json!(task.cancelled)),
+        ("headers", json!({})),
+    ] {
+        rendered[key] = value;
+    }
+    rendered
+}
+
+/// Groups tasks the way `_tasks` does by default: under their node.
+fn render_nodes(state: &AppState, tasks: &[TaskInfo], detailed: bool) -> Value {
+    let registry = &state.tasks;
+    let rendered: Map<String, Value> = tasks
+        .iter()
+        .map(|task| {
+            (
+                registry.task_id(task.id),
+                render_task(registry, task, detailed),
+            )
+        })
+        .collect();
+    if rendered.is_empty() {
+        return json!({ "nodes": {} });
+    }
+    json!({
+        "nodes": {
+            registry.node_id(): {
+                "name": state.config.cluster.node_name,
+                "transport_address": "127.0.0.1:9300",
+                "host": "127.0.0.1",
+                "ip": "127.0.0.1:9300",
+                "roles": ["data", "ingest", "master"],
+                "tasks": rendered
+            }
+        }
+    })
+}
+
+fn task_error(e: String) -> Response {
+    if e.starts_with("malformed") {
+        return to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e).into_response();
+    }
+    to_error(StatusCode::NOT_FOUND, "resource_not_found_exception", &e).into_response()
+}
+
+pub async fn list_tasks(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let tasks = state.tasks.list(|action| {
+        params
+            .get("actions")
+            .is_none_or(|patterns| matches_any(patterns, action))
+    });
+    let detailed = param_flag(&params, "detailed").unwrap_or(false);
+
+    match params.get("group_by").map(String::as_str) {
+        None | Some("nodes") => Json(render_nodes(&state, &tasks, detailed)).into_response(),
+        Some("none") => {
+            let rendered: Vec<Value> = tasks
+                .iter()
+                .map(|task| render_task(&state.tasks, task, detailed))
+                .collect();
+            Json(json!({ "tasks": rendered })).into_response()
+        }
+        Some("parents") => {
+            let rendered: Map<String, Value> = tasks
+                .iter()
+                .map(|task| {
+                    (
+                        state.tasks.task_id(task.id),
+                        render_task(&state.tasks, task, detailed),
+                    )
+                })
+                .collect();
+            Json(json!({ "tasks": rendered })).into_response()
+        }
+        Some(other) => to_error(
+            StatusCode::BAD_REQUEST,
+            "illegal_argument_exception",
+            &format!("No enum constant GroupBy.{}", other.to_uppercase()),
+        )
+        .into_response(),
+    }
+}
+
+pub async fn get_task(Path(task_id): Path<String>, State(state): State<Arc<AppState>>) -> Response {
+    let task = match state.tasks.get(&task_id) {
+        Ok(task) => task,
+        Err(e) => return task_error(e),
+    };
+
+    let mut body = json!({
+        "completed": task.completed(),
+        "task": render_task(&state.tasks, &task, true)
+    });
+    if let Some(response) = &task.response {
+        body["response"] = response.clone();
+    }
+    if let Some(error) = &task.error {
+        body["error"] = error.clone();
+    }
+    Json(body).into_response()
+}
+
+pub async fn cancel_task(
+    Path(task_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    match state.tasks.cancel(&task_id) {
+        Ok(task) => Json(render_nodes(&state, &[task], false)).into_response(),
+        Err(e) => task_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::setup_state;
+
+    async fn body_json(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_list_and_cancel_running_tasks() {
+        let state = setup_state();
+        let id = state
+            .tasks
+            .register("indices:data/write/reindex", "reindex".to_string());
+        state
+            .tasks
+            .register("indices:data/write/update/byquery", "ubq".to_string());
+        let task_id = state.tasks.task_id(id);
+
+        let params = HashMap::from([
+            ("actions".to_string(), "*reindex".to_string()),
+            ("group_by".to_string(), "none".to_string()),
+            ("detailed".to_string(), "true".to_string()),
+        ]);
+        let listed = body_json(list_tasks(Query(params), State(state.clone())).await).await;
+        assert_eq!(listed["tasks"].as_array().unwrap().len(), 1);
+        assert_eq!(listed["tasks"][0]["description"], "reindex");
+
+        let response = cancel_task(Path(task_id.clone()), State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let cancelled = body_json(response).await;
+        let node = &cancelled["nodes"][state.tasks.node_id()];
+        assert_eq!(node["tasks"][&task_id]["cancelled"], true);
+
+        state.tasks.complete(id, None);
+        let response = cancel_task(Path(task_id), State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = get_task(Path("garbage".to_string()), State(state)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,349 @@
+use super::{matches_any, param_flag, to_error};
+use crate::AppState;
+use crate::domain::template::{ComponentTemplate, IndexTemplate};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub async fn put_index_template(
+    Path(name): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<Value>,
+) -> Response {
+    let template: IndexTemplate = match serde_json::from_value(body) {
+        Ok(template) => template,
+        Err(e) => {
+            return to_error(
+                StatusCode::BAD_REQUEST,
+                "x_content_parse_exception",
+                &e.to_string(),
+            )
+            .into_response();
+        }
+    };
+
+    if param_flag(&params, "create").unwrap_or(false)
+        && state.store.index_templates().contains_key(&name)
+    {
+        return to_error(
+            StatusCode::BAD_REQUEST,
+            "illegal_argument_exception",
+            &format!("index template [{}] already exists", name),
+        )
+        .into_response();
+    }
+
+    let components = state.store.component_templates();
+    let missing: Vec<&str> = template
+        .composed_of
+        .iter()
+        .filter(|c| !components.contains_key(*c))
+        .map(|c| c.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return to_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_index_template_exception",
+            &format!(
+                "index_template [{}] invalid, cause [index template [{}] specifies component \
+                 templates {:?} that do not exist]",
+                name, name, missing
+            ),
+        )
+        .into_response();
+    }
+
+    match state.store.put_index_template(&name, template) {
+        Ok(()) => Json(json!({ "acknowledged": true })).into_response(),
+        Err(e) => {
+            to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e).into_response()
+        }
+    }
+}
+
+pub async fn get_index_templates(State(state): State<Arc<AppState>>) -> Response {
+    render_index_templates(&state, None)
+}
+
+pub async fn get_index_template(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    render_index_templates(&state, Some(&name))
+}
+
+pub async fn check_index_template(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> StatusCode {
+    if state
+        .store
+        .index_templates()
+        .keys()
+        .any(|template| matches_any(&name, template))
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+pub async fn delete_index_template(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    match state.store.delete_index_template(&name) {
+        Ok(true) => Json(json!({ "acknowledged": true })).into_response(),
+        Ok(false) => to_error(
+            StatusCode::NOT_FOUND,
+            "resource_not_found_exception",
+            &format!("index_template matching [{}] not found", name),
+        )
+        .into_response(),
+        Err(e) => to_error(StatusCode::INTERNAL_SERVER_ERROR, "exception", &e).into_response(),
+    }
+}
+
+pub async fn put_component_template(
+    Path(name): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<Value>,
+) -> Response {
+    let template: ComponentTemplate = match serde_json::from_value(body) {
+        Ok(template) => template,
+        Err(e) => {
+            return to_error(
+                StatusCode::BAD_REQUEST,
+                "x_content_parse_exception",
+                &e.to_string(),
+            )
+            .into_response();
+        }
+    };
+
+    if param_flag(&params, "create").unwrap_or(false)
+        && state.store.component_templates().contains_key(&name)
+    {
+        return to_error(
+            StatusCode::BAD_REQUEST,
+            "illegal_argument_exception",
+            &format!("component template [{}] already exists", name),
+        )
+        .into_response();
+    }
+
+    match state.store.put_component_template(&name, template) {
+        Ok(()) => Json(json!({ "acknowledged": true })).into_response(),
+        Err(e) => to_error(StatusCode::INTERNAL_SERVER_ERROR, "exception", &e).into_response(),
+    }
+}
+
+pub async fn get_component_templates(State(state): State<Arc<AppState>>) -> Response {
+    render_component_templates(&state, None)
+}
+
+pub async fn get_component_template(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    render_component_templates(&state, Some(&name))
+}
+
+pub async fn check_component_template(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> StatusCode {
+    if state
+        .store
+        .component_templates()
+        .keys()
+        .any(|template| matches_any(&name, template))
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+pub async fn delete_component_template(
+    Path(name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    match state.store.delete_component_template(&name) {
+        Ok(true) => Json(json!({ "acknowledged": true })).into_response(),
+        Ok(false) => to_error(
+            StatusCode::NOT_FOUND,
+            "resource_not_found_exception",
+            &format!("component_template matching [{}] not found", name),
+        )
+        .into_response(),
+        Err(e) => {
+            to_error(StatusCode::BAD_REQUEST, "illegal_argument_exception", &e).into_response()
+        }
+    }
+}
+
+fn render_index_templates(state: &AppState, name: Option<&str>) -> Response {
+    let mut templates: Vec<(String, IndexTemplate)> = state
+        .store
+        .index_templates()
+        .into_iter()
+        .filter(|(template, _)| name.is_none_or(|pattern| matches_any(pattern, template)))
+        .collect();
+    templates.sort_by(|a, b| a.0.cmp(&b.0));
+
+    if let Some(name) = name
+        && templates.is_empty()
+        && !name.contains('*')
+    {
+        return to_error(
+            StatusCode::NOT_FOUND,
+            "resource_not_found_exception",
+            &format!("index template matching [{}] not found", name),
+        )
+        .into_response();
+    }
+
+    let entries: Vec<Value> = templates
+        .into_iter()
+        .map(|(name, template)| json!({ "name": name, "index_template": template }))
+        .collect();
+    Json(json!({ "index_templates": entries })).into_response()
+}
+
+fn render_component_templates(state: &AppState, name: Option<&str>) -> Response {
+    let mut templates: Vec<(String, ComponentTemplate)> = state
+        .store
+        .component_templates()
+        .into_iter()
+        .filter(|(template, _)| name.is_none_or(|pattern| matches_any(pattern, template)))
+        .collect();
+    templates.sort_by(|a, b| a.0.cmp(&b.0));
+
+    if let Some(name) = name
+        && templates.is_empty()
+        && !name.contains('*')
+    {
+        return to_error(
+            StatusCode::NOT_FOUND,
+            "resource_not_found_exception",
+            &format!("component template matching [{}] not found", name),
+        )
+        .into_response();
+    }
+
+    let entries: Vec<Value> = templates
+        .into_iter()
+        .map(|(name, template)| json!({ "name": name, "component_template": template }))
+        .collect();
+    Json(json!({ "component_templates": entries })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::setup_state;
+
+    #[tokio::test]
+    async fn should_apply_composed_template_on_index_creation() {
+        let state = setup_state();
+        let component = json!({
+            "template": {
+                "settings": { "number_of_shards": 2 },
+                "mappings": { "properties": { "host": { "type": "keyword" } } }
+            }
+        });
+        let response = put_component_template(
+            Path("base".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(component),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let template = json!({
+            "index_patterns": ["logs-*"],
+            "priority": 10,
+            "composed_of": ["base"],
+            "template": {
+                "mappings": { "properties": { "message": { "type": "text" } } },
+                "aliases": { "logs": {} }
+            }
+        });
+        let response = put_index_template(
+            Path("logs".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(template),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        state.store.ensure_index("logs-app").unwrap();
+
+        let index = state.store.get_index("logs-app").unwrap();
+        assert!(index.mapping.properties.contains_key("host"));
+        assert!(index.mapping.properties.contains_key("message"));
+        assert_eq!(index.settings.get("number_of_shards"), Some("2"));
+        assert!(state.store.is_alias("logs"));
+    }
+
+    #[tokio::test]
+    async fn should_reject_missing_component_and_component_in_use() {
+        let state = setup_state();
+        let template = json!({ "index_patterns": ["a-*"], "composed_of": ["missing"] });
+        let response = put_index_template(
+            Path("a".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(template),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        put_component_template(
+            Path("c".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(json!({ "template": {} })),
+        )
+        .await;
+        put_index_template(
+            Path("a".to_string()),
+            Query(HashMap::new()),
+            State(state.clone()),
+            Json(json!({ "index_patterns": ["a-*"], "composed_of": ["c"] })),
+        )
+        .await;
+
+        let response = delete_component_template(Path("c".to_string()), State(state)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_reject_overlapping_templates_with_same_priority() {
+        let state = setup_state();
+        for (name, status) in [("one", StatusCode::OK), ("two", StatusCode::BAD_REQUEST)] {
+            let response = put_index_template(
+                Path(name.to_string()),
+                Query(HashMap::new()),
+                State(state.clone()),
+                Json(json!({ "index_patterns": ["logs-*"] })),
+            )
+            .await;
+            assert_eq!(response.status(), status);
+        }
+
+        let response = get_index_template(Path("one".to_string()), State(state)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -1,5 +1,5 @@
 use axum::{
-    body::{Body, Bytes},
+    body::Body,
     http::{Request, Response, StatusCode},
     middleware::Next,
 };
@@ -47,19 +47,20 @@ pub async fn debug_log(req: Request<Body>, next: Next) -> Result<Response<Body>,
     Ok(Response::from_parts(parts, Body::from(res_bytes)))
 }
 
-async fn buffer_body(body: Body) -> Result<Bytes, StatusCode> {
-    body.collect()
-        .await
-        .map(|c| c.to_bytes())
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use axum::body::Bytes;
     use axum::middleware::from_fn;
     use tower::{Layer, Service, ServiceExt};
 
+    async fn buffer_body(body: Body) -> Result<Bytes, StatusCode> {
+        body.collect()
+            .await
+            .map(|c| c.to_bytes())
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
     async fn handle_request(
         req: Request<Body>,
     ) -> Result<Response<Body>, std::convert::Infallible> {
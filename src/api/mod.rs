@@ -1,4 +1,6 @@
 pub mod auth;
 pub mod handlers;
 pub mod logging;
+pub mod namespaces;
 pub mod responses;
+pub mod seed;
@@ -0,0 +1,493 @@
+use crate::AppState;
+use crate::api::auth::{is_admin, is_authenticated, unauthorized};
+use crate::api::handlers::admin;
+use crate::api::responses::create_error_response;
+use crate::repository::store::InMemoryStore;
+use crate::repository::tasks::TaskRegistry;
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::State,
+    http::{Method, Request, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+};
+use dashmap::DashMap;
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+
+/// Header selecting the namespace a request runs against.
+pub const NAMESPACE_HEADER: &str = "x-fake-namespace";
+
+/// Isolated clusters selected per request by the `X-Fake-Namespace` header.
+/// Each namespace is created by the first authenticated request that may
+/// write to it, with its own empty store and task registry, up to
+/// `limits.max_namespaces`; requests without the header go to the default
+/// namespace, the only one backed by the data directory and seed data.
+pub struct Namespaces {
+    default: Arc<AppState>,
+    default_router: Router,
+    tenants: DashMap<String, Tenant>,
+    /// Held while a namespace is created, so the limit is never overshot.
+    creating: Mutex<()>,
+    build: fn(Arc<AppState>) -> Router,
+}
+
+struct Tenant {
+    state: Arc<AppState>,
+    router: Router,
+}
+
+impl Namespaces {
+    /// `build` creates the REST API of a namespace around its state.
+    pub fn new(default: Arc<AppState>, build: fn(Arc<AppState>) -> Router) -> Self {
+        Self {
+            default_router: build(default.clone()),
+            default,
+            tenants: DashMap::new(),
+            creating: Mutex::new(()),
+            build,
+        }
+    }
+
+    /// Names of the namespaces created so far, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tenants.iter().map(|t| t.key().clone()).collect();
+        names.sort();
+        names
+    }
+
+    /// Number of indices of a namespace.
+    pub fn index_count(&self, namespace: &str) -> Option<usize> {
+        self.tenants
+            .get(namespace)
+            .map(|t| t.state.store.index_names().len())
+    }
+
+    /// Drops a namespace with everything stored in it; the next request
+    /// naming it starts from an empty cluster again.
+    pub fn remove(&self, namespace: &str) -> bool {
+        self.tenants.remove(namespace).is_some()
+    }
+
+    /// The API of a namespace. A namespace that does not exist yet is only
+    /// created when `create` is set; otherwise the request runs against an
+    /// empty cluster that is thrown away afterwards.
+    fn router(&self, namespace: &str, create: bool) -> Result<Router, String> {
+        if let Some(tenant) = self.tenants.get(namespace) {
+            return Ok(tenant.router.clone());
+        }
+        if !create {
+            return Ok((self.build)(self.new_state()));
+        }
+        let _creating = self.creating.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(tenant) = self.tenants.get(namespace) {
+            return Ok(tenant.router.clone());
+        }
+        let limit = self.default.config.limits.max_namespaces;
+        if self.tenants.len() >= limit {
+            return Err(format!(
+                "cannot create namespace [{}], the limit of [{}] namespaces is reached",
+                namespace, limit
+            ));
+        }
+        let state = self.new_state();
+        let router = (self.build)(state.clone());
+        self.tenants.insert(
+            namespace.to_string(),
+            Tenant {
+                router: router.clone(),
+                state,
+            },
+        );
+        Ok(router)
+    }
+
+    fn new_state(&self) -> Arc<AppState> {
+        Arc::new(AppState {
+            store: InMemoryStore::new(),
+            tasks: TaskRegistry::new(),
+            config: self.default.config.clone(),
+        })
+    }
+}
+
+/// Routes the namespace admin endpoints, when the admin API is enabled and
+/// an admin password is set, and hands every other request to the namespace
+/// named by its header.
+pub fn router(namespaces: Arc<Namespaces>) -> Router {
+    let mut router = Router::new();
+    let config = &namespaces.default.config;
+    if config.features.admin_api && config.auth.admin_password.is_some() {
+        router = router
+            .route("/_fake/namespaces", get(admin::get_namespaces))
+            .route(
+                "/_fake/namespaces/{namespace}",
+                delete(admin::delete_namespace),
+            )
+            .route_layer(middleware::from_fn_with_state(
+                namespaces.default.clone(),
+                crate::api::auth::admin_auth,
+            ));
+    }
+    router.fallback(dispatch).with_state(namespaces)
+}
+
+async fn dispatch(State(namespaces): State<Arc<Namespaces>>, req: Request<Body>) -> Response {
+    let namespace = match req.headers().get(NAMESPACE_HEADER) {
+        Some(value) => match value.to_str().ok().filter(|name| is_valid(name)) {
+            Some(name) => Some(name.to_string()),
+            None => {
+                let reason = format!(
+                    "invalid [{}] header: a namespace is 1 to 64 letters, digits, '-', '_' or '.'",
+                    NAMESPACE_HEADER
+                );
+                return bad_request("illegal_argument_exception", &reason);
+            }
+        },
+        None => None,
+    };
+    let router = match namespace {
+        None => namespaces.default_router.clone(),
+        Some(namespace) => {
+            // Nothing is allocated for a caller the namespace's own API would
+            // turn away; the admin credential counts for the `/_fake`
+            // endpoints.
+            let auth = &namespaces.default.config.auth;
+            if !is_authenticated(auth, &req) && !is_admin(auth, &req) {
+                return unauthorized(&req).into_response();
+            }
+            let create = !matches!(*req.method(), Method::GET | Method::HEAD);
+            match namespaces.router(&namespace, create) {
+                Ok(router) => router,
+                Err(reason) => return bad_request("validation_exception", &reason),
+            }
+        }
+    };
+    match router.oneshot(req).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    }
+}
+
+fn bad_request(error_type: &str, reason: &str) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(create_error_response(400, error_type, reason)),
+    )
+        .into_response()
+}
+
+fn is_valid(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::setup_state;
+    use crate::config::{AuthConfig, Config, LimitsConfig};
+    use axum::http::header;
+    use base64::{Engine as _, engine::general_purpose};
+
+    fn setup_namespaces(admin_password: Option<&str>) -> Arc<Namespaces> {
+        let state = Arc::new(AppState {
+            store: InMemoryStore::new(),
+            tasks: TaskRegistry::new(),
+            config: Arc::new(Config {
+                auth: AuthConfig {
+                    admin_password: admin_password.map(|p| p.to_string()),
+                    ..AuthConfig::default()
+                },
+                ..Config::default()
+            }),
+        });
+        Arc::new(Namespaces::new(state, crate::app))
+    }
+
+    async fn send(
+        namespaces: &Arc<Namespaces>,
+        method: &str,
+        uri: &str,
+        namespace: Option<&str>,
+    ) -> StatusCode {
+        send_json(namespaces, method, uri, namespace, None).await.0
+    }
+
+    async fn send_json(
+        namespaces: &Arc<Namespaces>,
+        method: &str,
+        uri: &str,
+        namespace: Option<&str>,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let mut req = Request::builder().method(method).uri(uri);
+        if let Some(namespace) = namespace {
+            req = req.header(NAMESPACE_HEADER, namespace);
+        }
+        if uri.starts_with("/_fake") {
+            let credentials = general_purpose::STANDARD.encode("admin:secret");
+            req = req.header(header::AUTHORIZATION, format!("Basic {}", credentials));
+        }
+        let body = match body {
+            Some(body) => {
+                req = req.header(header::CONTENT_TYPE, "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        let response = router(namespaces.clone())
+            .oneshot(req.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn should_isolate_namespaces_selected_by_header() {
+        let namespaces = setup_namespaces(Some("secret"));
+
+        assert_eq!(
+            send(&namespaces, "PUT", "/orders", Some("suite-a")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&namespaces, "HEAD", "/orders", Some("suite-a")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&namespaces, "HEAD", "/orders", Some("suite-b")).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(&namespaces, "HEAD", "/orders", None).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(&namespaces, "HEAD", "/orders", Some("a/b")).await,
+            StatusCode::BAD_REQUEST
+        );
+        // Reading from a namespace does not create it.
+        assert_eq!(namespaces.names(), vec!["suite-a"]);
+
+        let dropped = send(&namespaces, "DELETE", "/_fake/namespaces/suite-a", None).await;
+        assert_eq!(dropped, StatusCode::OK);
+        assert_eq!(
+            send(&namespaces, "HEAD", "/orders", Some("suite-a")).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(&namespaces, "DELETE", "/_fake/namespaces/missing", None).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn should_create_namespaces_only_for_authenticated_callers_within_the_limit() {
+        let state = Arc::new(AppState {
+            store: InMemoryStore::new(),
+            tasks: TaskRegistry::new(),
+            config: Arc::new(Config {
+                auth: AuthConfig {
+                    password: Some("pw".to_string()),
+                    ..AuthConfig::default()
+                },
+                limits: LimitsConfig {
+                    max_namespaces: 2,
+                    ..LimitsConfig::default()
+                },
+                ..Config::default()
+            }),
+        });
+        let namespaces = Arc::new(Namespaces::new(state, crate::app));
+        let put = |namespace: &str, credential: Option<&str>| {
+            let mut req = Request::builder()
+                .method("PUT")
+                .uri("/orders")
+                .header(NAMESPACE_HEADER, namespace);
+            if let Some(credential) = credential {
+                let encoded = general_purpose::STANDARD.encode(credential);
+                req = req.header(header::AUTHORIZATION, format!("Basic {}", encoded));
+            }
+            router(namespaces.clone()).oneshot(req.body(Body::empty()).unwrap())
+        };
+
+        for credential in [None, Some("elastic:wrong")] {
+            let response = put("suite-a", credential).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        assert!(namespaces.names().is_empty());
+
+        for namespace in ["suite-a", "suite-b"] {
+            let response = put(namespace, Some("elastic:pw")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", namespace);
+        }
+        let response = put("suite-c", Some("elastic:pw")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"]["type"], "validation_exception");
+        assert_eq!(namespaces.names(), vec!["suite-a", "suite-b"]);
+
+        // Dropping a namespace makes room for another.
+        assert!(namespaces.remove("suite-a"));
+        let response = put("suite-c", Some("elastic:pw")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn should_serve_the_admin_api_only_with_an_admin_password() {
+        let open = Arc::new(Namespaces::new(setup_state(), crate::app));
+        assert_eq!(
+            send(&open, "GET", "/_fake/namespaces", None).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(&open, "POST", "/_fake/reset", None).await,
+            StatusCode::NOT_FOUND
+        );
+
+        let mut config = Config {
+            auth: AuthConfig {
+                admin_password: Some("secret".to_string()),
+                ..AuthConfig::default()
+            },
+            ..Config::default()
+        };
+        config.features.admin_api = false;
+        let disabled = Arc::new(Namespaces::new(
+            Arc::new(AppState {
+                store: InMemoryStore::new(),
+                tasks: TaskRegistry::new(),
+                config: Arc::new(config),
+            }),
+            crate::app,
+        ));
+        for (method, uri) in [
+            ("GET", "/_fake/namespaces"),
+            ("DELETE", "/_fake/namespaces/suite-a"),
+            ("POST", "/_fake/reset"),
+        ] {
+            assert_eq!(
+                send(&disabled, method, uri, None).await,
+                StatusCode::NOT_FOUND,
+                "{}",
+                uri
+            );
+        }
+
+        let guarded = setup_namespaces(Some("other"));
+        assert_eq!(
+            send(&guarded, "GET", "/_fake/namespaces", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(&guarded, "POST", "/_fake/reset", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn should_keep_documents_within_their_namespace() {
+        let namespaces = setup_namespaces(None);
+        let doc = |title: &str| Some(serde_json::json!({ "title": title }));
+        let match_all = || Some(serde_json::json!({ "query": { "match_all": {} } }));
+
+        let (status, _) = send_json(
+            &namespaces,
+            "PUT",
+            "/orders/_doc/1?refresh=true",
+            Some("suite-a"),
+            doc("from a"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, body) =
+            send_json(&namespaces, "GET", "/orders/_doc/1", Some("suite-a"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["_source"]["title"], "from a");
+        for namespace in [Some("suite-b"), None] {
+            let (status, _) =
+                send_json(&namespaces, "GET", "/orders/_doc/1", namespace, None).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{:?}", namespace);
+            let (_, body) = send_json(&namespaces, "POST", "/_count", namespace, match_all()).await;
+            assert_eq!(body["count"], 0, "{:?}", namespace);
+        }
+
+        // The same index and id in the default namespace are a separate document.
+        let (status, _) = send_json(
+            &namespaces,
+            "PUT",
+            "/orders/_doc/1",
+            None,
+            doc("from default"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, body) =
+            send_json(&namespaces, "GET", "/orders/_doc/1", Some("suite-a"), None).await;
+        assert_eq!(body["_source"]["title"], "from a");
+        assert_eq!(body["_version"], 1);
+        let (_, body) = send_json(
+            &namespaces,
+            "POST",
+            "/_search",
+            Some("suite-a"),
+            match_all(),
+        )
+        .await;
+        assert_eq!(body["hits"]["total"]["value"], 1);
+        assert_eq!(
+            namespaces.default.store.get_document("orders", "1"),
+            Some(serde_json::json!({ "title": "from default" }))
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_invalid_namespace_headers() {
+        let namespaces = setup_namespaces(None);
+        let too_long = "a".repeat(65);
+        let longest = "a".repeat(64);
+
+        for namespace in ["", too_long.as_str(), "a/b", "a b", "suite:a", "ünïcode"] {
+            let (status, body) =
+                send_json(&namespaces, "PUT", "/orders", Some(namespace), None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{:?}", namespace);
+            assert_eq!(body["error"]["type"], "illegal_argument_exception");
+            assert!(
+                body["error"]["reason"]
+                    .as_str()
+                    .unwrap()
+                    .contains(NAMESPACE_HEADER),
+                "{:?}",
+                body
+            );
+        }
+        assert!(namespaces.names().is_empty());
+        assert!(namespaces.default.store.index_names().is_empty());
+
+        for namespace in [longest.as_str(), "a", "Suite_1.v-2"] {
+            let (status, _) = send_json(
+                &namespaces,
+                "PUT",
+                "/orders",
+                Some(namespace),
+                Some(serde_json::json!({})),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{:?}", namespace);
+        }
+        assert_eq!(namespaces.names().len(), 3);
+    }
+}
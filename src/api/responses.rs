@@ -20,9 +20,11 @@ pub struct VersionInfo {
 pub struct IndexResponse {
     pub _index: String,
     pub _id: String,
+    pub _version: u64,
     pub result: String,
-    pub _version: u32,
     pub _shards: ShardsInfo,
+    pub _seq_no: u64,
+    pub _primary_term: u64,
 }
 
 #[derive(Serialize, Clone)]
@@ -51,7 +53,29 @@ pub struct SearchResponse {
     pub _shards: ShardsInfo,
     pub hits: HitsMetadata,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub aggregations: Option<HashMap<String, AggregationBuckets>>,
+    pub aggregations: Option<HashMap<String, AggregationResponse>>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(untagged)]
+pub enum AggregationResponse {
+    Buckets(AggregationBuckets),
+    Metric(MetricValue),
+    Stats(StatsResponse),
+}
+
+#[derive(Serialize, Clone)]
+pub struct MetricValue {
+    pub value: Value,
+}
+
+#[derive(Serialize, Clone)]
+pub struct StatsResponse {
+    pub count: usize,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub avg: Option<f64>,
+    pub sum: f64,
 }
 
 #[derive(Serialize, Clone)]
@@ -82,7 +106,15 @@ pub struct TotalHits {
 pub struct SearchHit {
     pub _index: String,
     pub _id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub _version: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub _seq_no: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub _primary_term: Option<u64>,
     pub _score: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub _routing: Option<String>,
     pub _source: Value,
 }
 
@@ -178,12 +210,12 @@ mod tests {
         let mut aggs = HashMap::new();
         aggs.insert(
             "colors".to_string(),
-            AggregationBuckets {
+            AggregationResponse::Buckets(AggregationBuckets {
                 buckets: vec![BucketResponse {
                     key: json!("red"),
                     doc_count: 10,
                 }],
-            },
+            }),
         );
 
         let resp = SearchResponse {
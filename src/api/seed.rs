@@ -0,0 +1,231 @@
+use crate::AppState;
+use crate::api::handlers::{bulk, indices};
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::Arc;
+
+/// Environment variable naming the seed file or directory when `--seed` is
+/// not given.
+pub const SEED_ENV: &str = "ES_FAKE_SEED";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SeedSummary {
+    pub indices: usize,
+    pub documents: usize,
+}
+
+/// Loads seed data from a file or from every file of a directory:
+///
+/// * `<index>.json` - the body of a create index request (`settings`,
+///   `mappings`, `aliases`) for the index named after the file,
+/// * `<index>.ndjson` - a bulk request body; actions without `_index` go to
+///   the index named after the file.
+///
+/// Index definitions are applied first, then bulk files, each in file name
+/// order, through the same code paths as the REST API. The first failure
+/// aborts loading with an error naming the file.
+pub async fn load(state: &Arc<AppState>, path: &FsPath) -> Result<SeedSummary, String> {
+    let mut files = if path.is_dir() {
+        let entries = std::fs::read_dir(path).map_err(|e| file_error(path, e))?;
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|file| file.is_file())
+            .collect()
+    } else if path.is_file() {
+        vec![path.to_path_buf()]
+    } else {
+        return Err(format!("seed path [{}] does not exist", path.display()));
+    };
+    files.sort();
+
+    let extension = |file: &PathBuf| {
+        file.extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_string)
+    };
+    let (definitions, bulk_files): (Vec<PathBuf>, Vec<PathBuf>) = files
+        .into_iter()
+        .filter(|file| matches!(extension(file).as_deref(), Some("json" | "ndjson")))
+        .partition(|file| extension(file).as_deref() == Some("json"));
+
+    let mut summary = SeedSummary::default();
+    for file in definitions {
+        create_index(state, &file).await?;
+        summary.indices += 1;
+    }
+    for file in bulk_files {
+        summary.documents += load_bulk(state, &file).await?;
+    }
+    Ok(summary)
+}
+
+async fn create_index(state: &Arc<AppState>, file: &FsPath) -> Result<(), String> {
+    let content = std::fs::read_to_string(file).map_err(|e| file_error(file, e))?;
+    let body: Value = serde_json::from_str(&content)
+        .map_err(|e| format!("seed file [{}] is not valid JSON: {}", file.display(), e))?;
+    let response = indices::create_index(
+        Path(index_name(file)),
+        State(state.clone()),
+        Some(Json(body)),
+    )
+    .await
+    .into_response();
+    check(file, response).await.map(|_| ())
+}
+
+/// Applies a bulk file, returning the number of documents its `index` and
+/// `create` actions wrote.
+async fn load_bulk(state: &Arc<AppState>, file: &FsPath) -> Result<usize, String> {
+    let content = std::fs::read(file).map_err(|e| file_error(file, e))?;
+    let response = bulk::bulk_index(
+        Path(index_name(file)),
+        Query(HashMap::new()),
+        State(state.clone()),
+        Body::from(content),
+    )
+    .await;
+    let body = check(file, response).await?;
+
+    let items = body["items"].as_array().cloned().unwrap_or_default();
+    let mut documents = 0;
+    for (position, item) in items.iter().enumerate() {
+        let Some((action, result)) = item.as_object().and_then(|item| item.iter().next()) else {
+            continue;
+        };
+        if let Some(error) = result.get("error") {
+            return Err(format!(
+                "seed file [{}]: {} action #{} failed: [{}] {}",
+                file.display(),
+                action,
+                position + 1,
+                error["type"].as_str().unwrap_or_default(),
+                error["reason"].as_str().unwrap_or_default()
+            ));
+        }
+        if matches!(action.as_str(), "index" | "create") {
+            documents += 1;
+        }
+    }
+    Ok(documents)
+}
+
+/// Reads the response body, turning an error response into the reason
+/// Elasticsearch gave for it.
+async fn check(file: &FsPath, response: Response) -> Result<Value, String> {
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|e| e.to_string())?;
+    let body: Value = serde_json::from_slice(&bytes).unwrap_or_default();
+    if status == StatusCode::OK {
+        return Ok(body);
+    }
+    Err(format!(
+        "seed file [{}]: [{}] {}",
+        file.display(),
+        body["error"]["type"].as_str().unwrap_or("error"),
+        body["error"]["reason"].as_str().unwrap_or_default()
+    ))
+}
+
+fn index_name(file: &FsPath) -> String {
+    file.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn file_error(path: &FsPath, error: std::io::Error) -> String {
+    format!("cannot read seed path [{}]: {}", path.display(), error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::setup_state;
+
+    #[tokio::test]
+    async fn should_load_index_definitions_and_bulk_files() {
+        let dir = std::env::temp_dir().join(format!("es_fake-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("products.json"),
+            r#"{ "mappings": { "properties": { "price": { "type": "integer" } } },
+                 "aliases": { "catalog": {} } }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("products.ndjson"),
+            concat!(
+                "{\"index\":{\"_id\":\"1\"}}\n{\"price\":10}\n",
+                "{\"index\":{\"_index\":\"orders\",\"_id\":\"a\"}}\n{\"total\":3}\n",
+                "{\"create\":{\"_index\":\"orders\",\"_id\":\"b\"}}\n{\"total\":4}\n",
+                "{\"update\":{\"_id\":\"1\"}}\n{\"doc\":{\"price\":11}}\n",
+                "{\"delete\":{\"_index\":\"orders\",\"_id\":\"b\"}}\n"
+            ),
+        )
+        .unwrap();
+        std::fs::write(dir.join("README.md"), "ignored").unwrap();
+
+        let state = setup_state();
+        let summary = load(&state, &dir).await.unwrap();
+        assert_eq!(
+            summary,
+            SeedSummary {
+                indices: 1,
+                documents: 3
+            }
+        );
+        assert!(state.store.is_alias("catalog"));
+        assert!(state.store.get_document("orders", "a").is_some());
+
+        std::fs::write(
+            dir.join("products.ndjson"),
+            "{\"index\":{\"_id\":\"2\"}}\n{\"price\":\"cheap\"}\n",
+        )
+        .unwrap();
+        let error = load(&setup_state(), &dir).await.unwrap_err();
+        assert!(error.contains("products.ndjson"), "{}", error);
+        assert!(error.contains("action #1"), "{}", error);
+
+        let error = load(&setup_state(), &dir.join("missing"))
+            .await
+            .unwrap_err();
+        assert!(error.contains("does not exist"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_name_the_file_of_invalid_seed_data() {
+        for (file, content, reason) in [
+            ("orders.json", "{ \"mappings\": ", "is not valid JSON"),
+            (
+                "orders.json",
+                r#"{ "mappings": { "properties": { "total": { "type": "money" } } } }"#,
+                "mapper_parsing_exception",
+            ),
+            (
+                "orders.ndjson",
+                "{\"index\":{\"_id\":\"1\"}}\n{\"total\":\n",
+                "orders.ndjson",
+            ),
+        ] {
+            let dir = std::env::temp_dir().join(format!("es_fake-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join(file), content).unwrap();
+
+            let error = load(&setup_state(), &dir).await.unwrap_err();
+            assert!(error.contains(file), "{}", error);
+            assert!(error.contains(reason), "{}", error);
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,764 @@
+use crate::api::seed::SEED_ENV;
+use crate::repository::persistence::{FsyncPolicy, PersistenceOptions};
+use serde::{Deserialize, Deserializer};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+
+/// Environment variable naming the config file when `--config` is not given.
+pub const CONFIG_ENV: &str = "ES_FAKE_CONFIG";
+
+/// Server settings. Each comes from, by increasing precedence, its default,
+/// the config file, its environment variable and its command line flag.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub listener: ListenerConfig,
+    pub auth: AuthConfig,
+    pub cluster: ClusterConfig,
+    pub limits: LimitsConfig,
+    pub persistence: PersistenceConfig,
+    pub path: PathConfig,
+    /// Seed file or directory, see [`crate::api::seed::load`].
+    pub seed: Option<PathBuf>,
+    pub features: FeaturesConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenerConfig {
+    pub bind: IpAddr,
+    pub port: u16,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 9200,
+        }
+    }
+}
+
+/// Basic authentication is required once a password is set; the `/_fake`
+/// admin API is only served once an admin password is set and accepts only
+/// the admin credential.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub user: String,
+    #[serde(deserialize_with = "non_empty")]
+    pub password: Option<String>,
+    pub admin_user: String,
+    #[serde(deserialize_with = "non_empty")]
+    pub admin_password: Option<String>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            user: "elastic".to_string(),
+            password: None,
+            admin_user: "admin".to_string(),
+            admin_password: None,
+        }
+    }
+}
+
+/// Names and version the server reports about itself.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClusterConfig {
+    pub name: String,
+    pub node_name: String,
+    pub version: String,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            name: "docker-cluster".to_string(),
+            node_name: "es_fake".to_string(),
+            version: "8.10.0".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Largest accepted request body in bytes, like `http.max_content_length`;
+    /// the file also accepts sizes such as `"100mb"`.
+    #[serde(deserialize_with = "byte_size")]
+    pub max_content_length: u64,
+    /// Largest `from + size` of a search, like `index.max_result_window`.
+    pub max_result_window: usize,
+    /// Most namespaces that may exist besides the default one.
+    pub max_namespaces: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_content_length: 100 * 1024 * 1024,
+            max_result_window: 10_000,
+            max_namespaces: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PersistenceConfig {
+    /// Without a data directory the store lives in memory only.
+    pub data_dir: Option<PathBuf>,
+    #[serde(deserialize_with = "fsync_policy")]
+    pub fsync: FsyncPolicy,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: None,
+            fsync: FsyncPolicy::Always,
+        }
+    }
+}
+
+impl PersistenceConfig {
+    pub fn options(&self) -> Option<PersistenceOptions> {
+        self.data_dir.clone().map(|data_dir| PersistenceOptions {
+            data_dir,
+            fsync: self.fsync,
+        })
+    }
+}
+
+/// Directories the server may write to on behalf of clients.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathConfig {
+    /// Like Elasticsearch's `path.repo`: the locations of `fs` snapshot
+    /// repositories must fall under one of these, and relative ones are
+    /// resolved against them. Without any, `fs` repositories are refused.
+    pub repo: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeaturesConfig {
+    /// Serves the `/_fake` admin API, including `/_fake/namespaces`,
+    /// provided an admin password is set.
+    pub admin_api: bool,
+    /// Honours the `X-Fake-Namespace` header and, with the admin API, serves
+    /// `/_fake/namespaces`.
+    pub namespaces: bool,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            admin_api: true,
+            namespaces: false,
+        }
+    }
+}
+
+/// A setting that can be overridden from the environment and, unless it is a
+/// secret, from the command line.
+pub struct ConfigOption {
+    pub key: &'static str,
+    pub env: &'static str,
+    pub flag: Option<&'static str>,
+    pub help: &'static str,
+}
+
+pub const OPTIONS: &[ConfigOption] = &[
+    option(
+        "listener.bind",
+        "ES_FAKE_BIND",
+        Some("--bind"),
+        "Address to listen on [default: 0.0.0.0]",
+    ),
+    option(
+        "listener.port",
+        "ES_FAKE_PORT",
+        Some("--port"),
+        "Port to listen on, 0 for any free port [default: 9200]",
+    ),
+    option(
+        "auth.user",
+        "ES_FAKE_USER",
+        Some("--user"),
+        "User of basic authentication [default: elastic]",
+    ),
+    option(
+        "auth.password",
+        "ELASTIC_PASSWORD",
+        None,
+        "Password of basic authentication; none disables it",
+    ),
+    option(
+        "auth.admin_user",
+        "ES_FAKE_ADMIN_USER",
+        Some("--admin-user"),
+        "User of the /_fake admin API [default: admin]",
+    ),
+    option(
+        "auth.admin_password",
+        "ES_FAKE_ADMIN_PASSWORD",
+        None,
+        "Password of the /_fake admin API; none disables the admin API",
+    ),
+    option(
+        "cluster.name",
+        "ES_FAKE_CLUSTER_NAME",
+        Some("--cluster-name"),
+        "Reported cluster name [default: docker-cluster]",
+    ),
+    option(
+        "cluster.node_name",
+        "ES_FAKE_NODE_NAME",
+        Some("--node-name"),
+        "Reported node name [default: es_fake]",
+    ),
+    option(
+        "cluster.version",
+        "ES_FAKE_VERSION",
+        Some("--es-version"),
+        "Reported Elasticsearch version [default: 8.10.0]",
+    ),
+    option(
+        "limits.max_content_length",
+        "ES_FAKE_MAX_CONTENT_LENGTH",
+        Some("--max-content-length"),
+        "Largest request body, e.g. 100mb [default: 100mb]",
+    ),
+    option(
+        "limits.max_result_window",
+        "ES_FAKE_MAX_RESULT_WINDOW",
+        Some("--max-result-window"),
+        "Largest from + size of a search [default: 10000]",
+    ),
+    option(
+        "limits.max_namespaces",
+        "ES_FAKE_MAX_NAMESPACES",
+        Some("--max-namespaces"),
+        "Most namespaces besides the default one [default: 100]",
+    ),
+    option(
+        "persistence.data_dir",
+        "ES_FAKE_DATA_DIR",
+        Some("--data-dir"),
+        "Directory the store is persisted to; none keeps it in memory",
+    ),
+    option(
+        "persistence.fsync",
+        "ES_FAKE_FSYNC",
+        Some("--fsync"),
+        "When the WAL is synced: always, never or an interval such as 5s [default: always]",
+    ),
+    option(
+        "path.repo",
+        "ES_FAKE_PATH_REPO",
+        Some("--path-repo"),
+        "Comma-separated directories snapshot repositories may use; none refuses them",
+    ),
+    option(
+        "seed",
+        SEED_ENV,
+        Some("--seed"),
+        "Seed file or directory loaded at startup",
+    ),
+    option(
+        "features.admin_api",
+        "ES_FAKE_ADMIN_API",
+        Some("--admin-api"),
+        "Serve the /_fake admin API: true or false [default: true]",
+    ),
+    option(
+        "features.namespaces",
+        "ES_FAKE_NAMESPACES",
+        Some("--namespaces"),
+        "Honour the X-Fake-Namespace header: true or false [default: false]",
+    ),
+];
+
+const fn option(
+    key: &'static str,
+    env: &'static str,
+    flag: Option<&'static str>,
+    help: &'static str,
+) -> ConfigOption {
+    ConfigOption {
+        key,
+        env,
+        flag,
+        help,
+    }
+}
+
+/// What the command line asks for.
+#[derive(Debug)]
+pub enum Command {
+    Run(Box<Config>),
+    Help,
+    Version,
+}
+
+impl Config {
+    /// Reads a `.toml`, `.yaml` or `.yml` config file. Missing settings keep
+    /// their default, unknown ones are an error.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config file [{}]: {}", path.display(), e))?;
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let parsed = match extension {
+            Some("toml") => toml::from_str(&content).map_err(|e| e.to_string()),
+            Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(|e| e.to_string()),
+            _ => Err("expected a .toml, .yaml or .yml file".to_string()),
+        };
+        parsed.map_err(|e| format!("invalid config file [{}]: {}", path.display(), e))
+    }
+
+    /// Builds the configuration from the command line arguments (without the
+    /// program name) and the environment: the config file named by
+    /// `--config` or [`CONFIG_ENV`], then the environment variables, then the
+    /// flags. Empty environment variables count as unset.
+    pub fn from_args(
+        args: impl IntoIterator<Item = String>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Command, String> {
+        let mut config_file = env(CONFIG_ENV).filter(|path| !path.is_empty());
+        let mut flags = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for [{}]", flag))
+            };
+            match flag.as_str() {
+                "-h" | "--help" => return Ok(Command::Help),
+                "-V" | "--version" => return Ok(Command::Version),
+                "-c" | "--config" => config_file = Some(value()?),
+                _ => match OPTIONS
+                    .iter()
+                    .find(|option| option.flag == Some(flag.as_str()))
+                {
+                    Some(option) => flags.push((option.key, value()?)),
+                    None => {
+                        return Err(format!(
+                            "unknown argument [{}], see --help for the supported ones",
+                            flag
+                        ));
+                    }
+                },
+            }
+        }
+
+        let mut config = match config_file {
+            Some(path) => Self::from_file(Path::new(&path))?,
+            None => Self::default(),
+        };
+        for option in OPTIONS {
+            if let Some(value) = env(option.env).filter(|value| !value.is_empty()) {
+                config
+                    .set(option.key, &value)
+                    .map_err(|e| format!("invalid [{}]: {}", option.env, e))?;
+            }
+        }
+        for (key, value) in flags {
+            let flag = OPTIONS.iter().find(|option| option.key == key);
+            config.set(key, &value).map_err(|e| {
+                format!(
+                    "invalid [{}]: {}",
+                    flag.and_then(|o| o.flag).unwrap_or(key),
+                    e
+                )
+            })?;
+        }
+        Ok(Command::Run(Box::new(config)))
+    }
+
+    /// Sets the setting named by its dotted key, e.g. `listener.port`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let parse_error = |e: &dyn std::fmt::Display| format!("[{}] {}", value, e);
+        match key {
+            "listener.bind" => self.listener.bind = value.parse().map_err(|e| parse_error(&e))?,
+            "listener.port" => self.listener.port = value.parse().map_err(|e| parse_error(&e))?,
+            "auth.user" => self.auth.user = value.to_string(),
+            "auth.password" => {
+                self.auth.password = Some(value.to_string()).filter(|p| !p.is_empty())
+            }
+            "auth.admin_user" => self.auth.admin_user = value.to_string(),
+            "auth.admin_password" => {
+                self.auth.admin_password = Some(value.to_string()).filter(|p| !p.is_empty())
+            }
+            "cluster.name" => self.cluster.name = value.to_string(),
+            "cluster.node_name" => self.cluster.node_name = value.to_string(),
+            "cluster.version" => self.cluster.version = value.to_string(),
+            "limits.max_content_length" => self.limits.max_content_length = parse_byte_size(value)?,
+            "limits.max_result_window" => {
+                self.limits.max_result_window = value.parse().map_err(|e| parse_error(&e))?
+            }
+            "limits.max_namespaces" => {
+                self.limits.max_namespaces = value.parse().map_err(|e| parse_error(&e))?
+            }
+            "persistence.data_dir" => self.persistence.data_dir = Some(PathBuf::from(value)),
+            "persistence.fsync" => self.persistence.fsync = FsyncPolicy::parse(value)?,
+            "path.repo" => {
+                self.path.repo = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|path| !path.is_empty())
+                    .map(PathBuf::from)
+                    .collect()
+            }
+            "seed" => self.seed = Some(PathBuf::from(value)),
+            "features.admin_api" => {
+                self.features.admin_api = value.parse().map_err(|e| parse_error(&e))?
+            }
+            "features.namespaces" => {
+                self.features.namespaces = value.parse().map_err(|e| parse_error(&e))?
+            }
+            _ => return Err(format!("unknown setting [{}]", key)),
+        }
+        Ok(())
+    }
+}
+
+/// The `--help` text.
+pub fn usage() -> String {
+    let mut usage = String::from(
+        "A fake Elasticsearch server for tests.\n\n\
+         Usage: es_fake [OPTIONS]\n\n\
+         Options:\n  \
+         -c, --config <path>\n          \
+         TOML or YAML config file [env: ES_FAKE_CONFIG]\n",
+    );
+    for option in OPTIONS {
+        let flag = match option.flag {
+            Some(flag) => format!("{} <value>", flag),
+            None => format!("(secret, environment only: {})", option.key),
+        };
+        usage.push_str(&format!(
+            "      {}\n          {} [env: {}]\n",
+            flag, option.help, option.env
+        ));
+    }
+    usage
+        .push_str("  -h, --help\n          Print help\n  -V, --version\n          Print version\n");
+    usage
+}
+
+/// Parses a byte size such as `512`, `64kb`, `100mb` or `1gb`.
+fn parse_byte_size(value: &str) -> Result<u64, String> {
+    let lower = value.trim().to_ascii_lowercase();
+    let split = lower
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(lower.len());
+    let (amount, unit) = lower.split_at(split);
+    let factor = match unit.trim() {
+        "" | "b" => 1,
+        "kb" => 1024,
+        "mb" => 1024 * 1024,
+        "gb" => 1024 * 1024 * 1024,
+        _ => 0,
+    };
+    amount
+        .parse::<u64>()
+        .ok()
+        .filter(|_| factor > 0)
+        .and_then(|amount| amount.checked_mul(factor))
+        .ok_or_else(|| format!("invalid byte size [{}], expected e.g. [100mb]", value))
+}
+
+fn non_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let value = Option::<String>::deserialize(deserializer)?;
+    Ok(value.filter(|value| !value.is_empty()))
+}
+
+fn byte_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ByteSize {
+        Bytes(u64),
+        Text(String),
+    }
+    match ByteSize::deserialize(deserializer)? {
+        ByteSize::Bytes(bytes) => Ok(bytes),
+        ByteSize::Text(text) => parse_byte_size(&text).map_err(serde::de::Error::custom),
+    }
+}
+
+fn fsync_policy<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FsyncPolicy, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    FsyncPolicy::parse(&value).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Writes `content` to a file named `name` in a fresh temporary
+    /// directory, returning the path of the file.
+    fn config_file(name: &str, content: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("es_fake-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(name);
+        std::fs::write(&file, content).unwrap();
+        file
+    }
+
+    fn run(args: &[&str], env: &[(&str, &str)]) -> Result<Config, String> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        match Config::from_args(args.iter().map(|arg| arg.to_string()), |name| {
+            env.get(name).cloned()
+        })? {
+            Command::Run(config) => Ok(*config),
+            command => panic!("expected a configuration, got {:?}", command),
+        }
+    }
+
+    #[test]
+    fn should_layer_file_environment_and_flags() {
+        let dir = std::env::temp_dir().join(format!("es_fake-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("es_fake.yaml");
+        std::fs::write(
+            &file,
+            "listener:\n  port: 9201\ncluster:\n  name: ci\nlimits:\n  max_content_length: 1mb\n",
+        )
+        .unwrap();
+        let env: HashMap<&str, &str> = HashMap::from([
+            (CONFIG_ENV, file.to_str().unwrap()),
+            ("ES_FAKE_PORT", "9202"),
+            ("ELASTIC_PASSWORD", "secret"),
+            (SEED_ENV, ""),
+        ]);
+        let env = |name: &str| env.get(name).map(|value| value.to_string());
+        let args = ["--port", "9203", "--fsync=5s", "--namespaces", "true"].map(String::from);
+
+        let Command::Run(config) = Config::from_args(args, env).unwrap() else {
+            panic!("expected a configuration");
+        };
+        assert_eq!(config.listener.port, 9203);
+        assert_eq!(config.cluster.name, "ci");
+        assert_eq!(config.cluster.version, "8.10.0");
+        assert_eq!(config.limits.max_content_length, 1024 * 1024);
+        assert_eq!(config.auth.password.as_deref(), Some("secret"));
+        assert_eq!(config.seed, None);
+        assert!(config.features.namespaces);
+        assert!(matches!(config.persistence.fsync, FsyncPolicy::Interval(_)));
+
+        std::fs::write(dir.join("es_fake.toml"), "[listener]\nprot = 1\n").unwrap();
+        let error = Config::from_file(&dir.join("es_fake.toml")).unwrap_err();
+        assert!(error.contains("prot"), "{}", error);
+        assert!(Config::from_args(["--bogus".to_string()], |_| None).is_err());
+        assert!(matches!(
+            Config::from_args(["--help".to_string()], |_| None),
+            Ok(Command::Help)
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn should_let_the_environment_override_the_file() {
+        let file = config_file(
+            "es_fake.toml",
+            "[cluster]\nname = \"from-file\"\nnode_name = \"file-node\"\n\n[auth]\npassword = \"file\"\n",
+        );
+        let path = file.to_str().unwrap();
+
+        let config = run(
+            &[],
+            &[
+                (CONFIG_ENV, path),
+                ("ES_FAKE_CLUSTER_NAME", "from-env"),
+                ("ELASTIC_PASSWORD", "env"),
+                ("ES_FAKE_NODE_NAME", ""),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.cluster.name, "from-env");
+        assert_eq!(config.auth.password.as_deref(), Some("env"));
+        // Empty variables count as unset and leave the file's value.
+        assert_eq!(config.cluster.node_name, "file-node");
+
+        let config = run(&["--config", path], &[("ES_FAKE_CLUSTER_NAME", "from-env")]).unwrap();
+        assert_eq!(config.cluster.name, "from-env");
+        std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn should_let_flags_override_the_environment() {
+        let env = [
+            ("ES_FAKE_PORT", "9201"),
+            ("ES_FAKE_ADMIN_API", "true"),
+            ("ES_FAKE_MAX_RESULT_WINDOW", "50"),
+            ("ES_FAKE_MAX_NAMESPACES", "5"),
+            ("ES_FAKE_PATH_REPO", "/from-env"),
+        ];
+        let config = run(
+            &[
+                "--port",
+                "9202",
+                "--admin-api=false",
+                "--bind",
+                "127.0.0.1",
+                "--path-repo",
+                "/a, /b",
+            ],
+            &env,
+        )
+        .unwrap();
+        assert_eq!(
+            config.path.repo,
+            vec![PathBuf::from("/a"), PathBuf::from("/b")]
+        );
+        assert_eq!(config.listener.port, 9202);
+        assert!(!config.features.admin_api);
+        assert_eq!(config.listener.bind, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(config.limits.max_result_window, 50);
+        assert_eq!(config.limits.max_namespaces, 5);
+    }
+
+    #[test]
+    fn should_parse_yaml_and_toml_files() {
+        let toml = config_file(
+            "es_fake.toml",
+            concat!(
+                "seed = \"./seed\"\n",
+                "[listener]\nbind = \"127.0.0.1\"\nport = 9300\n",
+                "[auth]\nuser = \"tester\"\nadmin_password = \"\"\n",
+                "[limits]\nmax_content_length = 2048\nmax_result_window = 20\n",
+                "[persistence]\ndata_dir = \"/tmp/data\"\nfsync = \"never\"\n",
+                "[path]\nrepo = [\"/backups\", \"/mnt/backups\"]\n",
+                "[features]\nnamespaces = true\n",
+            ),
+        );
+        let yaml = config_file(
+            "es_fake.yml",
+            concat!(
+                "seed: ./seed\n",
+                "listener:\n  bind: 127.0.0.1\n  port: 9300\n",
+                "auth:\n  user: tester\n  admin_password: \"\"\n",
+                "limits:\n  max_content_length: 2kb\n  max_result_window: 20\n",
+                "persistence:\n  data_dir: /tmp/data\n  fsync: never\n",
+                "path:\n  repo: [/backups, /mnt/backups]\n",
+                "features:\n  namespaces: true\n",
+            ),
+        );
+
+        let config = Config::from_file(&toml).unwrap();
+        assert_eq!(config, Config::from_file(&yaml).unwrap());
+        assert_eq!(config.listener.port, 9300);
+        assert_eq!(config.auth.user, "tester");
+        assert_eq!(config.auth.admin_password, None);
+        assert_eq!(config.limits.max_content_length, 2048);
+        assert_eq!(
+            config.persistence.data_dir,
+            Some(PathBuf::from("/tmp/data"))
+        );
+        assert_eq!(config.persistence.fsync, FsyncPolicy::Never);
+        assert_eq!(config.seed, Some(PathBuf::from("./seed")));
+        assert_eq!(
+            config.path.repo,
+            vec![PathBuf::from("/backups"), PathBuf::from("/mnt/backups")]
+        );
+        assert!(config.features.namespaces);
+        assert_eq!(config.cluster, ClusterConfig::default());
+
+        let json = config_file("es_fake.json", "{}");
+        let error = Config::from_file(&json).unwrap_err();
+        assert!(
+            error.contains("expected a .toml, .yaml or .yml file"),
+            "{}",
+            error
+        );
+        for file in [toml, yaml, json] {
+            std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn should_reject_unknown_keys_and_invalid_values() {
+        for (name, content, expected) in [
+            ("unknown.toml", "[cluster]\nnodes = 3\n", "nodes"),
+            ("unknown.yaml", "verbose: true\n", "verbose"),
+            (
+                "port.toml",
+                "[listener]\nport = \"high\"\n",
+                "invalid config file",
+            ),
+            (
+                "size.yaml",
+                "limits:\n  max_content_length: lots\n",
+                "invalid byte size",
+            ),
+            (
+                "fsync.yaml",
+                "persistence:\n  fsync: sometimes\n",
+                "sometimes",
+            ),
+            ("broken.toml", "[listener\n", "invalid config file"),
+        ] {
+            let file = config_file(name, content);
+            let error = Config::from_file(&file).unwrap_err();
+            assert!(error.contains(expected), "{}: {}", name, error);
+            std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+        }
+
+        let error = run(&[], &[("ES_FAKE_PORT", "high")]).unwrap_err();
+        assert!(error.contains("ES_FAKE_PORT"), "{}", error);
+        let error = run(&["--namespaces", "maybe"], &[]).unwrap_err();
+        assert!(error.contains("--namespaces"), "{}", error);
+        let error = run(&["--max-content-length=10tb"], &[]).unwrap_err();
+        assert!(error.contains("invalid byte size"), "{}", error);
+        let error = run(&["--port"], &[]).unwrap_err();
+        assert!(error.contains("missing value for [--port]"), "{}", error);
+        let error = run(&["--verbose"], &[]).unwrap_err();
+        assert!(error.contains("unknown argument [--verbose]"), "{}", error);
+        let error = run(&["--config", "/nonexistent/es_fake.toml"], &[]).unwrap_err();
+        assert!(error.contains("cannot read config file"), "{}", error);
+        // The password is a secret and has no flag.
+        assert!(run(&["--password", "secret"], &[]).is_err());
+    }
+
+    #[test]
+    fn should_answer_help_and_version_before_reading_the_config() {
+        let env = |_: &str| Some("/nonexistent/es_fake.toml".to_string());
+        for (args, expected) in [
+            (vec!["--help"], "help"),
+            (vec!["-h"], "help"),
+            (vec!["--version"], "version"),
+            (vec!["-V"], "version"),
+            (vec!["--port", "1", "--help"], "help"),
+        ] {
+            let command = Config::from_args(args.iter().map(|a| a.to_string()), env).unwrap();
+            match (command, expected) {
+                (Command::Help, "help") | (Command::Version, "version") => {}
+                (command, _) => panic!("{:?} gave {:?}", args, command),
+            }
+        }
+
+        let usage = usage();
+        for option in OPTIONS {
+            assert!(usage.contains(option.env), "{}", option.env);
+            if let Some(flag) = option.flag {
+                assert!(usage.contains(flag), "{}", flag);
+            }
+        }
+        assert!(usage.contains("--config"));
+        assert!(usage.contains("--version"));
+    }
+}
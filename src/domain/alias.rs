@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct AliasDefinition {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index_routing: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_routing: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_write_index: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_hidden: Option<bool>,
+}
+
+impl AliasDefinition {
+    /// Builds a definition from an alias body as accepted by the alias APIs,
+    /// where `routing` is shorthand for both index and search routing.
+    pub fn from_json(json: &Value) -> Self {
+        let routing = routing_value(json.get("routing"));
+        Self {
+            filter: json.get("filter").filter(|f| !f.is_null()).cloned(),
+            index_routing: routing_value(json.get("index_routing")).or_else(|| routing.clone()),
+            search_routing: routing_value(json.get("search_routing")).or(routing),
+            is_write_index: json.get("is_write_index").and_then(|v| v.as_bool()),
+            is_hidden: json.get("is_hidden").and_then(|v| v.as_bool()),
+        }
+    }
+}
+
+fn routing_value(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_expand_routing_shorthand() {
+        let alias = AliasDefinition::from_json(&json!({ "routing": "1", "is_write_index": true }));
+        assert_eq!(alias.index_routing.as_deref(), Some("1"));
+        assert_eq!(alias.search_routing.as_deref(), Some("1"));
+        assert_eq!(alias.is_write_index, Some(true));
+    }
+
+    #[test]
+    fn should_serialize_without_empty_fields() {
+        let alias = AliasDefinition::from_json(&json!({ "filter": { "term": { "a": 1 } } }));
+        let serialized = serde_json::to_value(&alias).unwrap();
+        assert_eq!(serialized, json!({ "filter": { "term": { "a": 1 } } }));
+    }
+}
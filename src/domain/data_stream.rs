@@ -0,0 +1,105 @@
+use crate::domain::time::{format_date, parse_duration_millis};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const TIMESTAMP_FIELD: &str = "@timestamp";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataStream {
+    pub name: String,
+    pub template: String,
+    pub generation: u64,
+    /// Backing indices, oldest first; the last one is the write index.
+    pub indices: Vec<String>,
+}
+
+impl DataStream {
+    pub fn write_index(&self) -> Option<&str> {
+        self.indices.last().map(|s| s.as_str())
+    }
+
+    pub fn backing_index_name(name: &str, generation: u64, now_millis: u64) -> String {
+        format!(".ds-{}-{}-{:06}", name, format_date(now_millis), generation)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RolloverConditions {
+    pub max_docs: Option<u64>,
+    pub max_age: Option<String>,
+}
+
+impl RolloverConditions {
+    pub fn from_json(json: &Value) -> Self {
+        let conditions = &json["conditions"];
+        Self {
+            max_docs: conditions["max_docs"]
+                .as_u64()
+                .or_else(|| conditions["max_primary_shard_docs"].as_u64()),
+            max_age: conditions["max_age"].as_str().map(|s| s.to_string()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max_docs.is_none() && self.max_age.is_none()
+    }
+
+    /// Evaluates every condition against the current write index, keyed the
+    /// way the rollover API reports them (e.g. `[max_docs: 5]`).
+    pub fn evaluate(&self, doc_count: u64, age_millis: u64) -> Vec<(String, bool)> {
+        let mut results = Vec::new();
+        if let Some(max_docs) = self.max_docs {
+            results.push((format!("[max_docs: {}]", max_docs), doc_count >= max_docs));
+        }
+        if let Some(max_age) = &self.max_age {
+            let met = parse_duration_millis(max_age).is_some_and(|limit| age_millis >= limit);
+            results.push((format!("[max_age: {}]", max_age), met));
+        }
+        results
+    }
+}
+
+/// Derives the next index name for an alias rollover by incrementing the
+/// numeric suffix, e.g. `logs-000001` becomes `logs-000002`.
+pub fn next_rollover_name(index: &str) -> Option<String> {
+    let (prefix, suffix) = index.rsplit_once('-')?;
+    if suffix.is_empty() || !suffix.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let next = suffix.parse::<u64>().ok()? + 1;
+    Some(format!("{}-{:0width$}", prefix, next, width = suffix.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_name_backing_indices() {
+        assert_eq!(
+            DataStream::backing_index_name("logs-app", 2, 0),
+            ".ds-logs-app-1970.01.01-000002"
+        );
+    }
+
+    #[test]
+    fn should_evaluate_rollover_conditions() {
+        let conditions = RolloverConditions::from_json(&json!({
+            "conditions": { "max_docs": 2, "max_age": "1d" }
+        }));
+
+        let results = conditions.evaluate(3, 1_000);
+        assert_eq!(results[0], ("[max_docs: 2]".to_string(), true));
+        assert_eq!(results[1], ("[max_age: 1d]".to_string(), false));
+    }
+
+    #[test]
+    fn should_increment_rollover_suffix() {
+        assert_eq!(
+            next_rollover_name("logs-000001").as_deref(),
+            Some("logs-000002")
+        );
+        assert_eq!(next_rollover_name("logs").as_deref(), None);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::domain::inverted_index::leaf_values;
+use crate::domain::mapping::{FieldType, Mapping};
+use crate::domain::time::parse_date_millis;
+use serde_json::{Value, json};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// A single field value as used for sorting and aggregations, converted
+/// according to the field's mapping.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DocValue {
+    Long(i64),
+    Double(f64),
+    Keyword(String),
+    Bool(bool),
+}
+
+impl DocValue {
+    /// Converts a JSON scalar for a field of the given type; unmapped
+    /// fields are typed from the JSON value itself.
+    pub fn from_json(value: &Value, field_type: Option<&FieldType>) -> Option<Self> {
+        let parsed = |text: &str| text.trim().parse::<f64>().ok();
+        match field_type {
+            Some(FieldType::Integer | FieldType::Long) => match value {
+                Value::Number(n) => n
+                    .as_i64()
+                    .or_else(|| n.as_f64().map(|f| f as i64))
+                    .map(Self::Long),
+                Value::String(s) => parsed(s).map(|f| Self::Long(f as i64)),
+                _ => None,
+            },
+            Some(FieldType::Double) => match value {
+                Value::Number(n) => n.as_f64().map(Self::Double),
+                Value::String(s) => parsed(s).map(Self::Double),
+                _ => None,
+            },
+            Some(FieldType::Keyword | FieldType::Text) => match value {
+                Value::String(s) => Some(Self::Keyword(s.clone())),
+                Value::Number(_) | Value::Bool(_) => Some(Self::Keyword(value.to_string())),
+                _ => None,
+            },
+            Some(FieldType::Boolean) => match value {
+                Value::Bool(b) => Some(Self::Bool(*b)),
+                Value::String(s) => s.parse().ok().map(Self::Bool),
+                _ => None,
+            },
+            Some(FieldType::Date) => match value {
+                Value::Number(n) => n.as_i64().map(Self::Long),
+                Value::String(s) => {
+                    Some(parse_date_millis(s).map_or_else(|| Self::Keyword(s.clone()), Self::Long))
+                }
+                _ => None,
+            },
+            None => match value {
+                Value::Number(n) => n
+                    .as_i64()
+                    .map(Self::Long)
+                    .or_else(|| n.as_f64().map(Self::Double)),
+                Value::String(s) => Some(Self::Keyword(s.clone())),
+                Value::Bool(b) => Some(Self::Bool(*b)),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Long(n) => Some(*n as f64),
+            Self::Double(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            Self::Long(n) => json!(n),
+            Self::Double(n) => json!(n),
+            Self::Keyword(s) => json!(s),
+            Self::Bool(b) => json!(b),
+        }
+    }
+
+    /// Numbers compare numerically, keywords lexically and booleans with
+    /// `false` first; values of different kinds are considered equal.
+    pub fn compare(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Keyword(a), Self::Keyword(b)) => a.cmp(b),
+            (Self::Bool(a), Self::Bool(b)) => a.cmp(b),
+            _ => match (self.as_f64(), other.as_f64()) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                _ => Ordering::Equal,
+            },
+        }
+    }
+}
+
+/// The values of one field, keyed by document position. Only documents that
+/// have the field get an entry, so sparse fields and high positions left by
+/// deletes cost nothing.
+type Column = im::OrdMap<u64, Vec<DocValue>>;
+
+/// Columnar doc-values: per field, the typed values of every document keyed
+/// by position, so sorts and aggregations read them directly instead of
+/// walking `_source`. Columns are persistent maps and share structure with
+/// the snapshots readers hold.
+#[derive(Clone, Default)]
+pub struct DocValues {
+    types: HashMap<String, FieldType>,
+    columns: im::HashMap<String, Column>,
+}
+
+impl DocValues {
+    pub fn for_mapping(mapping: &Mapping) -> Self {
+        Self {
+            types: mapping
+                .properties
+                .iter()
+                .map(|(field, property)| (field.clone(), property.field_type.clone()))
+                .collect(),
+            columns: im::HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, position: u64, source: &Value) {
+        let mut fields: HashMap<String, Vec<DocValue>> = HashMap::new();
+        for (field, value) in leaf_values(source) {
+            if let Some(value) = DocValue::from_json(value, self.types.get(&field)) {
+                fields.entry(field).or_default().push(value);
+            }
+        }
+        for (field, values) in fields {
+            self.columns
+                .entry(field)
+                .or_default()
+                .insert(position, values);
+        }
+    }
+
+    pub fn remove(&mut self, position: u64, source: &Value) {
+        for (field, _) in leaf_values(source) {
+            if let Some(column) = self.columns.get_mut(&field) {
+                column.remove(&position);
+                if column.is_empty() {
+                    self.columns.remove(&field);
+                }
+            }
+        }
+    }
+
+    /// Values of `field` (a trailing `.keyword` is ignored) for the document
+    /// at `position`.
+    pub fn get(&self, field: &str, position: u64) -> &[DocValue] {
+        let field = field.strip_suffix(".keyword").unwrap_or(field);
+        self.columns
+            .get(field)
+            .and_then(|column| column.get(&position))
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::mapping::Property;
+
+    #[test]
+    fn should_type_values_from_the_mapping() {
+        let mut mapping = Mapping::default();
+        for (field, field_type) in [
+            ("price", FieldType::Double),
+            ("code", FieldType::Keyword),
+            ("at", FieldType::Date),
+        ] {
+            mapping
+                .properties
+                .insert(field.to_string(), Property { field_type });
+        }
+        let mut values = DocValues::for_mapping(&mapping);
+        values.add(
+            2,
+            &json!({ "price": "9.5", "code": 42, "at": "1970-01-02", "tags": ["b", "a"] }),
+        );
+
+        assert_eq!(values.get("price", 2), &[DocValue::Double(9.5)]);
+        assert_eq!(
+            values.get("code.keyword", 2),
+            &[DocValue::Keyword("42".into())]
+        );
+        assert_eq!(values.get("at", 2), &[DocValue::Long(86_400_000)]);
+        assert_eq!(values.get("tags", 2).len(), 2);
+        assert!(values.get("price", 0).is_empty());
+
+        values.remove(2, &json!({ "price": "9.5" }));
+        assert!(values.get("price", 2).is_empty());
+        assert!(!values.columns.contains_key("price"));
+        assert_eq!(values.get("code", 2).len(), 1);
+    }
+}
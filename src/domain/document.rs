@@ -0,0 +1,17 @@
+use crate::domain::versioning::DocVersion;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A document as kept in an index: the user's `_source` untouched, with the
+/// metadata Elasticsearch reports alongside it held separately.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredDocument {
+    pub id: String,
+    pub source: Value,
+    pub version: DocVersion,
+    pub routing: Option<String>,
+    /// Epoch milliseconds of the first write of this `_id`.
+    pub created_at: u64,
+    /// Epoch milliseconds of the latest write of this `_id`.
+    pub updated_at: u64,
+}
@@ -1,5 +1,10 @@
-use crate::domain::query::{Query, TermsAggregation};
-use serde_json::Value;
+use crate::domain::doc_values::{DocValue, DocValues};
+use crate::domain::document::StoredDocument;
+use crate::domain::inverted_index::field_values;
+use crate::domain::query::{Aggregation, AggregationKind, Query};
+use crate::domain::script::Script;
+use serde_json::{Value, json};
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 
@@ -13,12 +18,44 @@ pub enum SortOrder {
 pub struct SortOptions {
     pub field: String,
     pub order: SortOrder,
+    /// Set for `_script` sorts, which order by the script's result instead
+    /// of a field.
+    pub script: Option<Script>,
 }
 
 #[derive(Debug, Clone)]
 pub struct AggregationResult {
     pub name: String,
-    pub buckets: Vec<Bucket>,
+    pub value: AggregationValue,
+}
+
+#[derive(Debug, Clone)]
+pub enum AggregationValue {
+    Buckets(Vec<Bucket>),
+    /// The single `value` of a metric aggregation.
+    Metric(Value),
+    Stats(Stats),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub count: usize,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub sum: f64,
+}
+
+impl Stats {
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+        self.sum += value;
+    }
+
+    pub fn avg(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -27,31 +64,80 @@ pub struct Bucket {
     pub doc_count: usize,
 }
 
+pub trait Searchable {
+    fn source(&self) -> &Value;
+
+    /// Values of a field for sorting and aggregations, read from `_source`
+    /// unless the document has doc-values.
+    fn doc_values(&self, field: &str) -> Cow<'_, [DocValue]> {
+        let field = field.strip_suffix(".keyword").unwrap_or(field);
+        Cow::Owned(
+            field_values(self.source(), field)
+                .into_iter()
+                .filter_map(|value| DocValue::from_json(value, None))
+                .collect(),
+        )
+    }
+}
+
+impl Searchable for Value {
+    fn source(&self) -> &Value {
+        self
+    }
+}
+
+/// A document borrowed from a named index, so hits merged across several
+/// indices keep track of where they came from, along with its position in
+/// the index's doc-values.
+#[derive(Clone, Copy)]
+pub struct IndexedDocument<'a> {
+    pub index: &'a str,
+    pub doc: &'a StoredDocument,
+    pub position: u64,
+    pub values: &'a DocValues,
+}
+
+impl Searchable for IndexedDocument<'_> {
+    fn source(&self) -> &Value {
+        &self.doc.source
+    }
+
+    fn doc_values(&self, field: &str) -> Cow<'_, [DocValue]> {
+        let metadata = match field {
+            "_id" => &self.doc.id,
+            "_index" => self.index,
+            _ => return Cow::Borrowed(self.values.get(field, self.position)),
+        };
+        Cow::Owned(vec![DocValue::Keyword(metadata.to_string())])
+    }
+}
+
 pub struct SearchEngine;
 
 impl SearchEngine {
-    pub fn search(
-        documents: &[Value],
+    /// Filters, sorts and pages the documents; only the documents of the
+    /// requested page are cloned.
+    pub fn search<T: Searchable + Clone>(
+        documents: &[T],
         query: &dyn Query,
         sort: Option<SortOptions>,
         from: usize,
         size: usize,
-    ) -> Vec<Value> {
-        let mut results: Vec<Value> = documents
+    ) -> Vec<T> {
+        let mut results: Vec<&T> = documents
             .iter()
-            .filter(|doc| query.matches(doc))
-            .cloned()
+            .filter(|doc| query.matches(doc.source()))
             .collect();
 
         if let Some(options) = sort {
-            let field_name = options.field.strip_suffix(".keyword").unwrap_or(&options.field);
-            
-            results.sort_by(|a, b| {
-                let val_a = a.get(field_name);
-                let val_b = b.get(field_name);
+            let mut keyed: Vec<(Option<DocValue>, &T)> = results
+                .into_iter()
+                .map(|doc| (Self::sort_key(doc, &options), doc))
+                .collect();
 
+            keyed.sort_by(|(val_a, _), (val_b, _)| {
                 let cmp = match (val_a, val_b) {
-                    (Some(v1), Some(v2)) => Self::compare_values(v1, v2),
+                    (Some(v1), Some(v2)) => v1.compare(v2),
                     (Some(_), None) => Ordering::Greater,
                     (None, Some(_)) => Ordering::Less,
                     (None, None) => Ordering::Equal,
@@ -62,56 +148,104 @@ impl SearchEngine {
                     SortOrder::Desc => cmp.reverse(),
                 }
             });
+            results = keyed.into_iter().map(|(_, doc)| doc).collect();
         }
 
-        results.into_iter().skip(from).take(size).collect()
+        results.into_iter().skip(from).take(size).cloned().collect()
+    }
+
+    /// The value a document sorts by: the script's result for `_script`
+    /// sorts, otherwise the lowest value of the field ascending and the
+    /// highest descending, as Elasticsearch does for multi-valued fields.
+    fn sort_key<T: Searchable>(doc: &T, options: &SortOptions) -> Option<DocValue> {
+        if let Some(script) = &options.script {
+            let value = script.evaluate(doc.source()).ok()?;
+            return DocValue::from_json(&value, None);
+        }
+        let values = doc.doc_values(&options.field);
+        let ordered = values.iter().min_by(|a, b| match options.order {
+            SortOrder::Asc => a.compare(b),
+            SortOrder::Desc => b.compare(a),
+        });
+        ordered.cloned()
     }
 
-    pub fn aggregate(
-        filtered_documents: &[Value],
-        aggregations: &[TermsAggregation],
+    pub fn aggregate<T: Searchable>(
+        filtered_documents: &[T],
+        aggregations: &[Aggregation],
     ) -> Vec<AggregationResult> {
-        let mut results = Vec::new();
-
-        for agg in aggregations {
-            let field_name = agg.field.strip_suffix(".keyword").unwrap_or(&agg.field);
-            let mut counts: HashMap<String, (Value, usize)> = HashMap::new();
-
-            for doc in filtered_documents {
-                if let Some(val) = doc.get(field_name) {
-                    let key_str = match val {
-                        Value::String(s) => s.clone(),
-                        Value::Number(n) => n.to_string(),
-                        Value::Bool(b) => b.to_string(),
-                        _ => continue,
-                    };
-
-                    let entry = counts.entry(key_str).or_insert((val.clone(), 0));
-                    entry.1 += 1;
+        aggregations
+            .iter()
+            .map(|agg| {
+                let stats = || Self::stats(filtered_documents, &agg.field);
+                let value = match agg.kind {
+                    AggregationKind::Terms => {
+                        AggregationValue::Buckets(Self::terms(filtered_documents, &agg.field))
+                    }
+                    AggregationKind::ValueCount => {
+                        let count: usize = filtered_documents
+                            .iter()
+                            .map(|doc| doc.doc_values(&agg.field).len())
+                            .sum();
+                        AggregationValue::Metric(json!(count))
+                    }
+                    AggregationKind::Min => AggregationValue::Metric(json!(stats().min)),
+                    AggregationKind::Max => AggregationValue::Metric(json!(stats().max)),
+                    AggregationKind::Avg => AggregationValue::Metric(json!(stats().avg())),
+                    AggregationKind::Sum => AggregationValue::Metric(json!(stats().sum)),
+                    AggregationKind::Stats => AggregationValue::Stats(stats()),
+                };
+                AggregationResult {
+                    name: agg.name.clone(),
+                    value,
                 }
-            }
+            })
+            .collect()
+    }
 
-            let mut buckets: Vec<Bucket> = counts
-                .into_values()
-                .map(|(key, doc_count)| Bucket { key, doc_count })
-                .collect();
+    /// Buckets per distinct value, each document counted once per value,
+    /// ordered by document count and then by key.
+    fn terms<T: Searchable>(documents: &[T], field: &str) -> Vec<Bucket> {
+        let mut counts: HashMap<String, (DocValue, usize)> = HashMap::new();
+        for doc in documents {
+            let values = doc.doc_values(field);
+            let mut seen: Vec<&DocValue> = Vec::new();
+            for value in values.iter() {
+                if seen.contains(&value) {
+                    continue;
+                }
+                seen.push(value);
+                let key = value.to_json().to_string();
+                counts.entry(key).or_insert_with(|| (value.clone(), 0)).1 += 1;
+            }
+        }
 
-            buckets.sort_by(|a, b| b.doc_count.cmp(&a.doc_count).then_with(|| {
-                let key_a = a.key.as_str().unwrap_or("");
-                let key_b = b.key.as_str().unwrap_or("");
-                key_a.cmp(key_b)
-            }));
+        let mut buckets: Vec<(DocValue, usize)> = counts.into_values().collect();
+        buckets.sort_by(|(key_a, count_a), (key_b, count_b)| {
+            count_b.cmp(count_a).then_with(|| key_a.compare(key_b))
+        });
+        buckets
+            .into_iter()
+            .map(|(key, doc_count)| Bucket {
+                key: key.to_json(),
+                doc_count,
+            })
+            .collect()
+    }
 
-            results.push(AggregationResult {
-                name: agg.name.clone(),
-                buckets,
-            });
+    fn stats<T: Searchable>(documents: &[T], field: &str) -> Stats {
+        let mut stats = Stats::default();
+        for doc in documents {
+            for value in doc.doc_values(field).iter() {
+                if let Some(number) = value.as_f64() {
+                    stats.add(number);
+                }
+            }
         }
-
-        results
+        stats
     }
 
-    fn compare_values(a: &Value, b: &Value) -> Ordering {
+    pub fn compare_values(a: &Value, b: &Value) -> Ordering {
         if let (Some(f1), Some(f2)) = (a.as_f64(), b.as_f64()) {
             return f1.partial_cmp(&f2).unwrap_or(Ordering::Equal);
         }
@@ -154,39 +288,36 @@ mod tests {
         let sort = Some(SortOptions {
             field: "val".to_string(),
             order: SortOrder::Asc,
+            script: None,
         });
 
         let results = SearchEngine::search(&docs, &MatchAllQuery, sort, 0, 10);
-        
+
         assert_eq!(results[0]["id"], 1);
         assert_eq!(results[2]["id"], 3);
     }
 
     #[test]
     fn should_sort_documents_descending() {
-        let docs = vec![
-            json!({"id": 1, "val": 10}),
-            json!({"id": 2, "val": 20}),
-        ];
+        let docs = vec![json!({"id": 1, "val": 10}), json!({"id": 2, "val": 20})];
         let sort = Some(SortOptions {
             field: "val".to_string(),
             order: SortOrder::Desc,
+            script: None,
         });
 
         let results = SearchEngine::search(&docs, &MatchAllQuery, sort, 0, 10);
-        
+
         assert_eq!(results[0]["id"], 2);
     }
 
     #[test]
     fn should_handle_keyword_suffix_in_sort() {
-        let docs = vec![
-            json!({"name": "B"}),
-            json!({"name": "A"}),
-        ];
+        let docs = vec![json!({"name": "B"}), json!({"name": "A"})];
         let sort = Some(SortOptions {
             field: "name.keyword".to_string(),
             order: SortOrder::Asc,
+            script: None,
         });
 
         let results = SearchEngine::search(&docs, &MatchAllQuery, sort, 0, 10);
@@ -215,7 +346,7 @@ mod tests {
         ];
 
         let results = SearchEngine::search(&docs, &MatchAllQuery, None, 1, 2);
-        
+
         assert_eq!(results.len(), 2);
         assert_eq!(results[0]["id"], 2);
         assert_eq!(results[1]["id"], 3);
@@ -229,21 +360,25 @@ mod tests {
             json!({"color": "red"}),
             json!({"color": "green"}),
         ];
-        let aggs = vec![TermsAggregation {
+        let aggs = vec![Aggregation {
             name: "colors".to_string(),
+            kind: AggregationKind::Terms,
             field: "color.keyword".to_string(),
         }];
 
         let results = SearchEngine::aggregate(&docs, &aggs);
-        
+
         assert_eq!(results.len(), 1);
         let agg_res = &results[0];
         assert_eq!(agg_res.name, "colors");
-        
-        let red_bucket = agg_res.buckets.iter().find(|b| b.key == json!("red")).unwrap();
+
+        let AggregationValue::Buckets(buckets) = &agg_res.value else {
+            panic!("expected buckets");
+        };
+        let red_bucket = buckets.iter().find(|b| b.key == json!("red")).unwrap();
         assert_eq!(red_bucket.doc_count, 2);
-        
-        let blue_bucket = agg_res.buckets.iter().find(|b| b.key == json!("blue")).unwrap();
+
+        let blue_bucket = buckets.iter().find(|b| b.key == json!("blue")).unwrap();
         assert_eq!(blue_bucket.doc_count, 1);
     }
-}
\ No newline at end of file
+}
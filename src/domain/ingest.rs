@@ -0,0 +1,297 @@
+use crate::domain::script::Script;
+use serde_json::{Map, Value};
+
+/// An ingest pipeline: an ordered list of processors applied to a document's
+/// `_source` before it is written.
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    /// The definition as submitted, returned verbatim by the GET API.
+    pub definition: Value,
+    processors: Vec<ConfiguredProcessor>,
+}
+
+#[derive(Debug, Clone)]
+struct ConfiguredProcessor {
+    processor: Processor,
+    condition: Option<Script>,
+    ignore_failure: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Processor {
+    Set {
+        field: String,
+        value: Value,
+        override_existing: bool,
+    },
+    Remove {
+        fields: Vec<String>,
+        ignore_missing: bool,
+    },
+    Rename {
+        field: String,
+        target_field: String,
+        ignore_missing: bool,
+    },
+    Lowercase(CaseOptions),
+    Uppercase(CaseOptions),
+    Script(Script),
+}
+
+#[derive(Debug, Clone)]
+struct CaseOptions {
+    field: String,
+    target_field: String,
+    ignore_missing: bool,
+}
+
+fn required_string(config: &Value, kind: &str, name: &str) -> Result<String, String> {
+    config[name]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("[{}] required property is missing", name))
+        .map_err(|e| format!("[{}] {}", kind, e))
+}
+
+impl Pipeline {
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let processors = match json.get("processors") {
+            Some(Value::Array(processors)) => processors,
+            Some(_) => return Err("[processors] must be an array".to_string()),
+            None => return Err("[processors] required property is missing".to_string()),
+        };
+
+        let processors = processors
+            .iter()
+            .map(parse_processor)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            definition: json.clone(),
+            processors,
+        })
+    }
+
+    /// Runs every processor in order against `source`.
+    pub fn execute(&self, source: &mut Value) -> Result<(), String> {
+        for configured in &self.processors {
+            if let Some(condition) = &configured.condition
+                && condition.execute_ingest(source)? != Value::Bool(true)
+            {
+                continue;
+            }
+            match configured.processor.execute(source) {
+                Ok(()) => {}
+                Err(_) if configured.ignore_failure => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_processor(json: &Value) -> Result<ConfiguredProcessor, String> {
+    let Some((kind, config)) = json.as_object().and_then(|o| o.iter().next()) else {
+        return Err("processor must be an object with a single processor type".to_string());
+    };
+    let ignore_missing = config["ignore_missing"].as_bool().unwrap_or(false);
+    let case_options = || -> Result<CaseOptions, String> {
+        let field = required_string(config, kind, "field")?;
+        Ok(CaseOptions {
+            target_field: config["target_field"]
+                .as_str()
+                .map_or_else(|| field.clone(), str::to_string),
+            field,
+            ignore_missing,
+        })
+    };
+
+    let processor = match kind.as_str() {
+        "set" => Processor::Set {
+            field: required_string(config, kind, "field")?,
+            value: config
+                .get("value")
+                .cloned()
+                .ok_or("[set] [value] required property is missing")?,
+            override_existing: config["override"].as_bool().unwrap_or(true),
+        },
+        "remove" => Processor::Remove {
+            fields: match &config["field"] {
+                Value::String(field) => vec![field.clone()],
+                Value::Array(fields) => fields
+                    .iter()
+                    .filter_map(|f| f.as_str().map(str::to_string))
+                    .collect(),
+                _ => return Err("[remove] [field] required property is missing".to_string()),
+            },
+            ignore_missing,
+        },
+        "rename" => Processor::Rename {
+            field: required_string(config, kind, "field")?,
+            target_field: required_string(config, kind, "target_field")?,
+            ignore_missing,
+        },
+        "lowercase" => Processor::Lowercase(case_options()?),
+        "uppercase" => Processor::Uppercase(case_options()?),
+        "script" => Processor::Script(Script::from_json(config)?),
+        other => return Err(format!("No processor type exists with name [{}]", other)),
+    };
+
+    Ok(ConfiguredProcessor {
+        processor,
+        condition: config.get("if").map(Script::from_json).transpose()?,
+        ignore_failure: config["ignore_failure"].as_bool().unwrap_or(false),
+    })
+}
+
+impl Processor {
+    fn execute(&self, source: &mut Value) -> Result<(), String> {
+        match self {
+            Processor::Set {
+                field,
+                value,
+                override_existing,
+            } => {
+                if *override_existing || get_path(source, field).is_none_or(Value::is_null) {
+                    set_path(source, field, value.clone());
+                }
+                Ok(())
+            }
+            Processor::Remove {
+                fields,
+                ignore_missing,
+            } => {
+                for field in fields {
+                    if remove_path(source, field).is_none() && !ignore_missing {
+                        return Err(missing_field(field));
+                    }
+                }
+                Ok(())
+            }
+            Processor::Rename {
+                field,
+                target_field,
+                ignore_missing,
+            } => match remove_path(source, field) {
+                Some(value) => {
+                    set_path(source, target_field, value);
+                    Ok(())
+                }
+                None if *ignore_missing => Ok(()),
+                None => Err(missing_field(field)),
+            },
+            Processor::Lowercase(options) => change_case(source, options, str::to_lowercase),
+            Processor::Uppercase(options) => change_case(source, options, str::to_uppercase),
+            Processor::Script(script) => script.execute_ingest(source).map(|_| ()),
+        }
+    }
+}
+
+fn change_case(
+    source: &mut Value,
+    options: &CaseOptions,
+    convert: fn(&str) -> String,
+) -> Result<(), String> {
+    match get_path(source, &options.field) {
+        Some(Value::String(text)) => {
+            let converted = Value::String(convert(text));
+            set_path(source, &options.target_field, converted);
+            Ok(())
+        }
+        Some(Value::Null) | None if options.ignore_missing => Ok(()),
+        Some(Value::Null) | None => Err(missing_field(&options.field)),
+        Some(_) => Err(format!(
+            "field [{}] cannot be cast to [java.lang.String]",
+            options.field
+        )),
+    }
+}
+
+fn missing_field(field: &str) -> String {
+    format!("field [{}] not present as part of path [{}]", field, field)
+}
+
+fn get_path<'a>(source: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(source, |current, key| current.get(key))
+}
+
+fn set_path(source: &mut Value, path: &str, value: Value) {
+    let mut current = source;
+    let mut keys = path.split('.').peekable();
+    while let Some(key) = keys.next() {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        let object = current.as_object_mut().expect("just made an object");
+        if keys.peek().is_none() {
+            object.insert(key.to_string(), value);
+            return;
+        }
+        current = object
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+}
+
+fn remove_path(source: &mut Value, path: &str) -> Option<Value> {
+    match path.rsplit_once('.') {
+        Some((parent, key)) => {
+            let parent = path_mut(source, parent)?;
+            parent.as_object_mut()?.shift_remove(key)
+        }
+        None => source.as_object_mut()?.shift_remove(path),
+    }
+}
+
+fn path_mut<'a>(source: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.')
+        .try_fold(source, |current, key| current.get_mut(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_run_processors_in_order() {
+        let pipeline = Pipeline::from_json(&json!({
+            "description": "migrate",
+            "processors": [
+                { "rename": { "field": "user.name", "target_field": "owner" } },
+                { "lowercase": { "field": "owner" } },
+                { "set": { "field": "meta.migrated", "value": true } },
+                { "set": { "field": "status", "value": "new", "override": false } },
+                { "remove": { "field": "tmp", "ignore_missing": true } },
+                { "script": {
+                    "if": "ctx.count != null",
+                    "source": "ctx.count *= params.factor",
+                    "params": { "factor": 10 }
+                } }
+            ]
+        }))
+        .unwrap();
+
+        let mut source = json!({ "user": { "name": "ADA" }, "status": "old", "count": 2 });
+        pipeline.execute(&mut source).unwrap();
+        assert_eq!(
+            source,
+            json!({
+                "user": {},
+                "status": "old",
+                "count": 20,
+                "owner": "ada",
+                "meta": { "migrated": true }
+            })
+        );
+
+        let mut missing = json!({});
+        assert!(pipeline.execute(&mut missing).is_err());
+    }
+
+    #[test]
+    fn should_reject_unknown_processors() {
+        let error = Pipeline::from_json(&json!({ "processors": [{ "grok": {} }] })).unwrap_err();
+        assert!(error.contains("No processor type exists with name [grok]"));
+    }
+}
@@ -0,0 +1,203 @@
+use serde_json::Value;
+
+/// Positions of the documents holding a term, in the order the documents
+/// were first written.
+pub type Postings = im::OrdSet<u64>;
+
+/// field -> term -> postings
+type FieldPostings = im::HashMap<String, im::HashMap<String, Postings>>;
+
+/// Per-field postings for the documents of an index, keyed by the dotted
+/// path of each field (`offers.url`), so term-level and full-text queries
+/// can find their candidates without visiting every document.
+///
+/// `terms` holds the exact scalar values and `tokens` their analyzed form.
+/// Both are persistent maps, which keeps snapshots of an index cheap.
+#[derive(Clone, Default)]
+pub struct InvertedIndex {
+    terms: FieldPostings,
+    tokens: FieldPostings,
+}
+
+impl InvertedIndex {
+    pub fn add(&mut self, position: u64, source: &Value) {
+        for (field, value) in leaf_values(source) {
+            insert(&mut self.terms, &field, term_key(value), position);
+            for token in value_tokens(value) {
+                insert(&mut self.tokens, &field, token, position);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, position: u64, source: &Value) {
+        for (field, value) in leaf_values(source) {
+            discard(&mut self.terms, &field, &term_key(value), position);
+            for token in value_tokens(value) {
+                discard(&mut self.tokens, &field, &token, position);
+            }
+        }
+    }
+
+    /// Documents holding exactly `value` in `field`.
+    pub fn term(&self, field: &str, value: &Value) -> Postings {
+        lookup(&self.terms, field, &term_key(value))
+    }
+
+    /// Documents whose analyzed `field` contains `token`.
+    pub fn token(&self, field: &str, token: &str) -> Postings {
+        lookup(&self.tokens, field, token)
+    }
+}
+
+fn insert(postings: &mut FieldPostings, field: &str, key: String, position: u64) {
+    postings
+        .entry(field.to_string())
+        .or_default()
+        .entry(key)
+        .or_default()
+        .insert(position);
+}
+
+fn discard(postings: &mut FieldPostings, field: &str, key: &str, position: u64) {
+    let Some(values) = postings.get_mut(field) else {
+        return;
+    };
+    if let Some(positions) = values.get_mut(key) {
+        positions.remove(&position);
+        if positions.is_empty() {
+            values.remove(key);
+        }
+    }
+    if values.is_empty() {
+        postings.remove(field);
+    }
+}
+
+fn lookup(postings: &FieldPostings, field: &str, key: &str) -> Postings {
+    postings
+        .get(field)
+        .and_then(|values| values.get(key))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Scalars are keyed by their JSON form, so `"1"` and `1` stay distinct as
+/// they are for `term` queries.
+fn term_key(value: &Value) -> String {
+    value.to_string()
+}
+
+/// The standard analyzer, reduced to what the fake needs: text is split on
+/// anything that is not a letter or a digit and lowercased.
+pub fn analyze(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Tokens of a single value: strings are analyzed, numbers and booleans are
+/// kept whole.
+pub fn value_tokens(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(text) => analyze(text),
+        Value::Number(_) | Value::Bool(_) => vec![value.to_string()],
+        _ => Vec::new(),
+    }
+}
+
+/// Values found under a dotted `path`, looking through arrays at any level
+/// the way Elasticsearch flattens them.
+pub fn field_values<'a>(doc: &'a Value, path: &str) -> Vec<&'a Value> {
+    let mut values = Vec::new();
+    collect_path(doc, path, &mut values);
+    values
+}
+
+fn collect_path<'a>(current: &'a Value, path: &str, values: &mut Vec<&'a Value>) {
+    match current {
+        Value::Array(items) => {
+            for item in items {
+                collect_path(item, path, values);
+            }
+        }
+        _ if path.is_empty() => values.push(current),
+        Value::Object(map) => {
+            let (key, rest) = path.split_once('.').unwrap_or((path, ""));
+            if let Some(next) = map.get(key) {
+                collect_path(next, rest, values);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Every scalar of a document with the dotted path it was found under.
+pub fn leaf_values(source: &Value) -> Vec<(String, &Value)> {
+    let mut leaves = Vec::new();
+    collect_leaves(source, String::new(), &mut leaves);
+    leaves
+}
+
+fn collect_leaves<'a>(value: &'a Value, path: String, leaves: &mut Vec<(String, &'a Value)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                let child_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                collect_leaves(child, child_path, leaves);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_leaves(item, path.clone(), leaves);
+            }
+        }
+        Value::Null => {}
+        _ => leaves.push((path, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_index_terms_and_tokens_per_field() {
+        let mut index = InvertedIndex::default();
+        index.add(
+            0,
+            &json!({ "title": "Quick Brown fox", "offers": [{ "price": 10 }] }),
+        );
+        index.add(1, &json!({ "title": "quick-fox", "tags": ["a", "b"] }));
+
+        assert_eq!(index.token("title", "quick"), Postings::from(vec![0, 1]));
+        assert_eq!(index.token("title", "brown"), Postings::unit(0));
+        assert_eq!(index.term("title", &json!("quick-fox")), Postings::unit(1));
+        assert_eq!(index.term("offers.price", &json!(10)), Postings::unit(0));
+        assert_eq!(index.term("tags", &json!("b")), Postings::unit(1));
+        assert!(index.term("offers.price", &json!("10")).is_empty());
+
+        index.remove(
+            0,
+            &json!({ "title": "Quick Brown fox", "offers": [{ "price": 10 }] }),
+        );
+        assert_eq!(index.token("title", "quick"), Postings::unit(1));
+        assert!(index.term("offers.price", &json!(10)).is_empty());
+    }
+
+    #[test]
+    fn should_read_values_through_arrays() {
+        let doc = json!({ "offers": [{ "url": "a" }, { "url": ["b", "c"] }], "n": 1 });
+        assert_eq!(
+            field_values(&doc, "offers.url"),
+            vec![&json!("a"), &json!("b"), &json!("c")]
+        );
+        assert_eq!(field_values(&doc, "n.missing"), Vec::<&Value>::new());
+        assert_eq!(analyze("Hello, World-42!"), vec!["hello", "world", "42"]);
+    }
+}
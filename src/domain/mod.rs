@@ -1,3 +1,17 @@
+pub mod alias;
+pub mod data_stream;
+pub mod doc_values;
+pub mod document;
 pub mod engine;
+pub mod ingest;
+pub mod inverted_index;
 pub mod mapping;
 pub mod query;
+pub mod script;
+pub mod settings;
+pub mod snapshot;
+pub mod source;
+pub mod template;
+pub mod time;
+pub mod update;
+pub mod versioning;
@@ -1,9 +1,25 @@
+use crate::domain::document::StoredDocument;
 use crate::domain::engine::{SortOptions, SortOrder};
+use crate::domain::inverted_index::{InvertedIndex, Postings, field_values, value_tokens};
+use crate::domain::script::Script;
 use serde_json::Value;
 use std::fmt::Debug;
 
 pub trait Query: Debug + Send + Sync {
     fn matches(&self, doc: &Value) -> bool;
+
+    /// Matches a document stored in `index`. Unlike `matches`, this sees the
+    /// `_id` and `_index` metadata fields, which are not part of `_source`.
+    fn matches_document(&self, _index: &str, doc: &StoredDocument) -> bool {
+        self.matches(&doc.source)
+    }
+
+    /// Plans the query against the postings of an index: returns the
+    /// documents that may match, or `None` when every document has to be
+    /// scanned. Candidates are still checked with `matches`.
+    fn candidates(&self, _index: &InvertedIndex) -> Option<Postings> {
+        None
+    }
 }
 
 #[derive(Debug)]
@@ -15,6 +31,32 @@ impl Query for MatchAllQuery {
     }
 }
 
+#[derive(Debug)]
+pub struct MatchNoneQuery;
+
+impl Query for MatchNoneQuery {
+    fn matches(&self, _doc: &Value) -> bool {
+        false
+    }
+
+    fn candidates(&self, _index: &InvertedIndex) -> Option<Postings> {
+        Some(Postings::new())
+    }
+}
+
+/// Matches documents for which the script returns `true`; a script that
+/// fails on a document does not match it.
+#[derive(Debug)]
+pub struct ScriptQuery {
+    pub script: Script,
+}
+
+impl Query for ScriptQuery {
+    fn matches(&self, doc: &Value) -> bool {
+        self.script.evaluate(doc) == Ok(Value::Bool(true))
+    }
+}
+
 #[derive(Debug)]
 pub struct TermQuery {
     pub field: String,
@@ -23,8 +65,145 @@ pub struct TermQuery {
 
 impl Query for TermQuery {
     fn matches(&self, doc: &Value) -> bool {
-        let field_path = self.field.strip_suffix(".keyword").unwrap_or(&self.field);
-        Self::matches_path(doc, field_path, &self.value)
+        field_values(doc, field_path(&self.field)).contains(&&self.value)
+    }
+
+    fn matches_document(&self, index: &str, doc: &StoredDocument) -> bool {
+        match metadata_field(&self.field, index, doc) {
+            Some(actual) => actual.is_some_and(|actual| is_term(&self.value, actual)),
+            None => self.matches(&doc.source),
+        }
+    }
+
+    fn candidates(&self, index: &InvertedIndex) -> Option<Postings> {
+        if is_metadata_field(&self.field) {
+            return None;
+        }
+        is_scalar(&self.value).then(|| index.term(field_path(&self.field), &self.value))
+    }
+}
+
+/// Matches documents holding any of the given values.
+#[derive(Debug)]
+pub struct TermsQuery {
+    pub field: String,
+    pub values: Vec<Value>,
+}
+
+impl Query for TermsQuery {
+    fn matches(&self, doc: &Value) -> bool {
+        field_values(doc, field_path(&self.field))
+            .iter()
+            .any(|value| self.values.contains(value))
+    }
+
+    fn matches_document(&self, index: &str, doc: &StoredDocument) -> bool {
+        match metadata_field(&self.field, index, doc) {
+            Some(actual) => {
+                actual.is_some_and(|actual| self.values.iter().any(|value| is_term(value, actual)))
+            }
+            None => self.matches(&doc.source),
+        }
+    }
+
+    fn candidates(&self, index: &InvertedIndex) -> Option<Postings> {
+        if is_metadata_field(&self.field) || !self.values.iter().all(is_scalar) {
+            return None;
+        }
+        let field = field_path(&self.field);
+        Some(Postings::unions(
+            self.values.iter().map(|value| index.term(field, value)),
+        ))
+    }
+}
+
+/// Full-text `match` query: the text is analyzed and documents containing
+/// any of its tokens (or all of them with `operator: and`) match. On a
+/// `.keyword` field the text has to match the whole value instead.
+#[derive(Debug)]
+pub struct MatchQuery {
+    pub field: String,
+    pub query: Value,
+    pub require_all: bool,
+}
+
+impl MatchQuery {
+    fn keyword(&self) -> Option<&str> {
+        self.field.strip_suffix(".keyword")
+    }
+}
+
+impl Query for MatchQuery {
+    fn matches(&self, doc: &Value) -> bool {
+        if let Some(field) = self.keyword() {
+            return field_values(doc, field).contains(&&self.query);
+        }
+        let tokens = value_tokens(&self.query);
+        if tokens.is_empty() {
+            return false;
+        }
+        let found: Vec<String> = field_values(doc, &self.field)
+            .into_iter()
+            .flat_map(value_tokens)
+            .collect();
+        let mut matched = tokens.iter().map(|token| found.contains(token));
+        if self.require_all {
+            matched.all(|m| m)
+        } else {
+            matched.any(|m| m)
+        }
+    }
+
+    fn candidates(&self, index: &InvertedIndex) -> Option<Postings> {
+        if let Some(field) = self.keyword() {
+            return is_scalar(&self.query).then(|| index.term(field, &self.query));
+        }
+        let mut postings = value_tokens(&self.query)
+            .into_iter()
+            .map(|token| index.token(&self.field, &token));
+        let first = postings.next().unwrap_or_default();
+        Some(if self.require_all {
+            postings.fold(first, Postings::intersection)
+        } else {
+            postings.fold(first, Postings::union)
+        })
+    }
+}
+
+fn field_path(field: &str) -> &str {
+    field.strip_suffix(".keyword").unwrap_or(field)
+}
+
+fn is_scalar(value: &Value) -> bool {
+    matches!(value, Value::String(_) | Value::Number(_) | Value::Bool(_))
+}
+
+fn is_metadata_field(field: &str) -> bool {
+    matches!(field, "_id" | "_index" | "_routing")
+}
+
+/// Value of a metadata field, which lives in the document envelope rather
+/// than in `_source`; `Some(None)` for a document without `_routing`.
+fn metadata_field<'a>(
+    field: &str,
+    index: &'a str,
+    doc: &'a StoredDocument,
+) -> Option<Option<&'a str>> {
+    match field {
+        "_id" => Some(Some(&doc.id)),
+        "_index" => Some(Some(index)),
+        "_routing" => Some(doc.routing.as_deref()),
+        _ => None,
+    }
+}
+
+/// Compares a query term with a metadata value, which is always a string;
+/// numeric terms such as `{"_id": 1}` are compared by their text.
+fn is_term(value: &Value, actual: &str) -> bool {
+    match value {
+        Value::String(value) => value == actual,
+        Value::Number(value) => value.to_string() == actual,
+        _ => false,
     }
 }
 
@@ -35,10 +214,10 @@ pub struct BoolQuery {
     pub must_not: Vec<Box<dyn Query>>,
 }
 
-impl Query for BoolQuery {
-    fn matches(&self, doc: &Value) -> bool {
-        let must_matches = self.must.iter().all(|q| q.matches(doc));
-        let must_not_matches = self.must_not.iter().all(|q| !q.matches(doc));
+impl BoolQuery {
+    fn combine(&self, matches: impl Fn(&dyn Query) -> bool) -> bool {
+        let must_matches = self.must.iter().all(|q| matches(q.as_ref()));
+        let must_not_matches = self.must_not.iter().all(|q| !matches(q.as_ref()));
 
         if !must_matches || !must_not_matches {
             return false;
@@ -48,67 +227,107 @@ impl Query for BoolQuery {
             return true;
         }
 
-        self.should.iter().any(|q| q.matches(doc))
+        self.should.iter().any(|q| matches(q.as_ref()))
     }
 }
 
-impl TermQuery {
-    fn matches_path(current_value: &Value, path: &str, target: &Value) -> bool {
-        if path.is_empty() {
-            return current_value == target;
-        }
+impl Query for BoolQuery {
+    fn matches(&self, doc: &Value) -> bool {
+        self.combine(|q| q.matches(doc))
+    }
 
-        let mut parts = path.splitn(2, '.');
-        let current_key = parts.next().unwrap();
-        let remaining_path = parts.next().unwrap_or("");
+    fn matches_document(&self, index: &str, doc: &StoredDocument) -> bool {
+        self.combine(|q| q.matches_document(index, doc))
+    }
 
-        match current_value {
-            Value::Object(map) => {
-                if let Some(next_val) = map.get(current_key) {
-                    return Self::matches_path(next_val, remaining_path, target);
-                }
-                false
+    /// Intersects the postings of the required clauses, narrowed down by the
+    /// union of the `should` clauses when all of them can be planned.
+    /// `must_not` clauses are left to `matches`.
+    fn candidates(&self, index: &InvertedIndex) -> Option<Postings> {
+        let mut candidates: Option<Postings> = None;
+        for postings in self.must.iter().filter_map(|q| q.candidates(index)) {
+            candidates = Some(match candidates {
+                Some(current) => current.intersection(postings),
+                None => postings,
+            });
+        }
+        if !self.should.is_empty() {
+            let should: Option<Vec<Postings>> =
+                self.should.iter().map(|q| q.candidates(index)).collect();
+            if let Some(should) = should {
+                let any = Postings::unions(should);
+                candidates = Some(match candidates {
+                    Some(current) => current.intersection(any),
+                    None => any,
+                });
             }
-            Value::Array(arr) => arr.iter().any(|item| {
-                if let Some(obj) = item.as_object() {
-                    if let Some(next_val) = obj.get(current_key) {
-                        return Self::matches_path(next_val, remaining_path, target);
-                    }
-                }
-                Self::matches_path(item, path, target)
-            }),
-            _ => false,
         }
+        candidates
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum AggregationKind {
+    Terms,
+    Min,
+    Max,
+    Avg,
+    Sum,
+    ValueCount,
+    Stats,
+}
+
+impl AggregationKind {
+    const ALL: [(&str, AggregationKind); 7] = [
+        ("terms", AggregationKind::Terms),
+        ("min", AggregationKind::Min),
+        ("max", AggregationKind::Max),
+        ("avg", AggregationKind::Avg),
+        ("sum", AggregationKind::Sum),
+        ("value_count", AggregationKind::ValueCount),
+        ("stats", AggregationKind::Stats),
+    ];
+}
+
+/// A `terms` bucket aggregation or a single-field metric aggregation.
 #[derive(Debug, Clone)]
-pub struct TermsAggregation {
+pub struct Aggregation {
     pub name: String,
+    pub kind: AggregationKind,
     pub field: String,
 }
 
-pub fn parse_query(json: &Value) -> Box<dyn Query> {
+/// Parses the `query` of a request body; the error is the reason a script
+/// in it failed to compile.
+pub fn parse_query(json: &Value) -> Result<Box<dyn Query>, String> {
     if let Some(query_obj) = json.get("query") {
         return parse_query_internal(query_obj);
     }
-    Box::new(MatchAllQuery)
+    Ok(Box::new(MatchAllQuery))
+}
+
+/// Parses a bare query clause such as an alias filter, which is not wrapped
+/// in a `query` object. A filter whose script does not compile matches
+/// nothing.
+pub fn parse_filter(json: &Value) -> Box<dyn Query> {
+    parse_query_internal(json).unwrap_or_else(|_| Box::new(MatchNoneQuery))
 }
 
-pub fn parse_aggregations(json: &Value) -> Vec<TermsAggregation> {
+pub fn parse_aggregations(json: &Value) -> Vec<Aggregation> {
     let mut aggregations = Vec::new();
     let aggs_node = json.get("aggs").or_else(|| json.get("aggregations"));
 
     if let Some(aggs_obj) = aggs_node.and_then(|v| v.as_object()) {
         for (name, body) in aggs_obj {
-            if let Some(terms) = body
-                .get("terms")
-                .and_then(|t| t.get("field"))
-                .and_then(|f| f.as_str())
-            {
-                aggregations.push(TermsAggregation {
+            let definition = AggregationKind::ALL.iter().find_map(|(key, kind)| {
+                let field = body.get(*key)?.get("field")?.as_str()?;
+                Some((kind.clone(), field))
+            });
+            if let Some((kind, field)) = definition {
+                aggregations.push(Aggregation {
                     name: name.clone(),
-                    field: terms.to_string(),
+                    kind,
+                    field: field.to_string(),
                 });
             }
         }
@@ -122,52 +341,99 @@ pub fn parse_pagination(json: &Value) -> (usize, usize) {
     (from, size)
 }
 
-fn parse_query_internal(json: &Value) -> Box<dyn Query> {
+fn parse_query_internal(json: &Value) -> Result<Box<dyn Query>, String> {
     if let Some(bool_obj) = json.get("bool") {
-        return Box::new(parse_bool(bool_obj));
+        return Ok(Box::new(parse_bool(bool_obj)?));
+    }
+    if let Some(term_obj) = json.get("term")
+        && let Some((field, value)) = term_obj.as_object().and_then(|o| o.iter().next())
+    {
+        return Ok(Box::new(TermQuery {
+            field: field.clone(),
+            value: value.get("value").unwrap_or(value).clone(),
+        }));
     }
-    if let Some(term_obj) = json.get("term") {
-        if let Some((field, value)) = term_obj.as_object().and_then(|o| o.iter().next()) {
-            return Box::new(TermQuery {
+    if let Some((field, values)) = json.get("terms").and_then(field_clause) {
+        return Ok(match values.as_array() {
+            Some(values) => Box::new(TermsQuery {
                 field: field.clone(),
-                value: value.clone(),
-            });
-        }
+                values: values.clone(),
+            }),
+            None => Box::new(MatchNoneQuery),
+        });
+    }
+    if let Some(ids) = json.get("ids") {
+        return Ok(Box::new(TermsQuery {
+            field: "_id".to_string(),
+            values: ids["values"].as_array().cloned().unwrap_or_default(),
+        }));
+    }
+    if let Some((field, body)) = json.get("match").and_then(field_clause) {
+        let query = body.get("query").unwrap_or(body).clone();
+        return Ok(Box::new(MatchQuery {
+            field: field.clone(),
+            query,
+            require_all: body
+                .get("operator")
+                .and_then(|o| o.as_str())
+                .is_some_and(|o| o.eq_ignore_ascii_case("and")),
+        }));
     }
-    Box::new(MatchAllQuery)
+    if let Some(script_obj) = json.get("script") {
+        let script = script_obj.get("script").unwrap_or(script_obj);
+        let script = Script::from_json(script)?;
+        return Ok(Box::new(ScriptQuery { script }));
+    }
+    if json.get("match_none").is_some() {
+        return Ok(Box::new(MatchNoneQuery));
+    }
+    Ok(Box::new(MatchAllQuery))
+}
+
+/// The `field: value` entry of a leaf query, skipping options such as
+/// `boost` that sit next to it.
+fn field_clause(json: &Value) -> Option<(&String, &Value)> {
+    json.as_object()?
+        .iter()
+        .find(|(key, _)| !matches!(key.as_str(), "boost" | "_name"))
 }
 
-fn parse_bool(json: &Value) -> BoolQuery {
+fn parse_bool(json: &Value) -> Result<BoolQuery, String> {
     let mut must = Vec::new();
     let mut should = Vec::new();
     let mut must_not = Vec::new();
 
     if let Some(m) = json.get("must") {
-        must = parse_list(m);
+        must = parse_list(m)?;
+    }
+    if let Some(f) = json.get("filter") {
+        must.extend(parse_list(f)?);
     }
     if let Some(s) = json.get("should") {
-        should = parse_list(s);
+        should = parse_list(s)?;
     }
     if let Some(mn) = json.get("must_not") {
-        must_not = parse_list(mn);
+        must_not = parse_list(mn)?;
     }
 
-    BoolQuery {
+    Ok(BoolQuery {
         must,
         should,
         must_not,
-    }
+    })
 }
 
-fn parse_list(json: &Value) -> Vec<Box<dyn Query>> {
+fn parse_list(json: &Value) -> Result<Vec<Box<dyn Query>>, String> {
     match json {
         Value::Array(arr) => arr.iter().map(|v| parse_query_internal(v)).collect(),
-        _ => vec![parse_query_internal(json)],
+        _ => Ok(vec![parse_query_internal(json)?]),
     }
 }
 
-pub fn parse_sort(json: &Value) -> Option<SortOptions> {
-    let sort_value = json.get("sort")?;
+pub fn parse_sort(json: &Value) -> Result<Option<SortOptions>, String> {
+    let Some(sort_value) = json.get("sort") else {
+        return Ok(None);
+    };
 
     if let Some(arr) = sort_value.as_array() {
         if let Some(first) = arr.first() {
@@ -177,31 +443,42 @@ pub fn parse_sort(json: &Value) -> Option<SortOptions> {
         return parse_single_sort(sort_value);
     }
 
-    None
+    Ok(None)
 }
 
-fn parse_single_sort(json: &Value) -> Option<SortOptions> {
+fn parse_single_sort(json: &Value) -> Result<Option<SortOptions>, String> {
     if let Some(field) = json.as_str() {
-        return Some(SortOptions {
+        return Ok(Some(SortOptions {
             field: field.to_string(),
             order: SortOrder::Asc,
-        });
+            script: None,
+        }));
     }
 
-    if let Some(obj) = json.as_object() {
-        if let Some((field, val)) = obj.iter().next() {
-            let order = if val.get("order").and_then(|v| v.as_str()) == Some("desc") {
-                SortOrder::Desc
-            } else {
-                SortOrder::Asc
-            };
-            return Some(SortOptions {
-                field: field.clone(),
-                order,
-            });
-        }
+    if let Some(obj) = json.as_object()
+        && let Some((field, val)) = obj.iter().next()
+    {
+        let order = val.get("order").unwrap_or(val);
+        let order = if order.as_str() == Some("desc") {
+            SortOrder::Desc
+        } else {
+            SortOrder::Asc
+        };
+        let script = if field == "_script" {
+            let script = val
+                .get("script")
+                .ok_or("[_script] sort requires a [script]")?;
+            Some(Script::from_json(script)?)
+        } else {
+            None
+        };
+        return Ok(Some(SortOptions {
+            field: field.clone(),
+            order,
+            script,
+        }));
     }
-    None
+    Ok(None)
 }
 
 #[cfg(test)]
@@ -226,7 +503,7 @@ mod tests {
                 "term": { "user_id": 1 }
             }
         });
-        let query = parse_query(&body);
+        let query = parse_query(&body).unwrap();
         let doc = json!({ "user_id": 1 });
         assert!(query.matches(&doc));
     }
@@ -243,7 +520,7 @@ mod tests {
                 }
             }
         });
-        let query = parse_query(&body);
+        let query = parse_query(&body).unwrap();
 
         assert!(query.matches(&json!({ "tags": "rust", "published": true })));
         assert!(!query.matches(&json!({ "tags": "rust", "published": false })));
@@ -258,16 +535,57 @@ mod tests {
                 }
             }
         });
-        let query = parse_query(&body);
+        let query = parse_query(&body).unwrap();
 
         assert!(query.matches(&json!({ "status": "active" })));
         assert!(!query.matches(&json!({ "status": "deleted" })));
     }
 
+    #[test]
+    fn should_treat_bool_filter_as_required() {
+        let body = json!({
+            "query": {
+                "bool": {
+                    "filter": [{ "term": { "status": "active" } }]
+                }
+            }
+        });
+        let query = parse_query(&body).unwrap();
+
+        assert!(query.matches(&json!({ "status": "active" })));
+        assert!(!query.matches(&json!({ "status": "deleted" })));
+    }
+
+    #[test]
+    fn should_parse_terms_and_match_queries() {
+        let terms = parse_query(&json!({
+            "query": { "terms": { "tags": ["rust", "go"], "boost": 2.0 } }
+        }))
+        .unwrap();
+        assert!(terms.matches(&json!({ "tags": ["java", "go"] })));
+        assert!(!terms.matches(&json!({ "tags": "java" })));
+
+        let any = parse_query(&json!({ "query": { "match": { "title": "Quick FOX" } } })).unwrap();
+        assert!(any.matches(&json!({ "title": "the quick brown dog" })));
+        assert!(!any.matches(&json!({ "title": "slow dog" })));
+
+        let all = parse_query(&json!({
+            "query": { "match": { "title": { "query": "quick fox", "operator": "and" } } }
+        }))
+        .unwrap();
+        assert!(all.matches(&json!({ "title": "Fox, quick!" })));
+        assert!(!all.matches(&json!({ "title": "quick dog" })));
+
+        let keyword =
+            parse_query(&json!({ "query": { "match": { "title.keyword": "Fox" } } })).unwrap();
+        assert!(keyword.matches(&json!({ "title": "Fox" })));
+        assert!(!keyword.matches(&json!({ "title": "Fox, quick!" })));
+    }
+
     #[test]
     fn should_parse_sort_string() {
         let body = json!({ "sort": ["created_at"] });
-        let sort = parse_sort(&body).unwrap();
+        let sort = parse_sort(&body).unwrap().unwrap();
         assert_eq!(sort.field, "created_at");
         assert!(matches!(sort.order, SortOrder::Asc));
     }
@@ -277,9 +595,14 @@ mod tests {
         let body = json!({
             "sort": { "price": { "order": "desc" } }
         });
-        let sort = parse_sort(&body).unwrap();
+        let sort = parse_sort(&body).unwrap().unwrap();
         assert_eq!(sort.field, "price");
         assert!(matches!(sort.order, SortOrder::Desc));
+
+        let sort = parse_sort(&json!({ "sort": [{ "price": "desc" }] }))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(sort.order, SortOrder::Desc));
     }
 
     #[test]
@@ -316,6 +639,21 @@ mod tests {
         assert_eq!(aggs.len(), 1);
         assert_eq!(aggs[0].name, "popular_colors");
         assert_eq!(aggs[0].field, "color.keyword");
+        assert_eq!(aggs[0].kind, AggregationKind::Terms);
+    }
+
+    #[test]
+    fn should_parse_metric_aggregations() {
+        let body = json!({
+            "aggs": {
+                "cheapest": { "min": { "field": "price" } },
+                "overview": { "stats": { "field": "price" } },
+                "unknown": { "median": { "field": "price" } }
+            }
+        });
+        let aggs = parse_aggregations(&body);
+        let kinds: Vec<&AggregationKind> = aggs.iter().map(|a| &a.kind).collect();
+        assert_eq!(kinds, vec![&AggregationKind::Min, &AggregationKind::Stats]);
     }
 
     #[test]
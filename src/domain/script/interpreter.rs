@@ -0,0 +1,1444 @@
+use super::parser::{BinaryOp, Expr, Literal, MAX_DEPTH, Stmt, UnaryOp};
+use indexmap::IndexMap;
+use serde_json::{Map, Number, Value};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Upper bound on loop iterations in a single execution, mirroring the
+/// Painless loop counter that keeps runaway scripts from hanging a request.
+pub const MAX_LOOP_COUNTER: u64 = 1_000_000;
+
+/// Longest string, in bytes, a script may build.
+pub const MAX_STRING_LENGTH: usize = 1_000_000;
+
+/// Most elements a script may put in a single list or map.
+pub const MAX_COLLECTION_SIZE: usize = 100_000;
+
+/// Deepest nesting of lists and maps a script may store or return.
+pub const MAX_VALUE_DEPTH: usize = 100;
+
+/// Upper bound on what a single execution may copy into collections, counted
+/// as one per value plus the bytes of every string.
+pub const MAX_ALLOCATION: usize = 10_000_000;
+
+/// The methods that store their arguments in the list or map they are
+/// called on, and so receive copies of them.
+const STORING_METHODS: &[&str] = &["add", "addAll", "set", "put", "putAll"];
+
+/// A runtime value. Lists and maps are shared references, so that
+/// `ctx._source.tags.add(x)` mutates the document the script was given, but
+/// anything stored into a list or map is copied first: a collection owns its
+/// elements, so no value can ever contain itself.
+#[derive(Debug, Clone)]
+pub enum Val {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    List(Rc<RefCell<Vec<Val>>>),
+    Map(Rc<RefCell<IndexMap<String, Val>>>),
+    /// The read-only `doc` map of search scripts: every field looks present,
+    /// missing ones simply have no values.
+    Doc(Rc<HashMap<String, Val>>),
+}
+
+impl Val {
+    pub fn list(items: Vec<Val>) -> Val {
+        Val::List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn map(entries: IndexMap<String, Val>) -> Val {
+        Val::Map(Rc::new(RefCell::new(entries)))
+    }
+
+    pub fn from_json(json: &Value) -> Val {
+        match json {
+            Value::Null => Val::Null,
+            Value::Bool(b) => Val::Bool(*b),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => Val::Int(i),
+                None => Val::Float(n.as_f64().unwrap_or(0.0)),
+            },
+            Value::String(s) => Val::Str(s.clone()),
+            Value::Array(items) => Val::list(items.iter().map(Val::from_json).collect()),
+            Value::Object(map) => Val::map(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), Val::from_json(v)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Converts the value back to JSON, failing when it is nested deeper than
+    /// [`MAX_VALUE_DEPTH`].
+    pub fn to_json(&self) -> Result<Value, String> {
+        self.to_json_at(0)
+    }
+
+    fn to_json_at(&self, depth: usize) -> Result<Value, String> {
+        Ok(match self {
+            Val::Null => Value::Null,
+            Val::Bool(b) => Value::Bool(*b),
+            Val::Int(i) => Value::from(*i),
+            Val::Float(f) => Number::from_f64(*f).map_or(Value::Null, Value::Number),
+            Val::Str(s) => Value::String(s.clone()),
+            Val::List(items) => {
+                check_depth(depth)?;
+                Value::Array(
+                    items
+                        .borrow()
+                        .iter()
+                        .map(|v| v.to_json_at(depth + 1))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            Val::Map(map) => {
+                check_depth(depth)?;
+                Value::Object(
+                    map.borrow()
+                        .iter()
+                        .map(|(k, v)| Ok((k.clone(), v.to_json_at(depth + 1)?)))
+                        .collect::<Result<Map<String, Value>, String>>()?,
+                )
+            }
+            Val::Doc(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), v.to_json_at(depth + 1)?)))
+                    .collect::<Result<Map<String, Value>, String>>()?,
+            ),
+        })
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Val::Null => "null",
+            Val::Bool(_) => "boolean",
+            Val::Int(_) => "long",
+            Val::Float(_) => "double",
+            Val::Str(_) => "String",
+            Val::List(_) => "List",
+            Val::Map(_) => "Map",
+            Val::Doc(_) => "doc",
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Val::Int(i) => Some(*i as f64),
+            Val::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    fn as_index(&self) -> Result<i64, String> {
+        match self {
+            Val::Int(i) => Ok(*i),
+            other => Err(format!("cannot use [{}] as an index", other.type_name())),
+        }
+    }
+
+    fn as_key(&self) -> String {
+        match self {
+            Val::Str(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+}
+
+impl Val {
+    /// Like `Display`, but elides collections nested deeper than
+    /// [`MAX_VALUE_DEPTH`] instead of recursing into them.
+    fn fmt_at(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        match self {
+            Val::Null => write!(f, "null"),
+            Val::Bool(b) => write!(f, "{}", b),
+            Val::Int(i) => write!(f, "{}", i),
+            Val::Float(x) => write!(f, "{:?}", x),
+            Val::Str(s) => write!(f, "{}", s),
+            Val::List(_) | Val::Map(_) if depth >= MAX_VALUE_DEPTH => write!(f, "..."),
+            Val::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_at(f, depth + 1)?;
+                }
+                write!(f, "]")
+            }
+            Val::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}=", key)?;
+                    value.fmt_at(f, depth + 1)?;
+                }
+                write!(f, "}}")
+            }
+            Val::Doc(_) => write!(f, "doc"),
+        }
+    }
+}
+
+impl std::fmt::Display for Val {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_at(f, 0)
+    }
+}
+
+/// Releases nested lists and maps one level at a time, so that dropping a
+/// deeply nested value cannot overflow the stack.
+impl Drop for Val {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        take_children(self, &mut pending);
+        while let Some(mut value) = pending.pop() {
+            take_children(&mut value, &mut pending);
+        }
+    }
+}
+
+fn take_children(value: &mut Val, pending: &mut Vec<Val>) {
+    match value {
+        Val::List(items) => {
+            if let Some(items) = Rc::get_mut(items) {
+                pending.append(items.get_mut());
+            }
+        }
+        Val::Map(map) => {
+            if let Some(map) = Rc::get_mut(map) {
+                pending.extend(map.get_mut().drain(..).map(|(_, v)| v));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_depth(depth: usize) -> Result<(), String> {
+    if depth >= MAX_VALUE_DEPTH {
+        return Err(format!(
+            "value exceeds the maximum nesting depth of [{}]",
+            MAX_VALUE_DEPTH
+        ));
+    }
+    Ok(())
+}
+
+/// Rejects strings and collections a script is not allowed to build.
+fn check_size(value: Val) -> Result<Val, String> {
+    match &value {
+        Val::Str(s) if s.len() > MAX_STRING_LENGTH => Err(format!(
+            "string length [{}] exceeds the maximum of [{}]",
+            s.len(),
+            MAX_STRING_LENGTH
+        )),
+        Val::List(items) => {
+            check_capacity(items.borrow().len(), 0)?;
+            Ok(value)
+        }
+        Val::Map(map) => {
+            check_capacity(map.borrow().len(), 0)?;
+            Ok(value)
+        }
+        _ => Ok(value),
+    }
+}
+
+fn check_capacity(len: usize, added: usize) -> Result<(), String> {
+    if len + added > MAX_COLLECTION_SIZE {
+        return Err(format!(
+            "collection size [{}] exceeds the maximum of [{}]",
+            len + added,
+            MAX_COLLECTION_SIZE
+        ));
+    }
+    Ok(())
+}
+
+/// Structural equality, walked with an explicit stack rather than recursion.
+fn equals(a: &Val, b: &Val) -> bool {
+    let mut pending = vec![(a.clone(), b.clone())];
+    while let Some((a, b)) = pending.pop() {
+        let equal = match (&a, &b) {
+            (Val::Null, Val::Null) => true,
+            (Val::Bool(x), Val::Bool(y)) => x == y,
+            (Val::Int(x), Val::Int(y)) => x == y,
+            (Val::Str(x), Val::Str(y)) => x == y,
+            (Val::List(x), Val::List(y)) => {
+                let (x, y) = (x.borrow(), y.borrow());
+                pending.extend(x.iter().cloned().zip(y.iter().cloned()));
+                x.len() == y.len()
+            }
+            (Val::Map(x), Val::Map(y)) => {
+                let (x, y) = (x.borrow(), y.borrow());
+                for (key, value) in x.iter() {
+                    match y.get(key) {
+                        Some(other) => pending.push((value.clone(), other.clone())),
+                        None => return false,
+                    }
+                }
+                x.len() == y.len()
+            }
+            _ => match (a.as_f64(), b.as_f64()) {
+                (Some(x), Some(y)) => x == y,
+                _ => false,
+            },
+        };
+        if !equal {
+            return false;
+        }
+    }
+    true
+}
+
+fn compare(a: &Val, b: &Val) -> Result<Ordering, String> {
+    match (a, b) {
+        (Val::Int(x), Val::Int(y)) => Ok(x.cmp(y)),
+        (Val::Str(x), Val::Str(y)) => Ok(x.cmp(y)),
+        _ => match (a.as_f64(), b.as_f64()) {
+            (Some(x), Some(y)) => Ok(x.partial_cmp(&y).unwrap_or(Ordering::Equal)),
+            _ => Err(format!(
+                "cannot compare [{}] with [{}]",
+                a.type_name(),
+                b.type_name()
+            )),
+        },
+    }
+}
+
+fn arithmetic(op: BinaryOp, a: &Val, b: &Val) -> Result<Val, String> {
+    if op == BinaryOp::Add && (matches!(a, Val::Str(_)) || matches!(b, Val::Str(_))) {
+        return Ok(Val::Str(format!("{}{}", a, b)));
+    }
+    if let (Val::Int(x), Val::Int(y)) = (a, b) {
+        let (x, y) = (*x, *y);
+        if matches!(op, BinaryOp::Div | BinaryOp::Rem) && y == 0 {
+            return Err("/ by zero".to_string());
+        }
+        return Ok(Val::Int(match op {
+            BinaryOp::Add => x.wrapping_add(y),
+            BinaryOp::Sub => x.wrapping_sub(y),
+            BinaryOp::Mul => x.wrapping_mul(y),
+            BinaryOp::Div => x.wrapping_div(y),
+            _ => x.wrapping_rem(y),
+        }));
+    }
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => Ok(Val::Float(match op {
+            BinaryOp::Add => x + y,
+            BinaryOp::Sub => x - y,
+            BinaryOp::Mul => x * y,
+            BinaryOp::Div => x / y,
+            _ => x % y,
+        })),
+        _ => Err(format!(
+            "cannot apply [{}] to types [{}] and [{}]",
+            symbol(op),
+            a.type_name(),
+            b.type_name()
+        )),
+    }
+}
+
+fn symbol(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Rem => "%",
+        BinaryOp::Eq => "==",
+        BinaryOp::Ne => "!=",
+        BinaryOp::Lt => "<",
+        BinaryOp::Le => "<=",
+        BinaryOp::Gt => ">",
+        BinaryOp::Ge => ">=",
+        BinaryOp::And => "&&",
+        BinaryOp::Or => "||",
+    }
+}
+
+fn truthy(value: &Val) -> Result<bool, String> {
+    match value {
+        Val::Bool(b) => Ok(*b),
+        other => Err(format!("cannot cast [{}] to [boolean]", other.type_name())),
+    }
+}
+
+fn position(index: i64, len: usize) -> Result<usize, String> {
+    if index >= 0 && (index as usize) < len {
+        Ok(index as usize)
+    } else {
+        Err(format!("Index {} out of bounds for length {}", index, len))
+    }
+}
+
+fn expect_args(method: &str, args: &[Val], count: usize) -> Result<(), String> {
+    if args.len() == count {
+        Ok(())
+    } else {
+        Err(format!(
+            "dynamic method [{}/{}] not found",
+            method,
+            args.len()
+        ))
+    }
+}
+
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Val),
+}
+
+/// Where an assignment writes to: a local variable or an entry of a list or
+/// map, resolved once so `a[i++] += 1` evaluates `i++` a single time.
+enum Place {
+    Var(String),
+    Member(Val, Val),
+}
+
+pub struct Interpreter {
+    scopes: Vec<HashMap<String, Val>>,
+    loop_counter: u64,
+    allocated: usize,
+    depth: usize,
+}
+
+impl Interpreter {
+    pub fn new(globals: Vec<(&str, Val)>) -> Self {
+        Self {
+            scopes: vec![
+                globals
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value))
+                    .collect(),
+            ],
+            loop_counter: 0,
+            allocated: 0,
+            depth: 0,
+        }
+    }
+
+    /// Runs the program and returns the value of its `return` statement, or
+    /// of the final expression statement when there is none.
+    pub fn run(&mut self, program: &[Stmt]) -> Result<Val, String> {
+        let mut last = Val::Null;
+        for stmt in program {
+            if let Stmt::Expr(expr) = stmt {
+                last = self.eval(expr)?;
+                continue;
+            }
+            match self.exec(stmt)? {
+                Flow::Return(value) => return Ok(value),
+                Flow::Normal => last = Val::Null,
+                Flow::Break | Flow::Continue => {
+                    return Err("break or continue outside of a loop".to_string());
+                }
+            }
+        }
+        Ok(last)
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Val> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn declare(&mut self, name: &str, value: Val) {
+        self.scopes
+            .last_mut()
+            .expect("at least the global scope")
+            .insert(name.to_string(), value);
+    }
+
+    fn scoped<T>(
+        &mut self,
+        body: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        self.scopes.push(HashMap::new());
+        let result = body(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn tick(&mut self) -> Result<(), String> {
+        self.loop_counter += 1;
+        if self.loop_counter > MAX_LOOP_COUNTER {
+            return Err(
+                "The maximum number of statements that can be executed in a loop has been reached."
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Deep-copies a value that is about to be stored in a list or map,
+    /// charging the copy against [`MAX_ALLOCATION`].
+    fn detach(&mut self, value: &Val) -> Result<Val, String> {
+        self.copy(value, 0)
+    }
+
+    fn copy(&mut self, value: &Val, depth: usize) -> Result<Val, String> {
+        self.allocated += match value {
+            Val::Str(s) => 1 + s.len(),
+            _ => 1,
+        };
+        if self.allocated > MAX_ALLOCATION {
+            return Err(format!(
+                "script exceeds the maximum allocation of [{}]",
+                MAX_ALLOCATION
+            ));
+        }
+        match value {
+            Val::List(items) => {
+                check_depth(depth)?;
+                let items = items
+                    .borrow()
+                    .iter()
+                    .map(|item| self.copy(item, depth + 1))
+                    .collect::<Result<_, _>>()?;
+                Ok(Val::list(items))
+            }
+            Val::Map(map) => {
+                check_depth(depth)?;
+                let entries = map
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| Ok((key.clone(), self.copy(value, depth + 1)?)))
+                    .collect::<Result<_, String>>()?;
+                Ok(Val::map(entries))
+            }
+            other => Ok(other.clone()),
+        }
+    }
+
+    /// Bounds the recursion over the syntax tree, in case a program deeper
+    /// than the parser allows is ever built.
+    fn nested<T>(
+        &mut self,
+        body: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!(
+                "script exceeds the maximum nesting depth of [{}]",
+                MAX_DEPTH
+            ));
+        }
+        self.depth += 1;
+        let result = body(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn exec_block(&mut self, statements: &[Stmt]) -> Result<Flow, String> {
+        self.scoped(|this| {
+            for stmt in statements {
+                match this.exec(stmt)? {
+                    Flow::Normal => {}
+                    flow => return Ok(flow),
+                }
+            }
+            Ok(Flow::Normal)
+        })
+    }
+
+    /// Runs a loop body, translating `break`/`continue` for the caller:
+    /// `Some(flow)` means the loop has to stop and propagate `flow`.
+    fn loop_body(&mut self, body: &Stmt) -> Result<Option<Flow>, String> {
+        self.tick()?;
+        match self.exec(body)? {
+            Flow::Break => Ok(Some(Flow::Normal)),
+            Flow::Return(value) => Ok(Some(Flow::Return(value))),
+            Flow::Normal | Flow::Continue => Ok(None),
+        }
+    }
+
+    fn exec(&mut self, stmt: &Stmt) -> Result<Flow, String> {
+        self.nested(|this| this.exec_nested(stmt))
+    }
+
+    fn exec_nested(&mut self, stmt: &Stmt) -> Result<Flow, String> {
+        match stmt {
+            Stmt::Expr(expr) => {
+                self.eval(expr)?;
+                Ok(Flow::Normal)
+            }
+            Stmt::Declare(variables) => {
+                for (name, value) in variables {
+                    let value = match value {
+                        Some(expr) => self.eval(expr)?,
+                        None => Val::Null,
+                    };
+                    self.declare(name, value);
+                }
+                Ok(Flow::Normal)
+            }
+            Stmt::If(condition, then, otherwise) => {
+                if truthy(&self.eval(condition)?)? {
+                    self.exec(then)
+                } else if let Some(otherwise) = otherwise {
+                    self.exec(otherwise)
+                } else {
+                    Ok(Flow::Normal)
+                }
+            }
+            Stmt::While(condition, body) => {
+                while truthy(&self.eval(condition)?)? {
+                    if let Some(flow) = self.loop_body(body)? {
+                        return Ok(flow);
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Stmt::DoWhile(body, condition) => loop {
+                if let Some(flow) = self.loop_body(body)? {
+                    return Ok(flow);
+                }
+                if !truthy(&self.eval(condition)?)? {
+                    return Ok(Flow::Normal);
+                }
+            },
+            Stmt::For {
+                init,
+                condition,
+                update,
+                body,
+            } => self.scoped(|this| {
+                if let Some(init) = init {
+                    this.exec(init)?;
+                }
+                loop {
+                    if let Some(condition) = condition
+                        && !truthy(&this.eval(condition)?)?
+                    {
+                        return Ok(Flow::Normal);
+                    }
+                    if let Some(flow) = this.loop_body(body)? {
+                        return Ok(flow);
+                    }
+                    for expr in update {
+                        this.eval(expr)?;
+                    }
+                }
+            }),
+            Stmt::ForEach(name, iterable, body) => {
+                let iterable = self.eval(iterable)?;
+                let items = match &iterable {
+                    Val::List(items) => items.borrow().clone(),
+                    Val::Map(map) => map.borrow().keys().cloned().map(Val::Str).collect(),
+                    other => {
+                        return Err(format!("cannot iterate over [{}]", other.type_name()));
+                    }
+                };
+                self.scoped(|this| {
+                    for item in items {
+                        this.declare(name, item);
+                        if let Some(flow) = this.loop_body(body)? {
+                            return Ok(flow);
+                        }
+                    }
+                    Ok(Flow::Normal)
+                })
+            }
+            Stmt::Block(statements) => self.exec_block(statements),
+            Stmt::Return(value) => Ok(Flow::Return(match value {
+                Some(expr) => self.eval(expr)?,
+                None => Val::Null,
+            })),
+            Stmt::Break => Ok(Flow::Break),
+            Stmt::Continue => Ok(Flow::Continue),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Val, String> {
+        self.nested(|this| this.eval_nested(expr))
+    }
+
+    fn eval_nested(&mut self, expr: &Expr) -> Result<Val, String> {
+        match expr {
+            Expr::Literal(literal) => Ok(match literal {
+                Literal::Null => Val::Null,
+                Literal::Bool(b) => Val::Bool(*b),
+                Literal::Int(i) => Val::Int(*i),
+                Literal::Float(f) => Val::Float(*f),
+                Literal::Str(s) => Val::Str(s.clone()),
+            }),
+            Expr::Var(name) => self
+                .lookup(name)
+                .cloned()
+                .ok_or_else(|| format!("cannot resolve symbol [{}]", name)),
+            Expr::List(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| {
+                        let item = self.eval(item)?;
+                        self.detach(&item)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Val::list(items))
+            }
+            Expr::Map(entries) => {
+                let mut map = IndexMap::new();
+                for (key, value) in entries {
+                    let key = self.eval(key)?.as_key();
+                    let value = self.eval(value)?;
+                    map.insert(key, self.detach(&value)?);
+                }
+                Ok(Val::map(map))
+            }
+            Expr::New(type_name, args) => {
+                let args = self.eval_args(args)?;
+                let args = self.detach_all(args)?;
+                construct(type_name, &args)
+            }
+            Expr::Field {
+                target,
+                name,
+                null_safe,
+            } => {
+                let target = self.eval(target)?;
+                if *null_safe && matches!(target, Val::Null) {
+                    return Ok(Val::Null);
+                }
+                field(&target, name)
+            }
+            Expr::Index(target, index) => {
+                let target = self.eval(target)?;
+                let index = self.eval(index)?;
+                get_member(&target, &index)
+            }
+            Expr::Call {
+                target,
+                method,
+                args,
+                null_safe,
+            } => {
+                if let Expr::Var(class) = target.as_ref()
+                    && self.lookup(class).is_none()
+                {
+                    let args = self.eval_args(args)?;
+                    return check_size(call_static(class, method, &args)?);
+                }
+                let target = self.eval(target)?;
+                if *null_safe && matches!(target, Val::Null) {
+                    return Ok(Val::Null);
+                }
+                let mut args = self.eval_args(args)?;
+                if matches!(target, Val::List(_) | Val::Map(_))
+                    && STORING_METHODS.contains(&method.as_str())
+                {
+                    args = self.detach_all(args)?;
+                }
+                check_size(call_method(&target, method, &args)?)
+            }
+            Expr::Cast(type_name, value) => {
+                let value = self.eval(value)?;
+                cast(type_name, value)
+            }
+            Expr::Unary(op, value) => {
+                let value = self.eval(value)?;
+                match (op, value) {
+                    (UnaryOp::Not, value) => Ok(Val::Bool(!truthy(&value)?)),
+                    (UnaryOp::Neg, Val::Int(i)) => Ok(Val::Int(i.wrapping_neg())),
+                    (UnaryOp::Neg, Val::Float(f)) => Ok(Val::Float(-f)),
+                    (UnaryOp::Neg, other) => Err(format!("cannot negate [{}]", other.type_name())),
+                }
+            }
+            Expr::Binary(op, left, right) => self.binary(*op, left, right),
+            Expr::Ternary(condition, then, otherwise) => {
+                if truthy(&self.eval(condition)?)? {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+            Expr::Elvis(value, fallback) => match self.eval(value)? {
+                Val::Null => self.eval(fallback),
+                value => Ok(value),
+            },
+            Expr::Assign { target, op, value } => {
+                let place = self.place(target)?;
+                let value = match op {
+                    Some(op) => {
+                        let current = self.read(&place)?;
+                        let operand = self.eval(value)?;
+                        check_size(arithmetic(*op, &current, &operand)?)?
+                    }
+                    None => self.eval(value)?,
+                };
+                self.write(&place, value.clone())?;
+                Ok(value)
+            }
+            Expr::Step {
+                target,
+                delta,
+                prefix,
+            } => {
+                let place = self.place(target)?;
+                let current = self.read(&place)?;
+                let next = check_size(arithmetic(BinaryOp::Add, &current, &Val::Int(*delta))?)?;
+                self.write(&place, next.clone())?;
+                Ok(if *prefix { next } else { current })
+            }
+        }
+    }
+
+    fn eval_args(&mut self, args: &[Expr]) -> Result<Vec<Val>, String> {
+        args.iter().map(|arg| self.eval(arg)).collect()
+    }
+
+    fn detach_all(&mut self, values: Vec<Val>) -> Result<Vec<Val>, String> {
+        values.iter().map(|value| self.detach(value)).collect()
+    }
+
+    fn binary(&mut self, op: BinaryOp, left: &Expr, right: &Expr) -> Result<Val, String> {
+        let left = self.eval(left)?;
+        match op {
+            BinaryOp::And => {
+                if !truthy(&left)? {
+                    return Ok(Val::Bool(false));
+                }
+                return Ok(Val::Bool(truthy(&self.eval(right)?)?));
+            }
+            BinaryOp::Or => {
+                if truthy(&left)? {
+                    return Ok(Val::Bool(true));
+                }
+                return Ok(Val::Bool(truthy(&self.eval(right)?)?));
+            }
+            _ => {}
+        }
+
+        let right = self.eval(right)?;
+        match op {
+            BinaryOp::Eq => Ok(Val::Bool(equals(&left, &right))),
+            BinaryOp::Ne => Ok(Val::Bool(!equals(&left, &right))),
+            BinaryOp::Lt => Ok(Val::Bool(compare(&left, &right)? == Ordering::Less)),
+            BinaryOp::Le => Ok(Val::Bool(compare(&left, &right)? != Ordering::Greater)),
+            BinaryOp::Gt => Ok(Val::Bool(compare(&left, &right)? == Ordering::Greater)),
+            BinaryOp::Ge => Ok(Val::Bool(compare(&left, &right)? != Ordering::Less)),
+            _ => check_size(arithmetic(op, &left, &right)?),
+        }
+    }
+
+    fn place(&mut self, target: &Expr) -> Result<Place, String> {
+        match target {
+            Expr::Var(name) => Ok(Place::Var(name.clone())),
+            Expr::Field { target, name, .. } => {
+                Ok(Place::Member(self.eval(target)?, Val::Str(name.clone())))
+            }
+            Expr::Index(target, index) => {
+                let target = self.eval(target)?;
+                Ok(Place::Member(target, self.eval(index)?))
+            }
+            _ => Err("invalid assignment target".to_string()),
+        }
+    }
+
+    fn read(&self, place: &Place) -> Result<Val, String> {
+        match place {
+            Place::Var(name) => self
+                .lookup(name)
+                .cloned()
+                .ok_or_else(|| format!("cannot resolve symbol [{}]", name)),
+            Place::Member(target, key) => get_member(target, key),
+        }
+    }
+
+    fn write(&mut self, place: &Place, value: Val) -> Result<(), String> {
+        let value = match place {
+            Place::Var(_) => value,
+            Place::Member(..) => self.detach(&value)?,
+        };
+        match place {
+            Place::Var(name) => {
+                let scope = self
+                    .scopes
+                    .iter_mut()
+                    .rev()
+                    .find(|scope| scope.contains_key(name))
+                    .ok_or_else(|| format!("cannot resolve symbol [{}]", name))?;
+                scope.insert(name.clone(), value);
+                Ok(())
+            }
+            Place::Member(Val::Map(map), key) => {
+                let mut map = map.borrow_mut();
+                let key = key.as_key();
+                if !map.contains_key(&key) {
+                    check_capacity(map.len(), 1)?;
+                }
+                map.insert(key, value);
+                Ok(())
+            }
+            Place::Member(Val::List(items), index) => {
+                let mut items = items.borrow_mut();
+                let i = position(index.as_index()?, items.len())?;
+                items[i] = value;
+                Ok(())
+            }
+            Place::Member(Val::Null, key) => {
+                Err(format!("cannot write [{}] on a null value", key.as_key()))
+            }
+            Place::Member(target, _) => Err(format!(
+                "cannot write to a member of [{}]",
+                target.type_name()
+            )),
+        }
+    }
+}
+
+/// `target.name`: map entries, list shortcuts and the doc-value accessors.
+fn field(target: &Val, name: &str) -> Result<Val, String> {
+    match (target, name) {
+        (Val::List(items), "value") => items.borrow().first().cloned().ok_or_else(|| {
+            "A document doesn't have a value for a field! \
+             Use doc[<field>].size()==0 to check if a document is missing a field!"
+                .to_string()
+        }),
+        (Val::List(items), "values") => Ok(Val::List(items.clone())),
+        (Val::List(items), "length") => Ok(Val::Int(items.borrow().len() as i64)),
+        (Val::List(items), "empty") => Ok(Val::Bool(items.borrow().is_empty())),
+        (Val::Str(s), "length") => Ok(Val::Int(s.chars().count() as i64)),
+        (Val::Map(_), _) | (Val::Doc(_), _) => get_member(target, &Val::Str(name.to_string())),
+        (Val::Null, _) => Err(format!("cannot access field [{}] of a null value", name)),
+        (other, _) => Err(format!(
+            "field [{}] not found on type [{}]",
+            name,
+            other.type_name()
+        )),
+    }
+}
+
+fn get_member(target: &Val, key: &Val) -> Result<Val, String> {
+    match target {
+        Val::Map(map) => Ok(map
+            .borrow()
+            .get(&key.as_key())
+            .cloned()
+            .unwrap_or(Val::Null)),
+        Val::Doc(fields) => Ok(fields
+            .get(&key.as_key())
+            .cloned()
+            .unwrap_or_else(|| Val::list(Vec::new()))),
+        Val::List(items) => {
+            let items = items.borrow();
+            let i = position(key.as_index()?, items.len())?;
+            Ok(items[i].clone())
+        }
+        Val::Null => Err(format!("cannot access [{}] of a null value", key.as_key())),
+        other => Err(format!("cannot index into type [{}]", other.type_name())),
+    }
+}
+
+fn construct(type_name: &str, args: &[Val]) -> Result<Val, String> {
+    match (type_name, args) {
+        ("ArrayList" | "HashSet", []) => Ok(Val::list(Vec::new())),
+        ("ArrayList" | "HashSet", [Val::List(items)]) => Ok(Val::list(items.borrow().clone())),
+        ("ArrayList", [Val::Int(_)]) => Ok(Val::list(Vec::new())),
+        ("HashMap", []) => Ok(Val::map(IndexMap::new())),
+        ("HashMap", [Val::Map(map)]) => Ok(Val::map(map.borrow().clone())),
+        _ => Err(format!(
+            "constructor [{}/{}] not found",
+            type_name,
+            args.len()
+        )),
+    }
+}
+
+fn cast(type_name: &str, value: Val) -> Result<Val, String> {
+    match (type_name, value) {
+        ("int" | "long" | "short" | "byte" | "Integer" | "Long", Val::Float(f)) => {
+            Ok(Val::Int(f as i64))
+        }
+        ("int" | "long" | "short" | "byte" | "Integer" | "Long", value @ Val::Int(_)) => Ok(value),
+        ("float" | "double" | "Float" | "Double", Val::Int(i)) => Ok(Val::Float(i as f64)),
+        ("float" | "double" | "Float" | "Double", value @ Val::Float(_)) => Ok(value),
+        ("String", value @ (Val::Str(_) | Val::Null)) => Ok(value),
+        ("boolean" | "Boolean", value @ Val::Bool(_)) => Ok(value),
+        (
+            "int" | "long" | "short" | "byte" | "Integer" | "Long" | "float" | "double" | "Float"
+            | "Double" | "String" | "boolean" | "Boolean",
+            value,
+        ) => Err(format!(
+            "cannot cast [{}] to [{}]",
+            value.type_name(),
+            type_name
+        )),
+        (_, value) => Ok(value),
+    }
+}
+
+fn number(value: &Val, method: &str) -> Result<f64, String> {
+    value
+        .as_f64()
+        .ok_or_else(|| format!("[{}] expects a number, got [{}]", method, value.type_name()))
+}
+
+fn string<'a>(value: &'a Val, method: &str) -> Result<&'a str, String> {
+    match value {
+        Val::Str(s) => Ok(s),
+        other => Err(format!(
+            "[{}] expects a String, got [{}]",
+            method,
+            other.type_name()
+        )),
+    }
+}
+
+fn call_static(class: &str, method: &str, args: &[Val]) -> Result<Val, String> {
+    match (class, method, args) {
+        ("Math", "abs", [Val::Int(i)]) => Ok(Val::Int(i.wrapping_abs())),
+        ("Math", "max" | "min", [Val::Int(a), Val::Int(b)]) => Ok(Val::Int(if method == "max" {
+            *a.max(b)
+        } else {
+            *a.min(b)
+        })),
+        ("Math", "max" | "min", [a, b]) => {
+            let (a, b) = (number(a, method)?, number(b, method)?);
+            Ok(Val::Float(if method == "max" {
+                a.max(b)
+            } else {
+                a.min(b)
+            }))
+        }
+        ("Math", "round", [value]) => Ok(Val::Int(number(value, method)?.round() as i64)),
+        ("Math", "pow", [a, b]) => Ok(Val::Float(number(a, method)?.powf(number(b, method)?))),
+        ("Math", _, [value]) => {
+            let x = number(value, method)?;
+            let result = match method {
+                "abs" => x.abs(),
+                "floor" => x.floor(),
+                "ceil" => x.ceil(),
+                "sqrt" => x.sqrt(),
+                "log" => x.ln(),
+                "log10" => x.log10(),
+                "exp" => x.exp(),
+                _ => return Err(format!("static method [Math.{}] not found", method)),
+            };
+            Ok(Val::Float(result))
+        }
+        ("Integer" | "Long", "parseInt" | "parseLong", [value]) => {
+            let text = string(value, method)?;
+            text.trim()
+                .parse()
+                .map(Val::Int)
+                .map_err(|_| format!("For input string: \"{}\"", text))
+        }
+        ("Double" | "Float", "parseDouble" | "parseFloat", [value]) => {
+            let text = string(value, method)?;
+            text.trim()
+                .parse()
+                .map(Val::Float)
+                .map_err(|_| format!("For input string: \"{}\"", text))
+        }
+        ("String", "valueOf", [value]) => Ok(Val::Str(value.to_string())),
+        ("String", "join", [delimiter, Val::List(items)]) => {
+            let delimiter = string(delimiter, method)?;
+            let parts: Vec<String> = items.borrow().iter().map(|v| v.to_string()).collect();
+            Ok(Val::Str(parts.join(delimiter)))
+        }
+        ("Objects", "isNull", [value]) => Ok(Val::Bool(matches!(value, Val::Null))),
+        ("Objects", "equals", [a, b]) => Ok(Val::Bool(equals(a, b))),
+        _ => Err(format!(
+            "cannot resolve symbol [{}] for static method [{}/{}]",
+            class,
+            method,
+            args.len()
+        )),
+    }
+}
+
+fn call_method(target: &Val, method: &str, args: &[Val]) -> Result<Val, String> {
+    match (method, args) {
+        ("toString", []) => return Ok(Val::Str(target.to_string())),
+        ("equals", [other]) => return Ok(Val::Bool(equals(target, other))),
+        _ => {}
+    }
+    match target {
+        Val::Str(s) => string_method(s, method, args),
+        Val::List(items) => list_method(items, method, args),
+        Val::Map(map) => map_method(map, method, args),
+        Val::Doc(fields) => match (method, args) {
+            ("containsKey", [key]) => Ok(Val::Bool(fields.contains_key(&key.as_key()))),
+            ("get", [key]) => get_member(target, key),
+            _ => Err(format!("dynamic method [doc.{}] not found", method)),
+        },
+        Val::Int(_) | Val::Float(_) => match (method, args, target) {
+            ("intValue" | "longValue", [], Val::Float(f)) => Ok(Val::Int(*f as i64)),
+            ("intValue" | "longValue", [], value) => Ok(value.clone()),
+            ("doubleValue" | "floatValue", [], value) => {
+                Ok(Val::Float(value.as_f64().unwrap_or_default()))
+            }
+            ("compareTo", [other], value) => Ok(Val::Int(compare(value, other)? as i64)),
+            _ => Err(format!(
+                "dynamic method [{}.{}] not found",
+                target.type_name(),
+                method
+            )),
+        },
+        Val::Null => Err(format!("cannot invoke method [{}] on a null value", method)),
+        Val::Bool(_) => Err(format!("dynamic method [boolean.{}] not found", method)),
+    }
+}
+
+fn string_method(s: &str, method: &str, args: &[Val]) -> Result<Val, String> {
+    let arg = |i: usize| string(&args[i], method);
+    let chars: Vec<char> = s.chars().collect();
+    match method {
+        "length" => Ok(Val::Int(chars.len() as i64)),
+        "isEmpty" => Ok(Val::Bool(s.is_empty())),
+        "toUpperCase" => Ok(Val::Str(s.to_uppercase())),
+        "toLowerCase" => Ok(Val::Str(s.to_lowercase())),
+        "trim" => Ok(Val::Str(s.trim().to_string())),
+        "contains" => {
+            expect_args(method, args, 1)?;
+            Ok(Val::Bool(s.contains(arg(0)?)))
+        }
+        "startsWith" => {
+            expect_args(method, args, 1)?;
+            Ok(Val::Bool(s.starts_with(arg(0)?)))
+        }
+        "endsWith" => {
+            expect_args(method, args, 1)?;
+            Ok(Val::Bool(s.ends_with(arg(0)?)))
+        }
+        "equalsIgnoreCase" => {
+            expect_args(method, args, 1)?;
+            Ok(Val::Bool(s.to_lowercase() == arg(0)?.to_lowercase()))
+        }
+        "indexOf" | "lastIndexOf" => {
+            expect_args(method, args, 1)?;
+            let needle = arg(0)?;
+            let found = if method == "indexOf" {
+                s.find(needle)
+            } else {
+                s.rfind(needle)
+            };
+            Ok(Val::Int(
+                found.map_or(-1, |byte| s[..byte].chars().count() as i64),
+            ))
+        }
+        "replace" => {
+            expect_args(method, args, 2)?;
+            Ok(Val::Str(s.replace(arg(0)?, arg(1)?)))
+        }
+        "charAt" => {
+            expect_args(method, args, 1)?;
+            let i = position(args[0].as_index()?, chars.len())?;
+            Ok(Val::Str(chars[i].to_string()))
+        }
+        "substring" => {
+            let start = args.first().map(Val::as_index).transpose()?.unwrap_or(0);
+            let end = args
+                .get(1)
+                .map(Val::as_index)
+                .transpose()?
+                .unwrap_or(chars.len() as i64);
+            if start < 0 || end > chars.len() as i64 || start > end {
+                return Err(format!(
+                    "begin {}, end {}, length {}",
+                    start,
+                    end,
+                    chars.len()
+                ));
+            }
+            Ok(Val::Str(
+                chars[start as usize..end as usize].iter().collect(),
+            ))
+        }
+        "splitOnToken" | "split" => {
+            expect_args(method, args, 1)?;
+            Ok(Val::list(
+                s.split(arg(0)?)
+                    .map(|part| Val::Str(part.to_string()))
+                    .collect(),
+            ))
+        }
+        "compareTo" => {
+            expect_args(method, args, 1)?;
+            Ok(Val::Int(s.cmp(arg(0)?) as i64))
+        }
+        _ => Err(format!("dynamic method [String.{}] not found", method)),
+    }
+}
+
+fn list_method(items: &Rc<RefCell<Vec<Val>>>, method: &str, args: &[Val]) -> Result<Val, String> {
+    match (method, args) {
+        ("size", []) => Ok(Val::Int(items.borrow().len() as i64)),
+        ("isEmpty", []) => Ok(Val::Bool(items.borrow().is_empty())),
+        ("add", [value]) => {
+            let mut items = items.borrow_mut();
+            check_capacity(items.len(), 1)?;
+            items.push(value.clone());
+            Ok(Val::Bool(true))
+        }
+        ("add", [index, value]) => {
+            let mut items = items.borrow_mut();
+            check_capacity(items.len(), 1)?;
+            let i = index.as_index()?;
+            if i < 0 || i as usize > items.len() {
+                return Err(format!(
+                    "Index {} out of bounds for length {}",
+                    i,
+                    items.len()
+                ));
+            }
+            items.insert(i as usize, value.clone());
+            Ok(Val::Null)
+        }
+        ("addAll", [Val::List(other)]) => {
+            let other = other.borrow().clone();
+            let mut items = items.borrow_mut();
+            check_capacity(items.len(), other.len())?;
+            items.extend(other);
+            Ok(Val::Bool(true))
+        }
+        ("get", [index]) => get_member(&Val::List(items.clone()), index),
+        ("set", [index, value]) => {
+            let mut items = items.borrow_mut();
+            let i = position(index.as_index()?, items.len())?;
+            Ok(std::mem::replace(&mut items[i], value.clone()))
+        }
+        // Like Java's List.remove overloads: an int argument removes by
+        // position, anything else removes the first equal element.
+        ("remove", [Val::Int(index)]) => {
+            let mut items = items.borrow_mut();
+            let i = position(*index, items.len())?;
+            Ok(items.remove(i))
+        }
+        ("remove", [value]) => {
+            let mut items = items.borrow_mut();
+            match items.iter().position(|item| equals(item, value)) {
+                Some(i) => {
+                    items.remove(i);
+                    Ok(Val::Bool(true))
+                }
+                None => Ok(Val::Bool(false)),
+            }
+        }
+        ("contains", [value]) => Ok(Val::Bool(
+            items.borrow().iter().any(|item| equals(item, value)),
+        )),
+        ("indexOf", [value]) => Ok(Val::Int(
+            items
+                .borrow()
+                .iter()
+                .position(|item| equals(item, value))
+                .map_or(-1, |i| i as i64),
+        )),
+        ("clear", []) => {
+            items.borrow_mut().clear();
+            Ok(Val::Null)
+        }
+        ("sort", [] | [Val::Null]) => {
+            items
+                .borrow_mut()
+                .sort_by(|a, b| compare(a, b).unwrap_or(Ordering::Equal));
+            Ok(Val::Null)
+        }
+        ("subList", [from, to]) => {
+            let items = items.borrow();
+            let (from, to) = (from.as_index()?, to.as_index()?);
+            if from < 0 || to > items.len() as i64 || from > to {
+                return Err(format!("fromIndex: {}, toIndex: {}", from, to));
+            }
+            Ok(Val::list(items[from as usize..to as usize].to_vec()))
+        }
+        _ => Err(format!(
+            "dynamic method [List.{}/{}] not found",
+            method,
+            args.len()
+        )),
+    }
+}
+
+fn map_method(
+    map: &Rc<RefCell<IndexMap<String, Val>>>,
+    method: &str,
+    args: &[Val],
+) -> Result<Val, String> {
+    match (method, args) {
+        ("size", []) => Ok(Val::Int(map.borrow().len() as i64)),
+        ("isEmpty", []) => Ok(Val::Bool(map.borrow().is_empty())),
+        ("get", [key]) => Ok(map
+            .borrow()
+            .get(&key.as_key())
+            .cloned()
+            .unwrap_or(Val::Null)),
+        ("getOrDefault", [key, default]) => Ok(map
+            .borrow()
+            .get(&key.as_key())
+            .cloned()
+            .unwrap_or_else(|| default.clone())),
+        ("put", [key, value]) => {
+            let mut map = map.borrow_mut();
+            let key = key.as_key();
+            if !map.contains_key(&key) {
+                check_capacity(map.len(), 1)?;
+            }
+            Ok(map.insert(key, value.clone()).unwrap_or(Val::Null))
+        }
+        ("putAll", [Val::Map(other)]) => {
+            let other = other.borrow().clone();
+            let mut map = map.borrow_mut();
+            check_capacity(map.len(), other.len())?;
+            map.extend(other);
+            Ok(Val::Null)
+        }
+        ("remove", [key]) => Ok(map
+            .borrow_mut()
+            .shift_remove(&key.as_key())
+            .unwrap_or(Val::Null)),
+        ("containsKey", [key]) => Ok(Val::Bool(map.borrow().contains_key(&key.as_key()))),
+        ("containsValue", [value]) => Ok(Val::Bool(
+            map.borrow().values().any(|item| equals(item, value)),
+        )),
+        ("keySet", []) => Ok(Val::list(
+            map.borrow().keys().cloned().map(Val::Str).collect(),
+        )),
+        ("values", []) => Ok(Val::list(map.borrow().values().cloned().collect())),
+        ("clear", []) => {
+            map.borrow_mut().clear();
+            Ok(Val::Null)
+        }
+        _ => Err(format!(
+            "dynamic method [Map.{}/{}] not found",
+            method,
+            args.len()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::script::parser::parse;
+    use serde_json::json;
+
+    fn run(source: &str, params: Value) -> Result<Value, String> {
+        let program = parse(source)?;
+        let mut interpreter = Interpreter::new(vec![("params", Val::from_json(&params))]);
+        interpreter.run(&program)?.to_json()
+    }
+
+    #[test]
+    fn should_evaluate_arithmetic_and_string_concatenation() {
+        assert_eq!(run("1 + 2 * 3", json!({})), Ok(json!(7)));
+        assert_eq!(run("7 / 2", json!({})), Ok(json!(3)));
+        assert_eq!(run("7 / 2.0", json!({})), Ok(json!(3.5)));
+        assert_eq!(run("'a' + 1 + 2", json!({})), Ok(json!("a12")));
+        assert!(run("1 / 0", json!({})).is_err());
+    }
+
+    #[test]
+    fn should_run_loops_and_conditionals() {
+        let source = "
+            int total = 0;
+            for (int i = 0; i < params.n; i++) {
+                if (i % 2 == 0) { continue; }
+                total += i;
+            }
+            def names = [];
+            for (def item : params.items) {
+                names.add(item.name.toUpperCase());
+            }
+            return total > 3 ? names : null;
+        ";
+        let params = json!({ "n": 6, "items": [{ "name": "a" }, { "name": "b" }] });
+        assert_eq!(run(source, params), Ok(json!(["A", "B"])));
+    }
+
+    #[test]
+    fn should_build_and_mutate_collections() {
+        let source = "
+            def m = [:];
+            m.put('a', 1);
+            m['b'] = [1, 2];
+            m.b.add(3);
+            m.c = m.getOrDefault('c', 0) + 1;
+            m.remove('a');
+            return m;
+        ";
+        assert_eq!(
+            run(source, json!({})),
+            Ok(json!({ "b": [1, 2, 3], "c": 1 }))
+        );
+    }
+
+    #[test]
+    fn should_stop_runaway_loops() {
+        let error = run("while (true) {}", json!({})).unwrap_err();
+        assert!(error.contains("maximum number of statements"));
+    }
+
+    #[test]
+    fn should_bound_the_evaluation_depth() {
+        let mut expr = Expr::Literal(Literal::Bool(true));
+        for _ in 0..MAX_DEPTH {
+            expr = Expr::Unary(UnaryOp::Not, Box::new(expr));
+        }
+        let mut interpreter = Interpreter::new(Vec::new());
+        let error = interpreter.run(&[Stmt::Expr(expr)]).unwrap_err();
+        assert!(error.contains("maximum nesting depth"));
+    }
+
+    #[test]
+    fn should_copy_values_stored_in_collections() {
+        let source = "
+            List l = [];
+            l.add(l);
+            l.add(l);
+            def m = ['a': 1];
+            m.me = m;
+            m.put('again', m);
+            return [l, m];
+        ";
+        assert_eq!(
+            run(source, json!({})),
+            Ok(json!([
+                [[], [[]]],
+                { "a": 1, "me": { "a": 1 }, "again": { "a": 1, "me": { "a": 1 } } }
+            ]))
+        );
+    }
+
+    #[test]
+    fn should_bound_the_size_of_built_values() {
+        let error = run("String s = 'x'; while (true) { s = s + s; }", json!({})).unwrap_err();
+        assert!(error.contains("string length"), "{}", error);
+
+        let error = run("List l = []; while (true) { l.add(1); }", json!({})).unwrap_err();
+        assert!(error.contains("collection size"), "{}", error);
+
+        let source = "
+            String s = 'x';
+            for (int i = 0; i < 19; i++) { s = s + s; }
+            List l = [];
+            while (true) { l.add(s); }
+        ";
+        let error = run(source, json!({})).unwrap_err();
+        assert!(error.contains("maximum allocation"), "{}", error);
+
+        let source = "
+            List l = [];
+            def current = l;
+            for (int i = 0; i < 10000; i++) { current.add([]); current = current[0]; }
+            return l;
+        ";
+        let error = run(source, json!({})).unwrap_err();
+        assert!(error.contains("maximum nesting depth"), "{}", error);
+    }
+
+    #[test]
+    fn should_handle_null_safe_access() {
+        assert_eq!(run("params.a?.b ?: 'none'", json!({})), Ok(json!("none")));
+        assert!(run("params.a.b", json!({})).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Punct(&'static str),
+    Eof,
+}
+
+/// Longest punctuators first so that e.g. `+=` is not read as `+` `=`.
+const PUNCTUATORS: &[&str] = &[
+    "?.", "?:", "++", "--", "+=", "-=", "*=", "/=", "%=", "==", "!=", "<=", ">=", "&&", "||", "->",
+    "+", "-", "*", "/", "%", "=", "<", ">", "!", "?", ":", ";", ",", ".", "(", ")", "[", "]", "{",
+    "}",
+];
+
+pub fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c.is_whitespace() {
+            pos += 1;
+            continue;
+        }
+        if c == '/' && chars.get(pos + 1) == Some(&'/') {
+            while pos < chars.len() && chars[pos] != '\n' {
+                pos += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(pos + 1) == Some(&'*') {
+            pos += 2;
+            while pos < chars.len() && !(chars[pos] == '*' && chars.get(pos + 1) == Some(&'/')) {
+                pos += 1;
+            }
+            pos += 2;
+            continue;
+        }
+
+        if c.is_ascii_digit() {
+            let (token, next) = read_number(&chars, pos)?;
+            tokens.push(token);
+            pos = next;
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' || c == '$' {
+            let start = pos;
+            while pos < chars.len()
+                && (chars[pos].is_alphanumeric() || chars[pos] == '_' || chars[pos] == '$')
+            {
+                pos += 1;
+            }
+            tokens.push(Token::Ident(chars[start..pos].iter().collect()));
+            continue;
+        }
+        if c == '"' || c == '\'' {
+            let (text, next) = read_string(&chars, pos)?;
+            tokens.push(Token::Str(text));
+            pos = next;
+            continue;
+        }
+
+        let rest: String = chars[pos..chars.len().min(pos + 2)].iter().collect();
+        match PUNCTUATORS.iter().find(|p| rest.starts_with(**p)) {
+            Some(punct) => {
+                tokens.push(Token::Punct(punct));
+                pos += punct.len();
+            }
+            None => return Err(format!("unexpected character [{}]", c)),
+        }
+    }
+
+    tokens.push(Token::Eof);
+    Ok(tokens)
+}
+
+fn read_number(chars: &[char], start: usize) -> Result<(Token, usize), String> {
+    let mut pos = start;
+    let mut is_float = false;
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c.is_ascii_digit() {
+            pos += 1;
+        } else if c == '.' && !is_float && chars.get(pos + 1).is_some_and(|n| n.is_ascii_digit()) {
+            is_float = true;
+            pos += 1;
+        } else {
+            break;
+        }
+    }
+    let text: String = chars[start..pos].iter().collect();
+
+    match chars.get(pos) {
+        Some('L') | Some('l') => Ok((Token::Int(parse_int(&text)?), pos + 1)),
+        Some('f') | Some('F') | Some('d') | Some('D') => {
+            Ok((Token::Float(parse_float(&text)?), pos + 1))
+        }
+        _ if is_float => Ok((Token::Float(parse_float(&text)?), pos)),
+        _ => Ok((Token::Int(parse_int(&text)?), pos)),
+    }
+}
+
+fn parse_int(text: &str) -> Result<i64, String> {
+    text.parse()
+        .map_err(|_| format!("invalid number [{}]", text))
+}
+
+fn parse_float(text: &str) -> Result<f64, String> {
+    text.parse()
+        .map_err(|_| format!("invalid number [{}]", text))
+}
+
+fn read_string(chars: &[char], start: usize) -> Result<(String, usize), String> {
+    let quote = chars[start];
+    let mut pos = start + 1;
+    let mut text = String::new();
+    while pos < chars.len() {
+        match chars[pos] {
+            c if c == quote => return Ok((text, pos + 1)),
+            '\\' => {
+                let escaped = chars.get(pos + 1).ok_or("unterminated string")?;
+                text.push(match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    other => *other,
+                });
+                pos += 2;
+            }
+            c => {
+                text.push(c);
+                pos += 1;
+            }
+        }
+    }
+    Err("unterminated string".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_tokenize_compound_assignment() {
+        let tokens = tokenize("ctx._source.count += params['n']; // bump").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("ctx".into()),
+                Token::Punct("."),
+                Token::Ident("_source".into()),
+                Token::Punct("."),
+                Token::Ident("count".into()),
+                Token::Punct("+="),
+                Token::Ident("params".into()),
+                Token::Punct("["),
+                Token::Str("n".into()),
+                Token::Punct("]"),
+                Token::Punct(";"),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_read_numeric_literals() {
+        let tokens = tokenize("1 2.5 3L 4f").unwrap();
+        assert_eq!(
+            &tokens[..4],
+            &[
+                Token::Int(1),
+                Token::Float(2.5),
+                Token::Int(3),
+                Token::Float(4.0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! A sandboxed interpreter for the subset of Painless used by update scripts,
+//! `script` queries and `_script` sorts: field access, arithmetic,
+//! conditionals and loops, list/map operations, `params` and `ctx.op`.
+
+mod interpreter;
+mod lexer;
+mod parser;
+
+use indexmap::IndexMap;
+use interpreter::{Interpreter, Val};
+use parser::Stmt;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+pub const DEFAULT_LANG: &str = "painless";
+
+/// A compiled script together with its `params`.
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub params: Value,
+    program: Arc<Vec<Stmt>>,
+}
+
+/// What an update script asked to happen to the document through `ctx.op`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptOp {
+    Index,
+    Noop,
+    Delete,
+}
+
+/// The `ctx` an update script sees and may modify.
+#[derive(Debug, Clone)]
+pub struct UpdateContext {
+    pub index: String,
+    pub id: String,
+    pub version: Option<u64>,
+    pub routing: Option<String>,
+    pub source: Value,
+    pub op: ScriptOp,
+}
+
+impl Script {
+    /// Parses the `script` element of a request: either the source as a plain
+    /// string or an object with `source`, `lang` and `params`.
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let (source, params) = match json {
+            Value::String(source) => (source.clone(), Value::Object(Default::default())),
+            Value::Object(object) => {
+                if object.contains_key("id") {
+                    return Err("compile error: stored scripts are not supported".to_string());
+                }
+                let lang = object
+                    .get("lang")
+                    .and_then(Value::as_str)
+                    .unwrap_or(DEFAULT_LANG);
+                if lang != DEFAULT_LANG {
+                    return Err(format!(
+                        "compile error: script_lang not supported [{}]",
+                        lang
+                    ));
+                }
+                let source = object
+                    .get("source")
+                    .or_else(|| object.get("inline"))
+                    .and_then(Value::as_str)
+                    .ok_or("compile error: must specify either [source] for an inline script")?;
+                let params = object
+                    .get("params")
+                    .cloned()
+                    .unwrap_or_else(|| Value::Object(Default::default()));
+                if !params.is_object() {
+                    return Err("compile error: [params] must be an object".to_string());
+                }
+                (source.to_string(), params)
+            }
+            _ => return Err("compile error: [script] must be a string or an object".to_string()),
+        };
+
+        Self::compile(&source, params)
+    }
+
+    pub fn compile(source: &str, params: Value) -> Result<Self, String> {
+        let program = parser::parse(source).map_err(|e| format!("compile error: {}", e))?;
+        Ok(Self {
+            params,
+            program: Arc::new(program),
+        })
+    }
+
+    fn run(&self, mut globals: Vec<(&str, Val)>) -> Result<Val, String> {
+        globals.push(("params", Val::from_json(&self.params)));
+        Interpreter::new(globals)
+            .run(&self.program)
+            .map_err(runtime_error)
+    }
+
+    /// Runs an update script against `ctx`, writing back whatever the script
+    /// changed in `_source`, `_index`, `_id`, `_routing` and `op`.
+    pub fn execute_update(&self, context: &mut UpdateContext) -> Result<(), String> {
+        let mut ctx = IndexMap::new();
+        ctx.insert("_index".to_string(), Val::Str(context.index.clone()));
+        ctx.insert("_id".to_string(), Val::Str(context.id.clone()));
+        ctx.insert(
+            "_version".to_string(),
+            context.version.map_or(Val::Null, |v| Val::Int(v as i64)),
+        );
+        ctx.insert(
+            "_routing".to_string(),
+            context.routing.clone().map_or(Val::Null, Val::Str),
+        );
+        ctx.insert(
+            "_now".to_string(),
+            Val::Int(crate::domain::time::now_millis() as i64),
+        );
+        ctx.insert("op".to_string(), Val::Str(op_name(context.op).to_string()));
+        ctx.insert("_source".to_string(), Val::from_json(&context.source));
+        let ctx = Val::map(ctx);
+
+        self.run(vec![("ctx", ctx.clone())])?;
+
+        let ctx = ctx.to_json().map_err(runtime_error)?;
+        context.op = match ctx["op"].as_str() {
+            Some("index") | Some("create") => ScriptOp::Index,
+            Some("none") | Some("noop") => ScriptOp::Noop,
+            Some("delete") => ScriptOp::Delete,
+            other => {
+                return Err(format!(
+                    "runtime error: Operation type [{}] not allowed, only [noop, index, delete] \
+                     are allowed",
+                    other.unwrap_or("null")
+                ));
+            }
+        };
+        if let Some(index) = ctx["_index"].as_str() {
+            context.index = index.to_string();
+        }
+        if let Some(id) = ctx["_id"].as_str() {
+            context.id = id.to_string();
+        }
+        context.routing = ctx["_routing"].as_str().map(str::to_string);
+        context.source = match &ctx["_source"] {
+            source @ Value::Object(_) => source.clone(),
+            _ => return Err("runtime error: [ctx._source] must be an object".to_string()),
+        };
+        Ok(())
+    }
+
+    /// Runs an ingest script, where `ctx` is the document itself, writing its
+    /// changes back to `source` and returning the script's result.
+    pub fn execute_ingest(&self, source: &mut Value) -> Result<Value, String> {
+        let ctx = Val::from_json(source);
+        let result = self.run(vec![("ctx", ctx.clone())])?;
+        *source = ctx.to_json().map_err(runtime_error)?;
+        result.to_json().map_err(runtime_error)
+    }
+
+    /// Runs a search script with `doc` bound to the document's values and
+    /// returns its result.
+    pub fn evaluate(&self, source: &Value) -> Result<Value, String> {
+        let doc = Val::Doc(Rc::new(doc_values(source)));
+        self.run(vec![("doc", doc)])?
+            .to_json()
+            .map_err(runtime_error)
+    }
+}
+
+pub fn is_script_error(error: &str) -> bool {
+    error.starts_with("compile error") || error.starts_with("runtime error")
+}
+
+fn runtime_error(error: String) -> String {
+    format!("runtime error: {}", error)
+}
+
+fn op_name(op: ScriptOp) -> &'static str {
+    match op {
+        ScriptOp::Index => "index",
+        ScriptOp::Noop => "noop",
+        ScriptOp::Delete => "delete",
+    }
+}
+
+/// Flattens a `_source` into the `doc['field']` view: every leaf path maps to
+/// its sorted values, and text also answers to its `.keyword` sub-field.
+fn doc_values(source: &Value) -> HashMap<String, Val> {
+    let mut fields: HashMap<String, Vec<Value>> = HashMap::new();
+    collect_values(source, "", &mut fields);
+
+    let mut doc = HashMap::new();
+    for (path, mut values) in fields {
+        values.sort_by(crate::domain::engine::SearchEngine::compare_values);
+        if values.iter().any(Value::is_string) {
+            doc.insert(
+                format!("{}.keyword", path),
+                Val::list(values.iter().map(Val::from_json).collect()),
+            );
+        }
+        doc.insert(path, Val::list(values.iter().map(Val::from_json).collect()));
+    }
+    doc
+}
+
+fn collect_values(value: &Value, path: &str, fields: &mut HashMap<String, Vec<Value>>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                let child_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                collect_values(child, &child_path, fields);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_values(item, path, fields);
+            }
+        }
+        Value::Null => {}
+        leaf => fields
+            .entry(path.to_string())
+            .or_default()
+            .push(leaf.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context(source: Value) -> UpdateContext {
+        UpdateContext {
+            index: "test".to_string(),
+            id: "1".to_string(),
+            version: Some(1),
+            routing: None,
+            source,
+            op: ScriptOp::Index,
+        }
+    }
+
+    #[test]
+    fn should_increment_counter_from_params() {
+        let script = Script::from_json(&json!({
+            "source": "ctx._source.count += params.n; ctx._source.tags.add('new')",
+            "params": { "n": 4 }
+        }))
+        .unwrap();
+        let mut ctx = context(json!({ "count": 1, "tags": [] }));
+        script.execute_update(&mut ctx).unwrap();
+
+        assert_eq!(ctx.op, ScriptOp::Index);
+        assert_eq!(ctx.source, json!({ "count": 5, "tags": ["new"] }));
+    }
+
+    #[test]
+    fn should_store_a_copy_when_the_source_is_assigned_into_itself() {
+        let script = Script::from_json(&json!("ctx._source.me = ctx._source")).unwrap();
+        let mut ctx = context(json!({ "count": 1 }));
+        script.execute_update(&mut ctx).unwrap();
+        assert_eq!(ctx.source, json!({ "count": 1, "me": { "count": 1 } }));
+    }
+
+    #[test]
+    fn should_honour_ctx_op() {
+        let script = Script::from_json(&json!(
+            "if (ctx._source.count > 1) { ctx.op = 'delete' } else { ctx.op = 'noop' }"
+        ))
+        .unwrap();
+
+        let mut ctx = context(json!({ "count": 2 }));
+        script.execute_update(&mut ctx).unwrap();
+        assert_eq!(ctx.op, ScriptOp::Delete);
+
+        let mut ctx = context(json!({ "count": 0 }));
+        script.execute_update(&mut ctx).unwrap();
+        assert_eq!(ctx.op, ScriptOp::Noop);
+
+        let bogus = Script::from_json(&json!("ctx.op = 'explode'")).unwrap();
+        assert!(bogus.execute_update(&mut context(json!({}))).is_err());
+    }
+
+    #[test]
+    fn should_expose_doc_values_to_search_scripts() {
+        let script = Script::from_json(&json!({
+            "source": "doc['price'].value * params.factor + doc['tags.keyword'].size()",
+            "params": { "factor": 2 }
+        }))
+        .unwrap();
+        let source = json!({ "price": 10, "tags": ["a", "b"] });
+        assert_eq!(script.evaluate(&source), Ok(json!(22)));
+
+        let missing = Script::from_json(&json!("doc['nope'].size() == 0")).unwrap();
+        assert_eq!(missing.evaluate(&source), Ok(json!(true)));
+    }
+
+    #[test]
+    fn should_report_compile_errors() {
+        let error = Script::from_json(&json!("ctx._source.count +")).unwrap_err();
+        assert!(is_script_error(&error));
+        assert!(Script::from_json(&json!({ "id": "stored" })).is_err());
+    }
+
+    #[test]
+    fn should_reject_scripts_nested_too_deeply() {
+        let parentheses = format!("{}1{}", "(".repeat(1000), ")".repeat(1000));
+        let sum = vec!["1"; 1000].join(" + ");
+        for source in [parentheses, sum] {
+            let error = Script::compile(&source, json!({})).unwrap_err();
+            assert!(is_script_error(&error));
+            assert!(error.contains("maximum nesting depth"), "{}", error);
+        }
+
+        let nested = format!("{}1{}", "(".repeat(60), ")".repeat(60));
+        let script = Script::compile(&nested, json!({})).unwrap();
+        assert_eq!(script.evaluate(&json!({})), Ok(json!(1)));
+    }
+}
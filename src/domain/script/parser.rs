@@ -0,0 +1,709 @@
+use super::lexer::{Token, tokenize};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Literal(Literal),
+    Var(String),
+    List(Vec<Expr>),
+    Map(Vec<(Expr, Expr)>),
+    New(String, Vec<Expr>),
+    Field {
+        target: Box<Expr>,
+        name: String,
+        null_safe: bool,
+    },
+    Index(Box<Expr>, Box<Expr>),
+    Call {
+        target: Box<Expr>,
+        method: String,
+        args: Vec<Expr>,
+        null_safe: bool,
+    },
+    Cast(String, Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    Elvis(Box<Expr>, Box<Expr>),
+    Assign {
+        target: Box<Expr>,
+        op: Option<BinaryOp>,
+        value: Box<Expr>,
+    },
+    Step {
+        target: Box<Expr>,
+        delta: i64,
+        prefix: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Expr(Expr),
+    Declare(Vec<(String, Option<Expr>)>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+    DoWhile(Box<Stmt>, Expr),
+    For {
+        init: Option<Box<Stmt>>,
+        condition: Option<Expr>,
+        update: Vec<Expr>,
+        body: Box<Stmt>,
+    },
+    ForEach(String, Expr, Box<Stmt>),
+    Block(Vec<Stmt>),
+    Return(Option<Expr>),
+    Break,
+    Continue,
+}
+
+/// Type names accepted in declarations, casts and `new` expressions. Painless
+/// is statically typed, but every value here is dynamically typed so the
+/// declared type only matters for numeric casts.
+const TYPE_NAMES: &[&str] = &[
+    "def",
+    "var",
+    "int",
+    "long",
+    "short",
+    "byte",
+    "char",
+    "float",
+    "double",
+    "boolean",
+    "String",
+    "Object",
+    "Number",
+    "Integer",
+    "Long",
+    "Double",
+    "Float",
+    "Boolean",
+    "List",
+    "ArrayList",
+    "Map",
+    "HashMap",
+    "Set",
+    "HashSet",
+    "Collection",
+];
+
+fn is_type_name(name: &str) -> bool {
+    TYPE_NAMES.contains(&name)
+}
+
+/// Deepest nesting of statements and expressions a script may have, so a
+/// pathological script fails to compile instead of overflowing the stack of
+/// the parser, the interpreter or the drop of its syntax tree.
+pub const MAX_DEPTH: usize = 128;
+
+pub fn parse(source: &str) -> Result<Vec<Stmt>, String> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+        depth: 0,
+    };
+    let mut statements = Vec::new();
+    while parser.peek() != &Token::Eof {
+        statements.push(parser.statement()?);
+    }
+    Ok(statements)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn peek_at(&self, offset: usize) -> &Token {
+        self.tokens.get(self.pos + offset).unwrap_or(&Token::Eof)
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token != Token::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Token::Punct(p) if *p == punct)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Ident(name) if name == keyword)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if self.is_punct(punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(format!(
+                "expected [{}] but found {}",
+                punct,
+                describe(self.peek())
+            ))
+        }
+    }
+
+    fn descend(&mut self) -> Result<(), String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!(
+                "script exceeds the maximum nesting depth of [{}]",
+                MAX_DEPTH
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        self.descend()?;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.advance() {
+            Token::Ident(name) => Ok(name),
+            other => Err(format!(
+                "expected identifier but found {}",
+                describe(&other)
+            )),
+        }
+    }
+
+    /// Statements may omit their trailing `;` right before `}` or the end of
+    /// the script, as Painless allows.
+    fn end_statement(&mut self) -> Result<(), String> {
+        if self.eat(";") || self.is_punct("}") || self.peek() == &Token::Eof {
+            Ok(())
+        } else {
+            Err(format!("expected [;] but found {}", describe(self.peek())))
+        }
+    }
+
+    fn statement(&mut self) -> Result<Stmt, String> {
+        self.nested(Self::nested_statement)
+    }
+
+    fn nested_statement(&mut self) -> Result<Stmt, String> {
+        if self.eat("{") {
+            let mut statements = Vec::new();
+            while !self.eat("}") {
+                if self.peek() == &Token::Eof {
+                    return Err("expected [}] but found end of script".to_string());
+                }
+                statements.push(self.statement()?);
+            }
+            return Ok(Stmt::Block(statements));
+        }
+        if self.eat(";") {
+            return Ok(Stmt::Block(Vec::new()));
+        }
+
+        let keyword = match self.peek() {
+            Token::Ident(name) => name.clone(),
+            _ => String::new(),
+        };
+        match keyword.as_str() {
+            "if" => {
+                self.advance();
+                self.expect("(")?;
+                let condition = self.expression()?;
+                self.expect(")")?;
+                let then = self.statement()?;
+                let otherwise = if self.is_keyword("else") {
+                    self.advance();
+                    Some(Box::new(self.statement()?))
+                } else {
+                    None
+                };
+                Ok(Stmt::If(condition, Box::new(then), otherwise))
+            }
+            "while" => {
+                self.advance();
+                self.expect("(")?;
+                let condition = self.expression()?;
+                self.expect(")")?;
+                Ok(Stmt::While(condition, Box::new(self.statement()?)))
+            }
+            "do" => {
+                self.advance();
+                let body = self.statement()?;
+                if !self.is_keyword("while") {
+                    return Err("expected [while] after do block".to_string());
+                }
+                self.advance();
+                self.expect("(")?;
+                let condition = self.expression()?;
+                self.expect(")")?;
+                self.end_statement()?;
+                Ok(Stmt::DoWhile(Box::new(body), condition))
+            }
+            "for" => {
+                self.advance();
+                self.for_statement()
+            }
+            "return" => {
+                self.advance();
+                let value =
+                    if self.is_punct(";") || self.is_punct("}") || self.peek() == &Token::Eof {
+                        None
+                    } else {
+                        Some(self.expression()?)
+                    };
+                self.end_statement()?;
+                Ok(Stmt::Return(value))
+            }
+            "break" => {
+                self.advance();
+                self.end_statement()?;
+                Ok(Stmt::Break)
+            }
+            "continue" => {
+                self.advance();
+                self.end_statement()?;
+                Ok(Stmt::Continue)
+            }
+            _ if self.at_declaration() => {
+                let declaration = self.declaration()?;
+                self.end_statement()?;
+                Ok(declaration)
+            }
+            _ => {
+                let expr = self.expression()?;
+                self.end_statement()?;
+                Ok(Stmt::Expr(expr))
+            }
+        }
+    }
+
+    fn for_statement(&mut self) -> Result<Stmt, String> {
+        self.expect("(")?;
+
+        // for (def item : list)
+        if self.at_declaration() {
+            let start = self.pos;
+            self.type_name()?;
+            let name = self.identifier()?;
+            if self.eat(":") {
+                let iterable = self.expression()?;
+                self.expect(")")?;
+                return Ok(Stmt::ForEach(name, iterable, Box::new(self.statement()?)));
+            }
+            self.pos = start;
+        }
+
+        let init = if self.is_punct(";") {
+            None
+        } else if self.at_declaration() {
+            Some(Box::new(self.declaration()?))
+        } else {
+            Some(Box::new(Stmt::Expr(self.expression()?)))
+        };
+        self.expect(";")?;
+        let condition = if self.is_punct(";") {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.expect(";")?;
+        let mut update = Vec::new();
+        while !self.is_punct(")") {
+            update.push(self.expression()?);
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(")")?;
+
+        Ok(Stmt::For {
+            init,
+            condition,
+            update,
+            body: Box::new(self.statement()?),
+        })
+    }
+
+    /// A declaration is a type name followed by an identifier, optionally
+    /// with generic arguments (`Map<String, def> m`) or array brackets.
+    fn at_declaration(&self) -> bool {
+        let Token::Ident(name) = self.peek() else {
+            return false;
+        };
+        if !is_type_name(name) {
+            return false;
+        }
+        let mut offset = 1;
+        if matches!(self.peek_at(offset), Token::Punct("<")) {
+            let mut depth = 0;
+            loop {
+                match self.peek_at(offset) {
+                    Token::Punct("<") => depth += 1,
+                    Token::Punct(">") => {
+                        depth -= 1;
+                        if depth == 0 {
+                            offset += 1;
+                            break;
+                        }
+                    }
+                    Token::Ident(_) | Token::Punct(",") => {}
+                    _ => return false,
+                }
+                offset += 1;
+            }
+        }
+        while matches!(self.peek_at(offset), Token::Punct("["))
+            && matches!(self.peek_at(offset + 1), Token::Punct("]"))
+        {
+            offset += 2;
+        }
+        matches!(self.peek_at(offset), Token::Ident(_))
+    }
+
+    fn type_name(&mut self) -> Result<String, String> {
+        let name = self.identifier()?;
+        if self.eat("<") {
+            let mut depth = 1;
+            while depth > 0 {
+                match self.advance() {
+                    Token::Punct("<") => depth += 1,
+                    Token::Punct(">") => depth -= 1,
+                    Token::Eof => return Err("unterminated generic type".to_string()),
+                    _ => {}
+                }
+            }
+        }
+        while self.is_punct("[") && matches!(self.peek_at(1), Token::Punct("]")) {
+            self.pos += 2;
+        }
+        Ok(name)
+    }
+
+    fn declaration(&mut self) -> Result<Stmt, String> {
+        self.type_name()?;
+        let mut variables = Vec::new();
+        loop {
+            let name = self.identifier()?;
+            let value = if self.eat("=") {
+                Some(self.expression()?)
+            } else {
+                None
+            };
+            variables.push((name, value));
+            if !self.eat(",") {
+                break;
+            }
+        }
+        Ok(Stmt::Declare(variables))
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        self.nested(Self::nested_expression)
+    }
+
+    fn nested_expression(&mut self) -> Result<Expr, String> {
+        let target = self.conditional()?;
+        let op = match self.peek() {
+            Token::Punct("=") => None,
+            Token::Punct("+=") => Some(BinaryOp::Add),
+            Token::Punct("-=") => Some(BinaryOp::Sub),
+            Token::Punct("*=") => Some(BinaryOp::Mul),
+            Token::Punct("/=") => Some(BinaryOp::Div),
+            Token::Punct("%=") => Some(BinaryOp::Rem),
+            _ => return Ok(target),
+        };
+        if !matches!(target, Expr::Var(_) | Expr::Field { .. } | Expr::Index(..)) {
+            return Err("invalid assignment target".to_string());
+        }
+        self.advance();
+        let value = self.expression()?;
+        Ok(Expr::Assign {
+            target: Box::new(target),
+            op,
+            value: Box::new(value),
+        })
+    }
+
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let condition = self.binary(0)?;
+        if self.eat("?") {
+            let then = self.expression()?;
+            self.expect(":")?;
+            let otherwise = self.nested(Self::conditional)?;
+            return Ok(Expr::Ternary(
+                Box::new(condition),
+                Box::new(then),
+                Box::new(otherwise),
+            ));
+        }
+        if self.eat("?:") {
+            let fallback = self.nested(Self::conditional)?;
+            return Ok(Expr::Elvis(Box::new(condition), Box::new(fallback)));
+        }
+        Ok(condition)
+    }
+
+    /// Precedence climbing over the binary operators, lowest level first.
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        const LEVELS: &[&[(&str, BinaryOp)]] = &[
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+            &[
+                ("<", BinaryOp::Lt),
+                ("<=", BinaryOp::Le),
+                (">", BinaryOp::Gt),
+                (">=", BinaryOp::Ge),
+            ],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+            &[
+                ("*", BinaryOp::Mul),
+                ("/", BinaryOp::Div),
+                ("%", BinaryOp::Rem),
+            ],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        // Every operator of a chain nests the tree one level deeper.
+        let depth = self.depth;
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let op = LEVELS[level]
+                .iter()
+                .find(|(punct, _)| self.is_punct(punct))
+                .map(|(_, op)| *op);
+            let Some(op) = op else {
+                self.depth = depth;
+                return Ok(left);
+            };
+            self.descend()?;
+            self.advance();
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.nested(Self::nested_unary)
+    }
+
+    fn nested_unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(match self.unary()? {
+                Expr::Literal(Literal::Int(n)) => Expr::Literal(Literal::Int(-n)),
+                Expr::Literal(Literal::Float(n)) => Expr::Literal(Literal::Float(-n)),
+                expr => Expr::Unary(UnaryOp::Neg, Box::new(expr)),
+            });
+        }
+        if self.eat("+") {
+            return self.unary();
+        }
+        for (punct, delta) in [("++", 1), ("--", -1)] {
+            if self.eat(punct) {
+                return Ok(Expr::Step {
+                    target: Box::new(self.unary()?),
+                    delta,
+                    prefix: true,
+                });
+            }
+        }
+        // (int) x
+        if self.is_punct("(")
+            && let Token::Ident(name) = self.peek_at(1)
+            && is_type_name(name)
+            && matches!(self.peek_at(2), Token::Punct(")"))
+        {
+            let name = name.clone();
+            self.pos += 3;
+            return Ok(Expr::Cast(name, Box::new(self.unary()?)));
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let depth = self.depth;
+        let mut expr = self.primary()?;
+        loop {
+            if self.is_punct(".") || self.is_punct("?.") {
+                self.descend()?;
+                let null_safe = self.advance() == Token::Punct("?.");
+                let name = self.identifier()?;
+                if self.eat("(") {
+                    let args = self.arguments()?;
+                    expr = Expr::Call {
+                        target: Box::new(expr),
+                        method: name,
+                        args,
+                        null_safe,
+                    };
+                } else {
+                    expr = Expr::Field {
+                        target: Box::new(expr),
+                        name,
+                        null_safe,
+                    };
+                }
+            } else if self.eat("[") {
+                self.descend()?;
+                let index = self.expression()?;
+                self.expect("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else if self.is_punct("++") || self.is_punct("--") {
+                self.descend()?;
+                let delta = if self.advance() == Token::Punct("++") {
+                    1
+                } else {
+                    -1
+                };
+                expr = Expr::Step {
+                    target: Box::new(expr),
+                    delta,
+                    prefix: false,
+                };
+            } else {
+                self.depth = depth;
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Expr>, String> {
+        let mut args = Vec::new();
+        if self.eat(")") {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expression()?);
+            if self.eat(")") {
+                return Ok(args);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.advance() {
+            Token::Int(n) => Ok(Expr::Literal(Literal::Int(n))),
+            Token::Float(n) => Ok(Expr::Literal(Literal::Float(n))),
+            Token::Str(s) => Ok(Expr::Literal(Literal::Str(s))),
+            Token::Punct("(") => {
+                let expr = self.expression()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Punct("[") => self.collection_literal(),
+            Token::Ident(name) => match name.as_str() {
+                "true" => Ok(Expr::Literal(Literal::Bool(true))),
+                "false" => Ok(Expr::Literal(Literal::Bool(false))),
+                "null" => Ok(Expr::Literal(Literal::Null)),
+                "new" => {
+                    let type_name = self.type_name()?;
+                    self.expect("(")?;
+                    let args = self.arguments()?;
+                    Ok(Expr::New(type_name, args))
+                }
+                _ => Ok(Expr::Var(name)),
+            },
+            other => Err(format!("unexpected {}", describe(&other))),
+        }
+    }
+
+    /// `[]` and `[a, b]` are lists, `[:]` and `[k: v]` are maps.
+    fn collection_literal(&mut self) -> Result<Expr, String> {
+        if self.eat("]") {
+            return Ok(Expr::List(Vec::new()));
+        }
+        if self.eat(":") {
+            self.expect("]")?;
+            return Ok(Expr::Map(Vec::new()));
+        }
+
+        let first = self.expression()?;
+        if self.eat(":") {
+            let mut entries = vec![(first, self.expression()?)];
+            while self.eat(",") {
+                let key = self.expression()?;
+                self.expect(":")?;
+                entries.push((key, self.expression()?));
+            }
+            self.expect("]")?;
+            return Ok(Expr::Map(entries));
+        }
+
+        let mut items = vec![first];
+        while self.eat(",") {
+            items.push(self.expression()?);
+        }
+        self.expect("]")?;
+        Ok(Expr::List(items))
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(name) => format!("[{}]", name),
+        Token::Int(n) => format!("[{}]", n),
+        Token::Float(n) => format!("[{}]", n),
+        Token::Str(s) => format!("['{}']", s),
+        Token::Punct(p) => format!("[{}]", p),
+        Token::Eof => "end of script".to_string(),
+    }
+}
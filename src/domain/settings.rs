@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Index settings flattened to dotted keys without the `index.` prefix, with
+/// every value kept as a string the way Elasticsearch reports them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct IndexSettings {
+    values: BTreeMap<String, String>,
+}
+
+impl IndexSettings {
+    pub fn from_json(json: &Value) -> Self {
+        let mut settings = Self::default();
+        settings.flatten("", json);
+        settings
+    }
+
+    fn flatten(&mut self, prefix: &str, json: &Value) {
+        match json {
+            Value::Object(obj) => {
+                for (key, value) in obj {
+                    let path = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", prefix, key)
+                    };
+                    self.flatten(&path, value);
+                }
+            }
+            Value::Null => {}
+            other => {
+                let key = prefix.strip_prefix("index.").unwrap_or(prefix).to_string();
+                let value = match other {
+                    Value::String(s) => s.clone(),
+                    Value::Array(items) => items
+                        .iter()
+                        .map(|i| i.as_str().map(|s| s.to_string()).unwrap_or(i.to_string()))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    v => v.to_string(),
+                };
+                self.values.insert(key, value);
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &IndexSettings) {
+        for (key, value) in &other.values {
+            self.values.insert(key.clone(), value.clone());
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|s| s.as_str())
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    /// Renders the settings as the nested `index` object returned by the
+    /// settings API.
+    pub fn to_json(&self) -> Value {
+        let mut root = Map::new();
+        for (key, value) in &self.values {
+            let mut current = &mut root;
+            let mut parts = key.split('.').peekable();
+            while let Some(part) = parts.next() {
+                if parts.peek().is_none() {
+                    current.insert(part.to_string(), Value::String(value.clone()));
+                } else {
+                    let entry = current
+                        .entry(part.to_string())
+                        .or_insert_with(|| Value::Object(Map::new()));
+                    if !entry.is_object() {
+                        *entry = Value::Object(Map::new());
+                    }
+                    current = entry.as_object_mut().unwrap();
+                }
+            }
+        }
+        Value::Object(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_normalize_nested_and_dotted_settings() {
+        let nested = IndexSettings::from_json(&json!({ "index": { "number_of_shards": 2 } }));
+        let dotted = IndexSettings::from_json(&json!({ "index.number_of_shards": "2" }));
+        let flat = IndexSettings::from_json(&json!({ "number_of_shards": 2 }));
+
+        assert_eq!(nested, dotted);
+        assert_eq!(nested, flat);
+        assert_eq!(nested.get("number_of_shards"), Some("2"));
+    }
+
+    #[test]
+    fn should_merge_and_render_settings() {
+        let mut settings = IndexSettings::from_json(&json!({ "number_of_shards": 1 }));
+        settings.merge(&IndexSettings::from_json(&json!({
+            "number_of_shards": 3,
+            "refresh_interval": "5s"
+        })));
+
+        assert_eq!(
+            settings.to_json(),
+            json!({ "number_of_shards": "3", "refresh_interval": "5s" })
+        );
+    }
+}
@@ -0,0 +1,347 @@
+use crate::domain::source::wildcard_match;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::{Component, Path, PathBuf};
+
+/// The numeric id Elasticsearch derives from a version, e.g. `8100099` for
+/// `8.10.0`.
+pub fn version_id(version: &str) -> u64 {
+    let mut parts = version
+        .split(['.', '-'])
+        .map(|part| part.parse::<u64>().unwrap_or(0));
+    let mut next = || parts.next().unwrap_or(0);
+    next() * 1_000_000 + next() * 10_000 + next() * 100 + 99
+}
+
+/// A registered snapshot repository. Only shared file system (`fs`)
+/// repositories are supported.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryDefinition {
+    #[serde(rename = "type")]
+    pub repository_type: String,
+    pub settings: Map<String, Value>,
+}
+
+impl RepositoryDefinition {
+    pub fn from_json(name: &str, json: &Value) -> Result<Self, String> {
+        let repository_type = json["type"]
+            .as_str()
+            .ok_or_else(|| "Validation Failed: 1: type is missing;".to_string())?;
+        if repository_type != "fs" {
+            return Err(format!(
+                "[{}] repository type [{}] does not exist",
+                name, repository_type
+            ));
+        }
+        let definition = Self {
+            repository_type: repository_type.to_string(),
+            settings: json["settings"].as_object().cloned().unwrap_or_default(),
+        };
+        if definition.location().is_none() {
+            return Err(format!("[{}] missing location", name));
+        }
+        Ok(definition)
+    }
+
+    pub fn location(&self) -> Option<PathBuf> {
+        match self.settings.get("location") {
+            Some(Value::String(location)) if !location.is_empty() => Some(location.into()),
+            _ => None,
+        }
+    }
+
+    /// The directory of the repository: its `location`, resolved against
+    /// each of the `path.repo` roots in turn, that falls under that root.
+    pub fn resolve_location(&self, name: &str, roots: &[PathBuf]) -> Result<PathBuf, String> {
+        let location = self
+            .location()
+            .ok_or_else(|| format!("[{}] missing location", name))?;
+        if roots.is_empty() {
+            return Err(format!(
+                "[{}] location [{}] doesn't match any of the locations specified by path.repo \
+                 because this setting is empty",
+                name,
+                location.display()
+            ));
+        }
+        roots
+            .iter()
+            .find_map(|root| {
+                let root = normalize(root)?;
+                let resolved = normalize(&root.join(&location))?;
+                resolved.starts_with(&root).then_some(resolved)
+            })
+            .ok_or_else(|| {
+                format!(
+                    "[{}] location [{}] doesn't match any of the locations specified by path.repo",
+                    name,
+                    location.display()
+                )
+            })
+    }
+}
+
+/// Resolves `.` and `..` components without touching the file system, so
+/// that a location cannot climb out of its root. `None` when `..` would go
+/// above the start of the path.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+    Some(normalized)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotShards {
+    pub total: usize,
+    pub failed: usize,
+    pub successful: usize,
+}
+
+/// Metadata of a completed snapshot, as returned by the get snapshot API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub snapshot: String,
+    pub uuid: String,
+    pub repository: String,
+    pub version_id: u64,
+    pub version: String,
+    pub indices: Vec<String>,
+    pub data_streams: Vec<String>,
+    pub include_global_state: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+    pub state: String,
+    pub start_time: String,
+    pub start_time_in_millis: u64,
+    pub end_time: String,
+    pub end_time_in_millis: u64,
+    pub duration_in_millis: u64,
+    pub failures: Vec<Value>,
+    pub shards: SnapshotShards,
+    pub feature_states: Vec<Value>,
+}
+
+/// Checks a snapshot name against the rules Elasticsearch applies, returning
+/// why it is invalid.
+pub fn validate_snapshot_name(name: &str) -> Result<(), String> {
+    const INVALID: &[char] = &['\\', '/', '*', '?', '"', '<', '>', '|', ' ', ',', '#'];
+    if name.is_empty() {
+        Err("cannot be empty".to_string())
+    } else if name == "." || name == ".." {
+        Err("must not be '.' or '..'".to_string())
+    } else if name.contains(INVALID) {
+        Err(
+            "must not contain the following characters [ , \", *, \\, <, |, ,, >, /, ?]"
+                .to_string(),
+        )
+    } else if name.starts_with('_') {
+        Err("must not start with '_'".to_string())
+    } else if name.to_lowercase() != name {
+        Err("must be lowercase".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Reads `indices` given either as a comma-separated string or as an array.
+pub fn indices_expression(json: &Value) -> Option<String> {
+    match json {
+        Value::String(indices) => Some(indices.clone()),
+        Value::Array(items) => Some(
+            items
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        _ => None,
+    }
+}
+
+/// Options of a create snapshot request.
+#[derive(Debug, Clone)]
+pub struct SnapshotRequest {
+    pub indices: String,
+    pub ignore_unavailable: bool,
+    pub include_global_state: bool,
+    pub metadata: Option<Value>,
+}
+
+impl SnapshotRequest {
+    pub fn from_json(json: &Value) -> Self {
+        Self {
+            indices: indices_expression(&json["indices"]).unwrap_or_else(|| "*".to_string()),
+            ignore_unavailable: json["ignore_unavailable"].as_bool().unwrap_or(false),
+            include_global_state: json["include_global_state"].as_bool().unwrap_or(true),
+            metadata: json.get("metadata").filter(|m| m.is_object()).cloned(),
+        }
+    }
+}
+
+/// Options of a restore request: which indices to restore and under which
+/// names.
+#[derive(Debug, Clone)]
+pub struct RestoreRequest {
+    pub indices: Option<String>,
+    pub ignore_unavailable: bool,
+    pub include_aliases: bool,
+    pub include_global_state: bool,
+    rename: Option<(Regex, String)>,
+}
+
+impl RestoreRequest {
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let rename = match json["rename_pattern"].as_str() {
+            Some(pattern) => {
+                let regex = Regex::new(pattern)
+                    .map_err(|e| format!("invalid rename_pattern [{}]: {}", pattern, e))?;
+                let replacement = json["rename_replacement"].as_str().ok_or_else(|| {
+                    "rename_replacement must be set together with rename_pattern".to_string()
+                })?;
+                Some((regex, java_replacement(replacement)))
+            }
+            None => None,
+        };
+        Ok(Self {
+            indices: indices_expression(&json["indices"]),
+            ignore_unavailable: json["ignore_unavailable"].as_bool().unwrap_or(false),
+            include_aliases: json["include_aliases"].as_bool().unwrap_or(true),
+            include_global_state: json["include_global_state"].as_bool().unwrap_or(false),
+            rename,
+        })
+    }
+
+    /// Whether `name` is selected by `indices`; an index excluded with a
+    /// `-` pattern stays excluded whatever comes after it.
+    pub fn selects(&self, name: &str) -> bool {
+        let Some(indices) = &self.indices else {
+            return true;
+        };
+        let mut selected = false;
+        for pattern in indices.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match pattern.strip_prefix('-') {
+                Some(excluded) if wildcard_match(excluded, name) => return false,
+                Some(_) => {}
+                None => selected |= pattern == "_all" || wildcard_match(pattern, name),
+            }
+        }
+        selected
+    }
+
+    /// The name an index or data stream is restored under.
+    pub fn renamed(&self, name: &str) -> String {
+        match &self.rename {
+            Some((regex, replacement)) => regex.replace_all(name, replacement.as_str()).into(),
+            None => name.to_string(),
+        }
+    }
+}
+
+/// Rewrites the Java `$1` group references of `rename_replacement` into the
+/// unambiguous `${1}` form, so `$1_copy` does not read as a group named
+/// `1_copy`.
+fn java_replacement(replacement: &str) -> String {
+    let mut converted = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('$') => converted.push_str("$$"),
+                Some(other) => converted.push(other),
+                None => converted.push('\\'),
+            },
+            '$' if chars.peek().is_some_and(char::is_ascii_digit) => {
+                converted.push_str("${");
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    converted.push(digit);
+                }
+                converted.push('}');
+            }
+            other => converted.push(other),
+        }
+    }
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_select_and_rename_restored_indices() {
+        let request = RestoreRequest::from_json(&json!({
+            "indices": ["logs-*", "-logs-old"],
+            "rename_pattern": "logs-(.+)",
+            "rename_replacement": "restored-$1_copy"
+        }))
+        .unwrap();
+
+        assert!(request.selects("logs-new"));
+        assert!(!request.selects("logs-old"));
+        assert!(!request.selects("metrics"));
+        assert_eq!(request.renamed("logs-new"), "restored-new_copy");
+        assert_eq!(request.renamed("other"), "other");
+
+        let invalid = RestoreRequest::from_json(&json!({ "rename_pattern": "(" }));
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn should_validate_repositories_and_snapshot_names() {
+        let fs = json!({ "type": "fs", "settings": { "location": "/tmp/backups" } });
+        let definition = RepositoryDefinition::from_json("backups", &fs).unwrap();
+        assert_eq!(definition.location(), Some(PathBuf::from("/tmp/backups")));
+
+        let s3 = json!({ "type": "s3", "settings": { "bucket": "b" } });
+        assert!(RepositoryDefinition::from_json("backups", &s3).is_err());
+        let missing = json!({ "type": "fs", "settings": {} });
+        assert!(RepositoryDefinition::from_json("backups", &missing).is_err());
+
+        assert!(validate_snapshot_name("nightly-1").is_ok());
+        assert!(validate_snapshot_name("Nightly").is_err());
+        assert!(validate_snapshot_name("_nightly").is_err());
+        assert!(validate_snapshot_name("a/b").is_err());
+        assert!(validate_snapshot_name(".").is_err());
+        assert!(validate_snapshot_name("..").is_err());
+        assert_eq!(version_id("8.10.0"), 8_10_00_99);
+    }
+
+    #[test]
+    fn should_confine_repository_locations_to_path_repo() {
+        let definition = |location: &str| {
+            let json = json!({ "type": "fs", "settings": { "location": location } });
+            RepositoryDefinition::from_json("backups", &json).unwrap()
+        };
+        let roots = [PathBuf::from("/srv/a"), PathBuf::from("/srv/b/")];
+        let resolve = |location: &str| definition(location).resolve_location("backups", &roots);
+
+        assert_eq!(resolve("nightly"), Ok(PathBuf::from("/srv/a/nightly")));
+        assert_eq!(resolve("/srv/b/x/../y"), Ok(PathBuf::from("/srv/b/y")));
+        assert_eq!(resolve("/srv/b"), Ok(PathBuf::from("/srv/b")));
+        for outside in ["/etc", "/srv/ab", "../b/../../etc", "/srv/a/../../etc"] {
+            let error = resolve(outside).unwrap_err();
+            assert!(
+                error.contains("doesn't match any of the locations"),
+                "{}",
+                error
+            );
+        }
+
+        let error = definition("/srv/a")
+            .resolve_location("backups", &[])
+            .unwrap_err();
+        assert!(error.contains("this setting is empty"), "{}", error);
+    }
+}
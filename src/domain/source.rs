@@ -0,0 +1,224 @@
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone)]
+pub struct SourceFilter {
+    pub enabled: bool,
+    pub includes: Vec<String>,
+    pub excludes: Vec<String>,
+}
+
+impl Default for SourceFilter {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            includes: Vec::new(),
+            excludes: Vec::new(),
+        }
+    }
+}
+
+impl SourceFilter {
+    pub fn parse(json: &Value) -> Self {
+        match json {
+            Value::Bool(enabled) => Self {
+                enabled: *enabled,
+                ..Self::default()
+            },
+            Value::String(s) => Self {
+                includes: split_patterns(s),
+                ..Self::default()
+            },
+            Value::Array(_) => Self {
+                includes: string_list(json),
+                ..Self::default()
+            },
+            Value::Object(obj) => Self {
+                enabled: true,
+                includes: obj
+                    .get("includes")
+                    .or_else(|| obj.get("include"))
+                    .map(string_list)
+                    .unwrap_or_default(),
+                excludes: obj
+                    .get("excludes")
+                    .or_else(|| obj.get("exclude"))
+                    .map(string_list)
+                    .unwrap_or_default(),
+            },
+            _ => Self::default(),
+        }
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.enabled && self.includes.is_empty() && self.excludes.is_empty()
+    }
+
+    pub fn apply(&self, source: &Value) -> Option<Value> {
+        if !self.enabled {
+            return None;
+        }
+        if self.is_noop() {
+            return Some(source.clone());
+        }
+        match source {
+            Value::Object(obj) => Some(Value::Object(self.filter_object(obj, "", false))),
+            other => Some(other.clone()),
+        }
+    }
+
+    fn filter_object(
+        &self,
+        obj: &Map<String, Value>,
+        prefix: &str,
+        included: bool,
+    ) -> Map<String, Value> {
+        let mut out = Map::new();
+        for (key, value) in obj {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+
+            if self.excludes.iter().any(|p| wildcard_match(p, &path)) {
+                continue;
+            }
+
+            let path_included = included
+                || self.includes.is_empty()
+                || self.includes.iter().any(|p| wildcard_match(p, &path));
+
+            match value {
+                Value::Object(inner) if path_included || self.may_include_children(&path) => {
+                    let filtered = self.filter_object(inner, &path, path_included);
+                    if path_included || !filtered.is_empty() {
+                        out.insert(key.clone(), Value::Object(filtered));
+                    }
+                }
+                Value::Object(_) => {}
+                _ if path_included => {
+                    out.insert(key.clone(), value.clone());
+                }
+                _ => {}
+            }
+        }
+        out
+    }
+
+    /// Whether some include could match a field below `path`, i.e. a leading
+    /// part of the pattern matches `path.`, so `us*.name` reaches into
+    /// `user`.
+    fn may_include_children(&self, path: &str) -> bool {
+        let prefix = format!("{}.", path);
+        self.includes.iter().any(|p| {
+            p.char_indices()
+                .map(|(i, _)| i)
+                .chain([p.len()])
+                .any(|end| wildcard_match(&p[..end], &prefix))
+        })
+    }
+}
+
+/// Matches `text` against a pattern where `*` stands for any (possibly empty)
+/// sequence of characters.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if pi < p.len() && p[pi] == t[ti] {
+            pi += 1;
+            ti += 1;
+        } else if let Some((star_pi, star_ti)) = star {
+            pi = star_pi + 1;
+            ti = star_ti + 1;
+            star = Some((star_pi, star_ti + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+fn split_patterns(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+fn string_list(json: &Value) -> Vec<String> {
+    match json {
+        Value::String(s) => split_patterns(s),
+        Value::Array(arr) => arr
+            .iter()
+            .filter_map(|v| v.as_str())
+            .map(|s| s.to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_match_wildcard_patterns() {
+        assert!(wildcard_match("logs-*", "logs-2024"));
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("a*c", "abbbc"));
+        assert!(!wildcard_match("logs-*", "metrics-2024"));
+        assert!(!wildcard_match("abc", "abcd"));
+    }
+
+    #[test]
+    fn should_disable_source_with_false() {
+        let filter = SourceFilter::parse(&json!(false));
+        assert!(filter.apply(&json!({ "a": 1 })).is_none());
+    }
+
+    #[test]
+    fn should_filter_includes_with_nested_paths() {
+        let filter = SourceFilter::parse(&json!(["title", "brand.name"]));
+        let source = json!({ "title": "t", "price": 10, "brand": { "name": "b", "id": 1 } });
+
+        let filtered = filter.apply(&source).unwrap();
+        assert_eq!(filtered, json!({ "title": "t", "brand": { "name": "b" } }));
+    }
+
+    #[test]
+    fn should_filter_includes_and_excludes() {
+        let filter = SourceFilter::parse(&json!({
+            "includes": ["user.*"],
+            "excludes": ["user.password"]
+        }));
+        let source = json!({ "user": { "name": "n", "password": "p" }, "other": true });
+
+        let filtered = filter.apply(&source).unwrap();
+        assert_eq!(filtered, json!({ "user": { "name": "n" } }));
+    }
+
+    #[test]
+    fn should_reach_nested_fields_through_wildcard_parents() {
+        let filter = SourceFilter::parse(&json!(["us*.name", "*.id"]));
+        let source = json!({
+            "user": { "name": "n", "email": "e" },
+            "group": { "id": 1, "label": "l" },
+            "other": { "email": "x" }
+        });
+
+        let filtered = filter.apply(&source).unwrap();
+        assert_eq!(
+            filtered,
+            json!({ "user": { "name": "n" }, "group": { "id": 1 } })
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::domain::alias::AliasDefinition;
+use crate::domain::mapping::Mapping;
+use crate::domain::settings::IndexSettings;
+use crate::domain::source::wildcard_match;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TemplateBody {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mappings: Option<Mapping>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<HashMap<String, Value>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IndexTemplate {
+    #[serde(deserialize_with = "one_or_many")]
+    pub index_patterns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub composed_of: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<TemplateBody>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_stream: Option<Value>,
+    #[serde(default, rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ComponentTemplate {
+    pub template: TemplateBody,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<i64>,
+    #[serde(default, rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
+}
+
+impl IndexTemplate {
+    pub fn priority(&self) -> i64 {
+        self.priority.unwrap_or(0)
+    }
+
+    pub fn matches(&self, index: &str) -> bool {
+        self.index_patterns
+            .iter()
+            .any(|pattern| wildcard_match(pattern, index))
+    }
+
+    /// Returns true when some index name could be matched by patterns of both
+    /// templates, which Elasticsearch forbids for templates of equal priority.
+    pub fn overlaps(&self, other: &IndexTemplate) -> bool {
+        self.index_patterns.iter().any(|a| {
+            other
+                .index_patterns
+                .iter()
+                .any(|b| wildcard_match(a, b) || wildcard_match(b, a))
+        })
+    }
+}
+
+/// The settings, mappings and aliases an index receives from its matching
+/// template and the component templates the template is composed of.
+#[derive(Debug, Clone, Default)]
+pub struct ComposedTemplate {
+    pub settings: IndexSettings,
+    pub mappings: Option<Mapping>,
+    pub aliases: HashMap<String, AliasDefinition>,
+}
+
+impl ComposedTemplate {
+    pub fn apply(&mut self, body: &TemplateBody) {
+        if let Some(settings) = &body.settings {
+            self.settings.merge(&IndexSettings::from_json(settings));
+        }
+        if let Some(mappings) = &body.mappings {
+            match &mut self.mappings {
+                Some(existing) => existing.update(mappings.clone()),
+                None => self.mappings = Some(mappings.clone()),
+            }
+        }
+        if let Some(aliases) = &body.aliases {
+            for (name, definition) in aliases {
+                self.aliases
+                    .insert(name.clone(), AliasDefinition::from_json(definition));
+            }
+        }
+    }
+}
+
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(pattern) => vec![pattern],
+        OneOrMany::Many(patterns) => patterns,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_parse_single_pattern_as_list() {
+        let template: IndexTemplate =
+            serde_json::from_value(json!({ "index_patterns": "logs-*" })).unwrap();
+        assert_eq!(template.index_patterns, vec!["logs-*"]);
+        assert!(template.matches("logs-app"));
+        assert!(!template.matches("metrics-app"));
+    }
+
+    #[test]
+    fn should_detect_overlapping_patterns() {
+        let a: IndexTemplate =
+            serde_json::from_value(json!({ "index_patterns": ["logs-*"] })).unwrap();
+        let b: IndexTemplate =
+            serde_json::from_value(json!({ "index_patterns": ["logs-app-*"] })).unwrap();
+        let c: IndexTemplate =
+            serde_json::from_value(json!({ "index_patterns": ["metrics-*"] })).unwrap();
+
+        assert!(a.overlaps(&b));
+        assert!(!a.overlaps(&c));
+    }
+
+    #[test]
+    fn should_compose_later_bodies_over_earlier_ones() {
+        let base: TemplateBody = serde_json::from_value(json!({
+            "settings": { "number_of_shards": 1 },
+            "mappings": { "properties": { "host": { "type": "keyword" } } }
+        }))
+        .unwrap();
+        let specific: TemplateBody = serde_json::from_value(json!({
+            "settings": { "number_of_shards": 2 },
+            "mappings": { "properties": { "message": { "type": "text" } } },
+            "aliases": { "logs": {} }
+        }))
+        .unwrap();
+
+        let mut composed = ComposedTemplate::default();
+        composed.apply(&base);
+        composed.apply(&specific);
+
+        assert_eq!(composed.settings.get("number_of_shards"), Some("2"));
+        let mappings = composed.mappings.unwrap();
+        assert!(mappings.properties.contains_key("host"));
+        assert!(mappings.properties.contains_key("message"));
+        assert!(composed.aliases.contains_key("logs"));
+    }
+}
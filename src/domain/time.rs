@@ -0,0 +1,162 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Parses an Elasticsearch time value such as `30s`, `12h` or `7d` into
+/// milliseconds.
+pub fn parse_duration_millis(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse().ok()?;
+    let factor = match unit {
+        "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        _ => return None,
+    };
+    Some(amount * factor)
+}
+
+/// Formats epoch milliseconds as a UTC `yyyy.MM.dd` date, the format used in
+/// data stream backing index names.
+pub fn format_date(millis: u64) -> String {
+    let days = (millis / 86_400_000) as i64;
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+/// Formats epoch milliseconds as a UTC ISO-8601 timestamp such as
+/// `2024-02-29T10:15:00.250Z`.
+pub fn format_timestamp(millis: u64) -> String {
+    let (year, month, day) = civil_from_days((millis / 86_400_000) as i64);
+    let of_day = millis % 86_400_000;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        of_day / 3_600_000,
+        of_day / 60_000 % 60,
+        of_day / 1_000 % 60,
+        of_day % 1_000
+    )
+}
+
+/// Parses an ISO-8601 date (`2024-02-29`, `2024-02-29T10:15:00.250Z`,
+/// `2024-02-29T10:15:00+02:00`) into epoch milliseconds; dates without an
+/// offset are taken as UTC.
+pub fn parse_date_millis(value: &str) -> Option<i64> {
+    let number = |text: &str| -> Option<i64> {
+        (!text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()))
+            .then(|| text.parse().ok())
+            .flatten()
+    };
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    let mut parts = date.splitn(3, '-');
+    let year = number(parts.next()?)?;
+    let month = number(parts.next()?)?;
+    let day = number(parts.next()?)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut millis = days_from_civil(year, month as u32, day as u32) * 86_400_000;
+
+    let Some(time) = time else {
+        return Some(millis);
+    };
+    let (clock, offset) = if let Some(clock) = time.strip_suffix('Z') {
+        (clock, 0)
+    } else if let Some(split) = time.rfind(['+', '-']) {
+        let (clock, offset) = time.split_at(split);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let (hours, minutes) = offset[1..].split_once(':').unwrap_or((&offset[1..], "0"));
+        (
+            clock,
+            sign * (number(hours)? * 60 + number(minutes)?) * 60_000,
+        )
+    } else {
+        (time, 0)
+    };
+    let (clock, fraction) = clock.split_once('.').unwrap_or((clock, ""));
+    let mut fields = clock.splitn(3, ':');
+    let hours = number(fields.next()?)?;
+    let minutes = fields.next().map_or(Some(0), number)?;
+    let seconds = fields.next().map_or(Some(0), number)?;
+    let fraction = format!("{:0<3}", &fraction[..fraction.len().min(3)]);
+    millis += ((hours * 60 + minutes) * 60 + seconds) * 1_000 + number(&fraction)?;
+    Some(millis - offset)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_time_values() {
+        assert_eq!(parse_duration_millis("500ms"), Some(500));
+        assert_eq!(parse_duration_millis("30s"), Some(30_000));
+        assert_eq!(parse_duration_millis("1d"), Some(86_400_000));
+        assert_eq!(parse_duration_millis("7x"), None);
+        assert_eq!(parse_duration_millis("d"), None);
+    }
+
+    #[test]
+    fn should_format_epoch_millis_as_date() {
+        assert_eq!(format_date(0), "1970.01.01");
+        assert_eq!(format_date(1_709_164_800_000), "2024.02.29");
+        assert_eq!(
+            format_timestamp(1_709_164_800_000 + 3_723_500),
+            "2024-02-29T01:02:03.500Z"
+        );
+    }
+
+    #[test]
+    fn should_parse_iso_dates() {
+        assert_eq!(parse_date_millis("1970-01-01"), Some(0));
+        assert_eq!(parse_date_millis("2024-02-29"), Some(1_709_164_800_000));
+        assert_eq!(
+            parse_date_millis("2024-02-29T01:02:03.5Z"),
+            Some(1_709_164_800_000 + 3_723_500)
+        );
+        assert_eq!(
+            parse_date_millis("2024-02-29T02:00:00+02:00"),
+            Some(1_709_164_800_000)
+        );
+        assert_eq!(parse_date_millis("yesterday"), None);
+        assert_eq!(parse_date_millis("2024-13-01"), None);
+    }
+}
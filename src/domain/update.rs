@@ -0,0 +1,287 @@
+use crate::domain::document::StoredDocument;
+use crate::domain::script::{Script, ScriptOp, UpdateContext};
+use crate::domain::source::SourceFilter;
+use crate::domain::versioning::WriteConditions;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateKind {
+    Created,
+    Updated,
+    Noop,
+    Deleted,
+}
+
+impl UpdateKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpdateKind::Created => "created",
+            UpdateKind::Updated => "updated",
+            UpdateKind::Noop => "noop",
+            UpdateKind::Deleted => "deleted",
+        }
+    }
+}
+
+/// What applying an update to a document resolved to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpdateAction {
+    Index(Value),
+    Noop,
+    Delete,
+}
+
+/// The body of an `_update` request (or of a bulk `update` action).
+#[derive(Debug, Clone)]
+pub struct UpdateRequest {
+    pub doc: Option<Value>,
+    pub script: Option<Script>,
+    pub upsert: Option<Value>,
+    /// Run the script against the `upsert` document instead of indexing it
+    /// as-is when the target does not exist.
+    pub scripted_upsert: bool,
+    pub doc_as_upsert: bool,
+    pub detect_noop: bool,
+    /// Set when the response should echo the updated `_source` under `get`.
+    pub fetch_source: Option<SourceFilter>,
+    /// How many times the update is recomputed when the document changes
+    /// between reading and writing it.
+    pub retry_on_conflict: u32,
+}
+
+impl UpdateRequest {
+    pub fn from_json(body: &Value) -> Result<Self, String> {
+        let doc = body.get("doc").cloned();
+        let script = body.get("script").map(Script::from_json).transpose()?;
+        match (&doc, &script) {
+            (None, None) => {
+                return Err("Validation Failed: 1: script or doc is missing;".to_string());
+            }
+            (Some(_), Some(_)) => {
+                return Err("Validation Failed: 1: can't provide both script and doc;".to_string());
+            }
+            _ => {}
+        }
+        Ok(Self {
+            doc,
+            script,
+            upsert: body.get("upsert").cloned(),
+            scripted_upsert: body["scripted_upsert"].as_bool().unwrap_or(false),
+            doc_as_upsert: body["doc_as_upsert"].as_bool().unwrap_or(false),
+            detect_noop: body["detect_noop"].as_bool().unwrap_or(true),
+            fetch_source: body
+                .get("_source")
+                .map(SourceFilter::parse)
+                .filter(|filter| filter.enabled),
+            retry_on_conflict: 0,
+        })
+    }
+
+    /// Reads the `retry_on_conflict` parameter, which cannot be combined with
+    /// an explicit version condition.
+    pub fn set_retry_on_conflict(
+        &mut self,
+        value: &str,
+        conditions: &WriteConditions,
+    ) -> Result<(), String> {
+        let retries = value.parse::<u32>().map_err(|_| {
+            format!(
+                "Failed to parse value [{}] for parameter [retry_on_conflict]",
+                value
+            )
+        })?;
+        if retries > 0 && (conditions.if_seq_no.is_some() || conditions.version.is_some()) {
+            return Err("can't provide both retry_on_conflict and a specific version".to_string());
+        }
+        self.retry_on_conflict = retries;
+        Ok(())
+    }
+
+    /// The update applied to each match of `_update_by_query`: the script if
+    /// given, otherwise a plain rewrite of the document.
+    pub fn from_script(script: Option<Script>) -> Self {
+        Self {
+            doc: None,
+            script,
+            upsert: None,
+            scripted_upsert: false,
+            doc_as_upsert: false,
+            detect_noop: false,
+            fetch_source: None,
+            retry_on_conflict: 0,
+        }
+    }
+
+    /// The document to index when the target does not exist yet, if any.
+    pub fn upsert_source(&self) -> Option<Value> {
+        match &self.upsert {
+            Some(upsert) => Some(upsert.clone()),
+            None if self.doc_as_upsert => self.doc.clone(),
+            None => None,
+        }
+    }
+
+    /// Resolves the write for a missing document, or `None` when the request
+    /// has nothing to upsert.
+    pub fn apply_upsert(&self, index: &str, id: &str) -> Result<Option<UpdateAction>, String> {
+        let Some(source) = self.upsert_source() else {
+            return Ok(None);
+        };
+        match &self.script {
+            Some(script) if self.scripted_upsert => {
+                let mut context = UpdateContext {
+                    index: index.to_string(),
+                    id: id.to_string(),
+                    version: None,
+                    routing: None,
+                    source,
+                    op: ScriptOp::Index,
+                };
+                script.execute_update(&mut context)?;
+                Ok(Some(match context.op {
+                    ScriptOp::Index => UpdateAction::Index(context.source),
+                    ScriptOp::Noop | ScriptOp::Delete => UpdateAction::Noop,
+                }))
+            }
+            _ => Ok(Some(UpdateAction::Index(source))),
+        }
+    }
+
+    /// Applies the partial document or the script to an existing document.
+    /// A partial document that changes nothing is a noop when `detect_noop`
+    /// is set; a script decides for itself through `ctx.op`.
+    pub fn apply(&self, index: &str, existing: &StoredDocument) -> Result<UpdateAction, String> {
+        if let Some(script) = &self.script {
+            let mut context = UpdateContext {
+                index: index.to_string(),
+                id: existing.id.clone(),
+                version: Some(existing.version.version),
+                routing: existing.routing.clone(),
+                source: existing.source.clone(),
+                op: ScriptOp::Index,
+            };
+            script.execute_update(&mut context)?;
+            return Ok(match context.op {
+                ScriptOp::Index => UpdateAction::Index(context.source),
+                ScriptOp::Noop => UpdateAction::Noop,
+                ScriptOp::Delete => UpdateAction::Delete,
+            });
+        }
+
+        let mut source = existing.source.clone();
+        let changed = match &self.doc {
+            Some(doc) => deep_merge(&mut source, doc),
+            None => false,
+        };
+        if !changed && self.detect_noop {
+            Ok(UpdateAction::Noop)
+        } else {
+            Ok(UpdateAction::Index(source))
+        }
+    }
+}
+
+/// Recursively merges `patch` into `target`: objects are merged key by key,
+/// any other value replaces what was there. Returns whether `target` changed.
+pub fn deep_merge(target: &mut Value, patch: &Value) -> bool {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            let mut changed = false;
+            for (key, value) in patch {
+                match target.get_mut(key) {
+                    Some(existing) => changed |= deep_merge(existing, value),
+                    None => {
+                        target.insert(key.clone(), value.clone());
+                        changed = true;
+                    }
+                }
+            }
+            changed
+        }
+        (target, patch) => {
+            if target == patch {
+                false
+            } else {
+                *target = patch.clone();
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_deep_merge_nested_objects() {
+        let mut source = json!({ "user": { "name": "a", "age": 1 }, "tags": ["x"] });
+        let changed = deep_merge(&mut source, &json!({ "user": { "age": 2 }, "tags": ["y"] }));
+
+        assert!(changed);
+        assert_eq!(
+            source,
+            json!({ "user": { "name": "a", "age": 2 }, "tags": ["y"] })
+        );
+        assert!(!deep_merge(&mut source, &json!({ "user": { "age": 2 } })));
+    }
+
+    #[test]
+    fn should_pick_upsert_document() {
+        let request = UpdateRequest::from_json(&json!({
+            "doc": { "a": 1 },
+            "doc_as_upsert": true
+        }))
+        .unwrap();
+        assert_eq!(request.upsert_source(), Some(json!({ "a": 1 })));
+
+        let request = UpdateRequest::from_json(&json!({
+            "doc": { "a": 1 },
+            "upsert": { "a": 0 }
+        }))
+        .unwrap();
+        assert_eq!(request.upsert_source(), Some(json!({ "a": 0 })));
+
+        assert!(UpdateRequest::from_json(&json!({ "upsert": {} })).is_err());
+    }
+
+    #[test]
+    fn should_run_update_script_and_scripted_upsert() {
+        let request = UpdateRequest::from_json(&json!({
+            "script": {
+                "source": "ctx._source.count = (ctx._source.count ?: 0) + params.n",
+                "params": { "n": 2 }
+            },
+            "upsert": { "count": 10 },
+            "scripted_upsert": true
+        }))
+        .unwrap();
+
+        assert_eq!(
+            request.apply_upsert("test", "1"),
+            Ok(Some(UpdateAction::Index(json!({ "count": 12 }))))
+        );
+
+        let existing = StoredDocument {
+            id: "1".to_string(),
+            source: json!({ "count": 1 }),
+            version: crate::domain::versioning::DocVersion {
+                version: 1,
+                seq_no: 0,
+                primary_term: 1,
+            },
+            routing: None,
+            created_at: 0,
+            updated_at: 0,
+        };
+        assert_eq!(
+            request.apply("test", &existing),
+            Ok(UpdateAction::Index(json!({ "count": 3 })))
+        );
+
+        assert!(
+            UpdateRequest::from_json(&json!({ "doc": {}, "script": "ctx.op = 'noop'" })).is_err()
+        );
+    }
+}
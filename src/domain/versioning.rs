@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Primary term reported for every document; there is a single primary that
+/// is never re-elected.
+pub const PRIMARY_TERM: u64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocVersion {
+    pub version: u64,
+    pub seq_no: u64,
+    pub primary_term: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VersionType {
+    #[default]
+    Internal,
+    External,
+    ExternalGte,
+}
+
+/// Optimistic concurrency control parameters of a single write
+/// (`if_seq_no`/`if_primary_term`, `version` with `version_type`, or
+/// `op_type=create` which requires the document not to exist yet).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteConditions {
+    pub if_seq_no: Option<u64>,
+    pub if_primary_term: Option<u64>,
+    pub version: Option<u64>,
+    pub version_type: VersionType,
+    pub create: bool,
+}
+
+impl WriteConditions {
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self, String> {
+        let number = |name: &str| {
+            params
+                .get(name)
+                .map(|value| {
+                    value.parse::<u64>().map_err(|_| {
+                        format!("Failed to parse value [{}] for parameter [{}]", value, name)
+                    })
+                })
+                .transpose()
+        };
+        let version_type = match params.get("version_type").map(|s| s.as_str()) {
+            None | Some("internal") => VersionType::Internal,
+            Some("external") => VersionType::External,
+            Some("external_gte") => VersionType::ExternalGte,
+            Some(other) => return Err(format!("No version type match [{}]", other)),
+        };
+        let create = match params.get("op_type").map(|s| s.as_str()) {
+            None | Some("index") => false,
+            Some("create") => true,
+            Some(other) => {
+                return Err(format!(
+                    "opType must be 'create' or 'index', found: [{}]",
+                    other
+                ));
+            }
+        };
+
+        let conditions = Self {
+            if_seq_no: number("if_seq_no")?,
+            if_primary_term: number("if_primary_term")?,
+            version: number("version")?,
+            version_type,
+            create,
+        };
+        conditions.validate()?;
+        Ok(conditions)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let error = match (self.if_seq_no, self.if_primary_term) {
+            (Some(_), None) => Some("ifSeqNo is set, but primary term is [0]"),
+            (None, Some(_)) => Some("ifPrimaryTerm is set, but ifSeqNo is unassigned"),
+            (Some(_), Some(_)) if self.version_type != VersionType::Internal => {
+                Some("compare and write operations can not be used with external versioning")
+            }
+            _ if self.version_type == VersionType::Internal && self.version.is_some() => Some(
+                "internal versioning can not be used for optimistic concurrency control. \
+                 Please use `if_seq_no` and `if_primary_term` instead",
+            ),
+            _ if self.version_type != VersionType::Internal && self.version.is_none() => {
+                Some("an external version must be provided")
+            }
+            _ => None,
+        };
+        match error {
+            Some(reason) => Err(format!("Validation Failed: 1: {};", reason)),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the conditions against the current version of the document and
+    /// returns the version the write should be stored with.
+    pub fn next_version(&self, id: &str, current: Option<DocVersion>) -> Result<u64, String> {
+        if self.create
+            && let Some(c) = current
+        {
+            return Err(format!(
+                "[{}]: version conflict, document already exists (current version [{}])",
+                id, c.version
+            ));
+        }
+        if let (Some(seq_no), Some(primary_term)) = (self.if_seq_no, self.if_primary_term) {
+            match current {
+                Some(c) if c.seq_no == seq_no && c.primary_term == primary_term => {}
+                Some(c) => {
+                    return Err(format!(
+                        "[{}]: version conflict, required seqNo [{}], primary term [{}]. \
+                         current document has seqNo [{}] and primary term [{}]",
+                        id, seq_no, primary_term, c.seq_no, c.primary_term
+                    ));
+                }
+                None => {
+                    return Err(format!(
+                        "[{}]: version conflict, required seqNo [{}], primary term [{}]. \
+                         but no document was found",
+                        id, seq_no, primary_term
+                    ));
+                }
+            }
+        }
+
+        match (self.version_type, self.version) {
+            (VersionType::External, Some(version)) => match current {
+                Some(c) if c.version >= version => Err(format!(
+                    "[{}]: version conflict, current version [{}] is higher or equal to the one \
+                     provided [{}]",
+                    id, c.version, version
+                )),
+                _ => Ok(version),
+            },
+            (VersionType::ExternalGte, Some(version)) => match current {
+                Some(c) if c.version > version => Err(format!(
+                    "[{}]: version conflict, current version [{}] is higher than the one \
+                     provided [{}]",
+                    id, c.version, version
+                )),
+                _ => Ok(version),
+            },
+            _ => Ok(current.map_or(1, |c| c.version + 1)),
+        }
+    }
+}
+
+pub fn is_version_conflict(error: &str) -> bool {
+    error.contains("version conflict")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn should_enforce_seq_no_and_primary_term() {
+        let current = Some(DocVersion {
+            version: 3,
+            seq_no: 7,
+            primary_term: PRIMARY_TERM,
+        });
+        let matching =
+            WriteConditions::from_params(&params(&[("if_seq_no", "7"), ("if_primary_term", "1")]))
+                .unwrap();
+        assert_eq!(matching.next_version("1", current), Ok(4));
+
+        let stale =
+            WriteConditions::from_params(&params(&[("if_seq_no", "6"), ("if_primary_term", "1")]))
+                .unwrap();
+        assert!(is_version_conflict(
+            &stale.next_version("1", current).unwrap_err()
+        ));
+        assert!(stale.next_version("1", None).is_err());
+    }
+
+    #[test]
+    fn should_apply_external_versions() {
+        let current = Some(DocVersion {
+            version: 5,
+            seq_no: 0,
+            primary_term: PRIMARY_TERM,
+        });
+        let external = WriteConditions::from_params(&params(&[
+            ("version", "5"),
+            ("version_type", "external"),
+        ]))
+        .unwrap();
+        assert!(external.next_version("1", current).is_err());
+        assert_eq!(external.next_version("1", None), Ok(5));
+
+        let gte = WriteConditions::from_params(&params(&[
+            ("version", "5"),
+            ("version_type", "external_gte"),
+        ]))
+        .unwrap();
+        assert_eq!(gte.next_version("1", current), Ok(5));
+    }
+
+    #[test]
+    fn should_reject_create_of_existing_document() {
+        let create = WriteConditions::from_params(&params(&[("op_type", "create")])).unwrap();
+        let current = Some(DocVersion {
+            version: 1,
+            seq_no: 0,
+            primary_term: PRIMARY_TERM,
+        });
+        assert_eq!(create.next_version("1", None), Ok(1));
+        assert!(is_version_conflict(
+            &create.next_version("1", current).unwrap_err()
+        ));
+    }
+
+    #[test]
+    fn should_reject_invalid_parameter_combinations() {
+        assert!(WriteConditions::from_params(&params(&[("if_seq_no", "1")])).is_err());
+        assert!(WriteConditions::from_params(&params(&[("version", "2")])).is_err());
+        assert!(WriteConditions::from_params(&params(&[("version_type", "external")])).is_err());
+        assert!(WriteConditions::from_params(&params(&[("version_type", "bogus")])).is_err());
+        assert!(WriteConditions::from_params(&params(&[("op_type", "upsert")])).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! A fake Elasticsearch server for tests, usable as a binary or embedded
+//! in-process through [`EsFake::builder`].
+
+pub mod api;
+pub mod config;
+pub mod domain;
+pub mod repository;
+mod server;
+
+use crate::api::handlers::{
+    admin, aliases, bulk, by_query, cluster, data_streams, documents, indices, ingest, reindex,
+    search, snapshots, tasks, templates,
+};
+use crate::config::Config;
+use crate::repository::store::InMemoryStore;
+use crate::repository::tasks::TaskRegistry;
+use axum::{
+    Router,
+    http::{HeaderValue, header},
+    middleware,
+    routing::{get, post, put},
+};
+use std::sync::Arc;
+use tower_http::set_header::SetResponseHeaderLayer;
+
+pub use crate::server::{EsFake, EsFakeBuilder, EsFakeServer};
+
+pub struct AppState {
+    pub store: InMemoryStore,
+    pub tasks: TaskRegistry,
+    pub config: Arc<Config>,
+}
+
+/// The REST API of one namespace, run against its own store.
+pub fn app(state: Arc<AppState>) -> Router {
+    let api = Router::new()
+        .route("/", get(cluster::info).head(cluster::ping))
+        .route("/_cluster/health", get(cluster::cluster_health))
+        .route("/_bulk", post(bulk::bulk))
+        .route("/_mget", post(documents::mget).get(documents::mget))
+        .route("/_search", post(search::search_all).get(search::search_all))
+        .route("/_count", post(search::count_all).get(search::count_all))
+        .route("/{index}/_bulk", post(bulk::bulk_index))
+        .route("/_index_template", get(templates::get_index_templates))
+        .route(
+            "/_index_template/{name}",
+            get(templates::get_index_template)
+                .head(templates::check_index_template)
+                .put(templates::put_index_template)
+                .post(templates::put_index_template)
+                .delete(templates::delete_index_template),
+        )
+        .route(
+            "/_component_template",
+            get(templates::get_component_templates),
+        )
+        .route(
+            "/_component_template/{name}",
+            get(templates::get_component_template)
+                .head(templates::check_component_template)
+                .put(templates::put_component_template)
+                .post(templates::put_component_template)
+                .delete(templates::delete_component_template),
+        )
+        .route("/_data_stream", get(data_streams::get_data_streams))
+        .route(
+            "/_data_stream/{name}",
+            get(data_streams::get_data_stream)
+                .put(data_streams::put_data_stream)
+                .delete(data_streams::delete_data_stream),
+        )
+        .route("/{index}/_rollover", post(data_streams::rollover))
+        .route(
+            "/{index}/_rollover/{new_index}",
+            post(data_streams::rollover_to),
+        )
+        .route("/_aliases", post(aliases::update_aliases))
+        .route("/_alias", get(aliases::get_all_aliases))
+        .route(
+            "/_alias/{name}",
+            get(aliases::get_aliases_by_name).head(aliases::check_alias),
+        )
+        .route("/{index}/_alias", get(aliases::get_index_aliases))
+        .route(
+            "/{index}/_alias/{name}",
+            get(aliases::get_index_aliases_by_name)
+                .put(aliases::put_alias)
+                .post(aliases::put_alias)
+                .delete(aliases::delete_alias),
+        )
+        .route(
+            "/{index}/_aliases/{name}",
+            put(aliases::put_alias)
+                .post(aliases::put_alias)
+                .delete(aliases::delete_alias),
+        )
+        .route("/{index}/_refresh", post(indices::refresh))
+        .route(
+            "/{index}",
+            put(indices::create_index)
+                .head(indices::check_index)
+                .delete(indices::delete_index),
+        )
+        .route(
+            "/{index}/_mapping",
+            get(indices::get_mapping).put(indices::put_mapping),
+        )
+        .route("/{index}/_settings", get(indices::get_settings))
+        .route("/{index}/_mappings", get(indices::get_mapping))
+        .route("/{index}/_doc", post(documents::index_document))
+        .route(
+            "/{index}/_doc/{id}",
+            get(documents::get_document)
+                .put(documents::index_document_with_id)
+                .post(documents::index_document_with_id)
+                .delete(documents::delete_document),
+        )
+        .route(
+            "/{index}/_create/{id}",
+            put(documents::create_document).post(documents::create_document),
+        )
+        .route(
+            "/{index}/_mget",
+            post(documents::mget_index).get(documents::mget_index),
+        )
+        .route("/{index}/_source/{id}", get(documents::get_source))
+        .route("/{index}/_delete_by_query", post(by_query::delete_by_query))
+        .route("/{index}/_update_by_query", post(by_query::update_by_query))
+        .route("/_tasks", get(tasks::list_tasks))
+        .route("/_tasks/{task_id}", get(tasks::get_task))
+        .route("/_tasks/{task_id}/_cancel", post(tasks::cancel_task))
+        .route("/_reindex", post(reindex::reindex))
+        .route("/_ingest/pipeline", get(ingest::get_pipelines))
+        .route(
+            "/_ingest/pipeline/{id}",
+            get(ingest::get_pipeline)
+                .put(ingest::put_pipeline)
+                .delete(ingest::delete_pipeline),
+        )
+        .route("/_snapshot", get(snapshots::get_repositories))
+        .route("/_snapshot/_status", get(snapshots::running_snapshots))
+        .route(
+            "/_snapshot/{repository}",
+            get(snapshots::get_repository)
+                .put(snapshots::put_repository)
+                .post(snapshots::put_repository)
+                .delete(snapshots::delete_repository),
+        )
+        .route(
+            "/_snapshot/{repository}/_status",
+            get(snapshots::running_repository_snapshots),
+        )
+        .route(
+            "/_snapshot/{repository}/{snapshot}",
+            get(snapshots::get_snapshots)
+                .put(snapshots::create_snapshot)
+                .post(snapshots::create_snapshot)
+                .delete(snapshots::delete_snapshot),
+        )
+        .route(
+            "/_snapshot/{repository}/{snapshot}/_status",
+            get(snapshots::snapshot_status),
+        )
+        .route(
+            "/_snapshot/{repository}/{snapshot}/_restore",
+            post(snapshots::restore_snapshot),
+        )
+        .route("/{index}/_update/{id}", post(documents::update_document))
+        .route("/{index}/_search", post(search::search).get(search::search))
+        .route("/{index}/_count", post(search::count).get(search::count))
+        .layer(SetResponseHeaderLayer::overriding(
+            header::HeaderName::from_static("x-elastic-product"),
+            HeaderValue::from_static("Elasticsearch"),
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            api::auth::basic_auth,
+        ));
+    // The admin API is only served with a credential of its own.
+    if !state.config.features.admin_api || state.config.auth.admin_password.is_none() {
+        return api.with_state(state);
+    }
+    api.merge(
+        Router::new()
+            .route("/_fake/reset", post(admin::reset))
+            .route("/_fake/dump", get(admin::dump))
+            .route("/_fake/load", post(admin::load).put(admin::load))
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                api::auth::admin_auth,
+            )),
+    )
+    .with_state(state)
+}
@@ -1,89 +1,58 @@
-mod api;
-mod domain;
-mod repository;
-
-use crate::api::handlers::{cluster, documents, indices, search};
-use crate::repository::store::InMemoryStore;
-use axum::{
-    Router, middleware,
-    routing::{get, post, put},
-    http::{HeaderValue, header},
-};
-use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::net::TcpListener;
-use tower_http::set_header::SetResponseHeaderLayer;
-pub struct AppState {
-    pub store: InMemoryStore,
-    pub auth_user: String,
-    pub auth_password: String,
-    pub auth_enabled: bool,
-}
+use es_fake::EsFake;
+use es_fake::config::{Command, Config, usage};
 
 #[tokio::main]
 async fn main() {
-    let password = std::env::var("ELASTIC_PASSWORD").ok();
-    let auth_enabled = password.is_some() && !password.as_ref().unwrap().is_empty();
-
-    let state = Arc::new(AppState {
-        store: InMemoryStore::new(),
-        auth_user: "elastic".to_string(),
-        auth_password: password.unwrap_or_default(),
-        auth_enabled,
-    });
+    let args = std::env::args().skip(1);
+    let config = match Config::from_args(args, |name| std::env::var(name).ok()) {
+        Ok(Command::Run(config)) => *config,
+        Ok(Command::Help) => {
+            print!("{}", usage());
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("es_fake {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let seed = config.seed.clone();
+    let data_dir = config.persistence.data_dir.clone();
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], 9200));
+    let server = EsFake::builder()
+        .config(config)
+        .start()
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+    if let Some(seed) = &seed {
+        // A data directory keeps what was seeded on an earlier start.
+        match server.seeded() {
+            Some(summary) => println!(
+                "Seeded {} indices and {} documents from {}",
+                summary.indices,
+                summary.documents,
+                seed.display()
+            ),
+            None => println!("Skipping seed data: the data directory already holds indices"),
+        }
+    }
 
     println!("--- MICRO-ES STARTING ---");
-    println!("Listening on: http://{}", addr);
-
-    let app = Router::new()
-        .route("/", get(cluster::info).head(cluster::ping))
-        .route("/_cluster/health", get(cluster::cluster_health))
-        .route("/_bulk", post(documents::bulk))
-        .route("/{index}/_bulk", post(documents::bulk))
-        .route("/{index}/_refresh", post(indices::refresh))
-        .route(
-            "/{index}",
-            put(indices::create_index)
-                .head(indices::check_index)
-                .delete(indices::delete_index),
-        )
-        .route(
-            "/{index}/_mapping",
-            get(indices::get_mapping).put(indices::put_mapping),
-        )
-        .route("/{index}/_settings", get(indices::get_settings))
-        .route("/{index}/_mappings", get(indices::get_mapping))
-        .route("/{index}/_doc", post(documents::index_document))
-        .route(
-            "/{index}/_doc/{id}",
-            get(documents::get_document)
-                .put(documents::index_document_with_id)
-                .post(documents::index_document_with_id)
-                .delete(documents::delete_document),
-        )
-        .route("/{index}/_delete_by_query", post(documents::delete_by_query))
-        .route("/{index}/_update/{id}", post(documents::update_document))
-        .route("/{index}/_search", post(search::search).get(search::search))
-        .route("/{index}/_count", post(search::count).get(search::count))
-        .layer(SetResponseHeaderLayer::overriding(
-            header::HeaderName::from_static("x-elastic-product"),
-            HeaderValue::from_static("Elasticsearch"),
-        ))
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            api::auth::basic_auth,
-        ))
-        .layer(middleware::from_fn(api::logging::debug_log))
-        .with_state(state);
-
-    let listener = TcpListener::bind(addr).await.unwrap();
+    println!("Listening on: {}", server.url());
+    if let Some(data_dir) = &data_dir {
+        println!("Data directory: {}", data_dir.display());
+    }
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .unwrap();
+    shutdown_signal().await;
+    if let Err(e) = server.shutdown().await {
+        eprintln!("{}", e);
+    }
 }
 
 async fn shutdown_signal() {
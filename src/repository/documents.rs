@@ -0,0 +1,208 @@
+use crate::domain::doc_values::DocValues;
+use crate::domain::document::StoredDocument;
+use crate::domain::inverted_index::InvertedIndex;
+use crate::domain::mapping::Mapping;
+use crate::domain::query::Query;
+use std::sync::Arc;
+
+/// The documents of an index, keyed by `_id` and iterated in the order they
+/// were first written.
+///
+/// Both maps are persistent, so cloning is O(1) and a snapshot taken by a
+/// reader stays consistent while writers go on modifying their own copy;
+/// lookups and writes are O(log n). The postings of `index` and the columns
+/// of `values` refer to the same positions and are kept in step on every
+/// write.
+#[derive(Clone, Default)]
+pub struct DocumentMap {
+    /// `_id` -> position in `ordered`.
+    slots: im::HashMap<String, u64>,
+    ordered: im::OrdMap<u64, Arc<StoredDocument>>,
+    next_slot: u64,
+    index: InvertedIndex,
+    values: DocValues,
+}
+
+impl DocumentMap {
+    /// An empty map whose doc-values are typed by `mapping`.
+    pub fn with_mapping(mapping: &Mapping) -> Self {
+        Self {
+            values: DocValues::for_mapping(mapping),
+            ..Self::default()
+        }
+    }
+
+    /// Retypes the doc-values after a mapping change.
+    pub fn set_mapping(&mut self, mapping: &Mapping) {
+        let mut values = DocValues::for_mapping(mapping);
+        for (slot, doc) in &self.ordered {
+            values.add(*slot, &doc.source);
+        }
+        self.values = values;
+    }
+
+    pub fn doc_values(&self) -> &DocValues {
+        &self.values
+    }
+
+    pub fn get(&self, id: &str) -> Option<&StoredDocument> {
+        let slot = self.slots.get(id)?;
+        self.ordered.get(slot).map(Arc::as_ref)
+    }
+
+    /// Inserts or replaces a document; a replaced document keeps its
+    /// position.
+    pub fn insert(&mut self, doc: StoredDocument) {
+        let slot = match self.slots.get(&doc.id) {
+            Some(slot) => {
+                if let Some(previous) = self.ordered.get(slot) {
+                    self.index.remove(*slot, &previous.source);
+                    self.values.remove(*slot, &previous.source);
+                }
+                *slot
+            }
+            None => {
+                let slot = self.next_slot;
+                self.next_slot += 1;
+                self.slots.insert(doc.id.clone(), slot);
+                slot
+            }
+        };
+        self.index.add(slot, &doc.source);
+        self.values.add(slot, &doc.source);
+        self.ordered.insert(slot, Arc::new(doc));
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<StoredDocument> {
+        let slot = self.slots.remove(id)?;
+        let doc = self.ordered.remove(&slot)?;
+        self.index.remove(slot, &doc.source);
+        self.values.remove(slot, &doc.source);
+        Some(Arc::unwrap_or_clone(doc))
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &StoredDocument> {
+        self.ordered.values().map(Arc::as_ref)
+    }
+
+    /// Documents matching `query` with their positions, in insertion order.
+    /// Only the candidates found in the postings are checked when the query
+    /// can be planned; other queries scan every document. `index` is the
+    /// name the documents are searched under, for `_index` clauses.
+    pub fn matching(&self, index: &str, query: &dyn Query) -> Vec<(u64, &StoredDocument)> {
+        let matches = |doc: &&Arc<StoredDocument>| query.matches_document(index, doc);
+        match query.candidates(&self.index) {
+            Some(candidates) => candidates
+                .iter()
+                .filter_map(|slot| Some((*slot, self.ordered.get(slot)?)))
+                .filter(|(_, doc)| matches(doc))
+                .map(|(slot, doc)| (slot, doc.as_ref()))
+                .collect(),
+            None => self
+                .ordered
+                .iter()
+                .filter(|(_, doc)| matches(doc))
+                .map(|(slot, doc)| (*slot, doc.as_ref()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::versioning::DocVersion;
+    use serde_json::{Value, json};
+
+    fn doc(id: &str, source: Value) -> StoredDocument {
+        StoredDocument {
+            id: id.to_string(),
+            source,
+            version: DocVersion {
+                version: 1,
+                seq_no: 0,
+                primary_term: 1,
+            },
+            routing: None,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn should_keep_insertion_order_across_replacements() {
+        let mut docs = DocumentMap::default();
+        docs.insert(doc("b", json!(1)));
+        docs.insert(doc("a", json!(2)));
+        docs.insert(doc("b", json!(3)));
+        docs.insert(doc("c", json!(4)));
+        assert!(docs.remove("a").is_some());
+        assert!(docs.remove("a").is_none());
+
+        let ids: Vec<&str> = docs.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "c"]);
+        assert_eq!(docs.get("b").unwrap().source, json!(3));
+        assert_eq!(docs.len(), 2);
+    }
+
+    #[test]
+    fn should_leave_snapshots_untouched() {
+        let mut docs = DocumentMap::default();
+        docs.insert(doc("a", json!(1)));
+        let snapshot = docs.clone();
+
+        docs.insert(doc("a", json!(2)));
+        docs.insert(doc("b", json!(3)));
+
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot.get("a").unwrap().source, json!(1));
+        assert_eq!(docs.get("a").unwrap().source, json!(2));
+    }
+
+    #[test]
+    fn should_find_documents_through_postings() {
+        let mut docs = DocumentMap::default();
+        docs.insert(doc("a", json!({ "status": "active", "title": "Red fox" })));
+        docs.insert(doc(
+            "b",
+            json!({ "status": "deleted", "title": "Blue fox" }),
+        ));
+        docs.insert(doc(
+            "a",
+            json!({ "status": "deleted", "title": "Red bird" }),
+        ));
+        docs.insert(doc("c", json!({ "status": "active", "title": "red FOX" })));
+        docs.remove("b");
+
+        let query = crate::domain::query::parse_query(&json!({ "query": { "bool": {
+            "must": { "match": { "title": { "query": "red fox", "operator": "and" } } },
+            "filter": { "terms": { "status": ["active", "archived"] } }
+        } } }))
+        .unwrap();
+        let ids: Vec<&str> = docs
+            .matching("test", query.as_ref())
+            .iter()
+            .map(|(_, d)| d.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["c"]);
+
+        let query = crate::domain::query::parse_query(&json!({ "query": { "bool": {
+            "must_not": { "term": { "status": "active" } }
+        } } }))
+        .unwrap();
+        let ids: Vec<&str> = docs
+            .matching("test", query.as_ref())
+            .iter()
+            .map(|(_, d)| d.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["a"]);
+    }
+}
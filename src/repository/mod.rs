@@ -1 +1,5 @@
-pub mod store;
\ No newline at end of file
+pub mod documents;
+pub mod persistence;
+pub mod snapshots;
+pub mod store;
+pub mod tasks;
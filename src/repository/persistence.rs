@@ -0,0 +1,491 @@
+use crate::domain::data_stream::DataStream;
+use crate::domain::document::StoredDocument;
+use crate::domain::ingest::Pipeline;
+use crate::domain::mapping::Mapping;
+use crate::domain::settings::IndexSettings;
+use crate::domain::snapshot::RepositoryDefinition;
+use crate::domain::template::{ComponentTemplate, IndexTemplate};
+use crate::domain::time::parse_duration_millis;
+use crate::repository::documents::DocumentMap;
+use crate::repository::store::{AliasTable, IndexData};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const SNAPSHOT_FILE: &str = "snapshot.json";
+const SNAPSHOT_TMP_FILE: &str = "snapshot.json.tmp";
+
+/// Size the write-ahead log may grow to before it is compacted into a new
+/// snapshot.
+const COMPACTION_THRESHOLD_BYTES: u64 = 64 * 1024 * 1024;
+
+/// When appended WAL records are forced to disk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FsyncPolicy {
+    /// After every request, before it is acknowledged.
+    Always,
+    /// In the background, at most this long after a write.
+    Interval(Duration),
+    /// Left to the operating system.
+    Never,
+}
+
+impl FsyncPolicy {
+    /// Parses `always`, `never` or an interval such as `5s`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            interval => parse_duration_millis(interval)
+                .filter(|millis| *millis > 0)
+                .map(|millis| Self::Interval(Duration::from_millis(millis)))
+                .ok_or_else(|| {
+                    format!(
+                        "invalid fsync policy [{}], expected [always], [never] or an interval \
+                         such as [5s]",
+                        value
+                    )
+                }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PersistenceOptions {
+    pub data_dir: PathBuf,
+    pub fsync: FsyncPolicy,
+}
+
+/// One mutation of the store, recorded as the state it left behind rather
+/// than the request that caused it, so replaying a record that is already
+/// reflected in a snapshot is harmless.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum WalRecord {
+    CreateIndex {
+        index: String,
+        mapping: Mapping,
+        settings: IndexSettings,
+    },
+    PutMapping {
+        index: String,
+        mapping: Mapping,
+    },
+    DeleteIndex {
+        index: String,
+    },
+    PutDocument {
+        index: String,
+        document: StoredDocument,
+        next_seq_no: u64,
+    },
+    DeleteDocument {
+        index: String,
+        id: String,
+        next_seq_no: u64,
+    },
+    Aliases {
+        aliases: AliasTable,
+    },
+    DataStreams {
+        data_streams: HashMap<String, DataStream>,
+    },
+    IndexTemplates {
+        templates: HashMap<String, IndexTemplate>,
+    },
+    ComponentTemplates {
+        templates: HashMap<String, ComponentTemplate>,
+    },
+    Pipelines {
+        pipelines: HashMap<String, Value>,
+    },
+    SnapshotRepositories {
+        repositories: HashMap<String, RepositoryDefinition>,
+    },
+}
+
+/// The whole store at one point in time. Indices are shared with the store,
+/// so taking a snapshot is cheap; it is only serialized afterwards.
+pub struct StoreSnapshot {
+    pub indices: BTreeMap<String, Arc<IndexData>>,
+    pub aliases: AliasTable,
+    pub data_streams: HashMap<String, DataStream>,
+    pub index_templates: HashMap<String, IndexTemplate>,
+    pub component_templates: HashMap<String, ComponentTemplate>,
+    pub pipelines: HashMap<String, Pipeline>,
+    pub snapshot_repositories: HashMap<String, RepositoryDefinition>,
+}
+
+/// One index serialized the way it appears in a [`StoreSnapshot`]; reads
+/// back as an [`IndexState`].
+pub struct IndexSnapshot<'a>(pub &'a IndexData);
+
+impl Serialize for IndexSnapshot<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        IndexView {
+            mapping: &self.0.mapping,
+            settings: &self.0.settings,
+            next_seq_no: self.0.next_seq_no,
+            documents: &self.0.documents,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+struct IndexView<'a> {
+    mapping: &'a Mapping,
+    settings: &'a IndexSettings,
+    next_seq_no: u64,
+    #[serde(serialize_with = "serialize_documents")]
+    documents: &'a DocumentMap,
+}
+
+fn serialize_documents<S: Serializer>(
+    documents: &&DocumentMap,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(documents.iter())
+}
+
+impl Serialize for StoreSnapshot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct View<'a> {
+            indices: BTreeMap<&'a str, IndexSnapshot<'a>>,
+            aliases: &'a AliasTable,
+            data_streams: &'a HashMap<String, DataStream>,
+            index_templates: &'a HashMap<String, IndexTemplate>,
+            component_templates: &'a HashMap<String, ComponentTemplate>,
+            pipelines: BTreeMap<&'a str, &'a Value>,
+            snapshot_repositories: &'a HashMap<String, RepositoryDefinition>,
+        }
+
+        View {
+            indices: self
+                .indices
+                .iter()
+                .map(|(name, data)| (name.as_str(), IndexSnapshot(data)))
+                .collect(),
+            aliases: &self.aliases,
+            data_streams: &self.data_streams,
+            index_templates: &self.index_templates,
+            component_templates: &self.component_templates,
+            pipelines: self
+                .pipelines
+                .iter()
+                .map(|(id, pipeline)| (id.as_str(), &pipeline.definition))
+                .collect(),
+            snapshot_repositories: &self.snapshot_repositories,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// A serialized [`StoreSnapshot`] read back into owned values.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct StoreState {
+    pub indices: BTreeMap<String, IndexState>,
+    pub aliases: AliasTable,
+    pub data_streams: HashMap<String, DataStream>,
+    pub index_templates: HashMap<String, IndexTemplate>,
+    pub component_templates: HashMap<String, ComponentTemplate>,
+    pub pipelines: HashMap<String, Value>,
+    pub snapshot_repositories: HashMap<String, RepositoryDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IndexState {
+    pub mapping: Mapping,
+    pub settings: IndexSettings,
+    pub next_seq_no: u64,
+    pub documents: Vec<StoredDocument>,
+}
+
+/// The snapshot file: the store plus the first WAL generation that is not
+/// reflected in it yet.
+#[derive(Serialize, Deserialize)]
+struct SnapshotFile<T> {
+    wal_generation: u64,
+    store: T,
+}
+
+/// What was found in a data directory at startup.
+pub struct Recovered {
+    pub state: StoreState,
+    pub records: Vec<WalRecord>,
+    /// Generation for the next WAL file, past every existing one.
+    pub next_generation: u64,
+}
+
+/// Reads the snapshot and replays the WAL files written after it. A record
+/// cut short at the end of a file, which is what a crash in the middle of
+/// an append leaves behind, is dropped; damage anywhere else is an error.
+pub fn recover(dir: &Path) -> Result<Recovered, String> {
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("cannot create data directory [{}]: {}", dir.display(), e))?;
+    let _ = fs::remove_file(dir.join(SNAPSHOT_TMP_FILE));
+
+    let snapshot_path = dir.join(SNAPSHOT_FILE);
+    let (state, first_generation) = if snapshot_path.exists() {
+        let file = File::open(&snapshot_path).map_err(|e| io_error(&snapshot_path, e))?;
+        let snapshot: SnapshotFile<StoreState> =
+            serde_json::from_reader(std::io::BufReader::new(file))
+                .map_err(|e| format!("corrupt snapshot [{}]: {}", snapshot_path.display(), e))?;
+        (snapshot.store, snapshot.wal_generation)
+    } else {
+        (StoreState::default(), 1)
+    };
+
+    let generations = wal_generations(dir)?;
+    let mut records = Vec::new();
+    for generation in generations.iter().filter(|g| **g >= first_generation) {
+        let path = wal_path(dir, *generation);
+        let content = fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+        let lines: Vec<&str> = content.lines().filter(|l| !l.trim().is_empty()).collect();
+        for (number, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(record) => records.push(record),
+                Err(_) if number + 1 == lines.len() => {
+                    eprintln!(
+                        "Dropping incomplete record at the end of [{}]",
+                        path.display()
+                    );
+                }
+                Err(e) => {
+                    return Err(format!(
+                        "corrupt WAL record [{}] line {}: {}",
+                        path.display(),
+                        number + 1,
+                        e
+                    ));
+                }
+            }
+        }
+    }
+
+    let next_generation = generations
+        .last()
+        .map_or(first_generation, |last| (last + 1).max(first_generation));
+    Ok(Recovered {
+        state,
+        records,
+        next_generation,
+    })
+}
+
+struct Wal {
+    file: File,
+    generation: u64,
+    size: u64,
+    /// Set while appended records have not been synced yet.
+    dirty: bool,
+    last_sync: Instant,
+}
+
+/// Prefix of the error of a change that was rejected because it could not
+/// be written to the WAL.
+const WAL_FAILURE: &str = "failed to append to the write-ahead log";
+
+fn wal_failure(error: &dyn std::fmt::Display) -> String {
+    format!("{}: {}", WAL_FAILURE, error)
+}
+
+pub fn is_wal_failure(error: &str) -> bool {
+    error.starts_with(WAL_FAILURE)
+}
+
+/// Appends mutations to the current WAL file and compacts the log into a
+/// snapshot once it grows too large.
+pub struct Persistence {
+    dir: PathBuf,
+    fsync: FsyncPolicy,
+    wal: Mutex<Wal>,
+}
+
+impl Persistence {
+    pub fn open(options: PersistenceOptions, generation: u64) -> Result<Self, String> {
+        let file = open_wal(&options.data_dir, generation)?;
+        let size = file.metadata().map_or(0, |metadata| metadata.len());
+        Ok(Self {
+            dir: options.data_dir,
+            fsync: options.fsync,
+            wal: Mutex::new(Wal {
+                file,
+                generation,
+                size,
+                dirty: false,
+                last_sync: Instant::now(),
+            }),
+        })
+    }
+
+    /// Appends records as one write, synced right away under the `always`
+    /// policy. A failed write is cut off again so that it cannot leave a torn
+    /// record in front of the next one.
+    pub fn append(&self, records: &[WalRecord]) -> Result<(), String> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut buffer = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut buffer, record).map_err(|e| wal_failure(&e))?;
+            buffer.push(b'\n');
+        }
+        let mut wal = self.wal.lock().unwrap();
+        let written = wal.file.write_all(&buffer).and_then(|_| match self.fsync {
+            FsyncPolicy::Always => wal.file.sync_data(),
+            _ => Ok(()),
+        });
+        if let Err(e) = written {
+            let _ = wal.file.set_len(wal.size);
+            return Err(wal_failure(&e));
+        }
+        wal.size += buffer.len() as u64;
+        if self.fsync == FsyncPolicy::Always {
+            wal.last_sync = Instant::now();
+        } else {
+            wal.dirty = true;
+        }
+        Ok(())
+    }
+
+    /// Syncs pending records once the fsync interval has passed.
+    pub fn sync_if_due(&self) -> Result<(), String> {
+        let FsyncPolicy::Interval(interval) = self.fsync else {
+            return Ok(());
+        };
+        let mut wal = self.wal.lock().unwrap();
+        if wal.dirty && wal.last_sync.elapsed() >= interval {
+            wal.file.sync_data().map_err(|e| e.to_string())?;
+            wal.dirty = false;
+            wal.last_sync = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Syncs whatever has been appended since the last sync.
+    pub fn sync(&self) -> Result<(), String> {
+        let mut wal = self.wal.lock().unwrap();
+        if wal.dirty {
+            wal.file.sync_data().map_err(|e| e.to_string())?;
+            wal.dirty = false;
+            wal.last_sync = Instant::now();
+        }
+        Ok(())
+    }
+
+    pub fn compaction_due(&self) -> bool {
+        self.wal.lock().unwrap().size >= COMPACTION_THRESHOLD_BYTES
+    }
+
+    /// Swaps the WAL file for a read-only handle, so that every following
+    /// append fails like it would on a full or read-only disk.
+    #[cfg(test)]
+    pub fn fail_appends(&self) {
+        let mut wal = self.wal.lock().unwrap();
+        wal.file = File::open(wal_path(&self.dir, wal.generation)).unwrap();
+    }
+
+    /// Syncs the current WAL file and continues in a new one, returning the
+    /// new generation. Every record in older files took effect before this
+    /// call, so a snapshot taken afterwards covers them.
+    pub fn rotate(&self) -> Result<u64, String> {
+        let mut wal = self.wal.lock().unwrap();
+        wal.file.sync_data().map_err(|e| e.to_string())?;
+        let generation = wal.generation + 1;
+        wal.file = open_wal(&self.dir, generation)?;
+        wal.generation = generation;
+        wal.size = 0;
+        wal.dirty = false;
+        wal.last_sync = Instant::now();
+        Ok(generation)
+    }
+
+    /// Atomically replaces the snapshot, then drops the WAL files it covers.
+    /// Records of `generation` onwards may already be reflected in the
+    /// snapshot; replaying them again on recovery is harmless.
+    pub fn write_snapshot(&self, generation: u64, store: &StoreSnapshot) -> Result<(), String> {
+        let tmp_path = self.dir.join(SNAPSHOT_TMP_FILE);
+        let file = File::create(&tmp_path).map_err(|e| io_error(&tmp_path, e))?;
+        let mut writer = BufWriter::new(file);
+        let snapshot = SnapshotFile {
+            wal_generation: generation,
+            store,
+        };
+        serde_json::to_writer(&mut writer, &snapshot).map_err(|e| e.to_string())?;
+        let file = writer.into_inner().map_err(|e| e.to_string())?;
+        file.sync_all().map_err(|e| io_error(&tmp_path, e))?;
+
+        let path = self.dir.join(SNAPSHOT_FILE);
+        fs::rename(&tmp_path, &path).map_err(|e| io_error(&path, e))?;
+        File::open(&self.dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(|e| io_error(&self.dir, e))?;
+
+        for old in wal_generations(&self.dir)?
+            .into_iter()
+            .filter(|g| *g < generation)
+        {
+            let _ = fs::remove_file(wal_path(&self.dir, old));
+        }
+        Ok(())
+    }
+}
+
+fn wal_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("wal-{:08}.log", generation))
+}
+
+fn open_wal(dir: &Path, generation: u64) -> Result<File, String> {
+    let path = wal_path(dir, generation);
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| io_error(&path, e))
+}
+
+/// Generations of the WAL files in `dir`, in ascending order.
+fn wal_generations(dir: &Path) -> Result<Vec<u64>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| io_error(dir, e))?;
+    let mut generations: Vec<u64> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            name.strip_prefix("wal-")?
+                .strip_suffix(".log")?
+                .parse()
+                .ok()
+        })
+        .collect();
+    generations.sort_unstable();
+    Ok(generations)
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> String {
+    format!("[{}]: {}", path.display(), error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_fsync_policies() {
+        assert_eq!(FsyncPolicy::parse("always"), Ok(FsyncPolicy::Always));
+        assert_eq!(FsyncPolicy::parse("never"), Ok(FsyncPolicy::Never));
+        assert_eq!(
+            FsyncPolicy::parse("5s"),
+            Ok(FsyncPolicy::Interval(Duration::from_secs(5)))
+        );
+        assert!(FsyncPolicy::parse("sometimes").is_err());
+        assert!(FsyncPolicy::parse("0s").is_err());
+    }
+}
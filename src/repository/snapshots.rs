@@ -0,0 +1,176 @@
+use crate::domain::snapshot::SnapshotInfo;
+use crate::domain::time::{format_timestamp, now_millis};
+use crate::repository::persistence::{IndexSnapshot, IndexState, StoreSnapshot, StoreState};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// A shared file system snapshot repository. Each snapshot is written as
+///
+/// * `indices/<uuid>/<index>.json` - mapping, settings and documents of
+///   every index, keyed by the snapshot uuid rather than its user-supplied
+///   name,
+/// * `meta-<snapshot>.json` - aliases, data streams, templates and pipelines,
+/// * `snap-<snapshot>.json` - the snapshot metadata, written last so a
+///   snapshot only shows up once it is complete.
+pub struct FsRepository {
+    location: PathBuf,
+}
+
+impl FsRepository {
+    pub fn new(location: PathBuf) -> Self {
+        Self { location }
+    }
+
+    /// Creates the repository directory and checks it can be written to.
+    pub fn verify(&self) -> Result<(), String> {
+        fs::create_dir_all(&self.location).map_err(|e| io_error(&self.location, e))?;
+        let probe = self
+            .location
+            .join(format!("tests-{}", uuid::Uuid::new_v4().simple()));
+        fs::write(&probe, b"").map_err(|e| io_error(&probe, e))?;
+        fs::remove_file(&probe).map_err(|e| io_error(&probe, e))
+    }
+
+    /// Completed snapshots, oldest first.
+    pub fn snapshots(&self) -> Result<Vec<SnapshotInfo>, String> {
+        let entries = match fs::read_dir(&self.location) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(io_error(&self.location, e)),
+        };
+        let mut snapshots = Vec::new();
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let file_name = entry.file_name();
+            let Some(name) = file_name
+                .to_str()
+                .and_then(|name| name.strip_prefix("snap-")?.strip_suffix(".json"))
+            else {
+                continue;
+            };
+            snapshots.push(read_json(&self.info_path(name))?);
+        }
+        snapshots.sort_by(|a: &SnapshotInfo, b| {
+            (a.start_time_in_millis, &a.snapshot).cmp(&(b.start_time_in_millis, &b.snapshot))
+        });
+        Ok(snapshots)
+    }
+
+    pub fn snapshot(&self, name: &str) -> Result<Option<SnapshotInfo>, String> {
+        let path = self.info_path(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        read_json(&path).map(Some)
+    }
+
+    /// Writes the indices and global state of `store`, then `info` stamped
+    /// with the time the data was complete. A snapshot that fails half way is
+    /// removed again.
+    pub fn write(
+        &self,
+        mut info: SnapshotInfo,
+        store: StoreSnapshot,
+    ) -> Result<SnapshotInfo, String> {
+        match self.write_files(&mut info, store) {
+            Ok(()) => Ok(info),
+            Err(e) => {
+                let _ = self.delete(&info);
+                Err(e)
+            }
+        }
+    }
+
+    fn write_files(&self, info: &mut SnapshotInfo, mut store: StoreSnapshot) -> Result<(), String> {
+        let indices_dir = self.indices_dir(info);
+        fs::create_dir_all(&indices_dir).map_err(|e| io_error(&indices_dir, e))?;
+        for (name, data) in std::mem::take(&mut store.indices) {
+            write_json(&self.index_path(info, &name), &IndexSnapshot(&data))?;
+        }
+        write_json(&self.global_path(&info.snapshot), &store)?;
+        let end = now_millis();
+        info.end_time = format_timestamp(end);
+        info.end_time_in_millis = end;
+        info.duration_in_millis = end.saturating_sub(info.start_time_in_millis);
+        write_json(&self.info_path(&info.snapshot), info)
+    }
+
+    /// Aliases, data streams, templates and pipelines of a snapshot.
+    pub fn read_global(&self, snapshot: &str) -> Result<StoreState, String> {
+        read_json(&self.global_path(snapshot))
+    }
+
+    pub fn read_index(&self, info: &SnapshotInfo, index: &str) -> Result<IndexState, String> {
+        read_json(&self.index_path(info, index))
+    }
+
+    /// Size on disk of each index of a snapshot.
+    pub fn index_sizes(&self, info: &SnapshotInfo) -> Vec<(String, u64)> {
+        info.indices
+            .iter()
+            .map(|index| {
+                let size =
+                    fs::metadata(self.index_path(info, index)).map_or(0, |metadata| metadata.len());
+                (index.clone(), size)
+            })
+            .collect()
+    }
+
+    pub fn delete(&self, info: &SnapshotInfo) -> Result<(), String> {
+        // The metadata goes first so a partially deleted snapshot is no longer
+        // listed.
+        for path in [
+            self.info_path(&info.snapshot),
+            self.global_path(&info.snapshot),
+        ] {
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(io_error(&path, e));
+                }
+                _ => {}
+            }
+        }
+        let indices_dir = self.indices_dir(info);
+        match fs::remove_dir_all(&indices_dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(io_error(&indices_dir, e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn info_path(&self, snapshot: &str) -> PathBuf {
+        self.location.join(format!("snap-{}.json", snapshot))
+    }
+
+    fn global_path(&self, snapshot: &str) -> PathBuf {
+        self.location.join(format!("meta-{}.json", snapshot))
+    }
+
+    fn indices_dir(&self, info: &SnapshotInfo) -> PathBuf {
+        self.location.join("indices").join(&info.uuid)
+    }
+
+    fn index_path(&self, info: &SnapshotInfo, index: &str) -> PathBuf {
+        self.indices_dir(info).join(format!("{}.json", index))
+    }
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| io_error(path, e))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, value).map_err(|e| io_error(path, e.into()))?;
+    let file = writer
+        .into_inner()
+        .map_err(|e| io_error(path, e.into_error()))?;
+    file.sync_all().map_err(|e| io_error(path, e))
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let file = File::open(path).map_err(|e| io_error(path, e))?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| io_error(path, e.into()))
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> String {
+    format!("[{}]: {}", path.display(), error)
+}
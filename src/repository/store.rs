@@ -1,31 +1,1230 @@
+use crate::domain::alias::AliasDefinition;
+use crate::domain::data_stream::{
+    DataStream, RolloverConditions, TIMESTAMP_FIELD, next_rollover_name,
+};
+use crate::domain::document::StoredDocument;
+use crate::domain::ingest::Pipeline;
 use crate::domain::mapping::Mapping;
+use crate::domain::settings::IndexSettings;
+use crate::domain::snapshot::RepositoryDefinition;
+use crate::domain::source::wildcard_match;
+use crate::domain::template::{ComponentTemplate, ComposedTemplate, IndexTemplate};
+use crate::domain::time::now_millis;
+use crate::domain::update::{UpdateAction, UpdateKind, UpdateRequest};
+use crate::domain::versioning::{DocVersion, PRIMARY_TERM, WriteConditions, is_version_conflict};
+use crate::repository::documents::DocumentMap;
+use crate::repository::persistence::{
+    IndexState, Persistence, PersistenceOptions, StoreSnapshot, StoreState, WalRecord, recover,
+};
 use dashmap::DashMap;
-use serde_json::Value;
-use std::sync::Arc;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+/// Alias name -> concrete index name -> alias metadata for that index.
+pub type AliasTable = HashMap<String, HashMap<String, AliasDefinition>>;
 
 #[derive(Clone)]
 pub struct IndexData {
     pub mapping: Mapping,
-    pub documents: Vec<Value>,
+    pub settings: IndexSettings,
+    pub documents: DocumentMap,
+    pub next_seq_no: u64,
+}
+
+impl IndexData {
+    fn new(mapping: Mapping, settings: IndexSettings) -> Self {
+        Self {
+            documents: DocumentMap::with_mapping(&mapping),
+            mapping,
+            settings,
+            next_seq_no: 0,
+        }
+    }
+
+    fn from_state(state: IndexState) -> Self {
+        let mut data = Self::new(state.mapping, state.settings);
+        for document in state.documents {
+            data.documents.insert(document);
+        }
+        data.next_seq_no = state.next_seq_no;
+        data
+    }
+
+    pub fn document(&self, id: &str) -> Option<&StoredDocument> {
+        self.documents.get(id)
+    }
+
+    fn put_record(&self, index: &str, id: &str) -> Option<WalRecord> {
+        Some(WalRecord::PutDocument {
+            index: index.to_string(),
+            document: self.document(id)?.clone(),
+            next_seq_no: self.next_seq_no,
+        })
+    }
+
+    /// Records recreating the whole index with its documents.
+    fn records(&self, index: &str) -> Vec<WalRecord> {
+        let create = WalRecord::CreateIndex {
+            index: index.to_string(),
+            mapping: self.mapping.clone(),
+            settings: self.settings.clone(),
+        };
+        let documents = self
+            .documents
+            .iter()
+            .map(|document| WalRecord::PutDocument {
+                index: index.to_string(),
+                document: document.clone(),
+                next_seq_no: self.next_seq_no,
+            });
+        std::iter::once(create).chain(documents).collect()
+    }
+
+    fn delete_record(&self, index: &str, id: String) -> WalRecord {
+        WalRecord::DeleteDocument {
+            index: index.to_string(),
+            id,
+            next_seq_no: self.next_seq_no,
+        }
+    }
+
+    fn next_doc_version(&mut self, version: u64) -> DocVersion {
+        let doc_version = DocVersion {
+            version,
+            seq_no: self.next_seq_no,
+            primary_term: PRIMARY_TERM,
+        };
+        self.next_seq_no += 1;
+        doc_version
+    }
+
+    /// Indexes a document in place; every check runs before anything is
+    /// modified so a failed write leaves the index untouched.
+    fn write(
+        &mut self,
+        id: String,
+        source: Value,
+        routing: Option<String>,
+        conditions: &WriteConditions,
+        requires_timestamp: bool,
+    ) -> Result<WriteResult, String> {
+        if source.get("_id").is_some() {
+            return Err(
+                "Field [_id] is a metadata field and cannot be added inside a document. \
+                 Use the index API request parameters."
+                    .to_string(),
+            );
+        }
+        if requires_timestamp && source.get(TIMESTAMP_FIELD).is_none() {
+            return Err(format!(
+                "data stream timestamp field [{}] is missing",
+                TIMESTAMP_FIELD
+            ));
+        }
+        self.mapping
+            .validate(&source)
+            .map_err(|e| format!("Validation failed: {:?}", e))?;
+
+        let existing = self.documents.get(&id);
+        let created = existing.is_none();
+        let version = conditions.next_version(&id, existing.map(|d| d.version))?;
+        let now = now_millis();
+        let created_at = existing.map_or(now, |d| d.created_at);
+        let doc_version = self.next_doc_version(version);
+        let doc = StoredDocument {
+            id: id.clone(),
+            source,
+            version: doc_version,
+            routing,
+            created_at,
+            updated_at: now,
+        };
+
+        self.documents.insert(doc);
+        Ok(WriteResult {
+            id,
+            version: doc_version,
+            created,
+        })
+    }
+
+    fn delete(&mut self, id: &str, conditions: &WriteConditions) -> Result<DeleteResult, String> {
+        let current = self.documents.get(id).map(|d| d.version);
+        let version = conditions.next_version(id, current)?;
+        let found = self.documents.remove(id).is_some();
+        Ok(DeleteResult {
+            version: self.next_doc_version(version),
+            found,
+        })
+    }
+}
+
+/// One write of a batch applied by [`InMemoryStore::apply_batch`].
+pub enum BatchOp {
+    Index {
+        id: String,
+        source: Value,
+        routing: Option<String>,
+        conditions: WriteConditions,
+    },
+    Delete {
+        id: String,
+        conditions: WriteConditions,
+    },
+}
+
+pub enum BatchResult {
+    Written(WriteResult),
+    Deleted(DeleteResult),
+}
+
+#[derive(Debug, Clone)]
+pub struct UpdateResult {
+    pub id: String,
+    pub version: DocVersion,
+    pub kind: UpdateKind,
+    pub source: Value,
 }
 
+#[derive(Debug, Clone)]
+pub struct WriteResult {
+    pub id: String,
+    pub version: DocVersion,
+    pub created: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeleteResult {
+    pub version: DocVersion,
+    /// Whether the document existed; deleting a missing document still
+    /// records a version, as Elasticsearch does.
+    pub found: bool,
+}
+
+/// What a create-index request asks for explicitly; anything left out is
+/// taken from the matching index template.
+#[derive(Debug, Clone, Default)]
+pub struct IndexDefinition {
+    pub mapping: Option<Mapping>,
+    pub settings: IndexSettings,
+    pub aliases: HashMap<String, AliasDefinition>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IndicesOptions {
+    pub ignore_unavailable: bool,
+    pub allow_no_indices: bool,
+}
+
+impl Default for IndicesOptions {
+    fn default() -> Self {
+        Self {
+            ignore_unavailable: false,
+            allow_no_indices: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexTarget {
+    pub index: String,
+    /// Alias filter restricting which documents of the index are visible,
+    /// `None` when the index was reached without a filtered alias.
+    pub filter: Option<Value>,
+}
+
+/// Where a single-document write goes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteTarget {
+    pub index: String,
+    /// The requested routing, or the `index_routing` of the alias the write
+    /// went through.
+    pub routing: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum AliasAction {
+    Add {
+        index: String,
+        alias: String,
+        definition: AliasDefinition,
+    },
+    Remove {
+        index: String,
+        alias: String,
+    },
+    RemoveIndex {
+        index: String,
+    },
+}
+
+#[derive(Default)]
+struct TargetSet {
+    order: Vec<String>,
+    filters: HashMap<String, Option<Vec<Value>>>,
+}
+
+impl TargetSet {
+    fn add(&mut self, index: &str, filter: Option<&Value>) {
+        match self.filters.entry(index.to_string()) {
+            Entry::Vacant(entry) => {
+                self.order.push(index.to_string());
+                entry.insert(filter.map(|f| vec![f.clone()]));
+            }
+            Entry::Occupied(mut entry) => match (entry.get_mut(), filter) {
+                (Some(existing), Some(f)) => existing.push(f.clone()),
+                (_, None) => *entry.get_mut() = None,
+                (None, Some(_)) => {}
+            },
+        }
+    }
+
+    fn exclude(&mut self, pattern: &str) {
+        self.order.retain(|name| !wildcard_match(pattern, name));
+        self.filters
+            .retain(|name, _| !wildcard_match(pattern, name));
+    }
+
+    fn into_targets(mut self) -> Vec<IndexTarget> {
+        self.order
+            .into_iter()
+            .map(|index| {
+                let filter = match self.filters.remove(&index).flatten() {
+                    None => None,
+                    Some(mut filters) if filters.len() == 1 => filters.pop(),
+                    Some(filters) => Some(json!({ "bool": { "should": filters } })),
+                };
+                IndexTarget { index, filter }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RolloverOutcome {
+    pub old_index: String,
+    pub new_index: String,
+    pub rolled_over: bool,
+    pub conditions: Vec<(String, bool)>,
+}
+
+/// Methods that hold more than one of the locks below take `aliases` before
+/// `data_streams`.
 pub struct InMemoryStore {
     indices: DashMap<String, Arc<IndexData>>,
+    aliases: RwLock<AliasTable>,
+    index_templates: RwLock<HashMap<String, IndexTemplate>>,
+    component_templates: RwLock<HashMap<String, ComponentTemplate>>,
+    data_streams: RwLock<HashMap<String, DataStream>>,
+    pipelines: RwLock<HashMap<String, Pipeline>>,
+    snapshot_repositories: RwLock<HashMap<String, RepositoryDefinition>>,
+    /// Held shared from logging a change to `indices` until the change is
+    /// made, and exclusively while [`InMemoryStore::compact`] rotates the
+    /// WAL, so no such change is half way through when the snapshot is taken.
+    changes: RwLock<()>,
+    /// Set when the store is backed by a data directory.
+    persistence: Option<Persistence>,
+}
+
+impl Default for InMemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InMemoryStore {
     pub fn new() -> Self {
         Self {
             indices: DashMap::new(),
+            aliases: RwLock::new(HashMap::new()),
+            index_templates: RwLock::new(HashMap::new()),
+            component_templates: RwLock::new(HashMap::new()),
+            data_streams: RwLock::new(HashMap::new()),
+            pipelines: RwLock::new(HashMap::new()),
+            snapshot_repositories: RwLock::new(HashMap::new()),
+            changes: RwLock::new(()),
+            persistence: None,
         }
     }
 
-    pub fn create_index(&self, name: String, mapping: Mapping) {
-        let index_data = IndexData {
-            mapping,
-            documents: Vec::new(),
+    /// Opens a store backed by a data directory: the last snapshot is loaded,
+    /// the WAL written after it is replayed and the result compacted into a
+    /// fresh snapshot before any new write is accepted.
+    pub fn open(options: PersistenceOptions) -> Result<Self, String> {
+        let recovered = recover(&options.data_dir)?;
+        let mut store = Self::new();
+        store.restore(recovered.state);
+        for record in recovered.records {
+            store.apply(record);
+        }
+        store.persistence = Some(Persistence::open(options, recovered.next_generation)?);
+        store.compact()?;
+        Ok(store)
+    }
+
+    /// Syncs and compacts the WAL when due; called periodically for stores
+    /// opened with a data directory.
+    pub fn maintain(&self) -> Result<(), String> {
+        let Some(persistence) = &self.persistence else {
+            return Ok(());
+        };
+        persistence.sync_if_due()?;
+        if persistence.compaction_due() {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn fail_wal_appends(&self) {
+        self.persistence.as_ref().unwrap().fail_appends();
+    }
+
+    /// Forces pending WAL records to disk, e.g. on shutdown.
+    pub fn flush(&self) -> Result<(), String> {
+        match &self.persistence {
+            Some(persistence) => persistence.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes a snapshot of the whole store and drops the WAL it replaces.
+    pub fn compact(&self) -> Result<(), String> {
+        let Some(persistence) = &self.persistence else {
+            return Ok(());
+        };
+        let generation = {
+            let _changes = self.changes.write().unwrap();
+            persistence.rotate()?
         };
-        self.indices.insert(name, Arc::new(index_data));
+        persistence.write_snapshot(generation, &self.snapshot())
+    }
+
+    pub fn snapshot(&self) -> StoreSnapshot {
+        StoreSnapshot {
+            indices: self
+                .indices
+                .iter()
+                .map(|entry| (entry.key().clone(), Arc::clone(entry.value())))
+                .collect(),
+            aliases: self.aliases(),
+            data_streams: self.data_streams(),
+            index_templates: self.index_templates(),
+            component_templates: self.component_templates(),
+            pipelines: self.pipelines(),
+            snapshot_repositories: self.snapshot_repositories(),
+        }
+    }
+
+    /// Replaces the whole content of the store with `state`, e.g. a dump
+    /// taken earlier. With a data directory the new content is compacted
+    /// into a fresh snapshot right away.
+    pub fn load(&self, state: StoreState) -> Result<(), String> {
+        self.restore(state);
+        self.compact()
+    }
+
+    /// Drops every index, alias, template, pipeline and repository.
+    pub fn reset(&self) -> Result<(), String> {
+        self.load(StoreState::default())
+    }
+
+    /// Replaces the whole content of the store.
+    fn restore(&self, state: StoreState) {
+        self.indices.clear();
+        for (name, index) in state.indices {
+            self.indices
+                .insert(name, Arc::new(IndexData::from_state(index)));
+        }
+        *self.aliases.write().unwrap() = state.aliases;
+        *self.data_streams.write().unwrap() = state.data_streams;
+        *self.index_templates.write().unwrap() = state.index_templates;
+        *self.component_templates.write().unwrap() = state.component_templates;
+        *self.pipelines.write().unwrap() = Self::parse_pipelines(state.pipelines);
+        *self.snapshot_repositories.write().unwrap() = state.snapshot_repositories;
+    }
+
+    fn parse_pipelines(definitions: HashMap<String, Value>) -> HashMap<String, Pipeline> {
+        definitions
+            .into_iter()
+            .filter_map(|(id, definition)| Some((id, Pipeline::from_json(&definition).ok()?)))
+            .collect()
+    }
+
+    /// Replays a WAL record.
+    fn apply(&self, record: WalRecord) {
+        match record {
+            WalRecord::CreateIndex {
+                index,
+                mapping,
+                settings,
+            } => {
+                self.indices
+                    .insert(index, Arc::new(IndexData::new(mapping, settings)));
+            }
+            WalRecord::PutMapping { index, mapping } => {
+                if let Some(mut data) = self.indices.get_mut(&index) {
+                    let data = Arc::make_mut(data.value_mut());
+                    data.documents.set_mapping(&mapping);
+                    data.mapping = mapping;
+                }
+            }
+            WalRecord::DeleteIndex { index } => {
+                self.indices.remove(&index);
+            }
+            WalRecord::PutDocument {
+                index,
+                document,
+                next_seq_no,
+            } => {
+                if let Some(mut data) = self.indices.get_mut(&index) {
+                    let data = Arc::make_mut(data.value_mut());
+                    data.documents.insert(document);
+                    data.next_seq_no = next_seq_no;
+                }
+            }
+            WalRecord::DeleteDocument {
+                index,
+                id,
+                next_seq_no,
+            } => {
+                if let Some(mut data) = self.indices.get_mut(&index) {
+                    let data = Arc::make_mut(data.value_mut());
+                    data.documents.remove(&id);
+                    data.next_seq_no = next_seq_no;
+                }
+            }
+            WalRecord::Aliases { aliases } => *self.aliases.write().unwrap() = aliases,
+            WalRecord::DataStreams { data_streams } => {
+                *self.data_streams.write().unwrap() = data_streams
+            }
+            WalRecord::IndexTemplates { templates } => {
+                *self.index_templates.write().unwrap() = templates
+            }
+            WalRecord::ComponentTemplates { templates } => {
+                *self.component_templates.write().unwrap() = templates
+            }
+            WalRecord::Pipelines { pipelines } => {
+                *self.pipelines.write().unwrap() = Self::parse_pipelines(pipelines)
+            }
+            WalRecord::SnapshotRepositories { repositories } => {
+                *self.snapshot_repositories.write().unwrap() = repositories
+            }
+        }
+    }
+
+    /// Appends the records built by `records` to the WAL when the store is
+    /// persistent. Callers hold the lock guarding what they change and only
+    /// apply the change once this succeeds, so the records of one index or
+    /// table are logged in the order they apply and nothing is acknowledged
+    /// that the WAL does not hold.
+    fn log(&self, records: impl FnOnce() -> Vec<WalRecord>) -> Result<(), String> {
+        match &self.persistence {
+            Some(persistence) => persistence.append(&records()),
+            None => Ok(()),
+        }
+    }
+
+    /// Like [`Self::log`] for changes to `indices`, whose entries are not
+    /// locked until the change is made: the returned guard keeps a
+    /// compaction from rotating the WAL past the records before the caller
+    /// has applied them.
+    fn log_indices(
+        &self,
+        records: impl FnOnce() -> Vec<WalRecord>,
+    ) -> Result<RwLockReadGuard<'_, ()>, String> {
+        let changes = self.changes.read().unwrap();
+        self.log(records)?;
+        Ok(changes)
+    }
+
+    /// Applies `change` to an index. With a data directory the change is made
+    /// to a copy that only replaces the index once the records built from it
+    /// are in the WAL.
+    fn change_index<T>(
+        &self,
+        index: &mut Arc<IndexData>,
+        change: impl FnOnce(&mut IndexData) -> Result<T, String>,
+        records: impl FnOnce(&IndexData, &mut T) -> Vec<WalRecord>,
+    ) -> Result<T, String> {
+        if self.persistence.is_none() {
+            // Copy-on-write: the index is only cloned while a reader still
+            // holds a snapshot of it.
+            return change(Arc::make_mut(index));
+        }
+        let mut data = IndexData::clone(index);
+        let mut outcome = change(&mut data)?;
+        self.log(|| records(&data, &mut outcome))?;
+        *index = Arc::new(data);
+        Ok(outcome)
+    }
+
+    /// Applies `change` to a copy of a table and only replaces the table once
+    /// the records built from the copy are in the WAL.
+    fn change_table<T: Clone, R>(
+        &self,
+        table: &mut T,
+        change: impl FnOnce(&mut T) -> R,
+        records: impl FnOnce(&T) -> Vec<WalRecord>,
+    ) -> Result<R, String> {
+        let mut updated = table.clone();
+        let outcome = change(&mut updated);
+        self.log(|| records(&updated))?;
+        *table = updated;
+        Ok(outcome)
+    }
+
+    pub fn create_index(&self, name: String, mapping: Mapping) -> Result<(), String> {
+        self.create_index_with(
+            name,
+            IndexDefinition {
+                mapping: Some(mapping),
+                ..IndexDefinition::default()
+            },
+        )
+    }
+
+    /// Creates an index from the matching index template (if any) with the
+    /// explicit definition layered on top of it.
+    pub fn create_index_with(
+        &self,
+        name: String,
+        definition: IndexDefinition,
+    ) -> Result<(), String> {
+        if let Some(template) = self.matching_template(&name)
+            && template.data_stream.is_some()
+        {
+            return Err(format!(
+                "cannot create index with name [{}], because it matches with a template that \
+                 creates data streams only, use create data stream api instead",
+                name
+            ));
+        }
+
+        let mut composed = self.compose_template(&name).unwrap_or_default();
+        composed.settings.merge(&definition.settings);
+        if let Some(mapping) = definition.mapping {
+            match &mut composed.mappings {
+                Some(existing) => existing.update(mapping),
+                None => composed.mappings = Some(mapping),
+            }
+        }
+        composed.aliases.extend(definition.aliases);
+        self.insert_index(name, composed)
+    }
+
+    fn insert_index(&self, name: String, mut composed: ComposedTemplate) -> Result<(), String> {
+        composed
+            .settings
+            .set("creation_date", &now_millis().to_string());
+        composed
+            .settings
+            .set("uuid", &uuid::Uuid::new_v4().simple().to_string());
+
+        let index_data = IndexData::new(composed.mappings.unwrap_or_default(), composed.settings);
+        let changes = self.log_indices(|| {
+            vec![WalRecord::CreateIndex {
+                index: name.clone(),
+                mapping: index_data.mapping.clone(),
+                settings: index_data.settings.clone(),
+            }]
+        })?;
+        self.indices.insert(name.clone(), Arc::new(index_data));
+        drop(changes);
+
+        if composed.aliases.is_empty() {
+            return Ok(());
+        }
+        let actions = composed
+            .aliases
+            .into_iter()
+            .map(|(alias, definition)| AliasAction::Add {
+                index: name.clone(),
+                alias,
+                definition,
+            })
+            .collect();
+        if let Err(e) = self.update_aliases(actions) {
+            self.discard_index(&name)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Drops an index created by a request that failed afterwards.
+    fn discard_index(&self, name: &str) -> Result<(), String> {
+        let _changes = self.log_indices(|| {
+            vec![WalRecord::DeleteIndex {
+                index: name.to_string(),
+            }]
+        })?;
+        self.indices.remove(name);
+        Ok(())
+    }
+
+    /// Auto-creates a missing write target from templates, as happens when a
+    /// document is written to an index that does not exist yet. Templates
+    /// with `data_stream` enabled create a data stream instead of an index.
+    pub fn ensure_index(&self, name: &str) -> Result<(), String> {
+        if self.indices.contains_key(name) || self.is_alias(name) || self.is_data_stream(name) {
+            return Ok(());
+        }
+        if self
+            .matching_template(name)
+            .is_some_and(|t| t.data_stream.is_some())
+        {
+            return self.create_data_stream(name);
+        }
+        self.create_index_with(name.to_string(), IndexDefinition::default())
+    }
+
+    pub fn is_data_stream(&self, name: &str) -> bool {
+        self.data_streams.read().unwrap().contains_key(name)
+    }
+
+    pub fn data_streams(&self) -> HashMap<String, DataStream> {
+        self.data_streams.read().unwrap().clone()
+    }
+
+    fn owning_data_stream(&self, index: &str) -> Option<String> {
+        self.data_streams
+            .read()
+            .unwrap()
+            .values()
+            .find(|stream| stream.indices.iter().any(|i| i == index))
+            .map(|stream| stream.name.clone())
+    }
+
+    pub fn create_data_stream(&self, name: &str) -> Result<(), String> {
+        let aliases = self.aliases.read().unwrap();
+        let mut streams = self.data_streams.write().unwrap();
+        if streams.contains_key(name) {
+            return Err(format!("data_stream [{}] already exists", name));
+        }
+        if self.indices.contains_key(name) || aliases.contains_key(name) {
+            return Err(format!(
+                "data stream [{}] conflicts with existing index or alias",
+                name
+            ));
+        }
+        let template_name = self
+            .index_templates
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, template)| template.matches(name))
+            .max_by_key(|(_, template)| template.priority())
+            .filter(|(_, template)| template.data_stream.is_some())
+            .map(|(template_name, _)| template_name.clone())
+            .ok_or_else(|| {
+                format!(
+                    "no matching index template found for data stream [{}]",
+                    name
+                )
+            })?;
+
+        let backing_index = DataStream::backing_index_name(name, 1, now_millis());
+        self.create_backing_index(name, &backing_index)?;
+        let stream = DataStream {
+            name: name.to_string(),
+            template: template_name,
+            generation: 1,
+            indices: vec![backing_index.clone()],
+        };
+        let added = self.change_table(
+            &mut *streams,
+            |streams| {
+                streams.insert(name.to_string(), stream);
+            },
+            |streams| {
+                vec![WalRecord::DataStreams {
+                    data_streams: streams.clone(),
+                }]
+            },
+        );
+        if let Err(e) = added {
+            self.discard_index(&backing_index)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn create_backing_index(&self, stream: &str, index: &str) -> Result<(), String> {
+        let mut composed = self.compose_template(stream).unwrap_or_default();
+        composed.aliases.clear();
+        composed.settings.set("hidden", "true");
+        self.insert_index(index.to_string(), composed)
+    }
+
+    pub fn delete_data_stream(&self, name: &str) -> Result<bool, String> {
+        let mut streams = self.data_streams.write().unwrap();
+        let Some(stream) = streams.get(name).cloned() else {
+            return Ok(false);
+        };
+        // The backing indices are logged with the table but removed after it.
+        let _changes = self.changes.read().unwrap();
+        self.change_table(
+            &mut *streams,
+            |streams| {
+                streams.remove(name);
+            },
+            |streams| {
+                let mut records: Vec<WalRecord> = stream
+                    .indices
+                    .iter()
+                    .map(|index| WalRecord::DeleteIndex {
+                        index: index.clone(),
+                    })
+                    .collect();
+                records.push(WalRecord::DataStreams {
+                    data_streams: streams.clone(),
+                });
+                records
+            },
+        )?;
+        for index in &stream.indices {
+            self.indices.remove(index);
+        }
+        Ok(true)
+    }
+
+    /// Rolls a data stream or an alias with a write index over to a new write
+    /// index when any of the conditions is met (or unconditionally when there
+    /// are none).
+    pub fn rollover(
+        &self,
+        target: &str,
+        new_index: Option<String>,
+        conditions: &RolloverConditions,
+        dry_run: bool,
+    ) -> Result<RolloverOutcome, String> {
+        if self.is_data_stream(target) {
+            return self.rollover_data_stream(target, conditions, dry_run);
+        }
+        if !self.is_alias(target) {
+            return Err(format!(
+                "rollover target [{}] does not point to an alias or data stream",
+                target
+            ));
+        }
+
+        let old_index = self.resolve_write_index(target)?;
+        let new_index = match new_index {
+            Some(name) => name,
+            None => next_rollover_name(&old_index).ok_or_else(|| {
+                format!(
+                    "index name [{}] does not match pattern '^.*-\\d+$'",
+                    old_index
+                )
+            })?,
+        };
+        let evaluated = self.evaluate_conditions(&old_index, conditions);
+        let rolled_over = Self::conditions_met(&evaluated);
+
+        if rolled_over && !dry_run {
+            if self.indices.contains_key(&new_index) {
+                return Err(format!("index [{}] already exists", new_index));
+            }
+            // The alias may have moved since the write index was resolved.
+            let definition = self
+                .aliases
+                .read()
+                .unwrap()
+                .get(target)
+                .and_then(|indices| indices.get(&old_index))
+                .cloned()
+                .ok_or_else(|| {
+                    format!(
+                        "alias [{}] no longer points to write index [{}]",
+                        target, old_index
+                    )
+                })?;
+            self.create_index_with(new_index.clone(), IndexDefinition::default())?;
+
+            let mut actions = Vec::new();
+            if definition.is_write_index == Some(true) {
+                actions.push(AliasAction::Add {
+                    index: old_index.clone(),
+                    alias: target.to_string(),
+                    definition: AliasDefinition {
+                        is_write_index: Some(false),
+                        ..definition.clone()
+                    },
+                });
+            } else {
+                actions.push(AliasAction::Remove {
+                    index: old_index.clone(),
+                    alias: target.to_string(),
+                });
+            }
+            actions.push(AliasAction::Add {
+                index: new_index.clone(),
+                alias: target.to_string(),
+                definition,
+            });
+            self.update_aliases(actions)?;
+        }
+
+        Ok(RolloverOutcome {
+            old_index,
+            new_index,
+            rolled_over: rolled_over && !dry_run,
+            conditions: evaluated,
+        })
+    }
+
+    fn rollover_data_stream(
+        &self,
+        name: &str,
+        conditions: &RolloverConditions,
+        dry_run: bool,
+    ) -> Result<RolloverOutcome, String> {
+        let mut streams = self.data_streams.write().unwrap();
+        let stream = streams
+            .get(name)
+            .ok_or_else(|| format!("data stream [{}] does not exist", name))?;
+        let old_index = stream.write_index().unwrap_or_default().to_string();
+        let new_index = DataStream::backing_index_name(name, stream.generation + 1, now_millis());
+        let evaluated = self.evaluate_conditions(&old_index, conditions);
+        let rolled_over = Self::conditions_met(&evaluated);
+
+        if rolled_over && !dry_run {
+            self.create_backing_index(name, &new_index)?;
+            let rolled = self.change_table(
+                &mut *streams,
+                |streams| {
+                    if let Some(stream) = streams.get_mut(name) {
+                        stream.generation += 1;
+                        stream.indices.push(new_index.clone());
+                    }
+                },
+                |streams| {
+                    vec![WalRecord::DataStreams {
+                        data_streams: streams.clone(),
+                    }]
+                },
+            );
+            if let Err(e) = rolled {
+                self.discard_index(&new_index)?;
+                return Err(e);
+            }
+        }
+
+        Ok(RolloverOutcome {
+            old_index,
+            new_index,
+            rolled_over: rolled_over && !dry_run,
+            conditions: evaluated,
+        })
+    }
+
+    fn evaluate_conditions(
+        &self,
+        index: &str,
+        conditions: &RolloverConditions,
+    ) -> Vec<(String, bool)> {
+        let (doc_count, created) = self
+            .get_index(index)
+            .map(|data| {
+                let created = data
+                    .settings
+                    .get("creation_date")
+                    .and_then(|c| c.parse::<u64>().ok())
+                    .unwrap_or(0);
+                (data.documents.len() as u64, created)
+            })
+            .unwrap_or((0, 0));
+        conditions.evaluate(doc_count, now_millis().saturating_sub(created))
+    }
+
+    fn conditions_met(evaluated: &[(String, bool)]) -> bool {
+        evaluated.is_empty() || evaluated.iter().any(|(_, met)| *met)
+    }
+
+    fn compose_template(&self, index: &str) -> Option<ComposedTemplate> {
+        let template = self.matching_template(index)?;
+        let components = self.component_templates.read().unwrap();
+        let mut composed = ComposedTemplate::default();
+        for name in &template.composed_of {
+            if let Some(component) = components.get(name) {
+                composed.apply(&component.template);
+            }
+        }
+        if let Some(body) = &template.template {
+            composed.apply(body);
+        }
+        Some(composed)
+    }
+
+    pub fn matching_template(&self, index: &str) -> Option<IndexTemplate> {
+        self.index_templates
+            .read()
+            .unwrap()
+            .values()
+            .filter(|template| template.matches(index))
+            .max_by_key(|template| template.priority())
+            .cloned()
+    }
+
+    pub fn index_templates(&self) -> HashMap<String, IndexTemplate> {
+        self.index_templates.read().unwrap().clone()
+    }
+
+    pub fn put_pipeline(&self, id: &str, pipeline: Pipeline) -> Result<(), String> {
+        let mut pipelines = self.pipelines.write().unwrap();
+        self.change_table(
+            &mut *pipelines,
+            |pipelines| {
+                pipelines.insert(id.to_string(), pipeline);
+            },
+            |pipelines| vec![Self::pipelines_record(pipelines)],
+        )
+    }
+
+    fn pipelines_record(pipelines: &HashMap<String, Pipeline>) -> WalRecord {
+        WalRecord::Pipelines {
+            pipelines: pipelines
+                .iter()
+                .map(|(id, pipeline)| (id.clone(), pipeline.definition.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn pipelines(&self) -> HashMap<String, Pipeline> {
+        self.pipelines.read().unwrap().clone()
+    }
+
+    pub fn pipeline(&self, id: &str) -> Option<Pipeline> {
+        self.pipelines.read().unwrap().get(id).cloned()
+    }
+
+    pub fn delete_pipeline(&self, id: &str) -> Result<bool, String> {
+        let mut pipelines = self.pipelines.write().unwrap();
+        if !pipelines.contains_key(id) {
+            return Ok(false);
+        }
+        self.change_table(
+            &mut *pipelines,
+            |pipelines| {
+                pipelines.remove(id);
+            },
+            |pipelines| vec![Self::pipelines_record(pipelines)],
+        )?;
+        Ok(true)
+    }
+
+    pub fn put_snapshot_repository(
+        &self,
+        name: &str,
+        definition: RepositoryDefinition,
+    ) -> Result<(), String> {
+        let mut repositories = self.snapshot_repositories.write().unwrap();
+        self.change_table(
+            &mut *repositories,
+            |repositories| {
+                repositories.insert(name.to_string(), definition);
+            },
+            |repositories| {
+                vec![WalRecord::SnapshotRepositories {
+                    repositories: repositories.clone(),
+                }]
+            },
+        )
+    }
+
+    pub fn snapshot_repositories(&self) -> HashMap<String, RepositoryDefinition> {
+        self.snapshot_repositories.read().unwrap().clone()
+    }
+
+    pub fn snapshot_repository(&self, name: &str) -> Option<RepositoryDefinition> {
+        self.snapshot_repositories
+            .read()
+            .unwrap()
+            .get(name)
+            .cloned()
+    }
+
+    pub fn delete_snapshot_repository(&self, name: &str) -> Result<bool, String> {
+        let mut repositories = self.snapshot_repositories.write().unwrap();
+        if !repositories.contains_key(name) {
+            return Ok(false);
+        }
+        self.change_table(
+            &mut *repositories,
+            |repositories| {
+                repositories.remove(name);
+            },
+            |repositories| {
+                vec![WalRecord::SnapshotRepositories {
+                    repositories: repositories.clone(),
+                }]
+            },
+        )?;
+        Ok(true)
+    }
+
+    /// Adds indices and data streams restored from a snapshot. Nothing is
+    /// restored when one of the names is already taken.
+    pub fn restore_indices(
+        &self,
+        indices: Vec<(String, IndexState)>,
+        data_streams: Vec<DataStream>,
+    ) -> Result<(), String> {
+        let aliases = self.aliases.read().unwrap();
+        let mut streams = self.data_streams.write().unwrap();
+        if let Some((name, _)) = indices
+            .iter()
+            .find(|(name, _)| self.indices.contains_key(name) || aliases.contains_key(name))
+        {
+            return Err(format!(
+                "cannot restore index [{}] because an open index with same name already exists \
+                 in the cluster. Either close or delete the existing index or restore the index \
+                 under a different name by providing a rename pattern and replacement name",
+                name
+            ));
+        }
+        if let Some(stream) = data_streams.iter().find(|s| streams.contains_key(&s.name)) {
+            return Err(format!(
+                "cannot restore data stream [{}] because a data stream with the same name \
+                 already exists in the cluster",
+                stream.name
+            ));
+        }
+
+        for (name, state) in indices {
+            let data = IndexData::from_state(state);
+            let _changes = self.log_indices(|| data.records(&name))?;
+            self.indices.insert(name, Arc::new(data));
+        }
+        if !data_streams.is_empty() {
+            self.change_table(
+                &mut *streams,
+                |streams| streams.extend(data_streams.into_iter().map(|s| (s.name.clone(), s))),
+                |streams| {
+                    vec![WalRecord::DataStreams {
+                        data_streams: streams.clone(),
+                    }]
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn put_index_template(&self, name: &str, template: IndexTemplate) -> Result<(), String> {
+        let mut templates = self.index_templates.write().unwrap();
+        let mut conflicting: Vec<&String> = templates
+            .iter()
+            .filter(|(other_name, other)| {
+                other_name.as_str() != name
+                    && other.priority() == template.priority()
+                    && other.overlaps(&template)
+            })
+            .map(|(other_name, _)| other_name)
+            .collect();
+        if !conflicting.is_empty() {
+            conflicting.sort();
+            let names: Vec<&str> = conflicting.iter().map(|s| s.as_str()).collect();
+            return Err(format!(
+                "index template [{}] has index patterns {:?} matching patterns from existing \
+                 templates [{}] that have the same priority [{}], multiple index templates may \
+                 not match during index creation, please use a different priority",
+                name,
+                template.index_patterns,
+                names.join(","),
+                template.priority()
+            ));
+        }
+        self.change_table(
+            &mut *templates,
+            |templates| {
+                templates.insert(name.to_string(), template);
+            },
+            |templates| {
+                vec![WalRecord::IndexTemplates {
+                    templates: templates.clone(),
+                }]
+            },
+        )
+    }
+
+    pub fn delete_index_template(&self, name: &str) -> Result<bool, String> {
+        let mut templates = self.index_templates.write().unwrap();
+        if !templates.contains_key(name) {
+            return Ok(false);
+        }
+        self.change_table(
+            &mut *templates,
+            |templates| {
+                templates.remove(name);
+            },
+            |templates| {
+                vec![WalRecord::IndexTemplates {
+                    templates: templates.clone(),
+                }]
+            },
+        )?;
+        Ok(true)
+    }
+
+    pub fn component_templates(&self) -> HashMap<String, ComponentTemplate> {
+        self.component_templates.read().unwrap().clone()
+    }
+
+    pub fn put_component_template(
+        &self,
+        name: &str,
+        template: ComponentTemplate,
+    ) -> Result<(), String> {
+        let mut templates = self.component_templates.write().unwrap();
+        self.change_table(
+            &mut *templates,
+            |templates| {
+                templates.insert(name.to_string(), template);
+            },
+            |templates| {
+                vec![WalRecord::ComponentTemplates {
+                    templates: templates.clone(),
+                }]
+            },
+        )
+    }
+
+    /// Removes a component template unless an index template still refers to
+    /// it.
+    pub fn delete_component_template(&self, name: &str) -> Result<bool, String> {
+        let index_templates = self.index_templates.read().unwrap();
+        let mut users: Vec<&str> = index_templates
+            .iter()
+            .filter(|(_, template)| template.composed_of.iter().any(|c| c == name))
+            .map(|(template_name, _)| template_name.as_str())
+            .collect();
+        if !users.is_empty() {
+            users.sort();
+            return Err(format!(
+                "component templates [{}] cannot be removed as they are still in use by index \
+                 templates [{}]",
+                name,
+                users.join(", ")
+            ));
+        }
+        let mut templates = self.component_templates.write().unwrap();
+        if !templates.contains_key(name) {
+            return Ok(false);
+        }
+        self.change_table(
+            &mut *templates,
+            |templates| {
+                templates.remove(name);
+            },
+            |templates| {
+                vec![WalRecord::ComponentTemplates {
+                    templates: templates.clone(),
+                }]
+            },
+        )?;
+        Ok(true)
     }
 
     pub fn update_mapping(&self, name: &str, new_mapping: Mapping) -> Result<(), String> {
@@ -36,15 +1235,203 @@ impl InMemoryStore {
 
         let current_data = index_ref.value();
         let mut new_data = (**current_data).clone();
-        
+
         new_data.mapping.update(new_mapping);
-        
+        new_data.documents.set_mapping(&new_data.mapping);
+        self.log(|| {
+            vec![WalRecord::PutMapping {
+                index: name.to_string(),
+                mapping: new_data.mapping.clone(),
+            }]
+        })?;
+
         *index_ref.value_mut() = Arc::new(new_data);
         Ok(())
     }
 
-    pub fn delete_index(&self, name: &str) -> bool {
-        self.indices.remove(name).is_some()
+    pub fn delete_index(&self, name: &str) -> Result<bool, String> {
+        let mut aliases = self.aliases.write().unwrap();
+        let mut streams = self.data_streams.write().unwrap();
+        if !self.indices.contains_key(name) {
+            return Ok(false);
+        }
+        let mut remaining_aliases = aliases.clone();
+        Self::remove_index_from_aliases(&mut remaining_aliases, name);
+        let mut remaining_streams = streams.clone();
+        for stream in remaining_streams.values_mut() {
+            stream.indices.retain(|index| index != name);
+        }
+        remaining_streams.retain(|_, stream| !stream.indices.is_empty());
+        let _changes = self.log_indices(|| {
+            vec![
+                WalRecord::DeleteIndex {
+                    index: name.to_string(),
+                },
+                WalRecord::Aliases {
+                    aliases: remaining_aliases.clone(),
+                },
+                WalRecord::DataStreams {
+                    data_streams: remaining_streams.clone(),
+                },
+            ]
+        })?;
+        let removed = self.indices.remove(name).is_some();
+        *aliases = remaining_aliases;
+        *streams = remaining_streams;
+        Ok(removed)
+    }
+
+    fn remove_index_from_aliases(aliases: &mut AliasTable, index: &str) {
+        for members in aliases.values_mut() {
+            members.remove(index);
+        }
+        aliases.retain(|_, members| !members.is_empty());
+    }
+
+    pub fn is_alias(&self, name: &str) -> bool {
+        self.aliases.read().unwrap().contains_key(name)
+    }
+
+    pub fn aliases(&self) -> AliasTable {
+        self.aliases.read().unwrap().clone()
+    }
+
+    /// Applies all actions as a single atomic change: either every action
+    /// takes effect or, if the resulting alias table is invalid, none does.
+    pub fn update_aliases(&self, actions: Vec<AliasAction>) -> Result<(), String> {
+        let mut aliases = self.aliases.write().unwrap();
+        let mut updated = aliases.clone();
+        let mut removed_indices = Vec::new();
+
+        for action in actions {
+            match action {
+                AliasAction::Add {
+                    index,
+                    alias,
+                    definition,
+                } => {
+                    if !self.indices.contains_key(&index) {
+                        return Err(format!("no such index [{}]", index));
+                    }
+                    updated.entry(alias).or_default().insert(index, definition);
+                }
+                AliasAction::Remove { index, alias } => {
+                    if let Some(members) = updated.get_mut(&alias) {
+                        members.remove(&index);
+                    }
+                }
+                AliasAction::RemoveIndex { index } => {
+                    if !self.indices.contains_key(&index) {
+                        return Err(format!("no such index [{}]", index));
+                    }
+                    Self::remove_index_from_aliases(&mut updated, &index);
+                    removed_indices.push(index);
+                }
+            }
+        }
+        updated.retain(|_, members| !members.is_empty());
+
+        for (alias, members) in &updated {
+            if self.indices.contains_key(alias) {
+                return Err(format!(
+                    "an index or data stream exists with the same name as the alias [{}]",
+                    alias
+                ));
+            }
+            let mut write_indices: Vec<&String> = members
+                .iter()
+                .filter(|(_, def)| def.is_write_index == Some(true))
+                .map(|(index, _)| index)
+                .collect();
+            if write_indices.len() > 1 {
+                write_indices.sort();
+                let names: Vec<&str> = write_indices.iter().map(|s| s.as_str()).collect();
+                return Err(format!(
+                    "alias [{}] has more than one write index [{}]",
+                    alias,
+                    names.join(",")
+                ));
+            }
+        }
+
+        let _changes = self.log_indices(|| {
+            let mut records = vec![WalRecord::Aliases {
+                aliases: updated.clone(),
+            }];
+            records.extend(removed_indices.iter().map(|index| WalRecord::DeleteIndex {
+                index: index.clone(),
+            }));
+            records
+        })?;
+        *aliases = updated;
+        for index in removed_indices {
+            self.indices.remove(&index);
+        }
+        Ok(())
+    }
+
+    /// Resolves the concrete index a single-document write should go to. Names
+    /// that are neither an index nor an alias are returned unchanged so the
+    /// caller can auto-create them.
+    pub fn resolve_write_index(&self, name: &str) -> Result<String, String> {
+        self.resolve_write_target(name, None)
+            .map(|target| target.index)
+    }
+
+    /// Like [`Self::resolve_write_index`], also settling the routing of the
+    /// write: an alias with `index_routing` supplies it when the request has
+    /// none, and rejects a request asking for a different one.
+    pub fn resolve_write_target(
+        &self,
+        name: &str,
+        routing: Option<&str>,
+    ) -> Result<WriteTarget, String> {
+        let target = |index: &str| WriteTarget {
+            index: index.to_string(),
+            routing: routing.map(str::to_string),
+        };
+        if self.indices.contains_key(name) {
+            return Ok(target(name));
+        }
+        if let Some(stream) = self.data_streams.read().unwrap().get(name) {
+            return Ok(target(stream.write_index().unwrap_or(name)));
+        }
+        let aliases = self.aliases.read().unwrap();
+        let Some(members) = aliases.get(name) else {
+            return Ok(target(name));
+        };
+
+        let explicit = members
+            .iter()
+            .find(|(_, def)| def.is_write_index == Some(true));
+        let implicit = if members.len() == 1 {
+            members
+                .iter()
+                .find(|(_, def)| def.is_write_index != Some(false))
+        } else {
+            None
+        };
+        let (index, definition) = explicit.or(implicit).ok_or_else(|| {
+            format!(
+                "no write index is defined for alias [{}]. The write index may be explicitly \
+                 disabled using is_write_index=false or the alias points to multiple indices \
+                 without one being designated as a write index",
+                name
+            )
+        })?;
+
+        match (&definition.index_routing, routing) {
+            (Some(alias_routing), Some(routing)) if alias_routing != routing => Err(format!(
+                "Alias [{}] has index routing associated with it [{}], and was provided with \
+                 routing value [{}], rejecting operation",
+                name, alias_routing, routing
+            )),
+            (Some(alias_routing), _) => Ok(WriteTarget {
+                index: index.clone(),
+                routing: Some(alias_routing.clone()),
+            }),
+            (None, _) => Ok(target(index)),
+        }
     }
 
     pub fn refresh(&self, index_name: &str) -> Result<(), String> {
@@ -55,97 +1442,381 @@ impl InMemoryStore {
         }
     }
 
-    pub fn add_document(&self, index_name: &str, mut doc: Value) -> Result<String, String> {
+    pub fn add_document(&self, index_name: &str, source: Value) -> Result<String, String> {
+        self.write_document(index_name, None, source, None, &WriteConditions::default())
+            .map(|result| result.id)
+    }
+
+    /// Indexes a document under the given `_id` (or a generated one),
+    /// replacing any existing document with the same `_id` after checking
+    /// the concurrency control conditions against its current version.
+    pub fn write_document(
+        &self,
+        index_name: &str,
+        id: Option<&str>,
+        source: Value,
+        routing: Option<&str>,
+        conditions: &WriteConditions,
+    ) -> Result<WriteResult, String> {
+        let requires_timestamp = self.owning_data_stream(index_name).is_some();
         let mut index_ref = self
             .indices
             .get_mut(index_name)
             .ok_or_else(|| "index_not_found_exception".to_string())?;
-
-        let current_data = index_ref.value();
-
-        current_data
-            .mapping
-            .validate(&doc)
-            .map_err(|e| format!("Validation failed: {:?}", e))?;
-
-        let id = doc
-            .get("_id")
-            .and_then(|v| v.as_str())
+        let id = id
             .map(|s| s.to_string())
-            .unwrap_or_else(|| {
-                let new_id = uuid::Uuid::new_v4().to_string();
-                if let Some(obj) = doc.as_object_mut() {
-                    obj.insert("_id".to_string(), Value::String(new_id.clone()));
-                }
-                new_id
-            });
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        self.change_index(
+            index_ref.value_mut(),
+            |data| {
+                data.write(
+                    id,
+                    source,
+                    routing.map(|r| r.to_string()),
+                    conditions,
+                    requires_timestamp,
+                )
+            },
+            |data, written| {
+                data.put_record(index_name, &written.id)
+                    .into_iter()
+                    .collect()
+            },
+        )
+    }
 
-        let mut new_data = (**current_data).clone();
+    /// Applies a batch of writes to one index under a single lock, returning
+    /// the outcome of each write in order. Fails as a whole only when the
+    /// index does not exist.
+    pub fn apply_batch(
+        &self,
+        index_name: &str,
+        ops: Vec<BatchOp>,
+    ) -> Result<Vec<Result<BatchResult, String>>, String> {
+        let requires_timestamp = self.owning_data_stream(index_name).is_some();
+        let mut index_ref = self
+            .indices
+            .get_mut(index_name)
+            .ok_or_else(|| "index_not_found_exception".to_string())?;
+        let persistent = self.persistence.is_some();
+        let apply = |data: &mut IndexData| {
+            let mut results = Vec::with_capacity(ops.len());
+            let mut records = Vec::new();
+            for op in ops {
+                let result = match op {
+                    BatchOp::Index {
+                        id,
+                        source,
+                        routing,
+                        conditions,
+                    } => {
+                        let result =
+                            data.write(id, source, routing, &conditions, requires_timestamp);
+                        if persistent && let Ok(written) = &result {
+                            records.extend(data.put_record(index_name, &written.id));
+                        }
+                        result.map(BatchResult::Written)
+                    }
+                    BatchOp::Delete { id, conditions } => {
+                        let result = data.delete(&id, &conditions);
+                        if persistent && result.is_ok() {
+                            records.push(data.delete_record(index_name, id));
+                        }
+                        result.map(BatchResult::Deleted)
+                    }
+                };
+                results.push(result);
+            }
+            Ok((results, records))
+        };
+        let (results, _) = self.change_index(index_ref.value_mut(), apply, |_, (_, records)| {
+            std::mem::take(records)
+        })?;
+        Ok(results)
+    }
 
-        if let Some(pos) = new_data.documents.iter().position(|d| d["_id"] == id) {
-            new_data.documents[pos] = doc;
-        } else {
-            new_data.documents.push(doc);
+    /// Applies a partial update, indexing the upsert document when the target
+    /// does not exist and skipping the write when nothing would change. The
+    /// update is recomputed up to `retry_on_conflict` times when the document
+    /// changes concurrently.
+    pub fn update_document(
+        &self,
+        index_name: &str,
+        id: &str,
+        request: &UpdateRequest,
+        routing: Option<&str>,
+        conditions: &WriteConditions,
+    ) -> Result<UpdateResult, String> {
+        let mut retries = request.retry_on_conflict;
+        loop {
+            match self.try_update_document(index_name, id, request, routing, conditions) {
+                Err(e) if retries > 0 && is_version_conflict(&e) => retries -= 1,
+                result => return result,
+            }
         }
-
-        *index_ref.value_mut() = Arc::new(new_data);
-
-        Ok(id)
     }
 
-    pub fn patch_document(
+    fn try_update_document(
         &self,
         index_name: &str,
         id: &str,
-        patch: Value,
-    ) -> Result<String, String> {
-        let mut existing_doc = self
-            .get_document(index_name, id)
-            .ok_or_else(|| "document_missing_exception".to_string())?;
-
-        if let (Some(existing_obj), Some(patch_obj)) =
-            (existing_doc.as_object_mut(), patch.as_object())
-        {
-            for (k, v) in patch_obj {
-                existing_obj.insert(k.clone(), v.clone());
+        request: &UpdateRequest,
+        routing: Option<&str>,
+        conditions: &WriteConditions,
+    ) -> Result<UpdateResult, String> {
+        let index = self
+            .get_index(index_name)
+            .ok_or_else(|| "index_not_found_exception".to_string())?;
+        let Some(existing) = index.document(id).cloned() else {
+            let action = request
+                .apply_upsert(index_name, id)?
+                .ok_or_else(|| format!("[{}]: document missing", id))?;
+            let UpdateAction::Index(source) = action else {
+                return Ok(UpdateResult {
+                    id: id.to_string(),
+                    version: DocVersion {
+                        version: 0,
+                        seq_no: 0,
+                        primary_term: PRIMARY_TERM,
+                    },
+                    kind: UpdateKind::Noop,
+                    source: Value::Null,
+                });
+            };
+            // Another request may have created the document meanwhile.
+            let must_not_exist = WriteConditions {
+                create: true,
+                ..*conditions
+            };
+            let written = self.write_document(
+                index_name,
+                Some(id),
+                source.clone(),
+                routing,
+                &must_not_exist,
+            )?;
+            return Ok(UpdateResult {
+                id: written.id,
+                version: written.version,
+                kind: UpdateKind::Created,
+                source,
+            });
+        };
+
+        // The new source is computed from `existing` outside of the index
+        // lock, so the write only goes through if nothing changed meanwhile.
+        conditions.next_version(id, Some(existing.version))?;
+        let unchanged = WriteConditions {
+            if_seq_no: Some(existing.version.seq_no),
+            if_primary_term: Some(existing.version.primary_term),
+            ..*conditions
+        };
+        let source = match request.apply(index_name, &existing)? {
+            UpdateAction::Index(source) => source,
+            UpdateAction::Noop => {
+                return Ok(UpdateResult {
+                    id: existing.id,
+                    version: existing.version,
+                    kind: UpdateKind::Noop,
+                    source: existing.source,
+                });
             }
-        }
+            UpdateAction::Delete => {
+                let deleted = self.remove_document(index_name, id, &unchanged)?;
+                return Ok(UpdateResult {
+                    id: existing.id,
+                    version: deleted.version,
+                    kind: UpdateKind::Deleted,
+                    source: existing.source,
+                });
+            }
+        };
 
-        self.add_document(index_name, existing_doc)
+        let written = self.write_document(
+            index_name,
+            Some(id),
+            source.clone(),
+            existing.routing.as_deref(),
+            &unchanged,
+        )?;
+        Ok(UpdateResult {
+            id: written.id,
+            version: written.version,
+            kind: UpdateKind::Updated,
+            source,
+        })
     }
 
     pub fn get_document(&self, index_name: &str, id: &str) -> Option<Value> {
-        let index = self.get_index(index_name)?;
-        index.documents.iter().find(|d| d["_id"] == id).cloned()
+        self.get_stored_document(index_name, id)
+            .map(|doc| doc.source)
     }
 
-    pub fn delete_document(&self, index_name: &str, id: &str) -> bool {
-        let mut index_ref = match self.indices.get_mut(index_name) {
-            Some(r) => r,
-            None => return false,
-        };
+    pub fn get_stored_document(&self, index_name: &str, id: &str) -> Option<StoredDocument> {
+        self.get_index(index_name)?.document(id).cloned()
+    }
 
-        let mut new_data = (**index_ref.value()).clone();
-        let initial_len = new_data.documents.len();
-        new_data.documents.retain(|d| d["_id"] != id);
+    pub fn delete_document(&self, index_name: &str, id: &str) -> bool {
+        self.remove_document(index_name, id, &WriteConditions::default())
+            .is_ok_and(|removed| removed.found)
+    }
 
-        let deleted = new_data.documents.len() < initial_len;
-        *index_ref.value_mut() = Arc::new(new_data);
-        deleted
+    /// Deletes a document after checking the concurrency control conditions,
+    /// returning the version recorded for the delete.
+    pub fn remove_document(
+        &self,
+        index_name: &str,
+        id: &str,
+        conditions: &WriteConditions,
+    ) -> Result<DeleteResult, String> {
+        let mut index_ref = self
+            .indices
+            .get_mut(index_name)
+            .ok_or_else(|| "index_not_found_exception".to_string())?;
+        self.change_index(
+            index_ref.value_mut(),
+            |data| data.delete(id, conditions),
+            |data, _| vec![data.delete_record(index_name, id.to_string())],
+        )
     }
 
     pub fn get_index(&self, name: &str) -> Option<Arc<IndexData>> {
         self.indices.get(name).map(|r| Arc::clone(r.value()))
     }
+
+    pub fn index_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.indices.iter().map(|r| r.key().clone()).collect();
+        names.sort();
+        names
+    }
+
+    /// Resolves a comma-separated index expression (concrete names, aliases,
+    /// wildcards, `_all` and `-exclusions`) into concrete index targets. On
+    /// failure the name of the missing index or unmatched expression is
+    /// returned.
+    pub fn resolve_indices(
+        &self,
+        expression: &str,
+        options: IndicesOptions,
+    ) -> Result<Vec<IndexTarget>, String> {
+        let all_names = self.index_names();
+        let aliases = self.aliases.read().unwrap();
+        let streams = self.data_streams.read().unwrap();
+        let mut targets = TargetSet::default();
+
+        for (pos, part) in expression.split(',').map(|p| p.trim()).enumerate() {
+            if part.is_empty() {
+                continue;
+            }
+
+            if pos > 0
+                && let Some(excluded) = part.strip_prefix('-')
+            {
+                targets.exclude(excluded);
+                continue;
+            }
+
+            if part == "_all" || part.contains('*') {
+                let pattern = if part == "_all" { "*" } else { part };
+                let mut matched = false;
+                // Hidden indices (such as data stream backing indices) are only
+                // matched by patterns that explicitly start with a dot.
+                for name in all_names.iter().filter(|name| {
+                    wildcard_match(pattern, name)
+                        && (pattern.starts_with('.') || !self.is_hidden(name))
+                }) {
+                    targets.add(name, None);
+                    matched = true;
+                }
+                let mut stream_names: Vec<&String> = streams
+                    .keys()
+                    .filter(|stream| wildcard_match(pattern, stream))
+                    .collect();
+                stream_names.sort();
+                for stream in stream_names {
+                    for index in &streams[stream].indices {
+                        targets.add(index, None);
+                    }
+                    matched = true;
+                }
+                if part != "_all" {
+                    let mut alias_names: Vec<&String> = aliases
+                        .keys()
+                        .filter(|alias| wildcard_match(pattern, alias))
+                        .collect();
+                    alias_names.sort();
+                    for alias in alias_names {
+                        Self::add_alias_members(&mut targets, &aliases[alias]);
+                        matched = true;
+                    }
+                }
+                if !matched && !options.allow_no_indices {
+                    return Err(part.to_string());
+                }
+            } else if self.indices.contains_key(part) {
+                targets.add(part, None);
+            } else if let Some(members) = aliases.get(part) {
+                Self::add_alias_members(&mut targets, members);
+            } else if let Some(stream) = streams.get(part) {
+                for index in &stream.indices {
+                    targets.add(index, None);
+                }
+            } else if !options.ignore_unavailable {
+                return Err(part.to_string());
+            }
+        }
+
+        let resolved = targets.into_targets();
+        if resolved.is_empty() && !options.allow_no_indices {
+            return Err(expression.to_string());
+        }
+
+        Ok(resolved)
+    }
+
+    fn is_hidden(&self, index: &str) -> bool {
+        self.indices
+            .get(index)
+            .is_some_and(|data| data.settings.get("hidden") == Some("true"))
+    }
+
+    fn add_alias_members(targets: &mut TargetSet, members: &HashMap<String, AliasDefinition>) {
+        let mut indices: Vec<&String> = members.keys().collect();
+        indices.sort();
+        for index in indices {
+            let definition = &members[index];
+            // `search_routing` narrows the alias to documents with one of its
+            // routing values, on top of any filter.
+            let filter = match &definition.search_routing {
+                Some(routing) => {
+                    let routing: Vec<&str> = routing.split(',').map(str::trim).collect();
+                    let routing = json!({ "terms": { "_routing": routing } });
+                    Some(match &definition.filter {
+                        Some(filter) => json!({ "bool": { "filter": [filter, routing] } }),
+                        None => routing,
+                    })
+                }
+                None => definition.filter.clone(),
+            };
+            targets.add(index, filter.as_ref());
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::domain::mapping::{FieldType, Property};
+    use crate::repository::persistence::{FsyncPolicy, is_wal_failure};
     use serde_json::json;
     use std::collections::HashMap;
 
+    fn put_document(store: &InMemoryStore, index: &str, id: &str, source: Value) -> WriteResult {
+        store
+            .write_document(index, Some(id), source, None, &WriteConditions::default())
+            .unwrap()
+    }
+
     fn mock_mapping() -> Mapping {
         let mut properties = HashMap::new();
         properties.insert(
@@ -163,7 +1834,9 @@ mod tests {
     #[test]
     fn should_update_mapping_in_store() {
         let store = InMemoryStore::new();
-        store.create_index("test-index".to_string(), mock_mapping());
+        store
+            .create_index("test-index".to_string(), mock_mapping())
+            .unwrap();
 
         let mut new_props = HashMap::new();
         new_props.insert(
@@ -188,10 +1861,12 @@ mod tests {
     #[test]
     fn should_delete_index() {
         let store = InMemoryStore::new();
-        store.create_index("to-delete".to_string(), Mapping::default());
+        store
+            .create_index("to-delete".to_string(), Mapping::default())
+            .unwrap();
         assert!(store.get_index("to-delete").is_some());
 
-        let deleted = store.delete_index("to-delete");
+        let deleted = store.delete_index("to-delete").unwrap();
         assert!(deleted);
         assert!(store.get_index("to-delete").is_none());
     }
@@ -199,7 +1874,9 @@ mod tests {
     #[test]
     fn should_handle_refresh_as_noop() {
         let store = InMemoryStore::new();
-        store.create_index("refresh-me".to_string(), Mapping::default());
+        store
+            .create_index("refresh-me".to_string(), Mapping::default())
+            .unwrap();
         let result = store.refresh("refresh-me");
         assert!(result.is_ok());
     }
@@ -209,7 +1886,9 @@ mod tests {
         let store = InMemoryStore::new();
         let mapping = mock_mapping();
 
-        store.create_index("test-index".to_string(), mapping);
+        store
+            .create_index("test-index".to_string(), mapping)
+            .unwrap();
 
         assert!(store.get_index("test-index").is_some());
     }
@@ -217,7 +1896,9 @@ mod tests {
     #[test]
     fn should_reject_document_with_wrong_mapping() {
         let store = InMemoryStore::new();
-        store.create_index("test-index".to_string(), mock_mapping());
+        store
+            .create_index("test-index".to_string(), mock_mapping())
+            .unwrap();
 
         let invalid_doc = json!({ "id": "not-an-integer" });
         let result = store.add_document("test-index", invalid_doc);
@@ -228,7 +1909,9 @@ mod tests {
     #[test]
     fn should_accept_valid_document() {
         let store = InMemoryStore::new();
-        store.create_index("test-index".to_string(), mock_mapping());
+        store
+            .create_index("test-index".to_string(), mock_mapping())
+            .unwrap();
 
         let valid_doc = json!({ "id": 1 });
         let result = store.add_document("test-index", valid_doc);
@@ -240,7 +1923,9 @@ mod tests {
     #[test]
     fn should_accept_extra_fields_on_default_mapping() {
         let store = InMemoryStore::new();
-        store.create_index(".migrations".to_string(), Mapping::default());
+        store
+            .create_index(".migrations".to_string(), Mapping::default())
+            .unwrap();
 
         let doc = json!({
             "filename": "0001_init.json",
@@ -254,7 +1939,9 @@ mod tests {
     #[test]
     fn should_get_and_delete_document_by_id() {
         let store = InMemoryStore::new();
-        store.create_index("test".to_string(), Mapping::default());
+        store
+            .create_index("test".to_string(), Mapping::default())
+            .unwrap();
 
         let id = store.add_document("test", json!({"name": "doc1"})).unwrap();
 
@@ -266,29 +1953,317 @@ mod tests {
     #[test]
     fn should_update_existing_document_with_same_id() {
         let store = InMemoryStore::new();
-        store.create_index("test".to_string(), Mapping::default());
-
-        let doc = json!({"_id": "1", "val": "old"});
-        store.add_document("test", doc).unwrap();
+        store
+            .create_index("test".to_string(), Mapping::default())
+            .unwrap();
 
-        let new_doc = json!({"_id": "1", "val": "new"});
-        store.add_document("test", new_doc).unwrap();
+        put_document(&store, "test", "1", json!({"val": "old"}));
+        put_document(&store, "test", "1", json!({"val": "new"}));
 
         let stored = store.get_document("test", "1").unwrap();
-        assert_eq!(stored["val"], "new");
+        assert_eq!(stored, json!({"val": "new"}));
         assert_eq!(store.get_index("test").unwrap().documents.len(), 1);
     }
 
+    fn target_names(targets: Vec<IndexTarget>) -> Vec<String> {
+        targets.into_iter().map(|t| t.index).collect()
+    }
+
     #[test]
-    fn should_partially_update_document() {
+    fn should_resolve_index_expressions() {
+        let store = InMemoryStore::new();
+        for name in ["logs-old", "logs-new", "metrics"] {
+            store
+                .create_index(name.to_string(), Mapping::default())
+                .unwrap();
+        }
+        let options = IndicesOptions::default();
+
+        assert_eq!(
+            target_names(store.resolve_indices("logs-*", options).unwrap()),
+            vec!["logs-new", "logs-old"]
+        );
+        assert_eq!(
+            target_names(store.resolve_indices("logs-*,-logs-old", options).unwrap()),
+            vec!["logs-new"]
+        );
+        assert_eq!(store.resolve_indices("_all", options).unwrap().len(), 3);
+        assert_eq!(
+            store.resolve_indices("metrics,missing", options),
+            Err("missing".to_string())
+        );
+
+        let lenient = IndicesOptions {
+            ignore_unavailable: true,
+            allow_no_indices: true,
+        };
+        assert_eq!(
+            target_names(store.resolve_indices("metrics,missing", lenient).unwrap()),
+            vec!["metrics"]
+        );
+
+        let strict = IndicesOptions {
+            ignore_unavailable: false,
+            allow_no_indices: false,
+        };
+        assert!(store.resolve_indices("traces-*", strict).is_err());
+    }
+
+    #[test]
+    fn should_resolve_aliases_with_filters_and_write_index() {
+        let store = InMemoryStore::new();
+        store
+            .create_index("events-1".to_string(), Mapping::default())
+            .unwrap();
+        store
+            .create_index("events-2".to_string(), Mapping::default())
+            .unwrap();
+
+        let filter = json!({ "term": { "tenant": "a" } });
+        store
+            .update_aliases(vec![
+                AliasAction::Add {
+                    index: "events-1".to_string(),
+                    alias: "events".to_string(),
+                    definition: AliasDefinition::default(),
+                },
+                AliasAction::Add {
+                    index: "events-2".to_string(),
+                    alias: "events".to_string(),
+                    definition: AliasDefinition {
+                        filter: Some(filter.clone()),
+                        is_write_index: Some(true),
+                        ..AliasDefinition::default()
+                    },
+                },
+            ])
+            .unwrap();
+
+        let targets = store
+            .resolve_indices("events", IndicesOptions::default())
+            .unwrap();
+        assert_eq!(targets[0].filter, None);
+        assert_eq!(targets[1].filter, Some(filter));
+        assert_eq!(store.resolve_write_index("events").unwrap(), "events-2");
+
+        store.delete_index("events-2").unwrap();
+        assert_eq!(store.resolve_write_index("events").unwrap(), "events-1");
+    }
+
+    #[test]
+    fn should_apply_alias_routing_to_writes_and_searches() {
+        let store = InMemoryStore::new();
+        store
+            .create_index("events".to_string(), Mapping::default())
+            .unwrap();
+        store
+            .update_aliases(vec![AliasAction::Add {
+                index: "events".to_string(),
+                alias: "tenant-a".to_string(),
+                definition: AliasDefinition::from_json(&json!({
+                    "index_routing": "a",
+                    "search_routing": "a,b"
+                })),
+            }])
+            .unwrap();
+
+        let target = store.resolve_write_target("tenant-a", None).unwrap();
+        assert_eq!(target.index, "events");
+        assert_eq!(target.routing.as_deref(), Some("a"));
+        assert!(store.resolve_write_target("tenant-a", Some("a")).is_ok());
+        let error = store
+            .resolve_write_target("tenant-a", Some("c"))
+            .unwrap_err();
+        assert!(error.contains("rejecting operation"), "{}", error);
+        let direct = store.resolve_write_target("events", Some("c")).unwrap();
+        assert_eq!(direct.routing.as_deref(), Some("c"));
+
+        for (id, routing) in [("1", Some("a")), ("2", Some("c")), ("3", None)] {
+            store
+                .write_document(
+                    "events",
+                    Some(id),
+                    json!({}),
+                    routing,
+                    &WriteConditions::default(),
+                )
+                .unwrap();
+        }
+        let targets = store
+            .resolve_indices("tenant-a", IndicesOptions::default())
+            .unwrap();
+        let filter = crate::domain::query::parse_filter(targets[0].filter.as_ref().unwrap());
+        let index = store.get_index("events").unwrap();
+        let visible: Vec<&str> = ["1", "2", "3"]
+            .into_iter()
+            .filter(|id| filter.matches_document("events", index.document(id).unwrap()))
+            .collect();
+        assert_eq!(visible, vec!["1"]);
+    }
+
+    #[test]
+    fn should_not_deadlock_creating_data_streams_while_deleting_indices() {
+        let store = Arc::new(InMemoryStore::new());
+        let template = serde_json::from_value(json!({
+            "index_patterns": ["logs-*"],
+            "data_stream": {}
+        }))
+        .unwrap();
+        store.put_index_template("logs", template).unwrap();
+
+        let (done, finished) = std::sync::mpsc::channel();
+        for worker in 0..8 {
+            let store = store.clone();
+            let done = done.clone();
+            std::thread::spawn(move || {
+                for i in 0..1000 {
+                    if worker % 2 == 0 {
+                        let _ = store.create_data_stream(&format!("logs-{}-{}", worker, i));
+                    } else {
+                        let name = format!("orders-{}-{}", worker, i);
+                        let _ = store.create_index(name.clone(), Mapping::default());
+                        store.delete_index(&name).unwrap();
+                    }
+                }
+                done.send(()).unwrap();
+            });
+        }
+        for _ in 0..8 {
+            finished
+                .recv_timeout(std::time::Duration::from_secs(30))
+                .expect("store operations deadlocked");
+        }
+    }
+
+    #[test]
+    fn should_not_lose_concurrent_updates() {
+        let store = Arc::new(InMemoryStore::new());
+        store
+            .create_index("counters".to_string(), Mapping::default())
+            .unwrap();
+        let request = Arc::new(
+            UpdateRequest::from_json(&json!({
+                "script": "ctx._source.n += 1",
+                "upsert": { "n": 1 }
+            }))
+            .unwrap(),
+        );
+
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                let request = request.clone();
+                std::thread::spawn(move || {
+                    (0..50)
+                        .map(|_| {
+                            store.update_document(
+                                "counters",
+                                "1",
+                                &request,
+                                None,
+                                &WriteConditions::default(),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let results: Vec<_> = workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect();
+
+        let applied = results.iter().filter(|r| r.is_ok()).count();
+        let created = results
+            .iter()
+            .filter(|r| r.as_ref().is_ok_and(|u| u.kind == UpdateKind::Created))
+            .count();
+        assert_eq!(created, 1);
+        assert!(
+            results
+                .iter()
+                .filter_map(|r| r.as_ref().err())
+                .all(|e| is_version_conflict(e))
+        );
+        let counter = store.get_document("counters", "1").unwrap();
+        assert_eq!(counter["n"], applied);
+
+        let mut retried = (*request).clone();
+        retried.retry_on_conflict = 1_000;
+        let retried = Arc::new(retried);
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                let request = retried.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..50 {
+                        store
+                            .update_document(
+                                "counters",
+                                "1",
+                                &request,
+                                None,
+                                &WriteConditions::default(),
+                            )
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        let counter = store.get_document("counters", "1").unwrap();
+        assert_eq!(counter["n"], applied + 400);
+    }
+
+    #[test]
+    fn should_reject_alias_actions_atomically() {
         let store = InMemoryStore::new();
-        store.create_index("test".to_string(), Mapping::default());
+        store
+            .create_index("a".to_string(), Mapping::default())
+            .unwrap();
+        store
+            .create_index("b".to_string(), Mapping::default())
+            .unwrap();
+
+        let write = AliasDefinition {
+            is_write_index: Some(true),
+            ..AliasDefinition::default()
+        };
+        let result = store.update_aliases(vec![
+            AliasAction::Add {
+                index: "a".to_string(),
+                alias: "both".to_string(),
+                definition: write.clone(),
+            },
+            AliasAction::Add {
+                index: "b".to_string(),
+                alias: "both".to_string(),
+                definition: write,
+            },
+        ]);
+
+        assert!(result.is_err());
+        assert!(!store.is_alias("both"));
+    }
 
+    #[test]
+    fn should_partially_update_document() {
+        let store = InMemoryStore::new();
         store
-            .add_document("test", json!({"_id": "1", "a": 1, "b": 2}))
+            .create_index("test".to_string(), Mapping::default())
             .unwrap();
+
+        put_document(&store, "test", "1", json!({"a": 1, "b": 2}));
         store
-            .patch_document("test", "1", json!({"b": 3, "c": 4}))
+            .update_document(
+                "test",
+                "1",
+                &UpdateRequest::from_json(&json!({"doc": {"b": 3, "c": 4}})).unwrap(),
+                None,
+                &WriteConditions::default(),
+            )
             .unwrap();
 
         let doc = store.get_document("test", "1").unwrap();
@@ -296,4 +2271,262 @@ mod tests {
         assert_eq!(doc["b"], 3);
         assert_eq!(doc["c"], 4);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_upsert_and_detect_noop_updates() {
+        let store = InMemoryStore::new();
+        store
+            .create_index("test".to_string(), Mapping::default())
+            .unwrap();
+        let conditions = WriteConditions::default();
+        let request = UpdateRequest::from_json(&json!({
+            "doc": {"user": {"name": "a"}},
+            "upsert": {"user": {"name": "init"}}
+        }))
+        .unwrap();
+
+        let created = store
+            .update_document("test", "1", &request, None, &conditions)
+            .unwrap();
+        assert_eq!(created.kind, UpdateKind::Created);
+        assert_eq!(created.source, json!({"user": {"name": "init"}}));
+
+        let updated = store
+            .update_document("test", "1", &request, None, &conditions)
+            .unwrap();
+        assert_eq!(updated.kind, UpdateKind::Updated);
+
+        let noop = store
+            .update_document("test", "1", &request, None, &conditions)
+            .unwrap();
+        assert_eq!(noop.kind, UpdateKind::Noop);
+        assert_eq!(noop.version, updated.version);
+
+        let missing = UpdateRequest::from_json(&json!({"doc": {"a": 1}})).unwrap();
+        let result = store.update_document("test", "2", &missing, None, &conditions);
+        assert_eq!(result.unwrap_err(), "[2]: document missing");
+    }
+
+    #[test]
+    fn should_track_versions_and_sequence_numbers() {
+        let store = InMemoryStore::new();
+        store
+            .create_index("test".to_string(), Mapping::default())
+            .unwrap();
+
+        let conditions = WriteConditions::default();
+        let first = put_document(&store, "test", "1", json!({}));
+        let second = put_document(&store, "test", "1", json!({}));
+        put_document(&store, "test", "2", json!({}));
+
+        assert_eq!((first.version.version, first.version.seq_no), (1, 0));
+        assert_eq!((second.version.version, second.version.seq_no), (2, 1));
+
+        let stale = WriteConditions {
+            if_seq_no: Some(0),
+            if_primary_term: Some(PRIMARY_TERM),
+            ..Default::default()
+        };
+        assert!(store.remove_document("test", "1", &stale).is_err());
+
+        let deleted = store.remove_document("test", "1", &conditions).unwrap();
+        assert_eq!((deleted.version.version, deleted.version.seq_no), (3, 3));
+        assert!(store.get_stored_document("test", "1").is_none());
+
+        let missing = store.remove_document("test", "1", &conditions).unwrap();
+        assert!(!missing.found);
+        assert_eq!((missing.version.version, missing.version.seq_no), (1, 4));
+    }
+
+    #[test]
+    fn should_keep_metadata_out_of_source() {
+        let store = InMemoryStore::new();
+        store
+            .create_index("test".to_string(), Mapping::default())
+            .unwrap();
+
+        store
+            .write_document(
+                "test",
+                Some("1"),
+                json!({"title": "a"}),
+                Some("user-1"),
+                &WriteConditions::default(),
+            )
+            .unwrap();
+        let stored = store.get_stored_document("test", "1").unwrap();
+        assert_eq!(stored.source, json!({"title": "a"}));
+        assert_eq!(stored.routing.as_deref(), Some("user-1"));
+
+        let result = store.add_document("test", json!({"_id": "2"}));
+        assert!(result.unwrap_err().contains("metadata field"));
+    }
+
+    fn persistent_store(dir: &std::path::Path) -> Result<InMemoryStore, String> {
+        InMemoryStore::open(PersistenceOptions {
+            data_dir: dir.to_path_buf(),
+            fsync: FsyncPolicy::Never,
+        })
+    }
+
+    fn wal_files(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+        let mut files: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn should_recover_store_from_data_directory() {
+        let dir = std::env::temp_dir().join(format!("es_fake-{}", uuid::Uuid::new_v4()));
+        {
+            let store = persistent_store(&dir).unwrap();
+            store
+                .create_index("test".to_string(), mock_mapping())
+                .unwrap();
+            put_document(&store, "test", "1", json!({"id": 1}));
+            put_document(&store, "test", "2", json!({"id": 2}));
+            store
+                .remove_document("test", "2", &WriteConditions::default())
+                .unwrap();
+            store
+                .update_aliases(vec![AliasAction::Add {
+                    index: "test".to_string(),
+                    alias: "current".to_string(),
+                    definition: AliasDefinition::default(),
+                }])
+                .unwrap();
+            let pipeline = Pipeline::from_json(&json!({ "processors": [] })).unwrap();
+            store.put_pipeline("noop", pipeline).unwrap();
+        }
+
+        // The first reopen replays the WAL, the second reads the snapshot it
+        // was compacted into.
+        for seq_no in [3, 5] {
+            let store = persistent_store(&dir).unwrap();
+            assert_eq!(store.get_document("test", "1"), Some(json!({"id": 1})));
+            assert_eq!(store.resolve_write_index("current").unwrap(), "test");
+            assert!(store.get_document("test", "2").is_none());
+            assert!(store.pipeline("noop").is_some());
+            assert_eq!(wal_files(&dir).len(), 1);
+
+            let next = put_document(&store, "test", "3", json!({"id": 3}));
+            assert_eq!(next.version.seq_no, seq_no);
+            store.delete_document("test", "3");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn should_reject_changes_the_wal_cannot_take() {
+        let dir = std::env::temp_dir().join(format!("es_fake-{}", uuid::Uuid::new_v4()));
+        {
+            let store = persistent_store(&dir).unwrap();
+            store
+                .create_index("test".to_string(), Mapping::default())
+                .unwrap();
+            put_document(&store, "test", "1", json!({"a": 1}));
+            store.fail_wal_appends();
+
+            let written = store.write_document(
+                "test",
+                Some("2"),
+                json!({"a": 2}),
+                None,
+                &WriteConditions::default(),
+            );
+            assert!(is_wal_failure(&written.unwrap_err()));
+            let removed = store.remove_document("test", "1", &WriteConditions::default());
+            assert!(is_wal_failure(&removed.unwrap_err()));
+            let batch = vec![BatchOp::Delete {
+                id: "1".to_string(),
+                conditions: WriteConditions::default(),
+            }];
+            let applied = store.apply_batch("test", batch).err().unwrap();
+            assert!(is_wal_failure(&applied));
+            let aliased = store.update_aliases(vec![AliasAction::Add {
+                index: "test".to_string(),
+                alias: "current".to_string(),
+                definition: AliasDefinition::default(),
+            }]);
+            assert!(is_wal_failure(&aliased.unwrap_err()));
+            assert!(is_wal_failure(&store.delete_index("test").unwrap_err()));
+            let created = store.create_index("other".to_string(), Mapping::default());
+            assert!(is_wal_failure(&created.unwrap_err()));
+
+            assert_eq!(store.get_document("test", "1"), Some(json!({"a": 1})));
+            assert!(store.get_document("test", "2").is_none());
+            assert!(!store.is_alias("current"));
+            assert_eq!(store.index_names(), vec!["test"]);
+        }
+
+        let store = persistent_store(&dir).unwrap();
+        assert_eq!(store.get_document("test", "1"), Some(json!({"a": 1})));
+        assert!(store.get_document("test", "2").is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn should_keep_index_changes_made_during_compaction() {
+        let dir = std::env::temp_dir().join(format!("es_fake-{}", uuid::Uuid::new_v4()));
+        {
+            let store = persistent_store(&dir).unwrap();
+            let done = std::sync::atomic::AtomicBool::new(false);
+            std::thread::scope(|scope| {
+                let compacting = scope.spawn(|| {
+                    while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                        store.compact().unwrap();
+                    }
+                });
+                for i in 0..1000 {
+                    store
+                        .create_index(format!("kept-{}", i), Mapping::default())
+                        .unwrap();
+                    store
+                        .create_index(format!("dropped-{}", i), Mapping::default())
+                        .unwrap();
+                    assert!(store.delete_index(&format!("dropped-{}", i)).unwrap());
+                }
+                done.store(true, std::sync::atomic::Ordering::Relaxed);
+                compacting.join().unwrap();
+            });
+        }
+
+        let store = persistent_store(&dir).unwrap();
+        let mut names = store.index_names();
+        names.sort();
+        let mut expected: Vec<String> = (0..1000).map(|i| format!("kept-{}", i)).collect();
+        expected.sort();
+        assert_eq!(names, expected);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn should_drop_a_torn_record_at_the_end_of_the_wal() {
+        let dir = std::env::temp_dir().join(format!("es_fake-{}", uuid::Uuid::new_v4()));
+        {
+            let store = persistent_store(&dir).unwrap();
+            store
+                .create_index("test".to_string(), Mapping::default())
+                .unwrap();
+            put_document(&store, "test", "1", json!({"a": 1}));
+        }
+        let wal = wal_files(&dir).pop().unwrap();
+        let mut content = std::fs::read_to_string(&wal).unwrap();
+        content.push_str(r#"{"op":"put_document","index":"te"#);
+        std::fs::write(&wal, &content).unwrap();
+
+        let store = persistent_store(&dir).unwrap();
+        assert_eq!(store.get_document("test", "1"), Some(json!({"a": 1})));
+        drop(store);
+
+        let wal = wal_files(&dir).pop().unwrap();
+        std::fs::write(&wal, "not json\n{}\n").unwrap();
+        let error = persistent_store(&dir).err().unwrap();
+        assert!(error.contains("corrupt WAL record"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+use crate::domain::time::now_millis;
+use dashmap::DashMap;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A long-running operation such as `_update_by_query` or `_reindex`.
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: u64,
+    pub action: String,
+    pub description: String,
+    pub start_time_millis: u64,
+    pub running_time_nanos: u64,
+    pub cancelled: bool,
+    /// Progress reported by the running operation, if any.
+    pub status: Option<Value>,
+    /// The final response body once the task has completed.
+    pub response: Option<Value>,
+    /// Why the task failed instead of producing a response.
+    pub error: Option<Value>,
+}
+
+impl TaskInfo {
+    pub fn completed(&self) -> bool {
+        self.response.is_some() || self.error.is_some()
+    }
+
+    fn running_time_nanos(&self) -> u64 {
+        now_millis().saturating_sub(self.start_time_millis) * 1_000_000
+    }
+}
+
+/// Keeps track of tasks under `<node_id>:<id>` identifiers, the format
+/// Elasticsearch hands out from asynchronous requests.
+///
+/// Running tasks live in `running`; the results of tasks started with
+/// `wait_for_completion=false` are kept in `results` once they finish, the
+/// counterpart of the `.tasks` index, so they can still be fetched later.
+pub struct TaskRegistry {
+    node_id: String,
+    next_id: AtomicU64,
+    running: DashMap<u64, TaskInfo>,
+    results: DashMap<u64, TaskInfo>,
+}
+
+impl Default for TaskRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TaskRegistry {
+    pub fn new() -> Self {
+        Self {
+            node_id: uuid::Uuid::new_v4().simple().to_string()[..22].to_string(),
+            next_id: AtomicU64::new(1),
+            running: DashMap::new(),
+            results: DashMap::new(),
+        }
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    pub fn task_id(&self, id: u64) -> String {
+        format!("{}:{}", self.node_id, id)
+    }
+
+    /// Registers a running task and returns its numeric id.
+    pub fn register(&self, action: &str, description: String) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.running.insert(
+            id,
+            TaskInfo {
+                id,
+                action: action.to_string(),
+                description,
+                start_time_millis: now_millis(),
+                running_time_nanos: 0,
+                cancelled: false,
+                status: None,
+                response: None,
+                error: None,
+            },
+        );
+        id
+    }
+
+    pub fn set_status(&self, id: u64, status: Value) {
+        if let Some(mut task) = self.running.get_mut(&id) {
+            task.status = Some(status);
+        }
+    }
+
+    pub fn is_cancelled(&self, id: u64) -> bool {
+        self.running.get(&id).is_some_and(|task| task.cancelled)
+    }
+
+    /// Finishes a task, storing `response` as its result when given.
+    pub fn complete(&self, id: u64, response: Option<Value>) {
+        let Some((_, mut task)) = self.running.remove(&id) else {
+            return;
+        };
+        if let Some(response) = response {
+            task.running_time_nanos = task.running_time_nanos();
+            task.response = Some(response);
+            self.results.insert(id, task);
+        }
+    }
+
+    /// Finishes a task that failed without a response, keeping `error` as
+    /// its result.
+    pub fn fail(&self, id: u64, error: Value) {
+        let Some((_, mut task)) = self.running.remove(&id) else {
+            return;
+        };
+        task.running_time_nanos = task.running_time_nanos();
+        task.error = Some(error);
+        self.results.insert(id, task);
+    }
+
+    /// Running tasks whose action matches `filter`, ordered by id.
+    pub fn list(&self, filter: impl Fn(&str) -> bool) -> Vec<TaskInfo> {
+        let mut tasks: Vec<TaskInfo> = self
+            .running
+            .iter()
+            .filter(|task| filter(&task.action))
+            .map(|task| self.snapshot(&task))
+            .collect();
+        tasks.sort_by_key(|task| task.id);
+        tasks
+    }
+
+    /// Looks a task up by its `<node_id>:<id>` identifier, whether it is
+    /// still running or has stored its result.
+    pub fn get(&self, task_id: &str) -> Result<TaskInfo, String> {
+        let id = self.parse_id(task_id)?;
+        if let Some(task) = self.running.get(&id) {
+            return Ok(self.snapshot(&task));
+        }
+        self.results
+            .get(&id)
+            .map(|task| task.clone())
+            .ok_or_else(|| not_running(task_id))
+    }
+
+    /// Flags a running task as cancelled; the operation stops at its next
+    /// checkpoint.
+    pub fn cancel(&self, task_id: &str) -> Result<TaskInfo, String> {
+        let id = self.parse_id(task_id)?;
+        let mut task = self
+            .running
+            .get_mut(&id)
+            .ok_or_else(|| format!("task [{}] is not found", task_id))?;
+        task.cancelled = true;
+        Ok(self.snapshot(&task))
+    }
+
+    fn snapshot(&self, task: &TaskInfo) -> TaskInfo {
+        TaskInfo {
+            running_time_nanos: task.running_time_nanos(),
+            ..task.clone()
+        }
+    }
+
+    fn parse_id(&self, task_id: &str) -> Result<u64, String> {
+        let (node, id) = task_id
+            .split_once(':')
+            .ok_or_else(|| format!("malformed task id {}", task_id))?;
+        let id = id
+            .parse()
+            .map_err(|_| format!("malformed task id {}", task_id))?;
+        if node != self.node_id {
+            return Err(not_running(task_id));
+        }
+        Ok(id)
+    }
+}
+
+fn not_running(task_id: &str) -> String {
+    format!(
+        "task [{}] isn't running and hasn't stored its results",
+        task_id
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_register_and_complete_tasks() {
+        let registry = TaskRegistry::new();
+        let id = registry.register("indices:data/write/update/byquery", "test".to_string());
+        let task_id = registry.task_id(id);
+
+        assert!(!registry.get(&task_id).unwrap().completed());
+        registry.complete(id, Some(json!({ "updated": 1 })));
+        assert_eq!(
+            registry.get(&task_id).unwrap().response,
+            Some(json!({ "updated": 1 }))
+        );
+
+        let failed = registry.register("indices:data/write/reindex", "copy".to_string());
+        registry.fail(failed, json!({ "type": "exception" }));
+        let task = registry.get(&registry.task_id(failed)).unwrap();
+        assert!(task.completed());
+        assert_eq!(task.error, Some(json!({ "type": "exception" })));
+
+        assert!(registry.get("bogus").is_err());
+        assert!(registry.get("other:1").is_err());
+    }
+
+    #[test]
+    fn should_list_cancel_and_forget_tasks() {
+        let registry = TaskRegistry::new();
+        let reindex = registry.register("indices:data/write/reindex", "copy".to_string());
+        let delete = registry.register("indices:data/write/delete/byquery", "purge".to_string());
+        registry.set_status(delete, json!({ "deleted": 3 }));
+
+        let listed = registry.list(|action| action.ends_with("byquery"));
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].status, Some(json!({ "deleted": 3 })));
+
+        assert!(
+            registry
+                .cancel(&registry.task_id(reindex))
+                .unwrap()
+                .cancelled
+        );
+        assert!(registry.is_cancelled(reindex));
+
+        // Results of synchronous tasks are not kept.
+        registry.complete(delete, None);
+        assert!(registry.get(&registry.task_id(delete)).is_err());
+        assert!(registry.cancel(&registry.task_id(delete)).is_err());
+    }
+}
@@ -0,0 +1,335 @@
+use crate::api::namespaces::{self, Namespaces};
+use crate::api::seed::{self, SeedSummary};
+use crate::config::{Config, ListenerConfig};
+use crate::repository::persistence::PersistenceOptions;
+use crate::repository::store::InMemoryStore;
+use crate::repository::tasks::TaskRegistry;
+use crate::{AppState, app};
+use axum::extract::DefaultBodyLimit;
+use axum::middleware;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tower_http::limit::RequestBodyLimitLayer;
+
+/// Entry point for running the server in-process, e.g. one isolated
+/// instance per test:
+///
+/// ```no_run
+/// # async fn example() -> Result<(), String> {
+/// let server = es_fake::EsFake::builder()
+///     .port(0)
+///     .auth("elastic", "secret")
+///     .start()
+///     .await?;
+/// println!("listening on {}", server.url());
+/// server.shutdown().await
+/// # }
+/// ```
+pub struct EsFake;
+
+impl EsFake {
+    pub fn builder() -> EsFakeBuilder {
+        EsFakeBuilder::default()
+    }
+}
+
+/// Options of an [`EsFake`] server: the defaults of [`Config`] except that it
+/// listens on `127.0.0.1`.
+#[derive(Debug, Clone)]
+pub struct EsFakeBuilder {
+    config: Config,
+}
+
+impl Default for EsFakeBuilder {
+    fn default() -> Self {
+        let mut config = Config::default();
+        config.listener.bind = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        Self { config }
+    }
+}
+
+impl EsFakeBuilder {
+    /// Replaces every option, e.g. with a configuration read from a file.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn bind(mut self, address: IpAddr) -> Self {
+        self.config.listener.bind = address;
+        self
+    }
+
+    /// Port to listen on; `0` picks a free one, see [`EsFakeServer::addr`].
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.listener.port = port;
+        self
+    }
+
+    /// Requires basic authentication with this credential.
+    pub fn auth(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+        self.config.auth.user = user.into();
+        self.config.auth.password = Some(password.into());
+        self
+    }
+
+    /// Credential of the `/_fake` admin API, which is only served once one is
+    /// set.
+    pub fn admin_auth(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+        self.config.auth.admin_user = user.into();
+        self.config.auth.admin_password = Some(password.into());
+        self
+    }
+
+    /// Index definitions and bulk files loaded before the server accepts
+    /// connections, see [`seed::load`].
+    pub fn seed(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.seed = Some(path.into());
+        self
+    }
+
+    /// Allows `fs` snapshot repositories under this directory, see
+    /// [`crate::config::PathConfig`].
+    pub fn path_repo(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.path.repo.push(path.into());
+        self
+    }
+
+    /// Keeps the default namespace in a data directory.
+    pub fn persistence(mut self, options: PersistenceOptions) -> Self {
+        self.config.persistence.data_dir = Some(options.data_dir);
+        self.config.persistence.fsync = options.fsync;
+        self
+    }
+
+    /// Opens the store, loads the seed data and starts serving in the
+    /// background. The seed is skipped when the data directory already holds
+    /// indices.
+    pub async fn start(self) -> Result<EsFakeServer, String> {
+        let config = Arc::new(self.config);
+        let persistence = config.persistence.options();
+        let store = match &persistence {
+            Some(options) => InMemoryStore::open(options.clone())
+                .map_err(|e| format!("failed to open data directory: {}", e))?,
+            None => InMemoryStore::new(),
+        };
+        let state = Arc::new(AppState {
+            store,
+            tasks: TaskRegistry::new(),
+            config: config.clone(),
+        });
+
+        let mut seeded = None;
+        if let Some(path) = &config.seed
+            && state.store.index_names().is_empty()
+        {
+            let summary = seed::load(&state, path)
+                .await
+                .map_err(|e| format!("failed to load seed data: {}", e))?;
+            seeded = Some(summary);
+        }
+
+        let ListenerConfig { bind, port } = config.listener;
+        let listener = TcpListener::bind(SocketAddr::new(bind, port))
+            .await
+            .map_err(|e| format!("cannot listen on {}:{}: {}", bind, port, e))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| format!("cannot read the bound address: {}", e))?;
+
+        let maintenance = persistence
+            .is_some()
+            .then(|| tokio::spawn(maintain(state.clone())));
+        let router = if config.features.namespaces {
+            namespaces::router(Arc::new(Namespaces::new(state.clone(), app)))
+        } else {
+            app(state.clone())
+        };
+        // The limit covers streamed bodies such as bulk requests too, so the
+        // smaller per-extractor default is lifted.
+        let router = router
+            .layer(RequestBodyLimitLayer::new(
+                usize::try_from(config.limits.max_content_length).unwrap_or(usize::MAX),
+            ))
+            .layer(DefaultBodyLimit::disable())
+            .layer(middleware::from_fn(crate::api::logging::debug_log));
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        let serve = tokio::spawn(async move {
+            axum::serve(listener, router)
+                .with_graceful_shutdown(async move {
+                    // Also resolves when the server handle is dropped.
+                    let _ = stopped.await;
+                })
+                .await
+        });
+
+        Ok(EsFakeServer {
+            addr,
+            state,
+            seeded,
+            shutdown: Some(shutdown),
+            serve: Some(serve),
+            maintenance,
+        })
+    }
+}
+
+/// A running server. Dropping it aborts the server and maintenance tasks
+/// without waiting; call [`EsFakeServer::shutdown`] to wait for in-flight
+/// requests and sync the write-ahead log.
+pub struct EsFakeServer {
+    addr: SocketAddr,
+    state: Arc<AppState>,
+    seeded: Option<SeedSummary>,
+    // Taken by `shutdown`, which leaves nothing for `drop` to abort.
+    shutdown: Option<oneshot::Sender<()>>,
+    serve: Option<JoinHandle<std::io::Result<()>>>,
+    maintenance: Option<JoinHandle<()>>,
+}
+
+impl EsFakeServer {
+    /// The address actually bound, with the chosen port when started with
+    /// port `0`.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// State of the default namespace.
+    pub fn state(&self) -> &Arc<AppState> {
+        &self.state
+    }
+
+    /// What the seed data added, when it was loaded.
+    pub fn seeded(&self) -> Option<&SeedSummary> {
+        self.seeded.as_ref()
+    }
+
+    /// Stops accepting connections, waits for in-flight requests and syncs
+    /// the write-ahead log.
+    pub async fn shutdown(mut self) -> Result<(), String> {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(maintenance) = self.maintenance.take() {
+            maintenance.abort();
+        }
+        if let Some(serve) = self.serve.take() {
+            serve
+                .await
+                .map_err(|e| format!("server task failed: {}", e))?
+                .map_err(|e| format!("server failed: {}", e))?;
+        }
+        self.state
+            .store
+            .flush()
+            .map_err(|e| format!("failed to sync the write-ahead log: {}", e))
+    }
+}
+
+impl Drop for EsFakeServer {
+    fn drop(&mut self) {
+        if let Some(serve) = &self.serve {
+            serve.abort();
+        }
+        if let Some(maintenance) = &self.maintenance {
+            maintenance.abort();
+        }
+    }
+}
+
+/// Syncs and compacts the write-ahead log in the background.
+async fn maintain(state: Arc<AppState>) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        ticker.tick().await;
+        let state = state.clone();
+        match tokio::task::spawn_blocking(move || state.store.maintain()).await {
+            Ok(Err(e)) => eprintln!("persistence maintenance failed: {}", e),
+            Err(e) => eprintln!("persistence maintenance panicked: {}", e),
+            Ok(Ok(())) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{Engine as _, engine::general_purpose};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    async fn get(addr: SocketAddr, path: &str, credentials: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nAuthorization: Basic {}\r\nConnection: close\r\n\r\n",
+            path,
+            general_purpose::STANDARD.encode(credentials)
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn should_serve_on_a_free_port_until_shut_down() {
+        let server = EsFake::builder()
+            .port(0)
+            .auth("elastic", "secret")
+            .start()
+            .await
+            .unwrap();
+        let addr = server.addr();
+        assert_ne!(addr.port(), 0);
+
+        let response = get(addr, "/", "elastic:secret").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        let response = get(addr, "/", "elastic:wrong").await;
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+
+        server.shutdown().await.unwrap();
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn should_refuse_to_start_with_invalid_seed_files() {
+        for (file, content, reason) in [
+            ("orders.json", "{ \"mappings\": ", "is not valid JSON"),
+            (
+                "orders.json",
+                r#"{ "mappings": { "properties": { "total": { "type": "money" } } } }"#,
+                "mapper_parsing_exception",
+            ),
+            (
+                "orders.ndjson",
+                "{\"index\":{\"_id\":\"1\"}}\n{\"total\":\n",
+                "orders.ndjson",
+            ),
+        ] {
+            let dir = std::env::temp_dir().join(format!("es_fake-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join(file), content).unwrap();
+
+            let error = EsFake::builder()
+                .port(0)
+                .seed(&dir)
+                .start()
+                .await
+                .err()
+                .unwrap();
+            assert!(error.starts_with("failed to load seed data"), "{}", error);
+            assert!(error.contains(file), "{}", error);
+            assert!(error.contains(reason), "{}", error);
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use es_fake::EsFake;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn request(addr: SocketAddr, method: &str, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        method, path
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn should_run_isolated_instances_side_by_side() {
+    let first = EsFake::builder().port(0).start().await.unwrap();
+    let second = EsFake::builder().port(0).start().await.unwrap();
+    assert_ne!(first.addr(), second.addr());
+
+    let response = request(first.addr(), "PUT", "/orders").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+    let response = request(first.addr(), "HEAD", "/orders").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    let response = request(second.addr(), "HEAD", "/orders").await;
+    assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+    assert!(second.state().store.index_names().is_empty());
+
+    second.shutdown().await.unwrap();
+    let response = request(first.addr(), "HEAD", "/orders").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    first.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn should_stop_serving_when_dropped() {
+    let server = EsFake::builder().port(0).start().await.unwrap();
+    let addr = server.addr();
+    let response = request(addr, "GET", "/").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+    drop(server);
+    // The aborted task releases the listener once the runtime polls it.
+    let mut refused = false;
+    for _ in 0..50 {
+        if TcpStream::connect(addr).await.is_err() {
+            refused = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(refused, "{} still accepts connections", addr);
+}